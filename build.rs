@@ -1,5 +1,5 @@
 use bootloader::{BootConfig, DiskImageBuilder};
-use std::{env, path::PathBuf};
+use std::{env, fs, path::{Path, PathBuf}};
 use std::process::Command;
 
 fn main() {
@@ -24,6 +24,18 @@ fn main() {
         .as_str().unwrap_or("128M");
     let accel_enabled = metadata["packages"][1]["metadata"]["os"]["accel_enabled"]
         .as_str().unwrap_or("true");
+    let control_port = metadata["packages"][1]["metadata"]["os"]["control_port"]
+        .as_str().unwrap_or("4444");
+    let disk_image = metadata["packages"][1]["metadata"]["os"]["disk_image"]
+        .as_str().unwrap_or("");
+    let disk_bus = metadata["packages"][1]["metadata"]["os"]["disk_bus"]
+        .as_str().unwrap_or("ahci");
+    let snapshot = metadata["packages"][1]["metadata"]["os"]["snapshot"]
+        .as_str().unwrap_or("false");
+    let nic = metadata["packages"][1]["metadata"]["os"]["nic"]
+        .as_str().unwrap_or("");
+    let ramdisk_files = metadata["packages"][1]["metadata"]["os"]["ramdisk_files"]
+        .as_str().unwrap_or("");
 
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
     let uefi_path = out_dir.join(format!("{}-uefi.img", os_name));
@@ -35,6 +47,18 @@ fn main() {
     let mut disk_builder = DiskImageBuilder::new(PathBuf::from(kernel_path));
     disk_builder.set_boot_config(&boot_config);
 
+    // The bootloader only passes a single ramdisk, so all files go into one cpio archive
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let mut ramdisk_entries = Vec::new();
+    for path in ramdisk_files.split(',').map(str::trim).filter(|path| !path.is_empty()) {
+        collect_files(&manifest_dir.join(path), Path::new(path), &mut ramdisk_entries);
+    }
+    if !ramdisk_entries.is_empty() {
+        let ramdisk_path = out_dir.join("ramdisk.cpio");
+        fs::write(&ramdisk_path, build_cpio(&ramdisk_entries)).unwrap();
+        disk_builder.set_ramdisk(ramdisk_path);
+    }
+
     disk_builder.create_uefi_image(&uefi_path).unwrap();
     disk_builder.create_bios_image(&bios_path).unwrap();
 
@@ -49,4 +73,51 @@ fn main() {
     println!("cargo:rustc-env=VGA_OPTIONS={}", vga_options);
     println!("cargo:rustc-env=AVAILABLE_MEMORY={}", mem_size);
     println!("cargo:rustc-env=ACCEL_ENABLED={}", accel_enabled);
+    println!("cargo:rustc-env=CONTROL_PORT={}", control_port);
+    println!("cargo:rustc-env=DISK_IMAGE={}", disk_image);
+    println!("cargo:rustc-env=DISK_BUS={}", disk_bus);
+    println!("cargo:rustc-env=SNAPSHOT={}", snapshot);
+    println!("cargo:rustc-env=NIC={}", nic);
+}
+
+/// Collects the file or all files below the directory as archive paths (relative to the directory
+/// given in the metadata) and their contents.
+fn collect_files(path: &Path, name: &Path, entries: &mut Vec<(String, Vec<u8>)>) {
+    println!("cargo:rerun-if-changed={}", path.display());
+    if path.is_dir() {
+        let mut children: Vec<_> = fs::read_dir(path).unwrap().map(|entry| entry.unwrap().path()).collect();
+        children.sort();
+        for child in children {
+            collect_files(&child, &name.join(child.file_name().unwrap()), entries);
+        }
+    } else {
+        let contents = fs::read(path).unwrap_or_else(|err| panic!("Failed to read ramdisk file {}: {}", path.display(), err));
+        entries.push((name.to_string_lossy().replace('\\', "/"), contents));
+    }
+}
+
+/// Builds a `newc` cpio archive, the format the kernel reads its initial ramdisk in.
+fn build_cpio(entries: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut archive = Vec::new();
+    let trailer = ("TRAILER!!!".to_string(), Vec::new());
+    for (index, (name, contents)) in entries.iter().chain(std::iter::once(&trailer)).enumerate() {
+        let mode = if index == entries.len() { 0 } else { 0o100644 };
+        let header = format!(
+            "070701{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}",
+            index + 1, mode, 0, 0, 1, 0, contents.len(), 0, 0, 0, 0, name.len() + 1, 0
+        );
+        archive.extend_from_slice(header.as_bytes());
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        pad(&mut archive);
+        archive.extend_from_slice(contents);
+        pad(&mut archive);
+    }
+    archive
+}
+
+fn pad(archive: &mut Vec<u8>) {
+    while archive.len() % 4 != 0 {
+        archive.push(0);
+    }
 }
\ No newline at end of file
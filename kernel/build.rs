@@ -0,0 +1,78 @@
+use std::env;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Embeds what the kernel was built from, read back by `build_info()` in the kernel.
+fn main() {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+
+    // Rebuilt on commits, checkouts and changes to the sources, which might make the tree dirty
+    if let Some(git_dir) = git(&manifest_dir, &["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        println!("cargo:rerun-if-changed={}/index", git_dir);
+    }
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let commit = match git(&manifest_dir, &["rev-parse", "--short=12", "HEAD"]) {
+        Some(commit) => {
+            let dirty = git(&manifest_dir, &["status", "--porcelain"]).is_some_and(|status| !status.is_empty());
+            if dirty { format!("{}-dirty", commit) } else { commit }
+        }, None => "unknown".to_string()
+    };
+
+    // Reproducible builds pin the timestamp
+    let seconds = env::var("SOURCE_DATE_EPOCH").ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs()));
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = Command::new(rustc).arg("--version").output().ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map_or_else(|| "unknown".to_string(), |version| version.trim().to_string());
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(|feature| feature.to_lowercase().replace('_', "-")))
+        .filter(|feature| feature != "default")
+        .collect();
+    features.sort();
+
+    println!("cargo:rustc-env=BUILD_COMMIT={}", commit);
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", format_timestamp(seconds));
+    println!("cargo:rustc-env=BUILD_RUSTC={}", rustc_version);
+    println!("cargo:rustc-env=BUILD_FEATURES={}", features.join(","));
+    println!("cargo:rustc-env=BUILD_PROFILE={}", env::var("PROFILE").unwrap_or_default());
+
+    // GRUB loads the image where it was linked, the 32-bit trampoline needs absolute addresses
+    if env::var_os("CARGO_FEATURE_MULTIBOOT2").is_some() {
+        println!("cargo:rerun-if-changed=multiboot2.ld");
+        println!("cargo:rustc-link-arg-bins=-T{}/multiboot2.ld", manifest_dir);
+        println!("cargo:rustc-link-arg-bins=--no-pie");
+    }
+}
+
+/// Runs git in the directory, giving its trimmed output if it succeeded.
+fn git(dir: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new("git").current_dir(dir).args(args).output().ok()?;
+    if !output.status.success() { return None; }
+    String::from_utf8(output.stdout).ok().map(|output| output.trim().to_string())
+}
+
+/// Formats seconds since the Unix epoch as UTC like `2024-03-15T13:45:00Z`.
+fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+
+    // Converts days to a civil date, counting in 400 year eras starting at March 1st
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
\ No newline at end of file
@@ -0,0 +1,247 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use log::LevelFilter;
+use crate::api::time::{DateTime, Month};
+use crate::drivers::timer::pit::PitMode;
+use crate::drivers::vbe::VideoMode;
+use crate::managers::display::DisplayMode;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlCommand {
+    /// Shuts the kernel down.
+    Shutdown,
+    /// Changes the log level of every module without a filter of its own.
+    LogLevel(LevelFilter),
+    /// Lists the module log filters, or sets the level of a module and the ones below it. No
+    /// level removes the module's filter again.
+    LogFilter(Option<(String, Option<LevelFilter>)>),
+    /// Dumps the current frame buffer contents as a binary PPM image over the control port.
+    Screenshot,
+    /// Injects a character as text input coming from the serial line.
+    InjectKey(char),
+    /// Dumps and clears the trace buffers over the control port.
+    TraceDump,
+    /// Shows how long each boot stage took.
+    BootChart,
+    /// Lists the loaded kernel modules and the ones available in the initial ramdisk.
+    ListModules,
+    /// Loads the kernel module with the given name from the initial ramdisk.
+    LoadModule(String),
+    /// Shows or hides the on-screen log viewer.
+    LogViewer(bool),
+    /// Lists the mapped regions of the address space, only the writable and executable ones if set.
+    MemoryMap(bool),
+    /// Runs the benchmarks and reports their results.
+    Benchmark,
+    /// Lists the thermal zones with their current and critical temperatures.
+    Sensors,
+    /// Shows the estimated CPU frequency and the idle and performance states.
+    CpuInfo,
+    /// Shows the hardware inventory from the SMBIOS tables.
+    HardwareInfo,
+    /// Shows the UEFI firmware, its clock and the boot order.
+    Firmware,
+    /// Shows the current date and time, or sets the real-time clock to the given one.
+    Date(Option<DateTime>),
+    /// Corrects the clock by the given nanoseconds, slewing instead of stepping it.
+    AdjustDate(i64),
+    /// Shows what the kernel was built from.
+    Version,
+    /// Lists the objects of the ACPI namespace at and below the given absolute path, the whole
+    /// namespace if None.
+    AcpiDump(Option<String>),
+    /// Evaluates the ACPI object at the absolute path, invoking it with the arguments if it is a method.
+    AcpiExec(String, Vec<AcpiArgument>),
+    /// Lists the platform devices the firmware describes with their resources.
+    Devices,
+    /// Dumps the address space of the embedded controller.
+    EmbeddedController,
+    /// Lists the serial ports with their line settings and roles.
+    SerialPorts,
+    /// Shows the tick rate and which timer drives it, or sets the rate (in Hz) of the PIT.
+    TickRate(Option<u64>),
+    /// Switches the PIT between periodic and one-shot interrupts.
+    TimerMode(PitMode),
+    /// Shows the resolution of the screen, or switches it through the Bochs VBE display interface.
+    Resolution(Option<VideoMode>),
+    /// Lists the screens with their display modes, or shows the mode on the screen with the index.
+    Output(Option<(usize, DisplayMode)>)
+}
+
+/// An argument passed to an ACPI method with `acpiexec`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AcpiArgument {
+    Integer(u64),
+    String(String)
+}
+
+impl ControlCommand {
+    /// Parses a single line received on the control channel.
+    pub fn parse(line: &str) -> Result<Self, &'static str> {
+        let mut words = line.split_whitespace();
+        let command = words.next().ok_or("Empty command")?;
+        let argument = words.next();
+
+        if words.next().is_some() {
+            return Err("Too many arguments");
+        }
+
+        match (command, argument) {
+            ("shutdown", None) => Ok(ControlCommand::Shutdown),
+            ("screenshot", None) => Ok(ControlCommand::Screenshot),
+            ("trace-dump", None) => Ok(ControlCommand::TraceDump),
+            ("bootchart", None) => Ok(ControlCommand::BootChart),
+            ("lsmod", None) => Ok(ControlCommand::ListModules),
+            ("bench", None) => Ok(ControlCommand::Benchmark),
+            ("sensors", None) => Ok(ControlCommand::Sensors),
+            ("cpuinfo", None) => Ok(ControlCommand::CpuInfo),
+            ("hwinfo", None) => Ok(ControlCommand::HardwareInfo),
+            ("firmware", None) => Ok(ControlCommand::Firmware),
+            ("version", None) => Ok(ControlCommand::Version),
+            ("devices", None) => Ok(ControlCommand::Devices),
+            ("ec", None) => Ok(ControlCommand::EmbeddedController),
+            ("serial", None) => Ok(ControlCommand::SerialPorts),
+            ("tickrate", None) => Ok(ControlCommand::TickRate(None)),
+            ("tickrate", Some("oneshot")) => Ok(ControlCommand::TimerMode(PitMode::OneShot)),
+            ("tickrate", Some("periodic")) => Ok(ControlCommand::TimerMode(PitMode::Periodic)),
+            ("tickrate", Some(hz)) => hz.parse().map(|hz| ControlCommand::TickRate(Some(hz)))
+                .map_err(|_| "Expected a rate in Hz, oneshot or periodic"),
+            ("resolution", None) => Ok(ControlCommand::Resolution(None)),
+            ("resolution", Some(mode)) => parse_video_mode(mode).map(|mode| ControlCommand::Resolution(Some(mode))),
+            ("output", None) => Ok(ControlCommand::Output(None)),
+            ("output", Some(assignment)) => parse_output(assignment).map(|output| ControlCommand::Output(Some(output))),
+            ("acpidump", scope) => Ok(ControlCommand::AcpiDump(scope.map(ToString::to_string))),
+            ("acpiexec", Some(call)) => parse_acpi_call(call),
+            ("date", None) => Ok(ControlCommand::Date(None)),
+            ("date", Some(argument)) => if let Some(value) = argument.strip_prefix("--set=") {
+                parse_date_time(value).map(|date_time| ControlCommand::Date(Some(date_time)))
+            } else if let Some(value) = argument.strip_prefix("--adjust=") {
+                parse_seconds(value).map(ControlCommand::AdjustDate)
+            } else {
+                Err("Expected --set=YYYY-MM-DDTHH:MM:SS, --adjust=SECONDS or nothing")
+            },
+            ("vmmap", None) => Ok(ControlCommand::MemoryMap(false)),
+            ("vmmap", Some("wx")) => Ok(ControlCommand::MemoryMap(true)),
+            ("vmmap", Some(_)) => Err("Expected wx or nothing"),
+            ("insmod", Some(name)) => Ok(ControlCommand::LoadModule(name.to_string())),
+            ("logview", Some(state)) => match state {
+                "on" => Ok(ControlCommand::LogViewer(true)),
+                "off" => Ok(ControlCommand::LogViewer(false)),
+                _ => Err("Expected on or off")
+            },
+            ("loglevel", Some(level)) => parse_level(level).map(ControlCommand::LogLevel),
+            ("logfilter", None) => Ok(ControlCommand::LogFilter(None)),
+            ("logfilter", Some(filter)) => {
+                let (target, level) = filter.split_once('=').ok_or("Expected MODULE=LEVEL or MODULE=default")?;
+                let level = if level == "default" { None } else { Some(parse_level(level)?) };
+                Ok(ControlCommand::LogFilter(Some((target.to_string(), level))))
+            }, ("inject-key", Some(key)) => {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(key), None) => Ok(ControlCommand::InjectKey(key)),
+                    _ => Err("Key must be a single character")
+                }
+            }, ("shutdown" | "screenshot" | "trace-dump" | "bootchart" | "lsmod" | "bench" | "sensors" | "cpuinfo" | "hwinfo" | "firmware" | "version" | "devices" | "ec" | "serial", Some(_)) => Err("Command takes no arguments"),
+            ("loglevel" | "inject-key" | "insmod" | "logview" | "acpiexec", None) => Err("Command needs an argument"),
+            _ => Err("Unknown command")
+        }
+    }
+}
+
+/// Parses an ACPI call like `\\_SB.PCI0._STA` or `\\_SB.EC0.MTHD(1,0x2A,name)`. Arguments are
+/// integers, decimal or hexadecimal, and anything else is passed as a string.
+fn parse_acpi_call(call: &str) -> Result<ControlCommand, &'static str> {
+    let Some((path, arguments)) = call.split_once('(') else {
+        return Ok(ControlCommand::AcpiExec(call.to_string(), Vec::new()));
+    };
+    let arguments = arguments.strip_suffix(')').ok_or("Expected PATH or PATH(ARG,...)")?;
+    let arguments = arguments.split(',').filter(|argument| !argument.is_empty()).map(|argument| {
+        let integer = match argument.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => argument.parse().ok()
+        };
+        integer.map_or_else(|| AcpiArgument::String(argument.to_string()), AcpiArgument::Integer)
+    }).collect();
+    Ok(ControlCommand::AcpiExec(path.to_string(), arguments))
+}
+
+/// Parses a log level like `warn`.
+fn parse_level(level: &str) -> Result<LevelFilter, &'static str> {
+    match level {
+        "off" => Ok(LevelFilter::Off),
+        "error" => Ok(LevelFilter::Error),
+        "warn" => Ok(LevelFilter::Warn),
+        "info" => Ok(LevelFilter::Info),
+        "debug" => Ok(LevelFilter::Debug),
+        "trace" => Ok(LevelFilter::Trace),
+        _ => Err("Unknown log level")
+    }
+}
+
+/// Parses a date and time like `2024-03-15T13:45:00`.
+fn parse_date_time(value: &str) -> Result<DateTime, &'static str> {
+    let (date, time) = value.split_once('T').ok_or("Expected YYYY-MM-DDTHH:MM:SS")?;
+    let mut date = date.split('-');
+    let mut time = time.split(':');
+    let next = |parts: &mut core::str::Split<char>| -> Result<u32, &'static str> {
+        parts.next().and_then(|part| part.parse().ok()).ok_or("Expected YYYY-MM-DDTHH:MM:SS")
+    };
+    let (year, month, day) = (next(&mut date)?, next(&mut date)?, next(&mut date)?);
+    let (hours, minutes, seconds) = (next(&mut time)?, next(&mut time)?, next(&mut time)?);
+    if date.next().is_some() || time.next().is_some() {
+        return Err("Expected YYYY-MM-DDTHH:MM:SS");
+    }
+
+    let month = Month::from_u8(month.try_into().unwrap_or(0)).ok_or("Month out of range")?;
+    if hours > 23 || minutes > 59 || seconds > 59 {
+        return Err("Time out of range");
+    }
+    let date_time = DateTime::new(0, seconds as u8, minutes as u8, hours as u8, 1, month, year as i32);
+    if day == 0 || day > date_time.days_in_month() as u32 {
+        return Err("Day out of range");
+    }
+    Ok(DateTime::new(0, seconds as u8, minutes as u8, hours as u8, day as u8, month, year as i32))
+}
+
+/// Parses signed seconds with up to nine decimals, like `-1.25`, into nanoseconds.
+fn parse_seconds(value: &str) -> Result<i64, &'static str> {
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value.strip_prefix('+').unwrap_or(value))
+    };
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if fraction.len() > 9 || !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err("Expected seconds with up to nine decimals");
+    }
+    let seconds: i64 = seconds.parse().map_err(|_| "Expected seconds with up to nine decimals")?;
+    let nanos = fraction.bytes().chain(core::iter::repeat(b'0')).take(9)
+        .fold(0, |nanos, digit| nanos * 10 + (digit - b'0') as i64);
+    let total = seconds.checked_mul(1_000_000_000).and_then(|total| total.checked_add(nanos))
+        .ok_or("Correction out of range")?;
+    Ok(if negative { -total } else { total })
+}
+
+/// Parses a video mode like `1024x768` or `1024x768x16`, 32 bits per pixel if not given.
+fn parse_video_mode(value: &str) -> Result<VideoMode, &'static str> {
+    let mut parts = value.split('x').map(|part| part.parse::<usize>());
+    let (Some(Ok(width)), Some(Ok(height))) = (parts.next(), parts.next()) else {
+        return Err("Expected WIDTHxHEIGHT or WIDTHxHEIGHTxBPP");
+    };
+    let bpp = match parts.next() {
+        None => 32,
+        Some(Ok(bpp)) => bpp,
+        Some(Err(_)) => return Err("Expected WIDTHxHEIGHT or WIDTHxHEIGHTxBPP")
+    };
+    if parts.next().is_some() {
+        return Err("Expected WIDTHxHEIGHT or WIDTHxHEIGHTxBPP");
+    }
+    Ok(VideoMode::new(width, height, bpp))
+}
+
+/// Parses the assignment of a display mode to a screen like `1=logview`.
+fn parse_output(value: &str) -> Result<(usize, DisplayMode), &'static str> {
+    let (index, mode) = value.split_once('=').ok_or("Expected OUTPUT=MODE")?;
+    let index = index.parse().map_err(|_| "Expected the number of an output")?;
+    let mode = DisplayMode::from_name(mode).ok_or("Display mode must be off, console, text or logview")?;
+    Ok((index, mode))
+}
\ No newline at end of file
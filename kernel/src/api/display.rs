@@ -1,4 +1,3 @@
-use bootloader_api::info::FrameBufferInfo;
 use embedded_graphics::{
     geometry::Point,
     mono_font::{
@@ -11,6 +10,7 @@ use embedded_graphics::{
 };
 use embedded_vintage_fonts::{FONT_6X8,FONT_8X16,FONT_12X16,FONT_24X32};
 use profont::*;
+use crate::internal::framebuffer::FrameBufferInfo;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Position {
@@ -48,6 +48,23 @@ pub struct Region {
     pub fn new(position: Position, size: Size) -> Region {
         Region { position, size }
     }
+
+    /// Returns whether the position lies within the region.
+    pub fn contains(&self, position: Position) -> bool {
+        position.x >= self.position.x && position.x < self.position.x + self.size.width
+            && position.y >= self.position.y && position.y < self.position.y + self.size.height
+    }
+
+    /// Returns the part both regions cover, None if they don't overlap.
+    pub fn intersection(&self, other: Region) -> Option<Region> {
+        let left = self.position.x.max(other.position.x);
+        let top = self.position.y.max(other.position.y);
+        let right = (self.position.x + self.size.width).min(other.position.x + other.size.width);
+        let bottom = (self.position.y + self.size.height).min(other.position.y + other.size.height);
+        (left < right && top < bottom).then(|| Region::new(
+            Position::new(left, top), Size::new(right - left, bottom - top)
+        ))
+    }
 } #[allow(dead_code)] impl Into<Rectangle> for Region {
     fn into(self) -> Rectangle {
         Rectangle::new(self.position.into(), self.size.into())
@@ -59,9 +76,34 @@ pub struct Color {
     pub red: u8,
     pub green: u8,
     pub blue: u8,
+    /// The opacity, 0 is fully transparent and 255 fully opaque.
+    pub alpha: u8
 } #[allow(dead_code)] impl Color {
+    /// Creates an opaque color.
     pub fn new(red: u8, green: u8, blue: u8) -> Color {
-        Color { red, green, blue }
+        Color { red, green, blue, alpha: u8::MAX }
+    }
+
+    pub fn with_alpha(red: u8, green: u8, blue: u8, alpha: u8) -> Color {
+        Color { red, green, blue, alpha }
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.alpha == u8::MAX
+    }
+
+    /// Mixes this color over the background according to this color's alpha.
+    /// The result is opaque, as the frame buffer has no alpha channel.
+    pub fn blend_over(&self, background: Color) -> Color {
+        let mix = |foreground: u8, background: u8| -> u8 {
+            let alpha = self.alpha as u32;
+            ((foreground as u32 * alpha + background as u32 * (255 - alpha) + 127) / 255) as u8
+        };
+        Color::new(
+            mix(self.red, background.red),
+            mix(self.green, background.green),
+            mix(self.blue, background.blue)
+        )
     }
 } #[allow(dead_code)] impl Into<Rgb888> for Color {
     fn into(self) -> Rgb888 {
@@ -155,6 +197,25 @@ pub enum Fonts {
         Fonts::ProFont16x29 => Size::new(16, 29),
         Fonts::Font24x32 => Size::new(24, 32),
     }}
+
+    /// Returns the bold face of the font, the font itself if it has none.
+    pub fn bold(self) -> Self { match self {
+        Fonts::Font6x13 => Fonts::Font6x13B,
+        Fonts::Font7x13 => Fonts::Font7x13B,
+        Fonts::Font7x14 => Fonts::Font7x14B,
+        Fonts::Font8x13 => Fonts::Font8x13B,
+        Fonts::Font9x15 => Fonts::Font9x15B,
+        Fonts::Font9x18 => Fonts::Font9x18B,
+        font => font
+    }}
+
+    /// Returns the italic face of the font, the font itself if it has none.
+    pub fn italic(self) -> Self { match self {
+        Fonts::Font6x13 => Fonts::Font6x13I,
+        Fonts::Font7x13 => Fonts::Font7x13I,
+        Fonts::Font8x13 => Fonts::Font8x13I,
+        font => font
+    }}
 } #[allow(dead_code)] impl Into<MonoFont<'_>> for Fonts {
     fn into(self) -> MonoFont<'static> { match self {
         Fonts::ProFont5x10 => PROFONT_7_POINT,
@@ -232,29 +293,56 @@ pub enum TextLineHeight {
     } }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum DisplayError {
+    /// The display is locked by someone else, the draw can be retried or deferred.
+    Busy,
+    /// There is no frame buffer to draw to.
+    NoFrameBuffer,
+    /// The given buffer does not have the size of the frame buffer.
+    SizeMismatch,
+    /// A pixel outside of the frame buffer was drawn to.
+    OutOfBounds,
+    /// The frame buffer uses a pixel format that can't be drawn to.
+    UnsupportedPixelFormat
+}
+
 pub trait DisplayApi {
     /// Draws the given buffer to the display without modification.
-    fn draw(&mut self, buffer: &[u8]);
+    fn draw(&mut self, buffer: &[u8]) -> Result<(), DisplayError>;
+    /// Draws a single pixel, blending it with what is there if the color is not opaque.
+    fn draw_pixel(&mut self, position: Position, color: Color) -> Result<(), DisplayError>;
+    /// Fills the region with the color, blending it with what is there if the color is not opaque.
+    fn fill_rect(&mut self, region: Region, color: Color) -> Result<(), DisplayError>;
     /// Draws a single character to the display at the given position with the given style.
     fn draw_char(
         &mut self, character: char, position: Position,
         text_color: Color, background_color: Option<Color>,
         font: MonoFont, underline: bool, strikethrough: bool,
         baseline: TextBaseline, alignment: TextAlignment, line_height: TextLineHeight
-    );
+    ) -> Result<(), DisplayError>;
     /// Draws a string to the display at the given position with the given style.
-    /// Does not wrap or scroll the text.
+    /// Does not wrap or scroll the text. Translucent text and background colors are blended.
     fn draw_text(
         &mut self, text: &str, position: Position,
         text_color: Color, background_color: Option<Color>,
         font: MonoFont, underline: bool, strikethrough: bool,
         baseline: TextBaseline, alignment: TextAlignment, line_height: TextLineHeight
-    );
+    ) -> Result<(), DisplayError>;
+    /// Copies pixels laid out as described by the info (e.g. those of a `Surface`) to the position.
+    /// The pixel format has to be the one of the display, the pixels are not blended.
+    fn blit(&mut self, pixels: &[u8], info: FrameBufferInfo, position: Position) -> Result<(), DisplayError>;
     /// Overwrites the entire display with the given color.
-    fn clear(&mut self, color: Color);
+    fn clear(&mut self, color: Color) -> Result<(), DisplayError>;
     /// Swaps the front and back buffers, displaying the changes made since the last swap.
     /// Only applicable to displays with multiple buffers.
-    fn swap(&mut self);
+    fn swap(&mut self) -> Result<(), DisplayError>;
     /// Returns the information about the frame buffer.
-    fn get_info(&self) -> FrameBufferInfo;
+    fn get_info(&self) -> Result<FrameBufferInfo, DisplayError>;
+    /// Restricts all drawing (including `draw` and `clear`) to the region, None removes the
+    /// restriction. Pixels outside of it are silently skipped.
+    fn set_clip(&mut self, clip: Option<Region>);
+    /// Returns the region drawing is currently restricted to.
+    fn get_clip(&self) -> Option<Region>;
 }
\ No newline at end of file
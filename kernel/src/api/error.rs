@@ -0,0 +1,61 @@
+use core::fmt;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::Size4KiB;
+use crate::api::display::DisplayError;
+use crate::api::module::ModuleError;
+
+/// Errors the kernel's subsystems report to their callers, which decide whether they are fatal.
+#[derive(Debug)]
+#[allow(dead_code)]
+pub enum KernelError {
+    /// A piece of hardware the kernel asked for is not present.
+    HardwareMissing(&'static str),
+    /// There is no frame buffer, the kernel has to run without a screen.
+    NoFrameBuffer,
+    /// A resource is locked by someone else, the operation can be retried later.
+    Busy(&'static str),
+    /// A subsystem was asked for a combination of settings it does not support.
+    InvalidConfiguration(&'static str),
+    /// Drawing to the display failed.
+    Display(DisplayError),
+    /// Mapping pages, like the ones of a heap, failed.
+    PageMapping(MapToError<Size4KiB>),
+    /// Loading a kernel module failed.
+    Module(ModuleError),
+    /// Physical memory that was asked for is reserved by someone else, together with the first
+    /// reserved address.
+    MemoryReserved(&'static str, u64),
+    /// I/O ports that were asked for are claimed by someone else, together with the first claimed port.
+    IoPortConflict(&'static str, u16),
+    /// A call into the firmware's runtime services failed with the EFI status code.
+    Firmware(u64)
+} impl From<DisplayError> for KernelError {
+    fn from(error: DisplayError) -> Self {
+        KernelError::Display(error)
+    }
+} impl From<MapToError<Size4KiB>> for KernelError {
+    fn from(error: MapToError<Size4KiB>) -> Self {
+        KernelError::PageMapping(error)
+    }
+} impl From<ModuleError> for KernelError {
+    fn from(error: ModuleError) -> Self {
+        KernelError::Module(error)
+    }
+} impl fmt::Display for KernelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KernelError::HardwareMissing(hardware) => write!(f, "{} not present", hardware),
+            KernelError::NoFrameBuffer => write!(f, "No frame buffer available"),
+            KernelError::Busy(resource) => write!(f, "{} is busy", resource),
+            KernelError::InvalidConfiguration(message) => write!(f, "Invalid configuration: {}", message),
+            KernelError::Display(error) => write!(f, "Display error: {:?}", error),
+            KernelError::PageMapping(error) => write!(f, "Failed to map pages: {:?}", error),
+            KernelError::Module(error) => write!(f, "Module error: {}", error),
+            KernelError::MemoryReserved(owner, address) => write!(f, "Memory at {:#X} is reserved by {}", address, owner),
+            KernelError::IoPortConflict(owner, port) => write!(f, "I/O port {:#X} is claimed by {}", port, owner),
+            KernelError::Firmware(status) => write!(
+                f, "Firmware call failed: {} ({:#X})", crate::internal::efi::status_name(*status), status
+            )
+        }
+    }
+}
\ No newline at end of file
@@ -1,26 +1,163 @@
 use alloc::collections::VecDeque;
 use alloc::string::String;
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use spin::mutex::Mutex;
 use spin::Once;
+use crate::api::control::ControlCommand;
+use crate::api::input::Input;
+use crate::api::thermal::ThermalTrip;
+use crate::drivers::timer::TimerTick;
 use crate::internal::cmos::Rtc;
+use crate::internal::heap::MemoryPressure;
+use crate::internal::trace::TraceCategory;
 
 static EVENT_DISPATCHER: Once<EventDispatcher> = Once::new();
+static DEFAULT_QUEUE_CAPACITY: usize = 1024;
 
 #[derive(Debug, Clone)]
 pub enum Event {
     /// A timer event is triggered when the system timer ticks.
-    Timer,
+    Timer(TimerTick),
     /// A real-time clock event is triggered when the real-time clock ticks.
     Rtc(Rtc),
+    /// An input event is triggered when an input device produced normalized input.
+    Input(Input),
+    /// A scancode event is triggered when the PS/2 keyboard sends a byte.
+    Scancode(u8),
+    /// A control input event is triggered when a byte is received on the control serial port.
+    ControlInput(u8),
+    /// A control event is triggered when a full command was received on the control channel.
+    Control(ControlCommand),
     /// An error event is triggered when the kernel encounters an error.
-    Error(ErrorEvent)
+    Error(ErrorEvent),
+    /// A memory pressure event is triggered when the heap usage crossed a watermark, so
+    /// subsystems can shrink their caches.
+    MemoryPressure(MemoryPressure),
+    /// A thermal trip event is triggered when a thermal zone reached its critical temperature.
+    ThermalTrip(ThermalTrip),
+    /// A tick rate event is triggered when the timer was set to tick at the given rate (in Hz),
+    /// timer events after it count ticks of the new length.
+    TickRateChanged(u64)
 } impl Event {
     pub fn error(event: ErrorEvent) -> Self {
         Event::Error(event)
     }
+
+    /// Returns the kind of the event, without any of its data.
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::Timer(..) => EventKind::Timer,
+            Event::Rtc(..) => EventKind::Rtc,
+            Event::Input(..) => EventKind::Input,
+            Event::Scancode(..) => EventKind::Scancode,
+            Event::ControlInput(..) => EventKind::ControlInput,
+            Event::Control(..) => EventKind::Control,
+            Event::Error(..) => EventKind::Error,
+            Event::MemoryPressure(..) => EventKind::MemoryPressure,
+            Event::ThermalTrip(..) => EventKind::ThermalTrip,
+            Event::TickRateChanged(..) => EventKind::TickRateChanged
+        }
+    }
+
+    /// Tries to merge the given event into this one, returns whether that was possible.
+    /// Timer events add up their ticks, real-time clock and memory pressure events only keep the
+    /// latest reading.
+    fn coalesce(&mut self, next: &Event) -> bool {
+        match (self, next) {
+            (Event::Timer(tick), Event::Timer(next)) if tick.idle == next.idle => {
+                tick.ticks += next.ticks;
+                true
+            }, (Event::Rtc(rtc), Event::Rtc(next)) => {
+                *rtc = next.clone();
+                true
+            }, (Event::MemoryPressure(pressure), Event::MemoryPressure(next)) => {
+                *pressure = *next;
+                true
+            }, _ => false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(dead_code)]
+pub enum EventKind {
+    Timer = 0,
+    Rtc = 1,
+    Input = 2,
+    Scancode = 3,
+    ControlInput = 4,
+    Control = 5,
+    Error = 6,
+    MemoryPressure = 7,
+    ThermalTrip = 8,
+    TickRateChanged = 9
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum OverflowPolicy {
+    /// Drops the event that is being pushed when the queue is full.
+    DropNewest,
+    /// Drops the oldest queued event to make room for the one being pushed.
+    DropOldest
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EventQueueStats {
+    /// Amount of events that were dropped because the queue was full.
+    pub dropped: u64,
+    /// Amount of events that were merged into the event queued before them.
+    pub coalesced: u64,
+    /// The longest the queue has been.
+    pub high_water_mark: usize
+}
+
+struct EventQueue {
+    events: VecDeque<Event>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    coalescing: u32,
+    stats: EventQueueStats
+} impl EventQueue {
+    fn new() -> Self { Self {
+        events: VecDeque::new(),
+        capacity: DEFAULT_QUEUE_CAPACITY,
+        policy: OverflowPolicy::DropOldest,
+        coalescing: (1 << EventKind::Timer as u8) | (1 << EventKind::Rtc as u8) | (1 << EventKind::MemoryPressure as u8),
+        stats: EventQueueStats::default()
+    } }
+
+    fn push(&mut self, event: Event) {
+        if self.coalescing & (1 << event.kind() as u8) != 0 {
+            if let Some(last) = self.events.back_mut() {
+                if last.coalesce(&event) {
+                    self.stats.coalesced += 1;
+                    return;
+                }
+            }
+        }
+
+        // Errors are never dropped, losing one would hide the reason why things went wrong
+        if self.events.len() >= self.capacity && event.kind() != EventKind::Error {
+            self.stats.dropped += 1;
+            match self.policy {
+                OverflowPolicy::DropNewest => return,
+                OverflowPolicy::DropOldest => {
+                    let oldest = self.events.iter()
+                        .position(|event| event.kind() != EventKind::Error);
+                    if let Some(index) = oldest {
+                        self.events.remove(index);
+                    } else { return; }
+                }
+            }
+        }
+
+        self.events.push_back(event);
+        self.stats.high_water_mark = self.stats.high_water_mark.max(self.events.len());
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -49,8 +186,10 @@ pub enum ErrorEvent {
     PageFault(String, u64),
     /// A general protection fault was encountered.
     GeneralProtectionFault(String, u64),
-    /// A double fault was encountered.
-    DoubleFault(String, u64)
+    /// A non-maskable interrupt was received, together with the system control port B status.
+    NonMaskableInterrupt(String, u8),
+    /// An interrupt vector fired too often and got masked for a while, together with the vector.
+    InterruptStorm(String, u8)
 } #[allow(dead_code)] impl ErrorEvent {
     /// Returns the message associated with the error event.
     pub fn message(&self) -> &String {
@@ -60,7 +199,8 @@ pub enum ErrorEvent {
             ErrorEvent::InvalidTss(message, ..) => message,
             ErrorEvent::PageFault(message, ..) => message,
             ErrorEvent::GeneralProtectionFault(message, ..) => message,
-            ErrorEvent::DoubleFault(message, ..) => message
+            ErrorEvent::NonMaskableInterrupt(message, ..) => message,
+            ErrorEvent::InterruptStorm(message, ..) => message
         }
     }
 
@@ -72,7 +212,8 @@ pub enum ErrorEvent {
             ErrorEvent::InvalidTss(..) => EventErrorLevel::Fault,
             ErrorEvent::PageFault(..) => EventErrorLevel::Fault,
             ErrorEvent::GeneralProtectionFault(..) => EventErrorLevel::Fault,
-            ErrorEvent::DoubleFault(..) => EventErrorLevel::Abort
+            ErrorEvent::NonMaskableInterrupt(..) => EventErrorLevel::Interrupt,
+            ErrorEvent::InterruptStorm(..) => EventErrorLevel::Fault
         }
     }
 }
@@ -81,49 +222,163 @@ pub trait EventHandler {
     fn handle(&mut self, event: Event);
 }
 
+/// Identifies a registered event handler, used to unregister it again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandlerId(u64);
+
+type WeakHandler = Weak<Mutex<dyn EventHandler + Send>>;
+type HandlerList = Arc<Vec<(HandlerId, WeakHandler)>>;
+
+/// The dispatcher reads the handler list far more often than it changes, so the list is never
+/// modified in place. Changes build a new list and swap it in, while dispatching works on a
+/// snapshot taken by cloning the `Arc`. The lock is only held for the swap or the clone, so
+/// dispatching never waits for a registration and handlers can register while being dispatched to.
 pub struct EventDispatcher {
-    handlers: Mutex<Vec<Arc<Mutex<dyn EventHandler + Send>>>>,
-    queue: Mutex<VecDeque<Event>>,
+    handlers: Mutex<HandlerList>,
+    next_handler_id: AtomicU64,
+    queue: Mutex<EventQueue>,
     new_event: AtomicBool
 } #[allow(dead_code)] impl EventDispatcher {
     pub fn global() -> &'static Self {
         EVENT_DISPATCHER.call_once(|| EventDispatcher::new())
     }
 
-    fn new() -> Self { Self {
-        handlers: Mutex::new(Vec::new()),
-        queue: Mutex::new(VecDeque::new()),
+    /// Creates a dispatcher of its own, most code wants the global one.
+    pub fn new() -> Self { Self {
+        handlers: Mutex::new(Arc::new(Vec::new())),
+        next_handler_id: AtomicU64::new(0),
+        queue: Mutex::new(EventQueue::new()),
         new_event: AtomicBool::new(false)
     } }
 
-    pub fn register(&self, handler: Arc<Mutex<dyn EventHandler + Send>>) {
-        self.handlers.lock().push(handler);
+    /// Registers the handler for all events. The dispatcher only keeps a weak reference, so the
+    /// handler is removed once the caller drops its last reference to it.
+    pub fn register(&self, handler: Arc<Mutex<dyn EventHandler + Send>>) -> HandlerId {
+        let id = HandlerId(self.next_handler_id.fetch_add(1, Ordering::SeqCst));
+        self.update_handlers(|handlers| handlers.push((id, Arc::downgrade(&handler))));
+        id
+    }
+
+    /// Removes the handler, returns whether it was still registered.
+    pub fn unregister(&self, id: HandlerId) -> bool {
+        let mut removed = false;
+        self.update_handlers(|handlers| {
+            let length = handlers.len();
+            handlers.retain(|(handler_id, ..)| *handler_id != id);
+            removed = handlers.len() != length;
+        });
+        removed
+    }
+
+    /// Returns how many handlers are registered, including dropped ones not cleaned up yet.
+    pub fn handler_count(&self) -> usize {
+        self.handlers().len()
+    }
+
+    /// Returns a snapshot of the handler list, which later changes don't affect.
+    fn handlers(&self) -> HandlerList {
+        crate::internal::idt::without_interrupts(|| self.handlers.lock().clone())
+    }
+
+    /// Applies the change to a copy of the handler list and swaps the copy in. The copy is made
+    /// outside of the lock, if the list changed in the meantime the change is made again.
+    fn update_handlers(&self, mut change: impl FnMut(&mut Vec<(HandlerId, WeakHandler)>)) {
+        loop {
+            let current = self.handlers();
+            let mut handlers = Vec::clone(&current);
+            change(&mut handlers);
+
+            let swapped = crate::internal::idt::without_interrupts(|| {
+                let mut list = self.handlers.lock();
+                if !Arc::ptr_eq(&list, &current) { return false; }
+                *list = Arc::new(handlers);
+                true
+            });
+            if swapped { return; }
+        }
     }
 
     pub fn push(&self, event: Event) {
-        self.queue.lock().push_back(event);
-        self.new_event.store(true, Ordering::Relaxed)
+        self.queue.lock().push(event);
+        self.new_event.store(true, Ordering::SeqCst)
+    }
+
+    /// Sets how many events can be queued and what happens to new events once the queue is full.
+    pub fn set_capacity(&self, capacity: usize, policy: OverflowPolicy) {
+        crate::internal::idt::without_interrupts(|| {
+            let mut queue = self.queue.lock();
+            queue.capacity = capacity.max(1);
+            queue.policy = policy;
+        })
+    }
+
+    /// Sets whether consecutive events of the given kind get merged into one.
+    /// Only timer and real-time clock events support coalescing, for other kinds this does nothing.
+    pub fn set_coalescing(&self, kind: EventKind, enabled: bool) {
+        crate::internal::idt::without_interrupts(|| {
+            let mut queue = self.queue.lock();
+            if enabled {
+                queue.coalescing |= 1 << kind as u8;
+            } else {
+                queue.coalescing &= !(1 << kind as u8);
+            }
+        })
+    }
+
+    /// Returns the drop and coalescing statistics of the event queue.
+    pub fn stats(&self) -> EventQueueStats {
+        crate::internal::idt::without_interrupts(|| self.queue.lock().stats)
+    }
+
+    /// Returns the statistics like `stats`, or None if the queue is locked. For interrupt handlers,
+    /// which may have interrupted code pushing an event.
+    pub fn try_stats(&self) -> Option<EventQueueStats> {
+        self.queue.try_lock().map(|queue| queue.stats)
+    }
+
+    /// Returns whether new events were pushed since the last dispatch.
+    pub fn has_new_events(&self) -> bool {
+        self.new_event.load(Ordering::SeqCst)
+    }
+
+    /// Puts the CPU to sleep until a new event is pushed, so loops don't have to spin on dispatch.
+    /// The `deadline` (in ticks) tells how long the caller can sleep at most without missing
+    /// anything; None means it only cares about events.
+    pub fn wait(&self, deadline: Option<u64>) {
+        crate::internal::idt::disable_interrupts();
+        if self.has_new_events() {
+            crate::internal::idt::enable_interrupts();
+        } else {
+            crate::drivers::timer::idle(deadline, &self.new_event);
+        }
     }
 
     pub fn dispatch(&self) {
         crate::internal::idt::without_interrupts(|| {
             let mut local_queue = VecDeque::new();
 
-            core::mem::swap(&mut *self.queue.lock(), &mut local_queue);
+            core::mem::swap(&mut self.queue.lock().events, &mut local_queue);
+            // Cleared before handling, so events pushed by handlers are noticed by the next wait
+            self.new_event.store(false, Ordering::SeqCst);
+            crate::trace!(TraceCategory::DispatchBegin, local_queue.len());
 
+            let handlers = self.handlers();
             while let Some(event) = local_queue.pop_front() {
-                let mut handlers = self.handlers.try_lock();
-                if let Some(handlers) = handlers.as_mut() {
-                    for handler in handlers.iter_mut() {
-                        let mut handler = handler.try_lock();
-                        if let Some(handler) = handler.as_mut() {
-                            handler.handle(event.clone());
-                        } else { log::warn!("Event handler is locked, skipping dispatch."); }
-                    }
-                } else { log::warn!("Event handlers are locked, skipping dispatch."); return; }
+                for (id, handler) in handlers.iter() {
+                    let Some(handler) = handler.upgrade() else { continue; };
+                    let mut handler = handler.try_lock();
+                    if let Some(handler) = handler.as_mut() {
+                        handler.handle(event.clone());
+                    } else { crate::log_fields!(log::Level::Warn, "Event handler is locked, skipping dispatch."; handler = id.0); }
+                }
+            }
+
+            // Forget handlers whose owners dropped them
+            if handlers.iter().any(|(.., handler)| handler.strong_count() == 0) {
+                self.update_handlers(|handlers| handlers.retain(|(.., handler)| handler.strong_count() > 0));
             }
 
-            self.new_event.store(false, Ordering::Relaxed);
+            crate::trace!(TraceCategory::DispatchEnd);
         })
     }
 }
\ No newline at end of file
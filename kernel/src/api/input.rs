@@ -0,0 +1,54 @@
+use crate::api::keyboard::{KeyCode, Modifiers};
+
+/// The kind of device an input came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum InputSource {
+    Keyboard,
+    Mouse,
+    Serial
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum PointerButton {
+    Left,
+    Right,
+    Middle,
+    Other(u8)
+}
+
+/// Input normalized across devices, so consumers don't need to know where it came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum InputEvent {
+    /// A key was pressed or released, with the modifiers at that moment.
+    Key { code: KeyCode, pressed: bool, modifiers: Modifiers },
+    /// The pointer moved by the given amount of pixels.
+    PointerMotion { dx: i32, dy: i32 },
+    /// A pointer button was pressed or released.
+    Button { button: PointerButton, pressed: bool },
+    /// Text was entered, either by a key press or by a device that only produces characters.
+    Text(char)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Input {
+    pub source: InputSource,
+    pub event: InputEvent
+} impl Input {
+    pub fn new(source: InputSource, event: InputEvent) -> Self {
+        Self { source, event }
+    }
+}
+
+/// Identifies a consumer that holds or held the input focus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusId(pub u64);
+
+/// Something that takes input while it has the focus, like a shell, a window or a virtual terminal.
+pub trait InputConsumer {
+    /// Handles the given input. Returns whether it was consumed, otherwise it is passed on to the
+    /// consumer that had the focus before.
+    fn on_input(&mut self, input: &Input) -> bool;
+}
\ No newline at end of file
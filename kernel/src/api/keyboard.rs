@@ -0,0 +1,97 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum KeyCode {
+    Escape,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Backtick,
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    Minus, Equals, Backspace, Tab,
+    Q, W, E, R, T, Y, U, I, O, P,
+    LeftBracket, RightBracket, Backslash, CapsLock,
+    A, S, D, F, G, H, J, K, L,
+    Semicolon, Quote, Enter, LeftShift,
+    Z, X, C, V, B, N, M,
+    Comma, Period, Slash, RightShift,
+    LeftControl, LeftAlt, Space, RightAlt, RightControl,
+    NumLock, ScrollLock,
+    Keypad0, Keypad1, Keypad2, Keypad3, Keypad4,
+    Keypad5, Keypad6, Keypad7, Keypad8, Keypad9,
+    KeypadMultiply, KeypadMinus, KeypadPlus, KeypadPeriod, KeypadEnter, KeypadSlash,
+    ArrowUp, ArrowDown, ArrowLeft, ArrowRight,
+    Home, End, PageUp, PageDown, Insert, Delete,
+    Unknown(u8)
+} impl KeyCode {
+    /// Returns the character the key produces with the given modifiers, if it produces one.
+    pub fn to_char(self, modifiers: Modifiers) -> Option<char> {
+        let shift = modifiers.shift();
+        let pick = |normal: char, shifted: char| Some(if shift { shifted } else { normal });
+        let letter = |letter: char| Some(if shift != modifiers.caps_lock {
+            letter.to_ascii_uppercase()
+        } else { letter });
+        let keypad = |digit: char| if modifiers.num_lock { Some(digit) } else { None };
+
+        match self {
+            KeyCode::Backtick => pick('`', '~'),
+            KeyCode::Key1 => pick('1', '!'), KeyCode::Key2 => pick('2', '@'),
+            KeyCode::Key3 => pick('3', '#'), KeyCode::Key4 => pick('4', '$'),
+            KeyCode::Key5 => pick('5', '%'), KeyCode::Key6 => pick('6', '^'),
+            KeyCode::Key7 => pick('7', '&'), KeyCode::Key8 => pick('8', '*'),
+            KeyCode::Key9 => pick('9', '('), KeyCode::Key0 => pick('0', ')'),
+            KeyCode::Minus => pick('-', '_'), KeyCode::Equals => pick('=', '+'),
+            KeyCode::LeftBracket => pick('[', '{'), KeyCode::RightBracket => pick(']', '}'),
+            KeyCode::Backslash => pick('\\', '|'),
+            KeyCode::Semicolon => pick(';', ':'), KeyCode::Quote => pick('\'', '"'),
+            KeyCode::Comma => pick(',', '<'), KeyCode::Period => pick('.', '>'),
+            KeyCode::Slash => pick('/', '?'),
+            KeyCode::Q => letter('q'), KeyCode::W => letter('w'), KeyCode::E => letter('e'),
+            KeyCode::R => letter('r'), KeyCode::T => letter('t'), KeyCode::Y => letter('y'),
+            KeyCode::U => letter('u'), KeyCode::I => letter('i'), KeyCode::O => letter('o'),
+            KeyCode::P => letter('p'), KeyCode::A => letter('a'), KeyCode::S => letter('s'),
+            KeyCode::D => letter('d'), KeyCode::F => letter('f'), KeyCode::G => letter('g'),
+            KeyCode::H => letter('h'), KeyCode::J => letter('j'), KeyCode::K => letter('k'),
+            KeyCode::L => letter('l'), KeyCode::Z => letter('z'), KeyCode::X => letter('x'),
+            KeyCode::C => letter('c'), KeyCode::V => letter('v'), KeyCode::B => letter('b'),
+            KeyCode::N => letter('n'), KeyCode::M => letter('m'),
+            KeyCode::Keypad0 => keypad('0'), KeyCode::Keypad1 => keypad('1'),
+            KeyCode::Keypad2 => keypad('2'), KeyCode::Keypad3 => keypad('3'),
+            KeyCode::Keypad4 => keypad('4'), KeyCode::Keypad5 => keypad('5'),
+            KeyCode::Keypad6 => keypad('6'), KeyCode::Keypad7 => keypad('7'),
+            KeyCode::Keypad8 => keypad('8'), KeyCode::Keypad9 => keypad('9'),
+            KeyCode::KeypadPeriod => keypad('.'),
+            KeyCode::KeypadMultiply => Some('*'), KeyCode::KeypadMinus => Some('-'),
+            KeyCode::KeypadPlus => Some('+'), KeyCode::KeypadSlash => Some('/'),
+            KeyCode::Space => Some(' '),
+            KeyCode::Tab => Some('\t'),
+            KeyCode::Enter | KeyCode::KeypadEnter => Some('\n'),
+            KeyCode::Backspace => Some('\u{8}'),
+            _ => None
+        }
+    }
+}
+
+/// State of the modifier and lock keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modifiers {
+    pub left_shift: bool,
+    pub right_shift: bool,
+    pub control: bool,
+    pub alt: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+    pub scroll_lock: bool
+} impl Modifiers {
+    pub fn shift(&self) -> bool {
+        self.left_shift || self.right_shift
+    }
+}
+
+/// How long a key has to be held down before it starts repeating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(dead_code)]
+pub enum TypematicDelay {
+    Ms250 = 0,
+    Ms500 = 1,
+    Ms750 = 2,
+    Ms1000 = 3
+}
\ No newline at end of file
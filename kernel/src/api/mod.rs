@@ -1,3 +1,9 @@
 pub mod event;
 pub mod time;
-pub mod display;
\ No newline at end of file
+pub mod display;
+pub mod control;
+pub mod keyboard;
+pub mod input;
+pub mod error;
+pub mod module;
+pub mod thermal;
\ No newline at end of file
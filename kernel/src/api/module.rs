@@ -0,0 +1,63 @@
+use alloc::string::String;
+use core::fmt;
+
+/// Errors of loading a kernel module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum ModuleError {
+    /// There is no initial ramdisk the module could be loaded from.
+    NoInitrd,
+    /// The initial ramdisk has no module with this name.
+    NotFound(String),
+    /// A module with this name is already loaded.
+    AlreadyLoaded(String),
+    /// The file is not a relocatable x86_64 ELF object or is cut off.
+    InvalidObject(&'static str),
+    /// The object has an allocated section the loader does not support, like constructors.
+    UnsupportedSection(String),
+    /// The object uses a relocation type the loader does not support.
+    UnsupportedRelocation(u32),
+    /// A relocated value does not fit into its field.
+    RelocationOverflow(u32),
+    /// The object references a symbol the kernel does not export.
+    UnresolvedSymbol(String),
+    /// The object has no `module_init` function.
+    MissingInit,
+    /// Memory for the module could not be allocated.
+    OutOfMemory,
+    /// The `module_init` function returned the non-zero code.
+    InitFailed(i32)
+} impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModuleError::NoInitrd => write!(f, "No initial ramdisk loaded"),
+            ModuleError::NotFound(name) => write!(f, "Module {} not found", name),
+            ModuleError::AlreadyLoaded(name) => write!(f, "Module {} is already loaded", name),
+            ModuleError::InvalidObject(reason) => write!(f, "Invalid object: {}", reason),
+            ModuleError::UnsupportedSection(name) => write!(f, "Unsupported section {}", name),
+            ModuleError::UnsupportedRelocation(kind) => write!(f, "Unsupported relocation type {}", kind),
+            ModuleError::RelocationOverflow(kind) => write!(f, "Relocation of type {} overflows", kind),
+            ModuleError::UnresolvedSymbol(name) => write!(f, "Unresolved symbol {}", name),
+            ModuleError::MissingInit => write!(f, "No module_init function"),
+            ModuleError::OutOfMemory => write!(f, "Out of memory"),
+            ModuleError::InitFailed(code) => write!(f, "module_init failed with code {}", code)
+        }
+    }
+}
+
+/// A function of the kernel that modules can call.
+#[derive(Debug, Clone, Copy)]
+pub struct KernelSymbol {
+    pub name: &'static str,
+    pub address: *const ()
+} unsafe impl Sync for KernelSymbol {}
+
+/// Describes a loaded module.
+#[derive(Debug, Clone)]
+pub struct ModuleInfo {
+    pub name: String,
+    /// The address the module's sections were loaded at.
+    pub base: usize,
+    /// The size of the module in memory in bytes.
+    pub size: usize
+}
\ No newline at end of file
@@ -0,0 +1,27 @@
+use alloc::string::String;
+use core::fmt;
+
+/// A temperature in tenths of a Kelvin, the unit ACPI reports temperatures in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Temperature(pub u64);
+impl Temperature {
+    /// Returns the temperature in tenths of a degree Celsius.
+    pub fn deci_celsius(&self) -> i64 {
+        self.0 as i64 - 2732
+    }
+} impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let deci_celsius = self.deci_celsius();
+        let sign = if deci_celsius < 0 { "-" } else { "" };
+        write!(f, "{}{}.{} °C", sign, deci_celsius.abs() / 10, deci_celsius.abs() % 10)
+    }
+}
+
+/// A thermal zone reached its critical temperature, at which the system has to shut down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThermalTrip {
+    /// The path of the thermal zone in the ACPI namespace.
+    pub zone: String,
+    pub temperature: Temperature,
+    pub critical: Temperature
+}
\ No newline at end of file
@@ -1,7 +1,15 @@
 use core::fmt::Display;
+use core::sync::atomic::{AtomicU64, Ordering};
 use crate::internal::cmos::{Rtc};
 
-#[derive(Debug, Clone, Copy)]
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+const NANOS_PER_DAY: u64 = 86_400 * NANOS_PER_SECOND;
+/// The proleptic Gregorian calendar repeats every 400 years.
+static DAYS_PER_ERA: i64 = 146_097;
+/// The days from 0000-03-01, where the eras start, to 1970-01-01.
+static EPOCH_DAY_OF_ERAS: i64 = 719_468;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Month {
     January = 1,
@@ -128,6 +136,9 @@ pub struct Duration {
 
     pub fn nanos(&self) -> u64 { self.nanos }
 
+    /// Returns the whole duration in nanoseconds.
+    pub fn total_nanos(&self) -> u128 { self.seconds as u128 * NANOS_PER_SECOND as u128 + self.nanos as u128 }
+
     pub fn micros(&self) -> u64 { self.nanos / 1000 }
 
     pub fn millis(&self) -> u64 { self.nanos / 1000000 }
@@ -181,7 +192,7 @@ pub struct Duration {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum TimeOffset {
     /// -12:00
@@ -351,9 +362,18 @@ pub enum TimeOffset {
             TimeOffset::Mt2 => (true, Duration::from_hms(14, 0, 0)),
         }
     }
+} impl Display for TimeOffset {
+    /// Writes the offset from UTC, like `UTC+05:45`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let (positive, duration) = self.get_offset();
+        write!(
+            f, "UTC{}{:02}:{:02}", if positive { '+' } else { '-' },
+            duration.hours(), duration.minutes() % 60
+        )
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Time {
     nano: u32,
     seconds: u8,
@@ -393,41 +413,28 @@ pub struct Time {
 
     pub fn as_hms_nano(&self) -> (u8, u8, u8, u32) { (self.hours, self.minutes, self.seconds, self.nano()) }
 
-    pub fn add(&self, rhs: Duration) -> Self {
-        let total_nanos = self.nano as u64 + rhs.nanos;
-        let extra_seconds = total_nanos / 1_000_000_000;
-        let nano = (total_nanos % 1_000_000_000) as u32;
-
-        let total_seconds = self.seconds as u64 + rhs.seconds + extra_seconds;
-        let seconds = (total_seconds % 60) as u8;
-
-        let total_minutes = self.minutes as u64 + (total_seconds / 60);
-        let minutes = (total_minutes % 60) as u8;
+    /// Returns the nanoseconds since midnight.
+    pub fn nanos_of_day(&self) -> u64 {
+        (self.hours as u64 * 3600 + self.minutes as u64 * 60 + self.seconds as u64) * NANOS_PER_SECOND + self.nano as u64
+    }
 
-        let hours = (self.hours as u64 + (total_minutes / 60)) % 24;
+    /// Returns the time the nanoseconds since midnight point at, wrapping around after a day.
+    pub fn from_nanos_of_day(nanos: u64) -> Self {
+        let nanos = nanos % NANOS_PER_DAY;
+        let seconds = nanos / NANOS_PER_SECOND;
+        Self::new((nanos % NANOS_PER_SECOND) as u32, (seconds % 60) as u8, (seconds / 60 % 60) as u8, (seconds / 3600) as u8)
+    }
 
-        Self { nano, seconds, minutes, hours: hours as u8 }
+    /// Adds the duration, wrapping around after midnight.
+    pub fn add(&self, rhs: Duration) -> Self {
+        let nanos = (self.nanos_of_day() as u128 + rhs.total_nanos()) % NANOS_PER_DAY as u128;
+        Self::from_nanos_of_day(nanos as u64)
     }
 
+    /// Subtracts the duration, wrapping around before midnight.
     pub fn sub(&self, rhs: Duration) -> Self {
-        let rhs_total_nanos = rhs.seconds * 1_000_000_000 + rhs.nanos;
-        let self_total_nanos = self.seconds as u64 * 1_000_000_000 + self.nano as u64;
-        let total_nanos = if self_total_nanos > rhs_total_nanos {
-            self_total_nanos - rhs_total_nanos
-        } else {
-            0
-        };
-
-        let nano = (total_nanos % 1_000_000_000) as u32;
-        let total_seconds = total_nanos / 1_000_000_000;
-
-        let seconds = (total_seconds % 60) as u8;
-        let total_minutes = self.minutes as u64 + (total_seconds / 60);
-
-        let minutes = (total_minutes % 60) as u8;
-        let hours = ((self.hours as u64 + (total_minutes / 60)) % 24) as u8;
-
-        Self { nano, seconds, minutes, hours }
+        let nanos = (self.nanos_of_day() as i128 - rhs.total_nanos() as i128).rem_euclid(NANOS_PER_DAY as i128);
+        Self::from_nanos_of_day(nanos as u64)
     }
 } impl Display for Time {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -435,7 +442,7 @@ pub struct Time {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Date {
     day: u8,
     month: Month,
@@ -463,31 +470,70 @@ pub struct Date {
         ordinal
     }
 
+    /// Returns the ISO 8601 week, which starts on Monday. The first week of a year is the one with
+    /// its first Thursday, so the first and last days of a year can be in a week of the year
+    /// before or after, see `as_week_date`.
     pub fn week(&self) -> u8 {
-        let mut ordinal = self.ordinal();
-        let mut week = 1;
-        while ordinal > 7 {
-            ordinal -= 7;
-            week += 1;
-        }
-        week
+        self.iso_week().1
     }
 
     pub fn weekday(&self) -> Weekday {
-        let mut year = self.year;
-        let mut month = self.month as u8;
-        let day = self.day as i32;
-
-        if month < 3 {
-            month += 12;
-            year -= 1;
+        // 1970-01-01 was a Thursday
+        Weekday::from_u8((self.days_since_epoch() + Weekday::Thursday as i64).rem_euclid(7) as u8).unwrap()
+    }
+
+    /// Returns the days since 1970-01-01, negative before. Works for any year of the proleptic
+    /// Gregorian calendar, which extends the Gregorian rules to the years before its introduction.
+    pub fn days_since_epoch(&self) -> i64 {
+        // Counted in eras of 400 years, with years starting in March so the leap day is last
+        let year = self.year as i64 - if (self.month as u8) <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * DAYS_PER_ERA + day_of_era - EPOCH_DAY_OF_ERAS
+    }
+
+    /// Returns the date the days since 1970-01-01 point at.
+    pub fn from_days_since_epoch(days: i64) -> Self {
+        let days = days + EPOCH_DAY_OF_ERAS;
+        let era = days.div_euclid(DAYS_PER_ERA);
+        let day_of_era = days - era * DAYS_PER_ERA;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        Date::new(day as u8, Month::from_u8(month as u8).unwrap(), year as i32)
+    }
+
+    /// Returns the year the ISO 8601 week belongs to, together with the week.
+    fn iso_week(&self) -> (i32, u8) {
+        let week = (self.ordinal() as i32 - self.iso_weekday() as i32 + 10) / 7;
+        if week < 1 {
+            (self.year - 1, Self::iso_weeks_in_year(self.year - 1))
+        } else if week > Self::iso_weeks_in_year(self.year) as i32 {
+            (self.year + 1, 1)
+        } else {
+            (self.year, week as u8)
         }
+    }
 
-        let k = year % 100;
-        let j = year / 100;
-        let h = (day + (13 * (month as i32 + 1) / 5) + k + (k / 4) + (j / 4) + 5 * j) % 7;
+    /// Returns the day of the week counted from Monday on, starting at 1.
+    fn iso_weekday(&self) -> u8 {
+        (self.weekday() as u8 + 5) % 7 + 1
+    }
 
-        Weekday::from_u8(h as u8).unwrap()
+    /// Years starting on a Thursday, and leap years starting on a Wednesday, have 53 weeks.
+    fn iso_weeks_in_year(year: i32) -> u8 {
+        let first = Date::new(1, Month::January, year);
+        match first.weekday() {
+            Weekday::Thursday => 53,
+            Weekday::Wednesday if first.is_leap_year() => 53,
+            _ => 52
+        }
     }
 
     pub fn month(&self) -> Month { self.month }
@@ -515,52 +561,16 @@ pub struct Date {
         }
     }
 
+    /// Adds the whole days of the duration.
     pub fn add(&self, rhs: Duration) -> Self {
-        let mut days_to_add = rhs.seconds / 86_400;
-        let mut new_day = self.day;
-        let mut new_month = self.month as u8;
-        let mut new_year = self.year;
-
-        while days_to_add > 0 {
-            let days_in_current_month = Date::new(1, Month::from_u8(new_month).unwrap(), new_year).days_in_month();
-            if new_day as u64 + days_to_add > days_in_current_month as u64 {
-                days_to_add -= (days_in_current_month - new_day) as u64 + 1;
-                new_day = 1;
-                new_month += 1;
-                if new_month > 12 {
-                    new_month = 1;
-                    new_year += 1;
-                }
-            } else {
-                new_day += days_to_add as u8;
-                days_to_add = 0;
-            }
-        }
-
-        Date::new(new_day, Month::from_u8(new_month).unwrap(), new_year)
+        let days = rhs.total_nanos() / NANOS_PER_DAY as u128;
+        Date::from_days_since_epoch(self.days_since_epoch() + days as i64)
     }
 
+    /// Subtracts the whole days of the duration.
     pub fn sub(&self, rhs: Duration) -> Self {
-        let mut days_to_sub = rhs.seconds / 86_400;
-        let mut new_day = self.day as i64;
-        let mut new_month = self.month as u8;
-        let mut new_year = self.year;
-
-        while days_to_sub > 0 {
-            if new_day as u64 <= days_to_sub {
-                days_to_sub -= new_day as u64;
-                new_month = if new_month == 1 { 12 } else { new_month - 1 };
-                new_day = Date::new(1, Month::from_u8(new_month).unwrap(), new_year).days_in_month() as i64;
-                if new_month == 12 {
-                    new_year -= 1;
-                }
-            } else {
-                new_day -= days_to_sub as i64;
-                days_to_sub = 0;
-            }
-        }
-
-        Date::new(new_day as u8, Month::from_u8(new_month).unwrap(), new_year)
+        let days = rhs.total_nanos() / NANOS_PER_DAY as u128;
+        Date::from_days_since_epoch(self.days_since_epoch() - days as i64)
     }
 
     pub fn as_calendar_date(&self) -> (i32, Month, u8) {
@@ -571,8 +581,11 @@ pub struct Date {
         (self.year, self.ordinal())
     }
 
+    /// Returns the ISO 8601 week date, whose year differs from the calendar year in the days of
+    /// a week that spans the new year.
     pub fn as_week_date(&self) -> (i32, u8, Weekday) {
-        (self.year, self.week(), self.weekday())
+        let (year, week) = self.iso_week();
+        (year, week, self.weekday())
     }
 } impl Display for Date {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -580,7 +593,7 @@ pub struct Date {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct DateTime {
     time: Time,
     date: Date,
@@ -647,17 +660,21 @@ pub struct DateTime {
     pub fn as_week_date(&self) -> (i32, u8, Weekday) { self.date.as_week_date() }
 
     pub fn add(&self, rhs: Duration) -> Self {
-        let new_time = self.time.add(rhs);
-        let day_overflow = new_time.hours / 24;
-        let new_date = self.date.add(Duration::from_days(day_overflow as u64));
-        DateTime { time: Time::new(new_time.nano, new_time.seconds, new_time.minutes, new_time.hours % 24), date: new_date }
+        let nanos = self.time.nanos_of_day() as u128 + rhs.total_nanos();
+        let days = (nanos / NANOS_PER_DAY as u128) as i64;
+        DateTime {
+            time: Time::from_nanos_of_day((nanos % NANOS_PER_DAY as u128) as u64),
+            date: Date::from_days_since_epoch(self.date.days_since_epoch() + days)
+        }
     }
 
     pub fn sub(&self, rhs: Duration) -> Self {
-        let new_time = self.time.sub(rhs);
-        let day_underflow = if new_time.hours > self.time.hours { 1 } else { 0 };
-        let new_date = self.date.sub(Duration::from_days(day_underflow as u64));
-        DateTime { time: Time::new(new_time.nano, new_time.seconds, new_time.minutes, new_time.hours % 24), date: new_date }
+        let nanos = self.time.nanos_of_day() as i128 - rhs.total_nanos() as i128;
+        let days = nanos.div_euclid(NANOS_PER_DAY as i128) as i64;
+        DateTime {
+            time: Time::from_nanos_of_day(nanos.rem_euclid(NANOS_PER_DAY as i128) as u64),
+            date: Date::from_days_since_epoch(self.date.days_since_epoch() + days)
+        }
     }
 
     pub fn with_offset(&self, offset: TimeOffset) -> DateTime {
@@ -668,15 +685,94 @@ pub struct DateTime {
             self.sub(duration)
         }
     }
+
+    /// Returns the nanoseconds since 1970-01-01 00:00:00, negative before. Only fits the years
+    /// from 1678 to 2261, use `Date::days_since_epoch` for the others.
+    pub fn unix_nanos(&self) -> i64 {
+        self.date.days_since_epoch() * NANOS_PER_DAY as i64 + self.time.nanos_of_day() as i64
+    }
+
+    /// Returns the date and time the nanoseconds since 1970-01-01 00:00:00 point at.
+    pub fn from_unix_nanos(nanos: i64) -> Self {
+        DateTime {
+            time: Time::from_nanos_of_day(nanos.rem_euclid(NANOS_PER_DAY as i64) as u64),
+            date: Date::from_days_since_epoch(nanos.div_euclid(NANOS_PER_DAY as i64))
+        }
+    }
 } impl Display for DateTime {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{} {}", self.date, self.time)
     }
 }
 
+/// The latest instant handed out, so no later one is ever earlier, even if the time stamp
+/// counters of the CPUs don't agree.
+static LAST_INSTANT: AtomicU64 = AtomicU64::new(0);
+
+/// A point in time since the boot started, measured with the time stamp counter. Unlike the wall
+/// clock it never goes backwards, no matter how the clock gets set or corrected, so it is what
+/// durations should be measured with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant {
+    nanos: u64
+} #[allow(dead_code)] impl Instant {
+    /// Returns the current instant. Before the time stamp counter is calibrated this is the boot.
+    pub fn now() -> Self {
+        let cycles = crate::internal::tsc::read().saturating_sub(crate::boot::start());
+        let nanos = match crate::internal::tsc::khz() {
+            0 => 0,
+            khz => (cycles as u128 * 1_000_000 / khz as u128) as u64
+        };
+        Self { nanos: LAST_INSTANT.fetch_max(nanos, Ordering::Relaxed).max(nanos) }
+    }
+
+    /// Returns the time since the boot started.
+    pub fn since_boot(&self) -> Duration {
+        Duration::new(self.nanos % 1_000_000_000, self.nanos / 1_000_000_000)
+    }
+
+    /// Returns the time since the earlier instant, zero if it is actually later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        let nanos = self.nanos.saturating_sub(earlier.nanos);
+        Duration::new(nanos % 1_000_000_000, nanos / 1_000_000_000)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+}
+
+/// A leap second announced for the end of a day (in UTC).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeapSecond {
+    /// The day ends with 23:59:60, the clock has to go back a second.
+    Insert,
+    /// The day ends with 23:59:58, the clock has to go forward a second.
+    Delete
+}
+
+/// How the clock is being corrected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClockStatus {
+    /// What is added to the time of the underlying clock, in nanoseconds.
+    pub offset_nanos: i64,
+    /// The part of the corrections that is still being slewed in, in nanoseconds.
+    pub pending_nanos: i64,
+    /// How fast the underlying clock is estimated to run, in parts per million.
+    pub drift_ppm: i64
+}
+
 pub trait TimeApi {
     /// Get the current date and time.
     fn now(&self) -> DateTime;
     /// Get the current date and time with an offset.
     fn with_offset(&self, offset: TimeOffset) -> DateTime;
+    /// Corrects the clock by the given nanoseconds by slowly speeding it up or slowing it down,
+    /// so it never jumps. Returns the new drift estimate if the correction changed it.
+    fn adjust(&mut self, correction_nanos: i64) -> Option<i64>;
+    /// Drops the corrections, for when the underlying clock was set to the right time.
+    fn reset_corrections(&mut self);
+    /// Slews the leap second in after the end of the given day.
+    fn schedule_leap_second(&mut self, date: Date, leap_second: LeapSecond);
+    fn status(&self) -> ClockStatus;
 }
\ No newline at end of file
@@ -0,0 +1,189 @@
+use alloc::format;
+use core::fmt;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spin::Mutex;
+use crate::api::display::{Colors, DisplayApi, Fonts, Position, TextAlignment, TextBaseline, TextLineHeight};
+use crate::internal::protocol::BootModule;
+use crate::systems::display::SimpleDisplay;
+
+const MAX_STAGES: usize = 32;
+static CHART_WIDTH: u64 = 40;
+static LINE_HEIGHT: usize = 18;
+/// The scancode F2 sends when pressed, the key that opens the settings screen during boot.
+static SETUP_SCANCODE: u8 = 0x3C;
+
+static BOOT_START: AtomicU64 = AtomicU64::new(0);
+static BOOT_END: AtomicU64 = AtomicU64::new(0);
+static STAGES: Mutex<BootStages> = Mutex::new(BootStages::new());
+static SETUP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// A boot stage that finished, with the amount of time stamp counter cycles it took.
+#[derive(Debug, Clone, Copy)]
+pub struct BootStage {
+    pub name: &'static str,
+    pub cycles: u64
+}
+
+struct BootStages {
+    stages: [Option<BootStage>; MAX_STAGES],
+    count: usize
+} impl BootStages {
+    const fn new() -> Self { Self {
+        stages: [None; MAX_STAGES],
+        count: 0
+    } }
+
+    fn push(&mut self, stage: BootStage) {
+        if self.count < MAX_STAGES {
+            self.stages[self.count] = Some(stage);
+            self.count += 1;
+        }
+    }
+}
+
+/// Marks the start of the boot, everything before this is not accounted to any stage.
+pub fn begin() {
+    BOOT_START.store(crate::internal::tsc::read(), Ordering::SeqCst);
+}
+
+/// Runs one step of the boot, measures how long it took and reports it on the serial port and,
+/// once there is one, the frame buffer. Stages can't use the heap before it is set up, so the list
+/// of stages is fixed in size and later stages beyond it are only logged.
+pub fn stage<F, R>(name: &'static str, func: F) -> R
+    where F: FnOnce() -> R
+{
+    let start = crate::internal::tsc::read();
+    let result = func();
+    let cycles = crate::internal::tsc::read().saturating_sub(start);
+
+    STAGES.lock().push(BootStage { name, cycles });
+    log::info!("Boot stage '{}' finished in {}.", name, Duration(cycles));
+    draw_progress();
+
+    result
+}
+
+/// Returns the time stamp counter reading at the start of the boot.
+pub fn start() -> u64 {
+    BOOT_START.load(Ordering::SeqCst)
+}
+
+/// Marks the end of the boot and logs how long it took in total.
+pub fn finish() {
+    BOOT_END.store(crate::internal::tsc::read(), Ordering::SeqCst);
+    log::info!("Boot finished in {}.", Duration(total_cycles()));
+}
+
+/// Looks at a scancode received while booting for the key that opens the settings screen. The
+/// scancodes are decoded only once the boot finished, too late to check for it there.
+pub fn on_scancode(scancode: u8) {
+    if scancode == SETUP_SCANCODE && BOOT_END.load(Ordering::SeqCst) == 0 {
+        request_setup();
+    }
+}
+
+/// Makes the kernel show the settings screen instead of its boot target.
+pub fn request_setup() {
+    SETUP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Returns whether the settings screen was asked for during boot.
+pub fn setup_requested() -> bool {
+    SETUP_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Returns the stages the boot went through so far.
+pub fn stages() -> impl Iterator<Item = BootStage> {
+    let stages = STAGES.lock();
+    let mut copy = [None; MAX_STAGES];
+    copy[..stages.count].copy_from_slice(&stages.stages[..stages.count]);
+    copy.into_iter().flatten()
+}
+
+/// Iterates over the files passed to the kernel at boot. Modules that are `newc` cpio archives,
+/// like the ramdisk the build attaches for the `bootloader` protocol, are replaced by their files.
+pub fn modules() -> impl Iterator<Item = BootModule> {
+    crate::internal::protocol::modules().iter().flat_map(|module| {
+        let data = module.data();
+        let archive = crate::internal::initrd::is_archive(data);
+        let files = archive.then(|| crate::internal::initrd::files(data)).into_iter().flatten()
+            .map(|(name, contents)| BootModule {
+                name, address: contents.as_ptr() as u64, size: contents.len() as u64
+            });
+        (!archive).then_some(*module).into_iter().chain(files)
+    })
+}
+
+/// Returns how many cycles the boot took, up to now if it hasn't finished yet.
+pub fn total_cycles() -> u64 {
+    let end = match BOOT_END.load(Ordering::SeqCst) {
+        0 => crate::internal::tsc::read(),
+        end => end
+    };
+    end.saturating_sub(BOOT_START.load(Ordering::SeqCst))
+}
+
+/// Writes a chart of where the boot time went, one bar per stage.
+pub fn write_chart(writer: &mut impl Write) -> fmt::Result {
+    let total = total_cycles().max(1);
+    let mut accounted = 0;
+
+    for stage in stages() {
+        accounted += stage.cycles;
+        writeln!(
+            writer, "{:<14} {:>10} {:>3}% {}",
+            stage.name, format!("{}", Duration(stage.cycles)), stage.cycles * 100 / total,
+            "#".repeat((stage.cycles * CHART_WIDTH / total) as usize)
+        )?;
+    }
+    writeln!(writer, "{:<14} {:>10}", "unaccounted", format!("{}", Duration(total.saturating_sub(accounted))))?;
+    writeln!(writer, "{:<14} {:>10}", "total", format!("{}", Duration(total)))
+}
+
+fn draw_progress() {
+    if !crate::internal::framebuffer::is_initialized() { return; }
+    let Ok(mut display) = SimpleDisplay::new(0) else { return; };
+
+    let mut count = 0;
+    for (index, stage) in stages().enumerate() {
+        let line = format!("[ OK ] {:<14} {:>10}", stage.name, format!("{}", Duration(stage.cycles)));
+        let _ = display.draw_text(
+            &line, Position::new(0, index * LINE_HEIGHT),
+            Colors::White.into(), Some(Colors::Black.into()),
+            Fonts::default().into(), false, false,
+            TextBaseline::Top, TextAlignment::Left, TextLineHeight::Full
+        );
+        count = index + 1;
+    }
+
+    // Padded, as the text is drawn over the previous one
+    if !cfg!(feature = "gui") { return; }
+    let hint = format!("{:<21}", if setup_requested() { "Entering settings..." } else { "Press F2 for settings" });
+    let _ = display.draw_text(
+        &hint, Position::new(0, (count + 1) * LINE_HEIGHT),
+        Colors::Gray.into(), Some(Colors::Black.into()),
+        Fonts::default().into(), false, false,
+        TextBaseline::Top, TextAlignment::Left, TextLineHeight::Full
+    );
+}
+
+/// Formats time stamp counter cycles as time, or as cycles while the counter is not calibrated.
+/// Does not allocate, as the first stages run before there is a heap.
+pub struct Duration(pub u64);
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let khz = crate::internal::tsc::khz();
+        if khz == 0 {
+            return write!(f, "{} cycles", self.0);
+        }
+
+        let micros = self.0 * 1000 / khz;
+        if micros >= 10_000 {
+            write!(f, "{} ms", micros / 1000)
+        } else {
+            write!(f, "{} us", micros)
+        }
+    }
+}
\ No newline at end of file
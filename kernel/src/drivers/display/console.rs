@@ -0,0 +1,82 @@
+use alloc::format;
+use alloc::sync::Arc;
+use core::any::Any;
+use spin::Mutex;
+use crate::api::display::{Color, Colors, DisplayApi, DisplayError, Fonts, Position, TextAlignment, TextBaseline, TextLineHeight};
+use crate::drivers::display::{CommonDisplayDriver, DisplayDriverExt};
+
+/// Minimal console that draws every line straight to the display, without a text buffer.
+/// Works on any display type, so it is the fallback when a more capable mode can't be used.
+pub struct ConsoleDisplayDriver {
+    display: Option<Arc<Mutex<dyn DisplayApi + Send>>>,
+    font: Fonts,
+    row: usize
+} #[allow(dead_code)] impl ConsoleDisplayDriver {
+    pub fn init(&mut self, font: Fonts) {
+        self.font = font;
+        self.row = 0;
+    }
+
+    /// Draws the line below the previous one, starting over at the top once the screen is full.
+    pub fn write_line(&mut self, text: &str) -> Result<(), DisplayError> {
+        let Some(display) = self.display.as_mut() else { return Ok(()); };
+        let mut display = super::lock_display(display)?;
+        let info = display.get_info()?;
+        let size = self.font.get_size();
+        let (columns, rows) = (info.width / size.width.max(1), info.height / size.height.max(1));
+        if rows == 0 { return Ok(()); }
+        if self.row >= rows { self.row = 0; }
+
+        // Padded so the line replaces whatever was drawn there before
+        let line = format!("{:<columns$}", text, columns = columns);
+        display.draw_text(
+            line.get(..columns).unwrap_or(&line), Position::new(0, self.row * size.height),
+            Colors::White.into(), Some(Colors::Black.into()),
+            self.font.into(), false, false,
+            TextBaseline::Top, TextAlignment::Left, TextLineHeight::Full
+        )?;
+        self.row += 1;
+        display.swap()
+    }
+
+    /// Moves back to the first line, without clearing the screen.
+    pub fn home(&mut self) {
+        self.row = 0;
+    }
+} impl CommonDisplayDriver for ConsoleDisplayDriver {
+    fn new() -> Self { Self {
+        display: None,
+        font: Fonts::default(),
+        row: 0
+    } }
+} impl DisplayDriverExt for ConsoleDisplayDriver {
+    fn draw_all(&mut self) -> Result<(), DisplayError> {
+        // Lines are drawn as they are written, there is nothing pending
+        Ok(())
+    }
+
+    fn is_dirty(&self) -> bool {
+        false
+    }
+
+    fn clear(&mut self, color: Color) -> Result<(), DisplayError> {
+        self.row = 0;
+        if let Some(display) = self.display.as_mut() {
+            let mut display = super::lock_display(display)?;
+            display.clear(color)?;
+            display.swap()
+        } else { Ok(()) }
+    }
+
+    fn activate(&mut self, display: Arc<Mutex<dyn DisplayApi + Send>>) {
+        self.display = Some(display);
+    }
+
+    fn deactivate(&mut self) {
+        self.display = None;
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
\ No newline at end of file
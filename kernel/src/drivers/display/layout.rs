@@ -0,0 +1,162 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::api::display::{Position, Region};
+use crate::drivers::display::text::TextDisplayDriver;
+
+static DEFAULT_TAB_WIDTH: usize = 4;
+
+/// How a line is placed within the width of a layout.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldAlignment {
+    Left, Center, Right
+}
+
+/// Lays out text in a rectangular region of a `TextDisplayDriver`: wraps at word boundaries,
+/// expands tabs to tab stops and aligns every line within the width of the region. Lines are
+/// padded with spaces, so writing replaces whatever was in the region before. The region has to lie
+/// within the text buffer.
+pub struct TextLayout {
+    region: Region,
+    alignment: FieldAlignment,
+    /// Columns relative to the region, after the last one tabs advance by `tab_width`.
+    tab_stops: Vec<usize>,
+    tab_width: usize
+} #[allow(dead_code)] impl TextLayout {
+    pub fn new(region: Region) -> Self { Self {
+        region,
+        alignment: FieldAlignment::Left,
+        tab_stops: Vec::new(),
+        tab_width: DEFAULT_TAB_WIDTH
+    } }
+
+    pub fn set_alignment(&mut self, alignment: FieldAlignment) {
+        self.alignment = alignment;
+    }
+
+    /// Sets the columns tabs advance to, which get sorted. Tabs past the last stop advance to the
+    /// next multiple of the tab width.
+    pub fn set_tab_stops(&mut self, tab_stops: &[usize]) {
+        self.tab_stops = tab_stops.to_vec();
+        self.tab_stops.sort_unstable();
+        self.tab_stops.dedup();
+    }
+
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width.max(1);
+    }
+
+    /// Splits the text into the lines it takes up in the region. Words longer than the width are
+    /// broken, lines that don't fit in the height are still returned.
+    pub fn wrap(&self, text: &str) -> Vec<String> {
+        let width = self.region.size.width;
+        let mut lines = Vec::new();
+        if width == 0 { return lines; }
+
+        for paragraph in text.split('\n') {
+            let first_line = lines.len();
+            let mut line = String::new();
+            let mut column = 0;
+            for word in split_words(paragraph) {
+                // Only the word itself has to fit, the whitespace after it may get cut off
+                let visible = word.trim_end().chars().count();
+                if column > 0 && column + visible > width {
+                    lines.push(String::from(line.trim_end()));
+                    line.clear();
+                    column = 0;
+                }
+
+                let word = self.expand_tabs(word, column);
+                let mut word = word.as_str();
+                // Words longer than a whole line are broken wherever the line ends
+                while column + word.trim_end().chars().count() > width {
+                    let split = word.char_indices().nth(width - column).map_or(word.len(), |(index, _)| index);
+                    line.push_str(&word[..split]);
+                    lines.push(core::mem::take(&mut line));
+                    column = 0;
+                    word = &word[split..];
+                }
+                line.push_str(word);
+                column += word.chars().count();
+                if column >= width {
+                    lines.push(String::from(line.trim_end()));
+                    line.clear();
+                    column = 0;
+                }
+            }
+            // An empty paragraph still takes up a line
+            if column > 0 || lines.len() == first_line {
+                lines.push(String::from(line.trim_end()));
+            }
+        }
+        lines
+    }
+
+    /// Writes the text into the region, starting at its top. Returns how many rows were used,
+    /// which is at most the height of the region as the rest of the text is cut off.
+    pub fn write(&self, driver: &mut TextDisplayDriver, text: &str) -> usize {
+        let lines = self.wrap(text);
+        let rows = lines.len().min(self.region.size.height);
+        for (row, line) in lines.iter().take(rows).enumerate() {
+            self.write_row(driver, row, line);
+        }
+        rows
+    }
+
+    /// Writes a single line into the given row of the region, cut off at the width of the region.
+    pub fn write_row(&self, driver: &mut TextDisplayDriver, row: usize, text: &str) {
+        if row >= self.region.size.height { return; }
+        let width = self.region.size.width;
+        let text: String = self.expand_tabs(text, 0).chars().take(width).collect();
+        let padding = width - text.chars().count();
+
+        let (left, right) = match self.alignment {
+            FieldAlignment::Left => (0, padding),
+            FieldAlignment::Center => (padding / 2, padding - padding / 2),
+            FieldAlignment::Right => (padding, 0)
+        };
+
+        let cursor = driver.get_cursor_position();
+        driver.move_cursor(Position::new(self.region.position.x, self.region.position.y + row));
+        for _ in 0..left { driver.write_char(' '); }
+        driver.write_string(&text);
+        for _ in 0..right { driver.write_char(' '); }
+        driver.move_cursor(cursor);
+    }
+
+    fn expand_tabs(&self, text: &str, start_column: usize) -> String {
+        let mut expanded = String::new();
+        let mut column = start_column;
+        for character in text.chars() {
+            if character == '\t' {
+                let next = self.next_tab_stop(column);
+                for _ in column..next { expanded.push(' '); }
+                column = next;
+            } else {
+                expanded.push(character);
+                column += 1;
+            }
+        }
+        expanded
+    }
+
+    fn next_tab_stop(&self, column: usize) -> usize {
+        self.tab_stops.iter().copied()
+            .find(|stop| *stop > column)
+            .unwrap_or((column / self.tab_width + 1) * self.tab_width)
+    }
+}
+
+/// Splits the text into words, each keeping the whitespace that follows it.
+fn split_words(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    core::iter::from_fn(move || {
+        if rest.is_empty() { return None; }
+        let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let end = rest[word_end..].find(|character: char| !character.is_whitespace())
+            .map_or(rest.len(), |index| word_end + index);
+        let (word, remainder) = rest.split_at(end);
+        rest = remainder;
+        Some(word)
+    })
+}
\ No newline at end of file
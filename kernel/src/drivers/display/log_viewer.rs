@@ -0,0 +1,251 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::fmt::Write;
+use log::{Level, LevelFilter};
+use spin::Mutex;
+use crate::api::display::{Color, DisplayApi, DisplayError, Position, Region, Size};
+use crate::api::input::{Input, InputConsumer, InputEvent};
+use crate::api::keyboard::KeyCode;
+use crate::drivers::display::{CommonDisplayDriver, DisplayDriverExt};
+use crate::drivers::display::selection::TextSelection;
+use crate::drivers::display::text::{TextColor, TextDisplayDriver, TextDisplayDriverArgs};
+use crate::drivers::display::tui::{Frame, Style, TuiState};
+use crate::internal::log_buffer::LogRecord;
+
+/// What the log viewer shows, changed by keyboard input while it has the focus.
+///
+/// Keys: arrows/`j`/`k` scroll by a line, page up/down by a page, home/`g` jumps to the oldest
+/// record, end/`G` follows new records again. `e`/`w`/`i`/`d`/`t` set the lowest level shown and
+/// `/` starts entering a module filter, which enter applies and escape discards. Text is selected
+/// and copied to the clipboard as `TextSelection` describes, scrolling clears the selection.
+pub struct LogViewerControls {
+    /// Records scrolled back from the newest one, 0 follows new records.
+    scroll: usize,
+    level: LevelFilter,
+    filter: String,
+    /// The filter being typed, if one is.
+    editing: Option<String>,
+    page: usize,
+    selection: TextSelection,
+    changed: bool
+} impl LogViewerControls {
+    fn new() -> Self { Self {
+        scroll: 0,
+        level: LevelFilter::Trace,
+        filter: String::new(),
+        editing: None,
+        page: 1,
+        selection: TextSelection::new(Size::new(0, 0), Size::new(0, 0)),
+        changed: true
+    } }
+
+    fn scroll_by(&mut self, lines: isize) {
+        self.scroll = self.scroll.saturating_add_signed(lines);
+        self.selection.clear();
+        self.changed = true;
+    }
+
+    fn on_text(&mut self, character: char) -> bool {
+        if let Some(editing) = self.editing.as_mut() {
+            match character {
+                '\n' => {
+                    self.filter = self.editing.take().unwrap_or_default();
+                    self.scroll = 0;
+                }, '\u{8}' => { editing.pop(); },
+                '\u{1b}' => self.editing = None,
+                character if !character.is_control() => editing.push(character),
+                _ => return false
+            }
+            self.changed = true;
+            return true;
+        }
+
+        match character {
+            'k' => self.scroll_by(1),
+            'j' => self.scroll_by(-1),
+            'g' => self.scroll_by(isize::MAX),
+            'G' => self.scroll_by(isize::MIN),
+            '/' => { self.editing = Some(String::new()); self.changed = true; },
+            'e' | 'w' | 'i' | 'd' | 't' => {
+                self.level = match character {
+                    'e' => LevelFilter::Error,
+                    'w' => LevelFilter::Warn,
+                    'i' => LevelFilter::Info,
+                    'd' => LevelFilter::Debug,
+                    _ => LevelFilter::Trace
+                };
+                self.changed = true;
+            }, _ => return false
+        }
+        true
+    }
+
+    fn on_key(&mut self, code: KeyCode) -> bool {
+        let page = self.page as isize;
+        match code {
+            KeyCode::ArrowUp => self.scroll_by(1),
+            KeyCode::ArrowDown => self.scroll_by(-1),
+            KeyCode::PageUp => self.scroll_by(page),
+            KeyCode::PageDown => self.scroll_by(-page),
+            KeyCode::Home => self.scroll_by(isize::MAX),
+            KeyCode::End => self.scroll_by(isize::MIN),
+            KeyCode::Escape if self.editing.is_some() => {
+                self.editing = None;
+                self.changed = true;
+            }, _ => return false
+        }
+        true
+    }
+
+    fn shows(&self, record: &LogRecord) -> bool {
+        record.level <= self.level && record.target().contains(self.filter.as_str())
+    }
+} impl InputConsumer for LogViewerControls {
+    fn on_input(&mut self, input: &Input) -> bool {
+        if self.editing.is_none() && self.selection.on_input(input) {
+            self.changed = true;
+            return true;
+        }
+        match input.event {
+            InputEvent::Key { code, pressed: true, .. } => self.on_key(code),
+            InputEvent::Text(character) => self.on_text(character),
+            _ => false
+        }
+    }
+}
+
+/// Shows the records kept in the kernel log buffer, colored by level, on top of a text buffer.
+/// Follows new records until scrolled back. Input reaches it through `controls`, which has to be
+/// given the focus.
+pub struct LogViewerDisplayDriver {
+    text: TextDisplayDriver,
+    controls: Arc<Mutex<LogViewerControls>>,
+    tui: TuiState,
+    /// The log sequence the screen was last rendered at.
+    rendered_sequence: Option<u64>
+} #[allow(dead_code)] impl LogViewerDisplayDriver {
+    pub fn init(&mut self, args: &mut TextDisplayDriverArgs) {
+        self.text.init(args);
+        let size = self.text.get_buffer_size();
+        let mut controls = self.controls.lock();
+        controls.page = size.height.saturating_sub(1).max(1);
+        controls.selection = TextSelection::new(size, self.text.get_cell_size());
+    }
+
+    /// Returns the controls, to give them the input focus.
+    pub fn controls(&self) -> Arc<Mutex<LogViewerControls>> {
+        self.controls.clone()
+    }
+
+    fn render(&mut self) {
+        let sequence = crate::internal::log_buffer::sequence();
+        let mut controls = self.controls.lock();
+        if !controls.changed && (controls.scroll > 0 || self.rendered_sequence == Some(sequence)) {
+            return;
+        }
+
+        let size = self.text.get_buffer_size();
+        let rows = size.height.saturating_sub(1);
+        let mut records: Vec<(Level, String)> = Vec::new();
+        crate::internal::log_buffer::for_each(|record| {
+            if controls.shows(record) {
+                let mut line = format!("{:<5} {}: {}", record.level.as_str(), record.target(), record.message());
+                for (key, value) in record.fields() {
+                    let _ = write!(line, " {}={}", key, value);
+                }
+                records.push((record.level, line));
+            }
+        });
+
+        // Can't scroll back past the oldest record
+        controls.scroll = controls.scroll.min(records.len().saturating_sub(rows));
+        let end = records.len() - controls.scroll;
+        let start = end.saturating_sub(rows);
+
+        let header = match controls.editing.as_ref() {
+            Some(editing) => format!("Filter module: {}", editing),
+            None => format!(
+                "Log {} | level <= {} | module: {} | {} of {} records",
+                if controls.scroll == 0 { "following" } else { "scrolled" },
+                controls.level, if controls.filter.is_empty() { "*" } else { controls.filter.as_str() },
+                end, records.len()
+            )
+        };
+        let mut frame = Frame::new(&mut self.text, &mut self.tui);
+        let theme = frame.theme();
+        frame.label(Region::new(Position::new(0, 0), Size::new(size.width, 1)), header.as_str(), theme.header);
+        for row in 0..rows {
+            let line = records.get(start + row);
+            let style = Style::new(line.map_or(TextColor::White, |(level, ..)| level_color(*level)), TextColor::Black);
+            frame.label(
+                Region::new(Position::new(0, row + 1), Size::new(size.width, 1)),
+                line.map_or("", |(.., line)| line.as_str()), style
+            );
+        }
+        drop(frame);
+
+        if controls.selection.take_changed() {
+            self.text.select(controls.selection.range());
+        }
+        if controls.selection.take_copy() {
+            if let Some(text) = self.text.selected_text() {
+                crate::systems::clipboard::set(&text);
+            }
+        }
+
+        let cursor = header.chars().count().min(size.width.saturating_sub(1));
+        self.text.move_cursor(Position::new(cursor, 0));
+        controls.changed = false;
+        self.rendered_sequence = Some(sequence);
+    }
+} impl CommonDisplayDriver for LogViewerDisplayDriver {
+    fn new() -> Self { Self {
+        text: TextDisplayDriver::new(),
+        controls: Arc::new(Mutex::new(LogViewerControls::new())),
+        tui: TuiState::default(),
+        rendered_sequence: None
+    } }
+} impl DisplayDriverExt for LogViewerDisplayDriver {
+    fn draw_all(&mut self) -> Result<(), DisplayError> {
+        self.render();
+        self.text.draw_all()
+    }
+
+    fn is_dirty(&self) -> bool {
+        if self.text.is_dirty() { return true; }
+        // Controls locked right now are being changed
+        self.controls.try_lock().map_or(true, |controls| controls.changed || controls.scroll == 0
+            && self.rendered_sequence != Some(crate::internal::log_buffer::sequence()))
+    }
+
+    fn clear(&mut self, color: Color) -> Result<(), DisplayError> {
+        self.text.clear(color)
+    }
+
+    fn activate(&mut self, display: Arc<Mutex<dyn DisplayApi + Send>>) {
+        self.text.activate(display);
+        self.text.init_redraw();
+        self.controls.lock().changed = true;
+    }
+
+    fn deactivate(&mut self) {
+        self.text.deactivate();
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+fn level_color(level: Level) -> TextColor {
+    match level {
+        Level::Error => TextColor::Red,
+        Level::Warn => TextColor::Yellow,
+        Level::Info => TextColor::White,
+        Level::Debug => TextColor::Silver,
+        Level::Trace => TextColor::Gray
+    }
+}
\ No newline at end of file
@@ -1,129 +1,112 @@
+use alloc::boxed::Box;
 use alloc::sync::Arc;
-use spin::Mutex;
-use crate::api::display::{Color, Colors, DisplayApi, Fonts, Position, TextAlignment, TextBaseline, TextLineHeight};
-use crate::drivers::display::text::{TextDisplayDriver, TextDisplayDriverArgs};
+use core::any::Any;
+use spin::{Mutex, MutexGuard};
+use crate::api::display::{Color, DisplayApi, DisplayError};
 
 pub mod text;
+pub mod console;
+pub mod layout;
+#[cfg(feature = "gui")] pub mod log_viewer;
+#[cfg(feature = "gui")] pub mod tui;
+#[cfg(feature = "gui")] pub mod settings;
+#[cfg(feature = "gui")] pub mod selection;
 
-#[allow(dead_code)]
-pub enum DisplayDriverType {
-    Unknown,
-    Dummy(DummyDisplayDriver),
-    Text(TextDisplayDriver, TextDisplayDriverArgs)
-}
+static LOCK_ATTEMPTS: u32 = 8;
 
-trait DisplayDriver {
+/// A display driver, kept by the driver manager as a trait object. Driver-specific APIs like
+/// `write_string` are reached by downcasting with `as_any_mut`.
+pub trait DisplayDriverExt: Any + Send {
     fn activate(&mut self, display: Arc<Mutex<dyn DisplayApi + Send>>);
     fn deactivate(&mut self);
+    fn draw_all(&mut self) -> Result<(), DisplayError>;
+    /// Returns whether there are changes `draw_all` still has to draw.
+    fn is_dirty(&self) -> bool { true }
+    fn clear(&mut self, color: Color) -> Result<(), DisplayError>;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
 pub trait CommonDisplayDriver {
     fn new() -> Self;
-    fn draw_all(&mut self);
+}
 
-    fn clear(&mut self, color: Color);
+/// Locks the display, retrying a few times with an exponentially growing pause in between.
+/// Gives up with `DisplayError::Busy` instead of waiting forever, as the holder of the lock might
+/// be the code this interrupted.
+pub fn lock_display(
+    display: &Arc<Mutex<dyn DisplayApi + Send>>
+) -> Result<MutexGuard<dyn DisplayApi + Send + 'static>, DisplayError> {
+    for attempt in 0..LOCK_ATTEMPTS {
+        if let Some(display) = display.try_lock() {
+            return Ok(display);
+        }
+        for _ in 0..(1 << attempt) {
+            core::hint::spin_loop();
+        }
+    }
+    Err(DisplayError::Busy)
 }
 
 pub struct DisplayDriverManager {
-    pub current_driver: DisplayDriverType
+    current_driver: Option<Box<dyn DisplayDriverExt>>
 } #[allow(dead_code)] impl DisplayDriverManager {
     pub fn new() -> Self { Self {
-        current_driver: DisplayDriverType::Unknown
+        current_driver: None
     } }
 
+    /// Deactivates the current driver and activates the new one, None leaves no driver active.
     pub fn set_driver(
-        &mut self, driver: DisplayDriverType,
+        &mut self, driver: Option<Box<dyn DisplayDriverExt>>,
         display: Arc<Mutex<dyn DisplayApi + Send>>
     ) {
-        match &mut self.current_driver {
-            DisplayDriverType::Dummy(driver) => {
-                driver.deactivate();
-            }, DisplayDriverType::Text(driver, ..) => {
-                driver.deactivate();
-            }, _ => {}
+        if let Some(driver) = self.current_driver.as_mut() {
+            driver.deactivate();
         }
         self.current_driver = driver;
-        match &mut self.current_driver {
-            DisplayDriverType::Dummy(driver) => {
-                driver.activate(display);
-            },
-            DisplayDriverType::Text(driver, args) => {
-                driver.init(args);
-                driver.activate(display);
-            }, _ => {}
+        if let Some(driver) = self.current_driver.as_mut() {
+            driver.activate(display);
         }
     }
 
-    pub fn clear(&mut self, color: Color) {
-        match &mut self.current_driver {
-            DisplayDriverType::Dummy(driver) => {
-                driver.clear(color);
-            }, DisplayDriverType::Text(driver, ..) => {
-                driver.clear(color);
-            }, _ => {}
-        }
+    pub fn clear(&mut self, color: Color) -> Result<(), DisplayError> {
+        self.current_driver.as_mut().map_or(Ok(()), |driver| driver.clear(color))
     }
 
-    pub fn draw_all(&mut self) {
-        match &mut self.current_driver {
-            DisplayDriverType::Dummy(driver) => {
-                driver.draw_all();
-            }, DisplayDriverType::Text(driver, ..) => {
-                driver.draw_all();
-            }, _ => {}
-        }
+    pub fn draw_all(&mut self) -> Result<(), DisplayError> {
+        self.current_driver.as_mut().map_or(Ok(()), |driver| driver.draw_all())
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.current_driver.as_ref().is_some_and(|driver| driver.is_dirty())
     }
 
-    pub fn get_driver(&self) -> &DisplayDriverType {
-        &self.current_driver
+    /// Returns the current driver if it is of the given type.
+    pub fn get_driver<T: DisplayDriverExt>(&mut self) -> Option<&mut T> {
+        self.current_driver.as_mut().and_then(|driver| driver.as_any_mut().downcast_mut::<T>())
     }
 }
 
 pub struct DummyDisplayDriver {
     display: Option<Arc<Mutex<dyn DisplayApi + Send>>>
-} impl DummyDisplayDriver {
-    pub fn draw_panic(&mut self, message: &str) {
-        if let Some(display) = self.display.as_mut() {
-            let mut display = display.try_lock()
-                .unwrap_or_else(|| panic!("Failed to lock display for panic message drawing!") );
-            display.clear(Colors::Blue.into());
-            display.draw_text(
-                "Kernel Panic -- please reboot your machine! See message below:", Position::new(0, 0),
-                Colors::White.into(), None,
-                Fonts::default().into(), false, false,
-                TextBaseline::Top, TextAlignment::Left, TextLineHeight::Full
-            );
-            display.draw_text(
-                message, Position::new(0, 18),
-                Colors::White.into(), None,
-                Fonts::Font9x18.into(), false, false,
-                TextBaseline::Top, TextAlignment::Left, TextLineHeight::Full
-            );
-            display.swap();
-        } else { panic!("No display to draw panic message to!"); }
-    }
 } impl CommonDisplayDriver for DummyDisplayDriver {
     fn new() -> Self { Self {
         display: None
     } }
-
-    fn draw_all(&mut self) {
+} impl DisplayDriverExt for DummyDisplayDriver {
+    fn draw_all(&mut self) -> Result<(), DisplayError> {
         if let Some(display) = self.display.as_mut() {
-            display.try_lock()
-                .unwrap_or_else(|| panic!("Failed to lock display for drawing!") )
-                .swap();
-        }
+            lock_display(display)?.swap()
+        } else { Ok(()) }
     }
 
-    fn clear(&mut self, color: Color) {
+    fn clear(&mut self, color: Color) -> Result<(), DisplayError> {
         if let Some(display) = self.display.as_mut() {
-            let mut display = display.try_lock()
-                .unwrap_or_else(|| panic!("Failed to lock display for clearing!") );
-            display.clear(color);
-            display.swap();
-        }
+            let mut display = lock_display(display)?;
+            display.clear(color)?;
+            display.swap()
+        } else { Ok(()) }
     }
-} impl DisplayDriver for DummyDisplayDriver {
+
     fn activate(&mut self, display: Arc<Mutex<dyn DisplayApi + Send>>) {
         self.display = Some(display);
     }
@@ -131,4 +114,8 @@ pub struct DummyDisplayDriver {
     fn deactivate(&mut self) {
         self.display = None;
     }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
\ No newline at end of file
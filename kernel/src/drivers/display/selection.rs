@@ -0,0 +1,139 @@
+use crate::api::display::{Position, Size};
+use crate::api::input::{Input, InputConsumer, InputEvent, PointerButton};
+use crate::api::keyboard::KeyCode;
+
+/// Selects text on a text buffer by keyboard or mouse. The driver showing the buffer applies
+/// the selection with `TextDisplayDriver::select` and copies it to the clipboard when asked to.
+///
+/// Keys: shift+arrows extend the selection by a character or row, shift+home/end to the start
+/// or end of the row, ctrl+shift+c copies it and escape clears it. Dragging with the left mouse
+/// button selects the cells passed over and copies them once released.
+pub struct TextSelection {
+    /// The size of the text buffer in cells.
+    size: Size,
+    /// The size of a cell in pixels, to find the cell under the pointer.
+    cell: Size,
+    /// Where the pointer is in pixels, it starts in the top left corner.
+    pointer: (usize, usize),
+    dragging: bool,
+    /// Where the selection started, None without one.
+    anchor: Option<Position>,
+    /// Where it ends, also where the next one starts from the keyboard.
+    end: Position,
+    changed: bool,
+    copy: bool
+} #[allow(dead_code)] impl TextSelection {
+    pub fn new(size: Size, cell: Size) -> Self { Self {
+        size,
+        cell,
+        pointer: (0, 0),
+        dragging: false,
+        anchor: None,
+        end: Position::new(0, size.height.saturating_sub(1)),
+        changed: false,
+        copy: false
+    } }
+
+    /// Returns the selected cells, in the order they were selected in.
+    pub fn range(&self) -> Option<(Position, Position)> {
+        self.anchor.map(|anchor| (anchor, self.end))
+    }
+
+    pub fn clear(&mut self) {
+        if self.anchor.take().is_some() {
+            self.changed = true;
+        }
+        self.dragging = false;
+    }
+
+    /// Returns whether the selection changed since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        core::mem::take(&mut self.changed)
+    }
+
+    /// Returns whether the selection is to be copied to the clipboard since the last call.
+    pub fn take_copy(&mut self) -> bool {
+        core::mem::take(&mut self.copy)
+    }
+
+    fn on_key(&mut self, code: KeyCode, shift: bool, control: bool) -> bool {
+        match code {
+            KeyCode::C if shift && control => {
+                self.copy = self.anchor.is_some();
+                return self.copy;
+            }, KeyCode::Escape if self.anchor.is_some() => {
+                self.clear();
+                return true;
+            }, _ if !shift || control => return false,
+            _ => {}
+        }
+
+        let (width, height) = (self.size.width.max(1), self.size.height.max(1));
+        let mut end = self.end;
+        match code {
+            KeyCode::ArrowLeft if end.x > 0 => end.x -= 1,
+            KeyCode::ArrowLeft if end.y > 0 => end = Position::new(width - 1, end.y - 1),
+            KeyCode::ArrowRight if end.x + 1 < width => end.x += 1,
+            KeyCode::ArrowRight if end.y + 1 < height => end = Position::new(0, end.y + 1),
+            KeyCode::ArrowUp => end.y = end.y.saturating_sub(1),
+            KeyCode::ArrowDown => end.y = (end.y + 1).min(height - 1),
+            KeyCode::Home => end.x = 0,
+            KeyCode::End => end.x = width - 1,
+            KeyCode::ArrowLeft | KeyCode::ArrowRight => {},
+            _ => return false
+        }
+        self.anchor.get_or_insert(self.end);
+        self.end = end;
+        self.changed = true;
+        true
+    }
+
+    fn on_pointer_motion(&mut self, dx: i32, dy: i32) -> bool {
+        let move_by = |value: usize, delta: i32, limit: usize| {
+            value.saturating_add_signed(delta as isize).min(limit.saturating_sub(1))
+        };
+        self.pointer = (
+            move_by(self.pointer.0, dx, self.size.width * self.cell.width),
+            move_by(self.pointer.1, dy, self.size.height * self.cell.height)
+        );
+        if !self.dragging { return false; }
+
+        let cell = self.pointer_cell();
+        if cell != self.end {
+            self.end = cell;
+            self.changed = true;
+        }
+        true
+    }
+
+    fn on_button(&mut self, pressed: bool) {
+        let cell = self.pointer_cell();
+        if pressed {
+            self.anchor = Some(cell);
+            self.dragging = true;
+        } else if self.dragging {
+            self.dragging = false;
+            self.copy = true;
+        }
+        self.end = cell;
+        self.changed = true;
+    }
+
+    fn pointer_cell(&self) -> Position {
+        Position::new(
+            (self.pointer.0 / self.cell.width.max(1)).min(self.size.width.saturating_sub(1)),
+            (self.pointer.1 / self.cell.height.max(1)).min(self.size.height.saturating_sub(1))
+        )
+    }
+} impl InputConsumer for TextSelection {
+    fn on_input(&mut self, input: &Input) -> bool {
+        match input.event {
+            InputEvent::Key { code, pressed: true, modifiers } => self.on_key(code, modifiers.shift(), modifiers.control),
+            InputEvent::PointerMotion { dx, dy } => self.on_pointer_motion(dx, dy),
+            InputEvent::Button { button: PointerButton::Left, pressed } => {
+                self.on_button(pressed);
+                true
+            }, _ => false
+        }
+    }
+}
\ No newline at end of file
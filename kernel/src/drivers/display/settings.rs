@@ -0,0 +1,165 @@
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use spin::Mutex;
+use crate::api::display::{Color, DisplayApi, DisplayError, Position, Region, Size};
+use crate::api::input::{Input, InputConsumer, InputEvent};
+use crate::api::keyboard::KeyCode;
+use crate::api::time::TimeOffset;
+use crate::drivers::display::{CommonDisplayDriver, DisplayDriverExt};
+use crate::drivers::display::layout::FieldAlignment;
+use crate::drivers::display::text::{TextDisplayDriver, TextDisplayDriverArgs};
+use crate::drivers::display::tui::{Frame, TuiState};
+use crate::systems::settings::{BootSettings, BootTarget, DisplaySetting, LOG_LEVELS, TIME_OFFSET_COUNT};
+
+static HELP: &str = "Tab: next  Arrows: choose  F10: save and boot  Esc: boot without saving";
+
+/// How the settings screen was left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsResult {
+    /// The settings are to be persisted and applied.
+    Save(BootSettings),
+    /// The settings are left as they were.
+    Discard
+}
+
+/// The settings being edited on the settings screen, changed by keyboard input while it has the
+/// focus. Tab and shift+tab move between the lists, F10 saves and escape discards the changes.
+pub struct SettingsControls {
+    tui: TuiState,
+    /// The selected item of every list.
+    display: usize,
+    time_offset: usize,
+    log_level: usize,
+    target: usize,
+    result: Option<SettingsResult>
+} impl SettingsControls {
+    fn new(settings: BootSettings) -> Self {
+        let mut tui = TuiState::default();
+        tui.invalidate();
+        Self {
+            tui,
+            display: settings.display as usize,
+            time_offset: settings.time_offset.as_u8() as usize,
+            log_level: LOG_LEVELS.iter().position(|level| *level == settings.log_level).unwrap_or(0),
+            target: settings.target as usize,
+            result: None
+        }
+    }
+
+    fn settings(&self) -> BootSettings {
+        let defaults = BootSettings::default();
+        BootSettings {
+            display: DisplaySetting::from_u8(self.display as u8).unwrap_or(defaults.display),
+            time_offset: TimeOffset::from_u8(self.time_offset as u8).unwrap_or(defaults.time_offset),
+            log_level: LOG_LEVELS.get(self.log_level).copied().unwrap_or(defaults.log_level),
+            target: BootTarget::from_u8(self.target as u8).unwrap_or(defaults.target)
+        }
+    }
+
+    /// Returns how the screen was left, once it was.
+    pub fn take_result(&mut self) -> Option<SettingsResult> {
+        self.result.take()
+    }
+} impl InputConsumer for SettingsControls {
+    fn on_input(&mut self, input: &Input) -> bool {
+        let InputEvent::Key { code, pressed: true, modifiers } = input.event else { return false; };
+        match code {
+            KeyCode::F10 => self.result = Some(SettingsResult::Save(self.settings())),
+            KeyCode::Escape => self.result = Some(SettingsResult::Discard),
+            code => return self.tui.on_key(code, modifiers)
+        }
+        true
+    }
+}
+
+/// Lets the display mode, time zone, log level and boot target be chosen before the kernel
+/// starts, on top of a text buffer. Entered by pressing F2 during boot. Input reaches it through
+/// `controls`, which has to be given the focus.
+pub struct SettingsDisplayDriver {
+    text: TextDisplayDriver,
+    controls: Arc<Mutex<SettingsControls>>
+} #[allow(dead_code)] impl SettingsDisplayDriver {
+    pub fn init(&mut self, args: &mut TextDisplayDriverArgs) {
+        self.text.init(args);
+    }
+
+    /// Returns the controls, to give them the input focus.
+    pub fn controls(&self) -> Arc<Mutex<SettingsControls>> {
+        self.controls.clone()
+    }
+
+    fn render(&mut self) {
+        let mut controls = self.controls.lock();
+        if !controls.tui.is_changed() { return; }
+        let SettingsControls { tui, display, time_offset, log_level, target, .. } = &mut *controls;
+
+        let displays: Vec<&str> = DisplaySetting::ALL.iter().map(DisplaySetting::name).collect();
+        let targets: Vec<&str> = BootTarget::ALL.iter().map(BootTarget::name).collect();
+        let levels: Vec<&str> = LOG_LEVELS.iter().map(|level| level.as_str()).collect();
+        let offsets: Vec<String> = (0..TIME_OFFSET_COUNT as u8)
+            .filter_map(TimeOffset::from_u8)
+            .map(|offset| offset.to_string())
+            .collect();
+
+        let size = self.text.get_buffer_size();
+        let mut frame = Frame::new(&mut self.text, tui);
+        let theme = frame.theme();
+        let row = |y: usize| Region::new(Position::new(0, y), Size::new(size.width, 1));
+        frame.label_aligned(row(0), "AkjoOS Settings", theme.header, FieldAlignment::Center);
+        frame.label(row(size.height.saturating_sub(1)), HELP, theme.header);
+
+        // Two columns of two panels between the title and the help line
+        let width = size.width / 2;
+        let height = size.height.saturating_sub(2) / 2;
+        let panel = |column: usize, row: usize| Region::new(
+            Position::new(column * width, 1 + row * height), Size::new(width, height)
+        );
+        let inner = frame.panel(panel(0, 0), " Display ");
+        frame.list(inner, &displays, display);
+        let inner = frame.panel(panel(1, 0), " Boot target ");
+        frame.list(inner, &targets, target);
+        let inner = frame.panel(panel(0, 1), " Time zone ");
+        frame.list(inner, &offsets, time_offset);
+        let inner = frame.panel(panel(1, 1), " Log level ");
+        frame.list(inner, &levels, log_level);
+        drop(frame);
+
+        self.text.move_cursor(Position::new(0, size.height.saturating_sub(1)));
+    }
+} impl CommonDisplayDriver for SettingsDisplayDriver {
+    fn new() -> Self { Self {
+        text: TextDisplayDriver::new(),
+        controls: Arc::new(Mutex::new(SettingsControls::new(BootSettings::load())))
+    } }
+} impl DisplayDriverExt for SettingsDisplayDriver {
+    fn draw_all(&mut self) -> Result<(), DisplayError> {
+        self.render();
+        self.text.draw_all()
+    }
+
+    fn is_dirty(&self) -> bool {
+        if self.text.is_dirty() { return true; }
+        // Controls locked right now are being changed
+        self.controls.try_lock().map_or(true, |controls| controls.tui.is_changed())
+    }
+
+    fn clear(&mut self, color: Color) -> Result<(), DisplayError> {
+        self.text.clear(color)
+    }
+
+    fn activate(&mut self, display: Arc<Mutex<dyn DisplayApi + Send>>) {
+        self.text.activate(display);
+        self.text.init_redraw();
+        self.controls.lock().tui.invalidate();
+    }
+
+    fn deactivate(&mut self) {
+        self.text.deactivate();
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
\ No newline at end of file
@@ -1,12 +1,13 @@
 use alloc::borrow::Cow;
-use alloc::string::{String, ToString};
+use alloc::string::String;
 use alloc::sync::Arc;
+use core::any::Any;
 use alloc::vec;
 use alloc::vec::Vec;
 use embedded_graphics::mono_font::MonoFont;
 use spin::{Mutex, RwLock};
-use crate::api::display::{Color, Colors, DisplayApi, Fonts, Position, Region, Size, TextAlignment, TextBaseline, TextLineHeight};
-use crate::drivers::display::{CommonDisplayDriver, DisplayDriver};
+use crate::api::display::{Color, Colors, DisplayApi, DisplayError, Fonts, Position, Region, Size, TextAlignment, TextBaseline, TextLineHeight};
+use crate::drivers::display::{CommonDisplayDriver, DisplayDriverExt};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -58,93 +59,172 @@ pub enum TextColor {
     }
 }
 
+/// A color of the 256-color palette known from xterm: the 16 text colors, a 6x6x6 color cube
+/// and 24 shades of gray.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
-struct ColorCode(u8); impl ColorCode {
+pub struct IndexedColor(pub u8); impl From<TextColor> for IndexedColor {
+    fn from(color: TextColor) -> Self {
+        Self(color as u8)
+    }
+} impl From<IndexedColor> for Color {
+    fn from(color: IndexedColor) -> Self {
+        match color.0 {
+            index @ 0..=15 => TextColor::from_u8(index).unwrap().into(),
+            index @ 16..=231 => {
+                let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+                let index = index - 16;
+                Color::new(level(index / 36), level(index / 6 % 6), level(index % 6))
+            }, index => {
+                let gray = 8 + (index - 232) * 10;
+                Color::new(gray, gray, gray)
+            }
+        }
+    }
+}
+
+/// The color of a cell, either from the palette or a 24-bit color that gets converted to the
+/// pixel format of the frame buffer when drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellColor {
+    Indexed(IndexedColor),
+    Rgb(u8, u8, u8)
+} impl CellColor {
+    /// Set on the packed value of a 24-bit color.
+    const RGB_FLAG: u32 = 1 << 24;
+
+    #[inline]
+    fn to_bits(self) -> u32 {
+        match self {
+            CellColor::Indexed(color) => color.0 as u32,
+            CellColor::Rgb(red, green, blue) => Self::RGB_FLAG | (red as u32) << 16 | (green as u32) << 8 | blue as u32
+        }
+    }
+
+    #[inline]
+    fn from_bits(bits: u32) -> Self {
+        if bits & Self::RGB_FLAG != 0 {
+            CellColor::Rgb((bits >> 16) as u8, (bits >> 8) as u8, bits as u8)
+        } else { CellColor::Indexed(IndexedColor(bits as u8)) }
+    }
+} impl From<TextColor> for CellColor {
+    fn from(color: TextColor) -> Self {
+        CellColor::Indexed(color.into())
+    }
+} impl From<IndexedColor> for CellColor {
+    fn from(color: IndexedColor) -> Self {
+        CellColor::Indexed(color)
+    }
+} impl From<Color> for CellColor {
+    /// Takes the color channels, cells are always opaque.
+    fn from(color: Color) -> Self {
+        CellColor::Rgb(color.red, color.green, color.blue)
+    }
+} impl From<CellColor> for Color {
+    fn from(color: CellColor) -> Self {
+        match color {
+            CellColor::Indexed(color) => color.into(),
+            CellColor::Rgb(red, green, blue) => Color::new(red, green, blue)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+struct ColorCode(u64); impl ColorCode {
     #[inline]
-    pub fn new(foreground: TextColor, background: TextColor) -> Self {
-        Self((background as u8) << 4 | (foreground as u8))
+    pub fn new(foreground: CellColor, background: CellColor) -> Self {
+        Self((background.to_bits() as u64) << 32 | (foreground.to_bits() as u64))
     }
 
     #[inline]
-    pub fn foreground(&self) -> TextColor {
-        TextColor::from_u8(self.0 & 0xF).unwrap()
+    pub fn foreground(&self) -> CellColor {
+        CellColor::from_bits(self.0 as u32)
     }
 
     #[inline]
-    pub fn background(&self) -> TextColor {
-        TextColor::from_u8((self.0 >> 4) & 0xF).unwrap()
+    pub fn background(&self) -> CellColor {
+        CellColor::from_bits((self.0 >> 32) as u32)
     }
 
     #[inline]
     pub fn invert(&self) -> Self {
-        Self((self.0 >> 4) | (self.0 << 4))
+        Self(self.0.rotate_left(32))
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How a character is drawn besides its colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 #[repr(transparent)]
-struct CharacterAttributes(u8); impl CharacterAttributes {
+pub struct CharacterAttributes(u8); #[allow(dead_code)] impl CharacterAttributes {
+    pub const UNDERLINE: Self = Self(1 << 0);
+    pub const STRIKETHROUGH: Self = Self(1 << 1);
+    /// Drawn with the bold face of the font, if it has one.
+    pub const BOLD: Self = Self(1 << 2);
+    /// Drawn with the italic face of the font, if it has one.
+    pub const ITALIC: Self = Self(1 << 3);
+    /// Drawn in a color halfway between the text and the background color.
+    pub const DIM: Self = Self(1 << 4);
+
     #[inline]
-    pub fn new(underline: bool, strikethrough: bool) -> Self {
-        let mut value = 0;
-        if underline { value |= 1; }
-        if strikethrough { value |= 2; }
-        Self(value)
+    pub fn empty() -> Self {
+        Self(0)
     }
 
     #[inline]
-    pub fn underline(&self) -> bool {
-        self.0 & 1 != 0
+    pub fn contains(&self, attributes: Self) -> bool {
+        self.0 & attributes.0 == attributes.0
     }
 
+    /// Returns the attributes with the given ones turned on or off.
     #[inline]
-    pub fn strikethrough(&self) -> bool {
-        self.0 & 2 != 0
+    pub fn with(self, attributes: Self, enabled: bool) -> Self {
+        if enabled { Self(self.0 | attributes.0) } else { Self(self.0 & !attributes.0) }
     }
 }
 
+/// A cell of the text buffer: the code point in the lowest 32 bits, the color code in the next 64
+/// bits and the attributes in the 8 bits after that.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
-struct ScreenChar(u32); impl ScreenChar {
+struct ScreenChar(u128); impl ScreenChar {
     #[inline]
     pub fn new(character: char, color: ColorCode, attributes: CharacterAttributes) -> Self {
-        Self((character as u32) | ((color.0 as u32) << 8) | ((attributes.0 as u32) << 16))
+        Self((character as u128) | ((color.0 as u128) << 32) | ((attributes.0 as u128) << 96))
     }
 
     #[inline]
     pub fn character(&self) -> char {
-        (self.0 & 0xFF) as u8 as char
+        char::from_u32(self.0 as u32).unwrap_or(char::REPLACEMENT_CHARACTER)
     }
 
     #[inline]
     pub fn color(&self) -> ColorCode {
-        ColorCode((self.0 >> 8) as u8)
+        ColorCode((self.0 >> 32) as u64)
     }
 
     #[inline]
     pub fn attributes(&self) -> CharacterAttributes {
-        CharacterAttributes((self.0 >> 16) as u8)
+        CharacterAttributes((self.0 >> 96) as u8)
     }
 }
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TextSegment {
     pub text: Cow<'static, str>,
     pub text_position: Position,
-    pub text_color: TextColor,
-    pub background_color: TextColor,
-    pub underline: bool,
-    pub strikethrough: bool
+    pub text_color: CellColor,
+    pub background_color: CellColor,
+    pub attributes: CharacterAttributes
 } impl TextSegment {
     #[inline]
     pub fn new(
         text: impl Into<Cow<'static, str>>, text_position: Position,
-        text_color: TextColor, background_color: TextColor,
-        underline: bool, strikethrough: bool
+        text_color: CellColor, background_color: CellColor,
+        attributes: CharacterAttributes
     ) -> Self { Self {
         text: text.into(), text_position,
         text_color, background_color,
-        underline, strikethrough
+        attributes
     } }
 }
 
@@ -171,12 +251,14 @@ pub struct TextDisplayDriver {
     text_buffer: Vec<ScreenChar>,
     text_cursor: Position,
     dirty_buffer: Vec<bool>,
+    cursor_dirty: bool,
     font: Option<Fonts>,
-    text_color: TextColor,
-    background_color: TextColor,
-    underline: bool,
-    strikethrough: bool,
+    text_color: CellColor,
+    background_color: CellColor,
+    attributes: CharacterAttributes,
     blink: bool,
+    /// The first and last selected cell, in reading order. Selected cells are drawn inverted.
+    selection: Option<(Position, Position)>,
     buffer_width: usize,
     buffer_height: usize
 } #[allow(dead_code)] impl TextDisplayDriver {
@@ -185,8 +267,8 @@ pub struct TextDisplayDriver {
         self.buffer_height = (*args.buffer_size.read()).height;
         self.text_buffer = vec![ScreenChar::new(
             ' ',
-            ColorCode::new(TextColor::Black, TextColor::Black),
-            CharacterAttributes::new(false, false)
+            ColorCode::new(TextColor::Black.into(), TextColor::Black.into()),
+            CharacterAttributes::empty()
         ); self.buffer_width * self.buffer_height];
         self.dirty_buffer = vec![false; self.buffer_width * self.buffer_height];
         self.font = Some(args.font.read().clone());
@@ -203,7 +285,7 @@ pub struct TextDisplayDriver {
                 self.write(ScreenChar::new(
                     character,
                     ColorCode::new(self.text_color, self.background_color),
-                    CharacterAttributes::new(self.underline, self.strikethrough)
+                    self.attributes
                 ))
             }
         }
@@ -228,34 +310,81 @@ pub struct TextDisplayDriver {
     }
 
 
-    /// Sets the text color for incoming text.
+    /// Sets the text color for incoming text, one of the text colors, of the 256-color palette or
+    /// any 24-bit color.
     #[inline]
-    pub fn set_text_color(&mut self, color: TextColor) {
-        self.text_color = color;
+    pub fn set_text_color(&mut self, color: impl Into<CellColor>) {
+        self.text_color = color.into();
     }
 
-    /// Sets the background color for incoming text.
+    /// Sets the background color for incoming text, one of the text colors, of the 256-color
+    /// palette or any 24-bit color.
     #[inline]
-    pub fn set_background_color(&mut self, color: TextColor) {
-        self.background_color = color;
+    pub fn set_background_color(&mut self, color: impl Into<CellColor>) {
+        self.background_color = color.into();
     }
 
     /// Sets the underline attribute for incoming text.
     #[inline]
     pub fn set_underline(&mut self, underline: bool) {
-        self.underline = underline;
+        self.attributes = self.attributes.with(CharacterAttributes::UNDERLINE, underline);
     }
 
     /// Sets the strikethrough attribute for incoming text.
     #[inline]
     pub fn set_strikethrough(&mut self, strikethrough: bool) {
-        self.strikethrough = strikethrough;
+        self.attributes = self.attributes.with(CharacterAttributes::STRIKETHROUGH, strikethrough);
+    }
+
+    /// Sets the bold attribute for incoming text.
+    #[inline]
+    pub fn set_bold(&mut self, bold: bool) {
+        self.attributes = self.attributes.with(CharacterAttributes::BOLD, bold);
+    }
+
+    /// Sets the italic attribute for incoming text.
+    #[inline]
+    pub fn set_italic(&mut self, italic: bool) {
+        self.attributes = self.attributes.with(CharacterAttributes::ITALIC, italic);
+    }
+
+    /// Sets the dim attribute for incoming text.
+    #[inline]
+    pub fn set_dim(&mut self, dim: bool) {
+        self.attributes = self.attributes.with(CharacterAttributes::DIM, dim);
+    }
+
+    /// Replaces all attributes for incoming text at once.
+    #[inline]
+    pub fn set_attributes(&mut self, attributes: CharacterAttributes) {
+        self.attributes = attributes;
+    }
+
+    /// Retrieves the text color incoming text is written with.
+    #[inline]
+    pub fn get_text_color(&self) -> CellColor {
+        self.text_color
+    }
+
+    /// Retrieves the background color incoming text is written with.
+    #[inline]
+    pub fn get_background_color(&self) -> CellColor {
+        self.background_color
+    }
+
+    /// Retrieves the attributes incoming text is written with.
+    #[inline]
+    pub fn get_attributes(&self) -> CharacterAttributes {
+        self.attributes
     }
 
 
     /// Moves the cursor to a specific position.
     #[inline]
     pub fn move_cursor(&mut self, position: Position) {
+        if self.text_cursor != position {
+            self.cursor_dirty = true;
+        }
         self.text_cursor = position;
     }
 
@@ -266,24 +395,37 @@ pub struct TextDisplayDriver {
     }
 
 
+    /// Retrieves the size of the text buffer in characters.
+    #[inline]
+    pub fn get_buffer_size(&self) -> Size {
+        Size::new(self.buffer_width, self.buffer_height)
+    }
+
+
     /// Clears a specific cell in the text buffer.
     pub fn clear_cell(&mut self, row: usize, col: usize) {
         let index = row * self.buffer_width + col;
         self.text_buffer[index] = ScreenChar::new(
             ' ',
             ColorCode::new(self.background_color, self.background_color),
-            CharacterAttributes::new(false, false),
+            CharacterAttributes::empty(),
         );
         self.dirty_buffer[index] = true;
     }
 
     /// Clears the entire text buffer.
     pub fn clear_buffer(&mut self) {
-        self.text_buffer.fill(ScreenChar::new(
+        let empty = ScreenChar::new(
             ' ',
-            ColorCode::new(TextColor::Black, TextColor::Black),
-            CharacterAttributes::new(false, false)
-        ));
+            ColorCode::new(TextColor::Black.into(), TextColor::Black.into()),
+            CharacterAttributes::empty()
+        );
+        for (character, dirty) in self.text_buffer.iter_mut().zip(self.dirty_buffer.iter_mut()) {
+            if *character != empty {
+                *character = empty;
+                *dirty = true;
+            }
+        }
         self.move_cursor(Position::new(0, 0));
     }
 
@@ -293,7 +435,7 @@ pub struct TextDisplayDriver {
         let screen_char = ScreenChar::new(
             character,
             ColorCode::new(self.text_color, self.background_color),
-            CharacterAttributes::new(self.underline, self.strikethrough)
+            self.attributes
         );
 
         for row in 0..self.buffer_height {
@@ -310,7 +452,7 @@ pub struct TextDisplayDriver {
         let screen_char = ScreenChar::new(
             character,
             ColorCode::new(self.text_color, self.background_color),
-            CharacterAttributes::new(self.underline, self.strikethrough)
+            self.attributes
         );
 
         for row in region.position.y..(region.position.y + region.size.height) {
@@ -370,6 +512,50 @@ pub struct TextDisplayDriver {
     /// Toggles the blink attribute for the text cursor.
     pub fn blink(&mut self) {
         self.blink = !self.blink;
+        self.cursor_dirty = true;
+    }
+
+    /// Selects the cells from one position to the other in reading order, wrapping from the end of
+    /// a row to the start of the next, like a terminal does. None clears the selection.
+    pub fn select(&mut self, selection: Option<(Position, Position)>) {
+        let selection = selection.map(|(from, to)| {
+            let (key_from, key_to) = ((from.y, from.x), (to.y, to.x));
+            if key_from <= key_to { (from, to) } else { (to, from) }
+        });
+        if selection == self.selection { return; }
+
+        for (from, to) in [self.selection, selection].into_iter().flatten() {
+            let start = (from.y * self.buffer_width + from.x).min(self.dirty_buffer.len());
+            let end = (to.y * self.buffer_width + to.x + 1).min(self.dirty_buffer.len());
+            self.dirty_buffer[start..end.max(start)].fill(true);
+        }
+        self.selection = selection;
+    }
+
+    /// Retrieves the selected cells, the first one first.
+    pub fn get_selection(&self) -> Option<(Position, Position)> {
+        self.selection
+    }
+
+    /// Returns the text of the selected cells, with a line break between rows and the spaces at
+    /// the end of every row left out.
+    pub fn selected_text(&self) -> Option<String> {
+        let (from, to) = self.selection?;
+        let mut text = String::new();
+        for y in from.y..=to.y.min(self.buffer_height.saturating_sub(1)) {
+            let start = if y == from.y { from.x } else { 0 };
+            let end = if y == to.y { (to.x + 1).min(self.buffer_width) } else { self.buffer_width };
+            let row = &self.text_buffer[y * self.buffer_width..(y + 1) * self.buffer_width];
+            let line: String = row[start.min(end)..end].iter().map(ScreenChar::character).collect();
+            if y > from.y { text.push('\n'); }
+            text.push_str(line.trim_end());
+        }
+        Some(text)
+    }
+
+    /// Retrieves the size of a cell in pixels, zero before the driver is initialized.
+    pub fn get_cell_size(&self) -> Size {
+        self.font.map_or(Size::new(0, 0), |font| font.get_size())
     }
 
     /// Initializes the whole text buffer to be redrawn on the next draw call.
@@ -427,8 +613,11 @@ pub struct TextDisplayDriver {
     #[inline]
     fn write_at(&mut self, character: ScreenChar, position: Position) {
         let index = position.y * self.buffer_width + position.x;
-        self.text_buffer[index] = character;
-        self.dirty_buffer[index] = true;
+        // Writing the same character again doesn't need a redraw
+        if self.text_buffer[index] != character {
+            self.text_buffer[index] = character;
+            self.dirty_buffer[index] = true;
+        }
     }
 
     fn get_text_segments(&mut self) -> Vec<TextSegment> {
@@ -443,65 +632,38 @@ pub struct TextDisplayDriver {
 
             let mut current_text = String::new();
             let mut current_position = Position::new(start_x, start_y);
-            let mut current_text_color = self.text_color;
-            let mut current_background_color = self.background_color;
-            let mut current_underline = false;
-            let mut current_strikethrough = false;
-            let mut last_x = start_x;
+            let mut current_color = ColorCode::new(self.text_color, self.background_color);
+            let mut current_attributes = CharacterAttributes::empty();
 
             for y in start_y..end_y {
                 for x in start_x..end_x {
-                    if x == 0 && last_x != 0 && !current_text.is_empty() {
-                        segments.push(TextSegment::new(
-                            current_text.clone(), current_position,
-                            current_text_color, current_background_color,
-                            current_underline, current_strikethrough
-                        ));
-                        current_text.clear();
+                    let mut screen_char = self.text_buffer[y * self.buffer_width + x];
+                    if self.is_selected(x, y) {
+                        screen_char = ScreenChar::new(
+                            screen_char.character(), screen_char.color().invert(), screen_char.attributes()
+                        );
                     }
 
-                    let index = y * self.buffer_width + x;
-                    let screen_char = self.text_buffer[index];
-                    let char_color = screen_char.color();
-                    let char_attributes = screen_char.attributes();
-
-                    if current_text.is_empty() {
-                        current_text_color = char_color.foreground();
-                        current_background_color = char_color.background();
-                        current_underline = char_attributes.underline();
-                        current_strikethrough = char_attributes.strikethrough();
-                        current_text.push(screen_char.character());
-                        current_position = Position::new(x, y);
-                    } else if (current_text_color != char_color.foreground() || current_background_color != char_color.background() ||
-                        current_underline != char_attributes.underline() || current_strikethrough != char_attributes.strikethrough()) &&
-                        (current_text_color == TextColor::Black && current_background_color == TextColor::Black) {
+                    // A segment is a run of characters in the same style on a single row
+                    if !current_text.is_empty() && (current_color != screen_char.color() || current_attributes != screen_char.attributes()) {
                         segments.push(TextSegment::new(
-                            current_text.clone(), current_position,
-                            current_text_color, current_background_color,
-                            current_underline, current_strikethrough
+                            core::mem::take(&mut current_text), current_position,
+                            current_color.foreground(), current_color.background(), current_attributes
                         ));
-
-                        current_text = screen_char.character().to_string();
+                    }
+                    if current_text.is_empty() {
                         current_position = Position::new(x, y);
-                        current_text_color = char_color.foreground();
-                        current_background_color = char_color.background();
-                        current_underline = char_attributes.underline();
-                        current_strikethrough = char_attributes.strikethrough();
-                    } else {
-                        current_text.push(screen_char.character());
+                        current_color = screen_char.color();
+                        current_attributes = screen_char.attributes();
                     }
-
-                    last_x = x;
+                    current_text.push(screen_char.character());
                 }
                 if !current_text.is_empty() {
                     segments.push(TextSegment::new(
-                        current_text.clone(), current_position,
-                        current_text_color, current_background_color,
-                        current_underline, current_strikethrough
+                        core::mem::take(&mut current_text), current_position,
+                        current_color.foreground(), current_color.background(), current_attributes
                     ));
-                    current_text.clear();
                 }
-                last_x = 0;
             }
         }
 
@@ -553,6 +715,10 @@ pub struct TextDisplayDriver {
         if y < self.buffer_height - 1 { self.dfs(x, y + 1, visited, bounds); }
     }
 
+    fn is_selected(&self, x: usize, y: usize) -> bool {
+        self.selection.map_or(false, |(from, to)| (from.y, from.x) <= (y, x) && (y, x) <= (to.y, to.x))
+    }
+
     fn map_position(&mut self, text_position: Position) -> Position {
         if let Some(font) = self.font.as_ref() {
             let font_size = font.get_size();
@@ -570,24 +736,27 @@ pub struct TextDisplayDriver {
         text_buffer: Vec::new(),
         text_cursor: Position::new(0, 0),
         dirty_buffer: Vec::new(),
+        cursor_dirty: false,
         font: None,
-        text_color: TextColor::White,
-        background_color: TextColor::Black,
-        underline: false,
-        strikethrough: false,
+        text_color: TextColor::White.into(),
+        background_color: TextColor::Black.into(),
+        attributes: CharacterAttributes::empty(),
         blink: false,
+        selection: None,
         buffer_width: 0,
         buffer_height: 0
     } }
-
-    fn draw_all(&mut self) {
+} impl DisplayDriverExt for TextDisplayDriver {
+    fn draw_all(&mut self) -> Result<(), DisplayError> {
         let segments = self.get_text_segments();
 
-        let pre_calculated_positions: Vec<(Cow<'static, str>, Position, Color, Color, bool, bool)> = segments.iter().map(|segment| {
+        let pre_calculated_positions: Vec<(Cow<'static, str>, Position, Color, Color, CharacterAttributes)> = segments.iter().map(|segment| {
             let screen_position = self.map_position(segment.text_position);
-            let text_color: Color = segment.text_color.into();
             let background_color: Color = segment.background_color.into();
-            (segment.text.clone(), screen_position, text_color, background_color, segment.underline, segment.strikethrough)
+            let text_color: Color = if segment.attributes.contains(CharacterAttributes::DIM) {
+                dim(segment.text_color.into(), background_color)
+            } else { segment.text_color.into() };
+            (segment.text.clone(), screen_position, text_color, background_color, segment.attributes)
         }).collect();
 
         let cursor_position = self.map_position(self.text_cursor);
@@ -596,26 +765,29 @@ pub struct TextDisplayDriver {
             Some(display),
             Some(font)
         ) = (self.display.as_mut(), self.font.as_ref()) {
-            let mut display = display.try_lock()
-                .unwrap_or_else(|| panic!("Failed to lock display for drawing!") );
-            let font: MonoFont = (*font).into();
+            let mut display = super::lock_display(display)?;
 
             for (
                 text,
                 screen_position,
                 text_color,
                 background_color,
-                underline,
-                strikethrough
+                attributes
             ) in pre_calculated_positions {
+                let mut segment_font = *font;
+                if attributes.contains(CharacterAttributes::BOLD) { segment_font = segment_font.bold(); }
+                if attributes.contains(CharacterAttributes::ITALIC) { segment_font = segment_font.italic(); }
                 display.draw_text(
                     &text, screen_position,
                     text_color, Some(background_color),
-                    font, underline, strikethrough,
+                    segment_font.into(),
+                    attributes.contains(CharacterAttributes::UNDERLINE),
+                    attributes.contains(CharacterAttributes::STRIKETHROUGH),
                     TextBaseline::Top, TextAlignment::Left, TextLineHeight::Full
-                );
+                )?;
             }
 
+            let font: MonoFont = (*font).into();
             if self.blink {
                 let color_code = ColorCode::new(self.text_color, self.background_color);
 
@@ -624,29 +796,35 @@ pub struct TextDisplayDriver {
                     color_code.invert().foreground().into(), Some(color_code.invert().background().into()),
                     font, false, false,
                     TextBaseline::Top, TextAlignment::Left, TextLineHeight::Full
-                );
+                )?;
             } else {
                 display.draw_char(
                     ' ', cursor_position,
                     self.text_color.into(), Some(self.background_color.into()),
                     font, false, false,
                     TextBaseline::Top, TextAlignment::Left, TextLineHeight::Full
-                );
+                )?;
             }
 
-            display.swap();
-        }
+            display.swap()?;
+            self.dirty_buffer.fill(false);
+            self.cursor_dirty = false;
+            Ok(())
+        } else { Ok(()) }
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.cursor_dirty || self.dirty_buffer.iter().any(|dirty| *dirty)
     }
 
-    fn clear(&mut self, color: Color) {
+    fn clear(&mut self, color: Color) -> Result<(), DisplayError> {
         if let Some(display) = self.display.as_mut() {
-            let mut display = display.try_lock()
-                .unwrap_or_else(|| panic!("Failed to lock display for clearing!") );
-            display.clear(color);
-            display.swap();
-        }
+            let mut display = super::lock_display(display)?;
+            display.clear(color)?;
+            display.swap()
+        } else { Ok(()) }
     }
-} impl DisplayDriver for TextDisplayDriver {
+
     fn activate(&mut self, display: Arc<Mutex<dyn DisplayApi + Send>>) {
         self.display = Some(display);
     }
@@ -654,4 +832,14 @@ pub struct TextDisplayDriver {
     fn deactivate(&mut self) {
         self.display = None;
     }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Returns the color halfway between the text and the background color.
+fn dim(text: Color, background: Color) -> Color {
+    let mix = |text: u8, background: u8| ((text as u16 + background as u16) / 2) as u8;
+    Color::with_alpha(mix(text.red, background.red), mix(text.green, background.green), mix(text.blue, background.blue), text.alpha)
 }
\ No newline at end of file
@@ -0,0 +1,320 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::api::display::{Position, Region, Size};
+use crate::api::keyboard::{KeyCode, Modifiers};
+use crate::drivers::display::layout::{FieldAlignment, TextLayout};
+use crate::drivers::display::text::{CellColor, CharacterAttributes, TextColor, TextDisplayDriver};
+
+/// The colors and attributes a widget is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub foreground: CellColor,
+    pub background: CellColor,
+    pub attributes: CharacterAttributes
+} #[allow(dead_code)] impl Style {
+    pub fn new(foreground: impl Into<CellColor>, background: impl Into<CellColor>) -> Self { Self {
+        foreground: foreground.into(),
+        background: background.into(),
+        attributes: CharacterAttributes::empty()
+    } }
+
+    pub fn with(self, attributes: CharacterAttributes) -> Self {
+        Self { attributes: self.attributes.with(attributes, true), ..self }
+    }
+
+    /// Swaps the text and the background color.
+    pub fn inverted(self) -> Self {
+        Self { foreground: self.background, background: self.foreground, ..self }
+    }
+}
+
+/// The styles the widgets of a frame are drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub normal: Style,
+    /// Title bars and headers.
+    pub header: Style,
+    /// Panel borders and their titles.
+    pub border: Style,
+    /// The focused widget, or the selected item of the focused list.
+    pub focused: Style,
+    /// The selected item of a list that doesn't have the focus.
+    pub selected: Style
+} impl Default for Theme {
+    fn default() -> Self { Self {
+        normal: Style::new(TextColor::White, TextColor::Black),
+        header: Style::new(TextColor::Black, TextColor::Silver),
+        border: Style::new(TextColor::Silver, TextColor::Black),
+        focused: Style::new(TextColor::Black, TextColor::Aqua),
+        selected: Style::new(TextColor::Black, TextColor::Gray)
+    } }
+}
+
+/// The part of a TUI that lasts from one frame to the next: which widget has the focus and the
+/// keys it still has to handle. Focusable widgets are counted in the order they are drawn, so a
+/// screen has to draw them in the same order every frame for the focus to stay where it is.
+#[derive(Debug, Default)]
+pub struct TuiState {
+    focus: usize,
+    /// Focusable widgets drawn in the last frame.
+    focusable: usize,
+    /// Keys for the focused widget, handled when it is drawn next.
+    keys: Vec<KeyCode>,
+    changed: bool
+} #[allow(dead_code)] impl TuiState {
+    /// Handles a pressed key. Tab and shift+tab move the focus to the next and previous widget,
+    /// the keys widgets react to are kept for the focused one. Returns whether the key was
+    /// consumed, which it isn't if the last frame had nothing to focus.
+    pub fn on_key(&mut self, code: KeyCode, modifiers: Modifiers) -> bool {
+        if self.focusable == 0 { return false; }
+        match code {
+            KeyCode::Tab if modifiers.shift() => self.focus = (self.focus + self.focusable - 1) % self.focusable,
+            KeyCode::Tab => self.focus = (self.focus + 1) % self.focusable,
+            KeyCode::ArrowUp | KeyCode::ArrowDown | KeyCode::PageUp | KeyCode::PageDown
+            | KeyCode::Home | KeyCode::End | KeyCode::Enter | KeyCode::KeypadEnter
+            | KeyCode::Space => self.keys.push(code),
+            _ => return false
+        }
+        self.changed = true;
+        true
+    }
+
+    /// Returns the number of the focused widget.
+    pub fn focus(&self) -> usize {
+        self.focus
+    }
+
+    pub fn set_focus(&mut self, focus: usize) {
+        self.focus = focus;
+        self.changed = true;
+    }
+
+    /// Returns whether input changed something since the last frame, so it has to be drawn again.
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    /// Makes the next frame get drawn even without input, like when what it shows changed.
+    pub fn invalidate(&mut self) {
+        self.changed = true;
+    }
+}
+
+/// Draws a frame of an immediate-mode TUI into a text driver. Every widget is drawn into the
+/// region it is given as soon as it is called and reports the input it got right away, so a
+/// screen is just a function drawing its widgets from its own state. Regions are clipped to the
+/// text buffer. The colors, attributes and cursor of the driver are restored when the frame is
+/// dropped.
+pub struct Frame<'a> {
+    driver: &'a mut TextDisplayDriver,
+    state: &'a mut TuiState,
+    theme: Theme,
+    /// The number the next focusable widget gets.
+    next_focus: usize,
+    saved: (CellColor, CellColor, CharacterAttributes, Position)
+} #[allow(dead_code)] impl<'a> Frame<'a> {
+    pub fn new(driver: &'a mut TextDisplayDriver, state: &'a mut TuiState) -> Self {
+        let saved = (
+            driver.get_text_color(), driver.get_background_color(),
+            driver.get_attributes(), driver.get_cursor_position()
+        );
+        Self { driver, state, theme: Theme::default(), next_focus: 0, saved }
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    pub fn theme(&self) -> Theme {
+        self.theme
+    }
+
+    /// Returns the region of the whole text buffer.
+    pub fn area(&self) -> Region {
+        Region::new(Position::new(0, 0), self.driver.get_buffer_size())
+    }
+
+    /// Gives access to the driver, for drawing anything the widgets don't cover.
+    pub fn driver(&mut self) -> &mut TextDisplayDriver {
+        self.driver
+    }
+
+    /// Fills the region with spaces in the given style.
+    pub fn fill(&mut self, region: Region, style: Style) {
+        let Some(region) = self.clip(region) else { return; };
+        self.apply(style);
+        self.driver.fill_region(region, ' ');
+    }
+
+    /// Writes a single line into the first row of the region, padded to its width.
+    pub fn label(&mut self, region: Region, text: &str, style: Style) {
+        self.label_aligned(region, text, style, FieldAlignment::Left);
+    }
+
+    pub fn label_aligned(&mut self, region: Region, text: &str, style: Style, alignment: FieldAlignment) {
+        let Some(region) = self.clip(region) else { return; };
+        self.apply(style);
+        let mut layout = TextLayout::new(region);
+        layout.set_alignment(alignment);
+        layout.write_row(self.driver, 0, text);
+    }
+
+    /// Writes the text wrapped at word boundaries into the region and clears the rows below it.
+    /// Returns how many rows the text took up.
+    pub fn text(&mut self, region: Region, text: &str, style: Style) -> usize {
+        let Some(region) = self.clip(region) else { return 0; };
+        self.apply(style);
+        let layout = TextLayout::new(region);
+        let rows = layout.write(self.driver, text);
+        for row in rows..region.size.height {
+            layout.write_row(self.driver, row, "");
+        }
+        rows
+    }
+
+    /// Draws a border around the region with the title in its top edge. Returns the region
+    /// inside the border, which is left as it is.
+    pub fn panel(&mut self, region: Region, title: &str) -> Region {
+        let Some(region) = self.clip(region) else { return Region::new(Position::new(0, 0), Size::new(0, 0)); };
+        let Size { width, height } = region.size;
+        let Position { x, y } = region.position;
+        self.apply(self.theme.border);
+
+        let mut top: String = String::from("+-");
+        top.extend(title.chars().take(width.saturating_sub(4)));
+        while top.chars().count() < width.saturating_sub(1) { top.push('-'); }
+        top.push('+');
+        self.write_at(Position::new(x, y), top.chars().take(width));
+        if height > 1 {
+            let bottom = core::iter::once('+').chain(core::iter::repeat('-').take(width.saturating_sub(2)))
+                .chain(core::iter::once('+'));
+            self.write_at(Position::new(x, y + height - 1), bottom.take(width));
+        }
+        for row in 1..height.saturating_sub(1) {
+            self.write_at(Position::new(x, y + row), core::iter::once('|'));
+            if width > 1 { self.write_at(Position::new(x + width - 1, y + row), core::iter::once('|')); }
+        }
+
+        Region::new(
+            Position::new(x + 1, y + 1),
+            Size::new(width.saturating_sub(2), height.saturating_sub(2))
+        )
+    }
+
+    /// Draws a bar in the first row of the region filled to the share `value` is of `maximum`,
+    /// with the percentage in its middle.
+    pub fn progress(&mut self, region: Region, value: u64, maximum: u64, style: Style) {
+        let Some(region) = self.clip(region) else { return; };
+        let width = region.size.width;
+        let percent = if maximum == 0 { 0 } else { value.min(maximum) * 100 / maximum };
+        let filled = if maximum == 0 { 0 } else {
+            (value.min(maximum) as u128 * width as u128 / maximum as u128) as usize
+        };
+
+        let caption = alloc::format!("{}%", percent);
+        let start = width.saturating_sub(caption.len()) / 2;
+        let mut row: Vec<char> = core::iter::repeat(' ').take(width).collect();
+        for (index, character) in caption.chars().enumerate().take(width) {
+            row[start + index] = character;
+        }
+
+        self.apply(style.inverted());
+        self.write_at(region.position, row[..filled].iter().copied());
+        self.apply(style);
+        self.write_at(Position::new(region.position.x + filled, region.position.y), row[filled..].iter().copied());
+    }
+
+    /// Draws the items one per row, scrolled so the selected one is visible. When focused, the
+    /// arrows, page up/down, home and end move the selection and enter activates the selected
+    /// item. Returns whether it was activated.
+    pub fn list<S: AsRef<str>>(&mut self, region: Region, items: &[S], selected: &mut usize) -> bool {
+        let (focused, keys) = self.focusable();
+        let Some(region) = self.clip(region) else { return false; };
+        let rows = region.size.height;
+        let last = items.len().saturating_sub(1);
+
+        let mut activated = false;
+        for key in keys {
+            *selected = match key {
+                KeyCode::ArrowUp => selected.saturating_sub(1),
+                KeyCode::ArrowDown => *selected + 1,
+                KeyCode::PageUp => selected.saturating_sub(rows),
+                KeyCode::PageDown => *selected + rows,
+                KeyCode::Home => 0,
+                KeyCode::End => last,
+                KeyCode::Enter | KeyCode::KeypadEnter => { activated = !items.is_empty(); *selected },
+                _ => *selected
+            };
+        }
+        *selected = (*selected).min(last);
+
+        let offset = (*selected + 1).saturating_sub(rows);
+        let layout = TextLayout::new(region);
+        for row in 0..rows {
+            let index = offset + row;
+            let style = match index == *selected && !items.is_empty() {
+                true if focused => self.theme.focused,
+                true => self.theme.selected,
+                false => self.theme.normal
+            };
+            self.apply(style);
+            layout.write_row(self.driver, row, items.get(index).map_or("", |item| item.as_ref()));
+        }
+        activated
+    }
+
+    /// Draws a checkbox with the label in the first row of the region. When focused, space and
+    /// enter flip the value. Returns whether it was flipped.
+    pub fn toggle(&mut self, region: Region, label: &str, value: &mut bool) -> bool {
+        let (focused, keys) = self.focusable();
+        let flips = keys.iter().filter(|key| matches!(
+            key, KeyCode::Space | KeyCode::Enter | KeyCode::KeypadEnter
+        )).count();
+        if flips % 2 == 1 { *value = !*value; }
+
+        let style = if focused { self.theme.focused } else { self.theme.normal };
+        let text = alloc::format!("[{}] {}", if *value { 'x' } else { ' ' }, label);
+        self.label(region, text.as_str(), style);
+        flips % 2 == 1
+    }
+
+    /// Counts a focusable widget. Returns whether it has the focus and, if so, the keys it has
+    /// to handle.
+    fn focusable(&mut self) -> (bool, Vec<KeyCode>) {
+        let number = self.next_focus;
+        self.next_focus += 1;
+        if number == self.state.focus {
+            (true, core::mem::take(&mut self.state.keys))
+        } else { (false, Vec::new()) }
+    }
+
+    fn clip(&self, region: Region) -> Option<Region> {
+        region.intersection(self.area())
+    }
+
+    fn apply(&mut self, style: Style) {
+        self.driver.set_text_color(style.foreground);
+        self.driver.set_background_color(style.background);
+        self.driver.set_attributes(style.attributes);
+    }
+
+    fn write_at(&mut self, position: Position, text: impl Iterator<Item = char>) {
+        self.driver.move_cursor(position);
+        for character in text {
+            self.driver.write_char(character);
+        }
+    }
+} impl Drop for Frame<'_> {
+    fn drop(&mut self) {
+        self.state.focusable = self.next_focus;
+        self.state.focus = self.state.focus.min(self.next_focus.saturating_sub(1));
+        self.state.keys.clear();
+        self.state.changed = false;
+
+        let (foreground, background, attributes, cursor) = self.saved;
+        self.driver.set_text_color(foreground);
+        self.driver.set_background_color(background);
+        self.driver.set_attributes(attributes);
+        self.driver.move_cursor(cursor);
+    }
+}
\ No newline at end of file
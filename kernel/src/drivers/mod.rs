@@ -1 +1,3 @@
-pub mod display;
\ No newline at end of file
+pub mod display;
+pub mod timer;
+pub mod vbe;
\ No newline at end of file
@@ -0,0 +1,41 @@
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+pub mod pit;
+
+/// The tick rate the kernel starts with, a tick every millisecond.
+pub static DEFAULT_TICK_HZ: u64 = 1000;
+
+static TICK_HZ: AtomicU64 = AtomicU64::new(DEFAULT_TICK_HZ);
+
+/// Describes a timer interrupt: how many ticks passed since the last one and whether the CPU was
+/// idle (halted) while waiting for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerTick {
+    pub ticks: u64,
+    pub idle: bool
+}
+
+/// Returns how many timer ticks there are in a second. Timer events and deadlines count in these
+/// ticks; when the rate changes an `Event::TickRateChanged` tells the ones that convert them.
+pub fn tick_hz() -> u64 {
+    TICK_HZ.load(Ordering::SeqCst)
+}
+
+fn set_tick_hz(hz: u64) {
+    TICK_HZ.store(hz, Ordering::SeqCst);
+}
+
+/// Puts the CPU to sleep until the next interrupt (or until `wake_flag` is set), letting
+/// whichever timer drives the ticks sleep through the ones before `deadline` (in ticks).
+///
+/// Has to be called with interrupts disabled (so no event can slip in between checking for work
+/// and halting) and returns with interrupts enabled.
+pub fn idle(deadline: Option<u64>, wake_flag: &AtomicBool) {
+    if crate::internal::apic::timer_active() {
+        crate::internal::apic::idle(deadline, wake_flag)
+    } else if crate::internal::hpet::timer_active() {
+        crate::internal::hpet::idle(deadline, wake_flag)
+    } else {
+        pit::idle(deadline, wake_flag)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,174 @@
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use spin::Once;
+use crate::api::error::KernelError;
+use crate::api::event::Event;
+use crate::drivers::timer::TimerTick;
+use crate::internal::ioport::IoPortRange;
+
+static PIT_PORT: u16 = 0x40;
+static PIT_PORT_COUNT: u16 = 4;
+static COMMAND_OFFSET: u16 = 3;
+/// Access the count as low byte followed by high byte.
+static ACCESS_LOW_HIGH: u8 = 0b0011_0000;
+
+/// The frequency of the PIT input clock, every channel counts down at this rate.
+pub static PIT_FREQUENCY: u64 = 1193182;
+/// The slowest and fastest tick rates the timer can be set to. Slower doesn't fit a 16-bit
+/// divisor, faster would leave little time for anything but timer interrupts.
+pub static MIN_TICK_HZ: u64 = 19;
+pub static MAX_TICK_HZ: u64 = 10_000;
+
+static PORTS: Once<IoPortRange> = Once::new();
+static MODE: AtomicU8 = AtomicU8::new(PitMode::Periodic as u8);
+static DIVISOR: AtomicU64 = AtomicU64::new(PIT_FREQUENCY / super::DEFAULT_TICK_HZ);
+static TIMER_INTERVAL: AtomicU64 = AtomicU64::new(1);
+static TIMER_FIRED: AtomicBool = AtomicBool::new(false);
+static IDLE: AtomicBool = AtomicBool::new(false);
+
+/// A counter of the PIT. Channel 0 drives the timer interrupt, channel 2 is gated through the
+/// speaker port and used to calibrate the time stamp counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PitChannel {
+    Channel0 = 0,
+    Channel2 = 2
+}
+
+/// How a channel counts down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PitMode {
+    /// Interrupt on terminal count: fires once when the count runs out, until it is programmed again.
+    OneShot = 0,
+    /// Rate generator: fires every time the count runs out and starts over.
+    Periodic = 2
+} impl PitMode {
+    fn from_u8(value: u8) -> Self {
+        if value == Self::OneShot as u8 { Self::OneShot } else { Self::Periodic }
+    }
+}
+
+/// Returns the ports of the PIT, claiming them on first use.
+fn ports() -> &'static IoPortRange {
+    PORTS.call_once(|| crate::internal::ioport::claim(PIT_PORT, PIT_PORT_COUNT, "PIT")
+        .unwrap_or_else(|err| panic!("Failed to claim the PIT ports: {}", err)))
+}
+
+/// Starts the channel counting down from the divisor (0 stands for 65536) in the mode.
+pub fn program(channel: PitChannel, mode: PitMode, divisor: u16) {
+    let ports = ports();
+    ports.write(COMMAND_OFFSET, (channel as u8) << 6 | ACCESS_LOW_HIGH | (mode as u8) << 1);
+    ports.write(channel as u16, (divisor & 0xFF) as u8);
+    ports.write(channel as u16, (divisor >> 8) as u8);
+}
+
+/// Reads how many input cycles are left until the channel's count runs out.
+pub fn read_count(channel: PitChannel) -> u16 {
+    let ports = ports();
+    // The latch command freezes the count until it is read
+    ports.write(COMMAND_OFFSET, (channel as u8) << 6);
+    let low_byte = ports.read::<u8>(channel as u16) as u16;
+    let high_byte = ports.read::<u8>(channel as u16) as u16;
+    (high_byte << 8) | low_byte
+}
+
+/// Starts the timer interrupt at the current tick rate.
+pub fn init() {
+    crate::internal::idt::without_interrupts(|| program_timer(1));
+}
+
+/// Returns the longest interval (in ticks) the timer can be programmed to at the current tick rate.
+pub fn max_interval() -> u64 {
+    0xFFFF / DIVISOR.load(Ordering::SeqCst)
+}
+
+/// Returns how the timer channel counts down.
+pub fn mode() -> PitMode {
+    PitMode::from_u8(MODE.load(Ordering::SeqCst))
+}
+
+/// Returns whether the PIT drives the timer, it only does if neither the local APIC nor the HPET can.
+pub fn drives_timer() -> bool {
+    !crate::internal::apic::timer_active() && !crate::internal::hpet::timer_active()
+}
+
+/// Changes the tick rate, and with it how long a tick is everywhere. Only possible while the PIT
+/// drives the timer, the local APIC and HPET timers keep the rate they were set up with.
+pub fn set_frequency(hz: u64) -> Result<(), KernelError> {
+    if !drives_timer() {
+        return Err(KernelError::InvalidConfiguration("The PIT doesn't drive the timer"));
+    }
+    if !(MIN_TICK_HZ..=MAX_TICK_HZ).contains(&hz) {
+        return Err(KernelError::InvalidConfiguration("Tick rate out of range"));
+    }
+
+    crate::internal::idt::without_interrupts(|| {
+        DIVISOR.store(PIT_FREQUENCY / hz, Ordering::SeqCst);
+        super::set_tick_hz(hz);
+        program_timer(1);
+    });
+    crate::api::event::EventDispatcher::global().push(Event::TickRateChanged(hz));
+    Ok(())
+}
+
+/// Switches the timer channel between firing periodically and firing once for every interval it
+/// is armed with, the tick rate stays the same.
+pub fn set_mode(mode: PitMode) -> Result<(), KernelError> {
+    if !drives_timer() {
+        return Err(KernelError::InvalidConfiguration("The PIT doesn't drive the timer"));
+    }
+    crate::internal::idt::without_interrupts(|| {
+        MODE.store(mode as u8, Ordering::SeqCst);
+        program_timer(TIMER_INTERVAL.load(Ordering::SeqCst));
+    });
+    Ok(())
+}
+
+/// Programs the timer channel to fire after the amount of ticks, clamped to `1..=max_interval()`.
+fn program_timer(ticks: u64) {
+    let ticks = ticks.clamp(1, max_interval());
+    program(PitChannel::Channel0, mode(), (DIVISOR.load(Ordering::SeqCst) * ticks) as u16);
+    TIMER_INTERVAL.store(ticks, Ordering::SeqCst);
+}
+
+/// Gets called by the timer interrupt handler to find out what the interrupt stands for. In
+/// one-shot mode the timer is armed again for the same interval.
+pub fn timer_tick() -> TimerTick {
+    TIMER_FIRED.store(true, Ordering::SeqCst);
+    let ticks = TIMER_INTERVAL.load(Ordering::SeqCst);
+    if mode() == PitMode::OneShot {
+        program_timer(ticks);
+    }
+    TimerTick { ticks, idle: IDLE.load(Ordering::SeqCst) }
+}
+
+/// Like `timer::idle`, for when the PIT drives the timer. If `deadline` is further away than the
+/// next tick, the timer is slowed down so it doesn't wake the CPU for nothing.
+pub fn idle(deadline: Option<u64>, wake_flag: &AtomicBool) {
+    let ticks = deadline.unwrap_or(u64::MAX).clamp(1, max_interval());
+    if ticks > 1 { program_timer(ticks); }
+
+    TIMER_FIRED.store(false, Ordering::SeqCst);
+    IDLE.store(true, Ordering::SeqCst);
+    crate::internal::cpuidle::enter(ticks, wake_flag);
+    x86_64::instructions::interrupts::disable();
+    IDLE.store(false, Ordering::SeqCst);
+
+    if ticks > 1 {
+        // Woken up early by some other interrupt, so account for the ticks that already passed
+        // before going back to the regular tick rate.
+        if !TIMER_FIRED.load(Ordering::SeqCst) {
+            let divisor = DIVISOR.load(Ordering::SeqCst);
+            let elapsed = (divisor * ticks).saturating_sub(read_count(PitChannel::Channel0) as u64) / divisor;
+            if elapsed > 0 {
+                crate::api::event::EventDispatcher::global().push(Event::Timer(TimerTick {
+                    ticks: elapsed, idle: true
+                }));
+            }
+        }
+
+        program_timer(1);
+    }
+
+    x86_64::instructions::interrupts::enable();
+}
\ No newline at end of file
@@ -0,0 +1,171 @@
+use spin::{Mutex, Once};
+use crate::api::error::KernelError;
+use crate::internal::framebuffer::{ChannelMask, FrameBufferInfo, PixelFormat};
+use crate::internal::ioport::IoPortRange;
+
+/// The index port, followed by the 16-bit data port at 0x1CF.
+static DISPI_PORT: u16 = 0x1CE;
+static DISPI_PORT_COUNT: u16 = 3;
+static DATA_OFFSET: u16 = 1;
+
+static INDEX_ID: u16 = 0x0;
+static INDEX_XRES: u16 = 0x1;
+static INDEX_YRES: u16 = 0x2;
+static INDEX_BPP: u16 = 0x3;
+static INDEX_ENABLE: u16 = 0x4;
+static INDEX_VIRT_WIDTH: u16 = 0x6;
+static INDEX_X_OFFSET: u16 = 0x8;
+static INDEX_Y_OFFSET: u16 = 0x9;
+static INDEX_VIDEO_MEMORY_64K: u16 = 0xA;
+
+/// The interface versions, the linear frame buffer and 32 bpp came with the third one.
+static ID_MIN: u16 = 0xB0C2;
+static ID_MAX: u16 = 0xB0C5;
+/// The first version that reports the size of the video memory.
+static ID_VIDEO_MEMORY: u16 = 0xB0C5;
+
+static ENABLED: u16 = 0x01;
+/// Makes the resolution registers return the largest supported values while set.
+static GET_CAPS: u16 = 0x02;
+static LFB_ENABLED: u16 = 0x40;
+/// Keeps the video memory from being cleared on a mode switch, the next frame overwrites it anyway.
+static NO_CLEAR_MEMORY: u16 = 0x80;
+
+static VBE: Once<Mutex<BochsVbe>> = Once::new();
+
+/// A resolution and bit depth of the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoMode {
+    pub width: usize,
+    pub height: usize,
+    /// Bits per pixel, 15, 16, 24 or 32.
+    pub bpp: usize
+} impl VideoMode {
+    pub const fn new(width: usize, height: usize, bpp: usize) -> Self { Self {
+        width, height, bpp
+    } }
+
+    fn pixel_format(&self) -> Option<PixelFormat> {
+        match self.bpp {
+            15 => Some(PixelFormat::from_masks(ChannelMask::new(10, 5), ChannelMask::new(5, 5), ChannelMask::new(0, 5))),
+            16 => Some(PixelFormat::from_masks(ChannelMask::new(11, 5), ChannelMask::new(5, 6), ChannelMask::new(0, 5))),
+            24 | 32 => Some(PixelFormat::Bgr),
+            _ => None
+        }
+    }
+} impl core::fmt::Display for VideoMode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}x{}x{}", self.width, self.height, self.bpp)
+    }
+}
+
+/// The display interface (DISPI) of the Bochs and QEMU `std` VGA devices. It switches the
+/// resolution of the linear frame buffer at runtime, whatever mode the bootloader set up.
+struct BochsVbe {
+    ports: IoPortRange,
+    id: u16,
+    max: VideoMode,
+    /// The size of the video memory in bytes, None if the device is too old to report it.
+    video_memory: Option<usize>
+} impl BochsVbe {
+    fn read(&self, index: u16) -> u16 {
+        self.ports.write(0, index);
+        self.ports.read(DATA_OFFSET)
+    }
+
+    fn write(&self, index: u16, value: u16) {
+        self.ports.write(0, index);
+        self.ports.write(DATA_OFFSET, value);
+    }
+
+    /// Reads the largest resolution and bit depth the device supports.
+    fn read_caps(&self) -> VideoMode {
+        let enable = self.read(INDEX_ENABLE);
+        self.write(INDEX_ENABLE, enable | GET_CAPS);
+        let max = VideoMode::new(
+            self.read(INDEX_XRES) as usize, self.read(INDEX_YRES) as usize, self.read(INDEX_BPP) as usize
+        );
+        self.write(INDEX_ENABLE, enable);
+        max
+    }
+
+    fn mode(&self) -> VideoMode {
+        VideoMode::new(self.read(INDEX_XRES) as usize, self.read(INDEX_YRES) as usize, self.read(INDEX_BPP) as usize)
+    }
+
+    /// Switches to the mode and returns the layout of the frame buffer in it.
+    fn set_mode(&self, mode: VideoMode) -> FrameBufferInfo {
+        self.write(INDEX_ENABLE, 0);
+        self.write(INDEX_XRES, mode.width as u16);
+        self.write(INDEX_YRES, mode.height as u16);
+        self.write(INDEX_BPP, mode.bpp as u16);
+        self.write(INDEX_X_OFFSET, 0);
+        self.write(INDEX_Y_OFFSET, 0);
+        self.write(INDEX_ENABLE, ENABLED | LFB_ENABLED | NO_CLEAR_MEMORY);
+
+        let bytes_per_pixel = (mode.bpp + 7) / 8;
+        // The device may pad the lines, the virtual width is the real one
+        let stride = (self.read(INDEX_VIRT_WIDTH) as usize).max(mode.width);
+        FrameBufferInfo {
+            byte_len: stride * mode.height * bytes_per_pixel,
+            width: mode.width,
+            height: mode.height,
+            pixel_format: mode.pixel_format().unwrap_or(PixelFormat::Unknown),
+            bytes_per_pixel,
+            stride
+        }
+    }
+}
+
+/// Looks for the display interface, returns whether there is one.
+pub fn init() -> Result<bool, KernelError> {
+    let ports = crate::internal::ioport::claim(DISPI_PORT, DISPI_PORT_COUNT, "Bochs VBE")?;
+    ports.write(0, INDEX_ID);
+    let id = ports.read::<u16>(DATA_OFFSET);
+    if !(ID_MIN..=ID_MAX).contains(&id) {
+        crate::internal::ioport::release(ports);
+        return Ok(false);
+    }
+
+    let mut vbe = BochsVbe { ports, id, max: VideoMode::new(0, 0, 0), video_memory: None };
+    vbe.max = vbe.read_caps();
+    vbe.video_memory = (id >= ID_VIDEO_MEMORY).then(|| vbe.read(INDEX_VIDEO_MEMORY_64K) as usize * 64 * 1024);
+    log::info!("Bochs VBE display interface {:#06X} found, supporting up to {}.", vbe.id, vbe.max);
+    VBE.call_once(|| Mutex::new(vbe));
+    Ok(true)
+}
+
+/// Returns how many bytes of video memory the display has, None without the display interface or
+/// if it doesn't tell.
+pub fn video_memory() -> Option<usize> {
+    VBE.get().and_then(|vbe| vbe.lock().video_memory)
+}
+
+/// Returns the largest resolution and bit depth the display supports.
+pub fn max_mode() -> Result<VideoMode, KernelError> {
+    Ok(VBE.get().ok_or(KernelError::HardwareMissing("Bochs VBE display"))?.lock().max)
+}
+
+/// Switches the display to the mode, which has to fit into the mapped video memory, and hands the
+/// new layout to the frame buffer. Everything drawing to the frame buffer has to be recreated.
+pub fn set_mode(mode: VideoMode) -> Result<FrameBufferInfo, KernelError> {
+    let vbe = VBE.get().ok_or(KernelError::HardwareMissing("Bochs VBE display"))?.lock();
+    if mode.pixel_format().is_none() {
+        return Err(KernelError::InvalidConfiguration("Bit depth must be 15, 16, 24 or 32"));
+    }
+    if mode.width == 0 || mode.height == 0 || mode.width > vbe.max.width || mode.height > vbe.max.height
+        || mode.bpp > vbe.max.bpp {
+        return Err(KernelError::InvalidConfiguration("Resolution not supported by the display"));
+    }
+    if mode.width * mode.height * ((mode.bpp + 7) / 8) > crate::internal::framebuffer::capacity() {
+        return Err(KernelError::InvalidConfiguration("Resolution needs more video memory than is mapped"));
+    }
+
+    let previous = vbe.mode();
+    let info = vbe.set_mode(mode);
+    if let Err(err) = crate::internal::framebuffer::set_info(info) {
+        vbe.set_mode(previous);
+        return Err(err);
+    }
+    Ok(info)
+}
\ No newline at end of file
@@ -1,17 +1,31 @@
 use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::fmt::{Display, Formatter};
 use core::ptr::NonNull;
-use acpi::{AcpiError, AcpiHandler, HpetInfo, InterruptModel, PciConfigRegions, PhysicalMapping, PlatformInfo, PowerProfile};
+use acpi::{AcpiError, AcpiHandler, AmlTable, HpetInfo, InterruptModel, PciConfigRegions, PhysicalMapping, PlatformInfo, PowerProfile};
+use acpi::address::AddressSpace;
 use acpi::fadt::Fadt;
 use acpi::madt::Madt;
 use acpi::platform::{PmTimer, ProcessorInfo};
-use aml::{AmlContext, AmlName, AmlValue, DebugVerbosity};
+use acpi::sdt::Signature;
+use aml::{AmlContext, AmlName, AmlValue, DebugVerbosity, LevelType};
+use aml::value::{AmlType, Args};
+use spin::{Mutex, MutexGuard};
 use x86_64::{PhysAddr, VirtAddr};
-use x86_64::instructions::port::Port;
+use crate::api::error::KernelError;
 use crate::internal::aml::AmlHandler;
+use crate::internal::ioport::IoPortRange;
 
-static mut PM1A_CNT_BLK: u32 = 0;
+static PM1_CONTROL_PORT_COUNT: u16 = 2;
 static mut SLP_TYPA: u16 = 0;
 static SLP_LEN: u16 = 1 << 13;
+/// Where the OEM table ID is in the header every system description table starts with.
+static SDT_HEADER_SIZE: usize = 36;
+static OEM_TABLE_ID_OFFSET: usize = 16;
+const OEM_TABLE_ID_LENGTH: usize = 8;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PlatformType {
@@ -66,16 +80,84 @@ pub struct PlatformInfoWrapper<'a>(PlatformInfo<'a, alloc::alloc::Global>);
 pub struct Acpi {
     physical_memory_offset: VirtAddr,
     internal_tables: acpi::AcpiTables<MainAcpiHandler>,
-    aml_handler: AmlHandler
+    aml_handler: AmlHandler,
+    /// The PM1a control register the sleep state is written to, None if it couldn't be claimed.
+    pm1a_control: Option<IoPortRange>,
+    /// The namespace of the DSDT and the SSDTs, parsed once so its objects can be evaluated later
+    /// on. None if the DSDT failed to parse.
+    namespace: Option<AcpiNamespace>
 } #[allow(dead_code)] impl Acpi {
     pub fn new(
         physical_memory_offset: VirtAddr,
         internal_tables: acpi::AcpiTables<MainAcpiHandler>
-    ) -> Self { Self {
-        physical_memory_offset,
-        internal_tables,
-        aml_handler: AmlHandler::new()
-    } }
+    ) -> Self {
+        let mut acpi = Self {
+            physical_memory_offset,
+            internal_tables,
+            aml_handler: AmlHandler::new(),
+            pm1a_control: None,
+            namespace: None
+        };
+        acpi.pm1a_control = acpi.claim_pm1a_control();
+        acpi.namespace = acpi.parse_namespace();
+        acpi
+    }
+
+    /// Parses the DSDT and then the SSDTs into one namespace. A table that fails to parse is
+    /// reported and the others are still parsed, the objects it defined before failing stay.
+    fn parse_namespace(&self) -> Option<AcpiNamespace> {
+        let dsdt = self.dsdt().ok()?;
+        let mut aml = AmlContext::new(Box::new(self.aml_handler.clone()), DebugVerbosity::None);
+        let dsdt_parsed = aml.parse_table(dsdt)
+            .inspect_err(|err| log::warn!("Failed to parse the DSDT: {:?}", err))
+            .is_ok();
+
+        let (mut parsed, mut count) = (0, 0);
+        for ssdt in self.internal_tables.ssdts() {
+            count += 1;
+            let id = self.table_id(&ssdt);
+            match aml.parse_table(self.aml_stream(&ssdt)) {
+                Ok(()) => {
+                    parsed += 1;
+                    log::debug!("Parsed SSDT '{}' with {} bytes of AML.", id, ssdt.length);
+                }, Err(err) => log::warn!("Failed to parse SSDT '{}': {:?}", id, err)
+            }
+        }
+        log::info!(
+            "Parsed the ACPI namespace from the DSDT{} and {} of {} SSDTs.",
+            if dsdt_parsed { "" } else { " (partially)" }, parsed, count
+        );
+        let ec_regions = crate::internal::aml::redirect_ec_regions(&mut aml);
+        if ec_regions > 0 {
+            log::debug!("Redirected {} embedded controller regions to the EC driver.", ec_regions);
+        }
+        Some(AcpiNamespace { aml: Arc::new(Mutex::new(aml)) })
+    }
+
+    /// Returns the OEM table ID from the header in front of the table's AML code, which tells
+    /// the SSDTs apart as they all have the same signature.
+    fn table_id(&self, table: &AmlTable) -> String {
+        let phys_addr = PhysAddr::new((table.address - SDT_HEADER_SIZE + OEM_TABLE_ID_OFFSET) as u64);
+        let virt_addr = crate::internal::memory::phys_to_virt(self.physical_memory_offset, phys_addr);
+        let id = unsafe { core::slice::from_raw_parts(virt_addr.as_ptr::<u8>(), OEM_TABLE_ID_LENGTH) };
+        String::from_utf8_lossy(id).trim_end_matches([' ', '\0']).to_string()
+    }
+
+    /// Returns the parsed ACPI namespace, shared with whoever evaluates objects in it.
+    pub fn namespace(&self) -> Option<AcpiNamespace> {
+        self.namespace.clone()
+    }
+
+    fn claim_pm1a_control(&self) -> Option<IoPortRange> {
+        let block = self.fadt().ok()?.pm1a_control_block().ok()?;
+        if block.address_space != AddressSpace::SystemIo {
+            log::warn!("ACPI PM1a control block is not in I/O space, shutdown is not supported.");
+            return None;
+        }
+        crate::internal::ioport::claim(block.address as u16, PM1_CONTROL_PORT_COUNT, "ACPI PM1a control")
+            .inspect_err(|err| log::warn!("Failed to claim the ACPI PM1a control block: {}", err))
+            .ok()
+    }
 
     pub fn platform_info(&self) -> Result<PlatformInfoWrapper, AcpiError> {
         match self.internal_tables.platform_info() {
@@ -113,44 +195,176 @@ pub struct Acpi {
             Ok(dsdt) => dsdt,
             Err(err) => return Err(err)
         };
+        Ok(self.aml_stream(&dsdt))
+    }
 
-        let phys_addr = PhysAddr::new(dsdt.address as u64);
+    /// Returns the AML code of the table, which stays mapped for as long as the kernel runs.
+    fn aml_stream(&self, table: &AmlTable) -> &[u8] {
+        let phys_addr = PhysAddr::new(table.address as u64);
         let virt_addr = crate::internal::memory::phys_to_virt(self.physical_memory_offset, phys_addr);
         let ptr: *const u8 = virt_addr.as_ptr();
 
-        Ok(unsafe {
-            core::slice::from_raw_parts(ptr, dsdt.length as usize)
-        })
+        unsafe {
+            core::slice::from_raw_parts(ptr, table.length as usize)
+        }
     }
 
     pub fn shutdown(&self) -> Result<(), AcpiError> {
-        let dsdt_table = match self.dsdt() {
-            Ok(dsdt) => dsdt,
-            Err(err) => return Err(err)
+        let name = AmlName::from_str("\\_S5").unwrap();
+        let s5 = self.namespace.as_ref().and_then(|namespace| {
+            match namespace.lock().namespace.get_by_path(&name) {
+                Ok(AmlValue::Package(s5)) => match s5.first() {
+                    Some(AmlValue::Integer(value)) => Some(*value as u16),
+                    _ => None
+                }, _ => None
+            }
+        });
+        // SLP_TYPa goes into bits 10 to 12, without `\_S5` fall back to 5, what most firmware uses
+        unsafe { SLP_TYPA = (s5.unwrap_or(5) & 7) << 10 }
+        if s5.is_none() {
+            log::warn!("No \\_S5 sleep type in the ACPI namespace for shutdown, assuming 5.");
+        }
+
+        let Some(pm1a_control) = self.pm1a_control.as_ref() else {
+            return Err(AcpiError::TableMissing(Signature::FADT));
         };
-        let handler = Box::new(self.aml_handler.clone());
-        let mut aml = AmlContext::new(handler, DebugVerbosity::None);
-        if aml.parse_table(dsdt_table).is_ok() {
-            let name = AmlName::from_str("\\_S5").unwrap();
-            let res = aml.namespace.get_by_path(&name);
-            if let Ok(AmlValue::Package(s5)) = res {
-                if let AmlValue::Integer(value) = s5[0] {
-                    unsafe {
-                        SLP_TYPA = value as u16;
-                    }
-                }
+        pm1a_control.write(0, unsafe { SLP_TYPA } | SLP_LEN);
+
+        Ok(())
+    }
+}
+
+/// The ACPI namespace, parsed once at boot. Cloning it shares the namespace.
+#[derive(Clone)]
+pub struct AcpiNamespace {
+    aml: Arc<Mutex<AmlContext>>
+} impl AcpiNamespace {
+    /// Locks the namespace for the functions below that work on the AML context directly.
+    pub fn lock(&self) -> MutexGuard<AmlContext> {
+        self.aml.lock()
+    }
+
+    /// Evaluates the object at the absolute path like `\_SB.PCI0._STA`, invoking it with the
+    /// arguments if it is a method and returning its value otherwise.
+    /// Methods declaring a region in a space that can't be serviced are refused, as they would panic.
+    pub fn evaluate(&self, path: &str, args: Vec<AmlValue>) -> Result<AmlValue, KernelError> {
+        let name = AmlName::from_str(path).ok()
+            .filter(AmlName::is_absolute)
+            .ok_or(KernelError::InvalidConfiguration("Expected an absolute AML path"))?;
+        let args = Args::from_list(args).map_err(|_| KernelError::InvalidConfiguration("Too many AML arguments"))?;
+        let mut aml = self.aml.lock();
+        match aml.namespace.get_by_path(&name) {
+            Ok(method) if crate::internal::aml::declares_unserviced_region(method) => {
+                Err(KernelError::InvalidConfiguration("AML method accesses a region the kernel can't service"))
+            }, Ok(AmlValue::Method { .. }) => aml.invoke_method(&name, args)
+                .map_err(|_| KernelError::InvalidConfiguration("AML method failed")),
+            Ok(value) => Ok(value.clone()),
+            Err(_) => Err(KernelError::HardwareMissing("AML object"))
+        }
+    }
+
+    /// Returns the paths and types of the objects at and below the scope, like `\_SB`.
+    pub fn objects(&self, scope: &str) -> Result<Vec<(String, AmlType)>, KernelError> {
+        let scope = AmlName::from_str(scope).ok()
+            .filter(AmlName::is_absolute)
+            .ok_or(KernelError::InvalidConfiguration("Expected an absolute AML path"))?;
+        let scope = scope.as_string();
+        let mut aml = self.aml.lock();
+
+        // The values can't be looked at while the namespace is being walked
+        let mut handles = Vec::new();
+        aml.namespace.traverse(|name, level| {
+            for (segment, handle) in level.values.iter() {
+                let Ok(path) = AmlName::from_name_seg(*segment).resolve(name) else { continue };
+                let path = path.as_string();
+                let below = path.strip_prefix(scope.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.') || scope.ends_with('\\'));
+                if below { handles.push((path, *handle)); }
             }
-        } else {
-            log::warn!("Failed to parse DSDT table for ACPI shutdown.");
-            unsafe { SLP_TYPA = ( 5 & 7 ) << 10 }
+            Ok(true)
+        }).map_err(|_| KernelError::InvalidConfiguration("Failed to walk the ACPI namespace"))?;
+
+        let mut objects: Vec<_> = handles.into_iter()
+            .filter_map(|(path, handle)| Some((path, aml.namespace.get(handle).ok()?.type_of())))
+            .collect();
+        objects.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(objects)
+    }
+}
+
+/// Shows an AML value the way the control channel prints it, e.g. packages as `[0x1, "name"]`.
+pub struct DisplayValue<'a>(pub &'a AmlValue);
+impl Display for DisplayValue<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            AmlValue::Boolean(value) => write!(f, "{}", value),
+            AmlValue::Integer(value) => write!(f, "{:#X}", value),
+            AmlValue::String(value) => write!(f, "\"{}\"", value),
+            AmlValue::Buffer(bytes) => {
+                write!(f, "buffer[")?;
+                for (index, byte) in bytes.lock().iter().enumerate() {
+                    write!(f, "{}{:02X}", if index == 0 { "" } else { " " }, byte)?;
+                }
+                write!(f, "]")
+            }, AmlValue::Package(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    write!(f, "{}{}", if index == 0 { "" } else { ", " }, DisplayValue(value))?;
+                }
+                write!(f, "]")
+            }, value => write!(f, "{:?}", value.type_of())
         }
+    }
+}
 
-        unsafe {
-            let mut port: Port<u16> = Port::new(PM1A_CNT_BLK as u16);
-            port.write(SLP_TYPA | SLP_LEN);
+/// Evaluates the object relative to the scope, invoking it if it is a method and returning its
+/// value otherwise.
+pub fn evaluate(aml: &mut AmlContext, scope: &str, object: &str) -> Result<AmlValue, KernelError> {
+    let name = AmlName::from_str(object)
+        .and_then(|object| object.resolve(&AmlName::from_str(scope)?))
+        .map_err(|_| KernelError::InvalidConfiguration("Invalid AML path"))?;
+    match aml.namespace.get_by_path(&name) {
+        Ok(AmlValue::Method { .. }) => aml.invoke_method(&name, Args::EMPTY),
+        Ok(value) => Ok(value.clone()),
+        Err(err) => Err(err)
+    }.map_err(|_| KernelError::HardwareMissing("AML object"))
+}
+
+/// Returns whether the object relative to the scope can be evaluated without touching an operation
+/// region the kernel can't service, which would panic. Missing objects can be evaluated, that fails.
+pub fn is_serviceable(aml: &AmlContext, scope: &str, object: &str) -> bool {
+    let Ok(name) = AmlName::from_str(object).and_then(|object| object.resolve(&AmlName::from_str(scope)?)) else {
+        return true;
+    };
+    aml.namespace.get_by_path(&name).map_or(true, |value| !crate::internal::aml::declares_unserviced_region(value))
+}
+
+/// Returns the paths of the namespace levels of the given type, like devices or thermal zones.
+pub fn find_levels(aml: &mut AmlContext, level_type: LevelType) -> Vec<String> {
+    let mut paths = Vec::new();
+    let result = aml.namespace.traverse(|name, level| {
+        if level.typ == level_type {
+            paths.push(name.as_string());
         }
+        Ok(true)
+    });
+    if let Err(err) = result {
+        log::warn!("Failed to walk the ACPI namespace: {:?}", err);
+    }
+    paths
+}
 
-        Ok(())
+/// Returns the hardware ID of a `_HID` or `_CID` value, which is either a string or a compressed
+/// EISA ID like `PNP0C0A`.
+pub fn hardware_id(value: &AmlValue) -> Option<String> {
+    match value {
+        AmlValue::String(id) => Some(id.clone()),
+        AmlValue::Integer(id) => {
+            // Three 5-bit letters and four hex digits, stored big-endian
+            let id = (*id as u32).swap_bytes();
+            let letter = |shift: u32| (b'@' + ((id >> shift) & 0x1F) as u8) as char;
+            Some(alloc::format!("{}{}{}{:04X}", letter(26), letter(21), letter(16), id & 0xFFFF))
+        }, _ => None
     }
 }
 
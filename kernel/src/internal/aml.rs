@@ -1,4 +1,21 @@
-use aml::Handler;
+use alloc::vec::Vec;
+use aml::{AmlContext, AmlValue, Handler};
+use aml::value::{MethodCode, RegionSpace};
+use x86_64::instructions::port::Port;
+
+/// The opcode of `DefOpRegion`, and the first region space the `aml` crate can't access itself.
+static OP_REGION_OPCODE: [u8; 2] = [0x5B, 0x80];
+static FIRST_UNSERVICED_SPACE: u8 = 0x03;
+static ROOT_CHAR: u8 = b'\\';
+static PARENT_PREFIX_CHAR: u8 = b'^';
+static NULL_NAME: u8 = 0x00;
+static DUAL_NAME_PREFIX: u8 = 0x2E;
+static MULTI_NAME_PREFIX: u8 = 0x2F;
+static NAME_SEG_SIZE: usize = 4;
+
+/// Where the `EmbeddedControl` operation regions are moved to in memory space, as the `aml` crate
+/// can't access that space itself. It is not canonical, so no real memory region can be there.
+static EC_WINDOW: usize = 0x8000_0000_0000;
 
 #[derive(Clone)]
 pub struct AmlHandler;
@@ -6,58 +23,172 @@ impl AmlHandler {
     pub fn new() -> Self { Self }
 } impl Handler for AmlHandler {
     fn read_u8(&self, address: usize) -> u8 {
+        if is_ec_window(address) { return read_ec(address, 1) as u8; }
         crate::internal::memory::read_address::<u8>(address)
     }
 
     fn read_u16(&self, address: usize) -> u16 {
+        if is_ec_window(address) { return read_ec(address, 2) as u16; }
         crate::internal::memory::read_address::<u16>(address)
     }
 
     fn read_u32(&self, address: usize) -> u32 {
+        if is_ec_window(address) { return read_ec(address, 4) as u32; }
         crate::internal::memory::read_address::<u32>(address)
     }
 
     fn read_u64(&self, address: usize) -> u64 {
+        if is_ec_window(address) { return read_ec(address, 8); }
         crate::internal::memory::read_address::<u64>(address)
     }
 
     fn write_u8(&mut self, address: usize, value: u8) {
+        if is_ec_window(address) { return write_ec(address, 1, value as u64); }
         crate::internal::memory::write_address::<u8>(address, value);
     }
 
     fn write_u16(&mut self, address: usize, value: u16) {
+        if is_ec_window(address) { return write_ec(address, 2, value as u64); }
         crate::internal::memory::write_address::<u16>(address, value);
     }
 
     fn write_u32(&mut self, address: usize, value: u32) {
+        if is_ec_window(address) { return write_ec(address, 4, value as u64); }
         crate::internal::memory::write_address::<u32>(address, value);
     }
 
     fn write_u64(&mut self, address: usize, value: u64) {
+        if is_ec_window(address) { return write_ec(address, 8, value); }
         crate::internal::memory::write_address::<u64>(address, value);
     }
 
-    fn read_io_u8(&self, _port: u16) -> u8 { unimplemented!() }
+    // The firmware's operation regions decide which ports are used, so they bypass the port registry
+    fn read_io_u8(&self, port: u16) -> u8 {
+        unsafe { Port::new(port).read() }
+    }
+
+    fn read_io_u16(&self, port: u16) -> u16 {
+        unsafe { Port::new(port).read() }
+    }
 
-    fn read_io_u16(&self, _port: u16) -> u16 { unimplemented!() }
+    fn read_io_u32(&self, port: u16) -> u32 {
+        unsafe { Port::new(port).read() }
+    }
 
-    fn read_io_u32(&self, _port: u16) -> u32 { unimplemented!() }
+    fn write_io_u8(&self, port: u16, value: u8) {
+        unsafe { Port::new(port).write(value) }
+    }
 
-    fn write_io_u8(&self, _port: u16, _value: u8) { unimplemented!() }
+    fn write_io_u16(&self, port: u16, value: u16) {
+        unsafe { Port::new(port).write(value) }
+    }
 
-    fn write_io_u16(&self, _port: u16, _value: u16) { unimplemented!() }
+    fn write_io_u32(&self, port: u16, value: u32) {
+        unsafe { Port::new(port).write(value) }
+    }
 
-    fn write_io_u32(&self, _port: u16, _value: u32) { unimplemented!() }
+    fn read_pci_u8(&self, segment: u16, bus: u8, device: u8, function: u8, offset: u16) -> u8 {
+        crate::internal::pci::read_u8(segment, bus, device, function, offset)
+    }
 
-    fn read_pci_u8(&self, _segment: u16, _bus: u8, _device: u8, _function: u8, _offset: u16) -> u8 { unimplemented!() }
+    fn read_pci_u16(&self, segment: u16, bus: u8, device: u8, function: u8, offset: u16) -> u16 {
+        crate::internal::pci::read_u16(segment, bus, device, function, offset)
+    }
 
-    fn read_pci_u16(&self, _segment: u16, _bus: u8, _device: u8, _function: u8, _offset: u16) -> u16 { unimplemented!() }
+    fn read_pci_u32(&self, segment: u16, bus: u8, device: u8, function: u8, offset: u16) -> u32 {
+        crate::internal::pci::read_u32(segment, bus, device, function, offset)
+    }
 
-    fn read_pci_u32(&self, _segment: u16, _bus: u8, _device: u8, _function: u8, _offset: u16) -> u32 { unimplemented!() }
+    fn write_pci_u8(&self, segment: u16, bus: u8, device: u8, function: u8, offset: u16, value: u8) {
+        crate::internal::pci::write_u8(segment, bus, device, function, offset, value)
+    }
 
-    fn write_pci_u8(&self, _segment: u16, _bus: u8, _device: u8, _function: u8, _offset: u16, _value: u8) { unimplemented!() }
+    fn write_pci_u16(&self, segment: u16, bus: u8, device: u8, function: u8, offset: u16, value: u16) {
+        crate::internal::pci::write_u16(segment, bus, device, function, offset, value)
+    }
 
-    fn write_pci_u16(&self, _segment: u16, _bus: u8, _device: u8, _function: u8, _offset: u16, _value: u16) { unimplemented!() }
+    fn write_pci_u32(&self, segment: u16, bus: u8, device: u8, function: u8, offset: u16, value: u32) {
+        crate::internal::pci::write_u32(segment, bus, device, function, offset, value)
+    }
+}
+
+fn is_ec_window(address: usize) -> bool {
+    (EC_WINDOW..EC_WINDOW + crate::internal::ec::SPACE_SIZE).contains(&address)
+}
+
+/// Reads the bytes from the EC little endian first, bytes the EC doesn't answer for read as all ones.
+fn read_ec(address: usize, width: usize) -> u64 {
+    (0..width).rev().fold(0, |value, index| {
+        let offset = (address - EC_WINDOW + index) as u8;
+        let byte = crate::internal::ec::read(offset)
+            .inspect_err(|err| log::debug!("AML read of EC offset {:#04X} failed: {}", offset, err))
+            .unwrap_or(0xFF);
+        value << 8 | byte as u64
+    })
+}
+
+/// Writes the bytes to the EC little endian first, bytes the EC doesn't take are dropped.
+fn write_ec(address: usize, width: usize, value: u64) {
+    for index in 0..width {
+        let offset = (address - EC_WINDOW + index) as u8;
+        if let Err(err) = crate::internal::ec::write(offset, (value >> (index * 8)) as u8) {
+            log::debug!("AML write of EC offset {:#04X} failed: {}", offset, err);
+        }
+    }
+}
+
+/// Moves the `EmbeddedControl` operation regions into the EC window of memory space, so their
+/// fields are read and written through the EC driver. Returns how many regions were moved.
+/// Regions declared inside a method only exist while it runs and can't be moved.
+pub fn redirect_ec_regions(aml: &mut AmlContext) -> usize {
+    // The values can't be changed while the namespace is being walked
+    let mut handles = Vec::new();
+    let result = aml.namespace.traverse(|_, level| {
+        handles.extend(level.values.values().copied());
+        Ok(true)
+    });
+    if let Err(err) = result {
+        log::warn!("Failed to walk the ACPI namespace for EC regions: {:?}", err);
+    }
 
-    fn write_pci_u32(&self, _segment: u16, _bus: u8, _device: u8, _function: u8, _offset: u16, _value: u32) { unimplemented!() }
+    let mut count = 0;
+    for handle in handles {
+        let Ok(AmlValue::OpRegion { region, offset, .. }) = aml.namespace.get_mut(handle) else { continue };
+        if *region != RegionSpace::EmbeddedControl { continue; }
+        *region = RegionSpace::SystemMemory;
+        *offset += EC_WINDOW as u64;
+        count += 1;
+    }
+    count
+}
+
+/// Returns whether the method declares an operation region in a space other than memory, I/O or
+/// PCI configuration space. Those regions are created when the method runs, so they can't be
+/// redirected, and the `aml` crate panics when their fields are accessed. The methods it calls are
+/// not looked at, and bytes that only look like a declaration make it say yes.
+pub fn declares_unserviced_region(method: &AmlValue) -> bool {
+    let AmlValue::Method { code: MethodCode::Aml(code), .. } = method else { return false };
+    code.windows(OP_REGION_OPCODE.len()).enumerate()
+        .filter(|(_, window)| *window == OP_REGION_OPCODE)
+        .filter_map(|(index, _)| {
+            let name = &code[index + OP_REGION_OPCODE.len()..];
+            code.get(index + OP_REGION_OPCODE.len() + name_string_length(name)?).copied()
+        })
+        .any(|space| space >= FIRST_UNSERVICED_SPACE)
+}
+
+/// Returns how many bytes the name string at the start of the code takes.
+fn name_string_length(code: &[u8]) -> Option<usize> {
+    let prefix = match code.first()? {
+        &char if char == ROOT_CHAR => 1,
+        _ => code.iter().take_while(|&&char| char == PARENT_PREFIX_CHAR).count()
+    };
+    let path = match *code.get(prefix)? {
+        char if char == NULL_NAME => 1,
+        char if char == DUAL_NAME_PREFIX => 1 + 2 * NAME_SEG_SIZE,
+        char if char == MULTI_NAME_PREFIX => 2 + *code.get(prefix + 1)? as usize * NAME_SEG_SIZE,
+        char if char == b'_' || char.is_ascii_uppercase() => NAME_SEG_SIZE,
+        _ => return None
+    };
+    Some(prefix + path)
 }
\ No newline at end of file
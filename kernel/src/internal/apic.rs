@@ -0,0 +1,149 @@
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use x86_64::registers::model_specific::Msr;
+use crate::api::event::Event;
+use crate::internal::msr::ApicBase;
+use crate::drivers::timer::TimerTick;
+use crate::internal::pic::PicInterrupts;
+
+static X2APIC_ID: u32 = 0x802;
+static X2APIC_EOI: u32 = 0x80B;
+static X2APIC_SPURIOUS_VECTOR: u32 = 0x80F;
+static X2APIC_LVT_TIMER: u32 = 0x832;
+
+static SPURIOUS_APIC_ENABLE: u64 = 1 << 8;
+static LVT_MASKED: u64 = 1 << 16;
+static LVT_TSC_DEADLINE: u64 = 0b10 << 17;
+
+/// The vector the local APIC timer interrupts on.
+pub const TIMER_VECTOR: u8 = 0x30;
+/// The vector the local APIC signals spurious interrupts on, they must not be acknowledged.
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+/// Without any deadline the CPU still wakes up once in a while, like with the slowest PIT rate.
+static MAX_IDLE_TICKS: u64 = 1000;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static CYCLES_PER_TICK: AtomicU64 = AtomicU64::new(0);
+/// Time stamp counter reading up to which ticks were reported.
+static LAST_TICK_TSC: AtomicU64 = AtomicU64::new(0);
+static TIMER_FIRED: AtomicBool = AtomicBool::new(false);
+static IDLE: AtomicBool = AtomicBool::new(false);
+
+/// Enables the local APIC in x2APIC mode, without its timer, so interrupts can be delivered to it
+/// through the IOAPICs. Returns whether it is enabled.
+pub fn enable() -> bool {
+    if ENABLED.load(Ordering::SeqCst) { return true; }
+    if !crate::internal::cpu::supports_x2apic() { return false; }
+
+    let apic_base = crate::internal::msr::apic_base();
+    if crate::internal::msr::set_apic_base(ApicBase { enabled: true, x2apic: true, ..apic_base }).is_err() {
+        return false;
+    }
+    unsafe { Msr::new(X2APIC_SPURIOUS_VECTOR).write(SPURIOUS_APIC_ENABLE | SPURIOUS_VECTOR as u64); }
+    ENABLED.store(true, Ordering::SeqCst);
+    true
+}
+
+/// Returns whether the local APIC is enabled, with or without its timer.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Switches the timer to one-shot interrupts of the local APIC at time stamp counter deadlines,
+/// if the CPU supports the TSC-deadline mode (and x2APIC, so the APIC is reachable through MSRs).
+/// The time stamp counter has to be calibrated before. Returns whether the APIC timer is used (the
+/// PIT timer interrupt is masked then), if not the PIT stays the timer.
+pub fn init_timer() -> bool {
+    let khz = crate::internal::tsc::khz();
+    if khz == 0 || !crate::internal::cpu::supports_tsc_deadline() || !enable() {
+        return false;
+    }
+
+    crate::internal::idt::without_interrupts(|| unsafe {
+        Msr::new(X2APIC_LVT_TIMER).write(LVT_TSC_DEADLINE | TIMER_VECTOR as u64);
+
+        CYCLES_PER_TICK.store(khz * 1000 / crate::drivers::timer::tick_hz(), Ordering::SeqCst);
+        LAST_TICK_TSC.store(crate::internal::tsc::read(), Ordering::SeqCst);
+        ACTIVE.store(true, Ordering::SeqCst);
+        arm(1);
+        crate::internal::pic::mask(PicInterrupts::Timer);
+    });
+    true
+}
+
+/// Masks the APIC timer, so it doesn't fire anymore.
+pub fn stop_timer() {
+    if !ACTIVE.swap(false, Ordering::SeqCst) { return; }
+    crate::internal::msr::set_tsc_deadline(0);
+    unsafe { Msr::new(X2APIC_LVT_TIMER).write(LVT_MASKED | LVT_TSC_DEADLINE | TIMER_VECTOR as u64); }
+}
+
+/// Returns whether the APIC timer drives the timer ticks instead of the PIT.
+pub fn timer_active() -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+/// Arms the timer to fire the given amount of ticks after the last reported one.
+fn arm(ticks: u64) {
+    let cycles_per_tick = CYCLES_PER_TICK.load(Ordering::SeqCst);
+    let deadline = LAST_TICK_TSC.load(Ordering::SeqCst).saturating_add(ticks.saturating_mul(cycles_per_tick));
+    // A deadline in the past fires right away, which is what is wanted for one that was missed
+    crate::internal::msr::set_tsc_deadline(deadline.max(1));
+}
+
+/// Takes the whole ticks that passed since the last reported one, the rest of a tick is kept.
+fn take_elapsed_ticks() -> u64 {
+    let cycles_per_tick = CYCLES_PER_TICK.load(Ordering::SeqCst).max(1);
+    let last = LAST_TICK_TSC.load(Ordering::SeqCst);
+    let ticks = crate::internal::tsc::read().saturating_sub(last) / cycles_per_tick;
+    LAST_TICK_TSC.store(last + ticks * cycles_per_tick, Ordering::SeqCst);
+    ticks
+}
+
+/// Gets called by the APIC timer interrupt handler to find out what the interrupt stands for.
+/// Arms the timer for the next tick.
+pub fn timer_tick() -> TimerTick {
+    TIMER_FIRED.store(true, Ordering::SeqCst);
+    let ticks = take_elapsed_ticks();
+    arm(1);
+    TimerTick { ticks, idle: IDLE.load(Ordering::SeqCst) }
+}
+
+/// Like `pit::idle`, but as the deadline can be arbitrarily far away the CPU only wakes up when
+/// there is something to do. Ticks that passed while woken up early by another interrupt are
+/// measured with the time stamp counter, so none get lost.
+pub fn idle(deadline: Option<u64>, wake_flag: &AtomicBool) {
+    let ticks = deadline.unwrap_or(MAX_IDLE_TICKS).max(1);
+    if ticks > 1 { arm(ticks); }
+
+    TIMER_FIRED.store(false, Ordering::SeqCst);
+    IDLE.store(true, Ordering::SeqCst);
+    crate::internal::cpuidle::enter(ticks, wake_flag);
+    x86_64::instructions::interrupts::disable();
+    IDLE.store(false, Ordering::SeqCst);
+
+    if ticks > 1 {
+        if !TIMER_FIRED.load(Ordering::SeqCst) {
+            let elapsed = take_elapsed_ticks();
+            if elapsed > 0 {
+                crate::api::event::EventDispatcher::global().push(Event::Timer(TimerTick {
+                    ticks: elapsed, idle: true
+                }));
+            }
+        }
+        arm(1);
+    }
+
+    x86_64::instructions::interrupts::enable();
+}
+
+/// Returns the id of the local APIC of the current CPU, None if the local APIC isn't enabled.
+pub fn id() -> Option<u32> {
+    if !ENABLED.load(Ordering::SeqCst) { return None; }
+    Some(unsafe { Msr::new(X2APIC_ID).read() } as u32)
+}
+
+pub fn end_of_interrupt() {
+    unsafe { Msr::new(X2APIC_EOI).write(0); }
+}
\ No newline at end of file
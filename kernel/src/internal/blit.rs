@@ -0,0 +1,84 @@
+use core::arch::asm;
+
+static AVX_BLOCK: usize = 32;
+
+/// Copies the source into the target, which have to be of the same length. Uses `rep movsb` if
+/// the CPU has enhanced support for it, otherwise AVX if available.
+pub fn copy(target: &mut [u8], source: &[u8]) {
+    assert_eq!(target.len(), source.len(), "blit copy between buffers of different length");
+    if crate::internal::cpu::supports_erms() {
+        unsafe { copy_erms(target.as_mut_ptr(), source.as_ptr(), source.len()) }
+    } else if crate::internal::cpu::supports_avx() {
+        let blocks = source.len() / AVX_BLOCK;
+        if blocks > 0 {
+            unsafe { copy_avx(target.as_mut_ptr(), source.as_ptr(), blocks) }
+        }
+        let done = blocks * AVX_BLOCK;
+        target[done..].copy_from_slice(&source[done..]);
+    } else {
+        target.copy_from_slice(source);
+    }
+}
+
+/// Fills the buffer with the repeated pattern, e.g. the bytes of a single pixel. The length of the
+/// buffer is expected to be a multiple of the pattern length.
+pub fn fill(buffer: &mut [u8], pattern: &[u8]) {
+    if pattern.is_empty() {
+        return;
+    }
+
+    // Patterns dividing 8 bytes get written a u64 at a time
+    if 8 % pattern.len() == 0 {
+        let mut wide = [0; 8];
+        for chunk in wide.chunks_exact_mut(pattern.len()) {
+            chunk.copy_from_slice(pattern);
+        }
+        let wide = u64::from_ne_bytes(wide);
+
+        let (head, body, tail) = unsafe { buffer.align_to_mut::<u64>() };
+        // The head breaks the pattern alignment, so the body gets the pattern rotated accordingly
+        let rotated = wide.rotate_right((head.len() * 8) as u32);
+        fill_bytes(head, pattern, 0);
+        body.fill(rotated);
+        let offset = (head.len() + body.len() * 8) % pattern.len();
+        fill_bytes(tail, pattern, offset);
+    } else {
+        for chunk in buffer.chunks_exact_mut(pattern.len()) {
+            chunk.copy_from_slice(pattern);
+        }
+    }
+}
+
+fn fill_bytes(buffer: &mut [u8], pattern: &[u8], offset: usize) {
+    for (index, byte) in buffer.iter_mut().enumerate() {
+        *byte = pattern[(offset + index) % pattern.len()];
+    }
+}
+
+unsafe fn copy_erms(target: *mut u8, source: *const u8, length: usize) {
+    asm!(
+        "rep movsb",
+        inout("rcx") length => _, inout("rdi") target => _, inout("rsi") source => _,
+        options(nostack, preserves_flags)
+    );
+}
+
+/// Copies the given number of 32 byte blocks through a YMM register. The kernel is compiled without
+/// SIMD, so YMM0 can't be holding anything and isn't declared as clobbered (it can't be, as the
+/// target has no vector register class).
+unsafe fn copy_avx(target: *mut u8, source: *const u8, blocks: usize) {
+    asm!(
+        "2:",
+        "vmovdqu ymm0, [{source}]",
+        "vmovdqu [{target}], ymm0",
+        "add {source}, 32",
+        "add {target}, 32",
+        "dec {blocks}",
+        "jnz 2b",
+        // Avoids the penalty of mixing dirty upper YMM halves with legacy SSE code
+        "vzeroupper",
+        source = inout(reg) source => _, target = inout(reg) target => _,
+        blocks = inout(reg) blocks => _,
+        options(nostack)
+    );
+}
\ No newline at end of file
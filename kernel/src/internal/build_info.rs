@@ -0,0 +1,39 @@
+use core::fmt;
+use core::fmt::{Display, Formatter};
+
+static BUILD_INFO: BuildInfo = BuildInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    commit: env!("BUILD_COMMIT"),
+    timestamp: env!("BUILD_TIMESTAMP"),
+    rustc: env!("BUILD_RUSTC"),
+    features: env!("BUILD_FEATURES"),
+    profile: env!("BUILD_PROFILE")
+};
+
+/// What the kernel was built from, embedded by the build script.
+#[derive(Debug, Clone, Copy)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// Abbreviated commit hash, ending in `-dirty` if the tree had changes, or `unknown` outside git.
+    pub commit: &'static str,
+    /// When the kernel was built in UTC, like `2024-03-15T13:45:00Z`.
+    pub timestamp: &'static str,
+    pub rustc: &'static str,
+    /// The enabled cargo features, separated by commas.
+    pub features: &'static str,
+    /// `debug` or `release`.
+    pub profile: &'static str
+} impl Display for BuildInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "AkjoOS {} ({} {}, built {} with {}, features: {})",
+            self.version, self.commit, self.profile, self.timestamp, self.rustc,
+            if self.features.is_empty() { "none" } else { self.features }
+        )
+    }
+}
+
+/// Returns what the running kernel was built from.
+pub fn build_info() -> &'static BuildInfo {
+    &BUILD_INFO
+}
\ No newline at end of file
@@ -1,27 +1,205 @@
 use core::hint::spin_loop;
 use bit_field::BitField;
 use spin::{Mutex, Once};
-use x86_64::instructions::port::Port;
+use crate::api::error::KernelError;
+use crate::api::time::DateTime;
+use crate::internal::ioport::IoPortRange;
 
 static CENTURY: u16 = 2000;
 
-static CMOS_PORT_1: u16 = 0x70;
-static CMOS_PORT_2: u16 = 0x71;
+static CMOS_PORT: u16 = 0x70;
+static CMOS_PORT_COUNT: u16 = 2;
+static INDEX_OFFSET: u16 = 0;
+static DATA_OFFSET: u16 = 1;
 
 static CMOS: Once<Mutex<Cmos>> = Once::new();
 
+/// Setting this bit in the register index written to the first port masks the NMI line.
+static NMI_DISABLE_BIT: u8 = 0x80;
+
+/// Status register B bits: stop the clock from updating, binary instead of BCD values and 24-hour
+/// instead of 12-hour format.
+static STATUS_B_SET: u8 = 0x80;
+static STATUS_B_BINARY: u8 = 0x04;
+static STATUS_B_24_HOUR: u8 = 0x02;
+/// Set on the hours in 12-hour format for the afternoon.
+static HOURS_PM: u8 = 0x80;
+
+/// Start of the scratch area the kernel uses to persist its own settings. Not used by the
+/// standard register layout, the firmware checksum (0x10 - 0x2D) or QEMU's extensions.
+static SCRATCH_START: u8 = 0x60;
+pub const SCRATCH_SIZE: usize = 14;
+static SCRATCH_CHECKSUM_SEED: u16 = 0xA5A5;
+
 #[repr(u8)]
 #[derive(Debug, Clone)]
 enum CmosRegister {
     Seconds = 0x00,
     Minutes = 0x02,
     Hours = 0x04,
+    Weekday = 0x06,
     Day = 0x07,
     Month = 0x08,
     Year = 0x09,
     StatusA = 0x0A,
     StatusB = 0x0B,
-    StatusC = 0x0C
+    StatusC = 0x0C,
+    FloppyTypes = 0x10,
+    Equipment = 0x14
+}
+
+/// The rate of the periodic real-time clock interrupt, set through the divider bits in Status A.
+/// Faster rates give a more precise time base but also wake the CPU more often.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(dead_code)]
+pub enum RtcRate {
+    /// 8192 Hz, about every 122 µs. The fastest rate that is reliable on all hardware.
+    Hz8192 = 3,
+    /// 4096 Hz, about every 244 µs.
+    Hz4096 = 4,
+    /// 2048 Hz, about every 488 µs.
+    Hz2048 = 5,
+    /// 1024 Hz, about every 977 µs. The usual firmware default.
+    Hz1024 = 6,
+    /// 512 Hz, about every 1.95 ms.
+    Hz512 = 7,
+    /// 256 Hz, about every 3.9 ms.
+    Hz256 = 8,
+    /// 128 Hz, about every 7.8 ms.
+    Hz128 = 9,
+    /// 64 Hz, about every 15.6 ms.
+    Hz64 = 10,
+    /// 32 Hz, about every 31.3 ms.
+    Hz32 = 11,
+    /// 16 Hz, every 62.5 ms.
+    Hz16 = 12,
+    /// 8 Hz, every 125 ms.
+    Hz8 = 13,
+    /// 4 Hz, every 250 ms.
+    Hz4 = 14,
+    /// 2 Hz, every 500 ms.
+    Hz2 = 15
+} impl RtcRate {
+    /// Returns the interrupt frequency in Hz.
+    pub fn frequency(&self) -> u32 {
+        32768 >> (*self as u8 - 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum FloppyType {
+    None,
+    Kb360,
+    Mb1_2,
+    Kb720,
+    Mb1_44,
+    Mb2_88,
+    Unknown(u8)
+} impl FloppyType {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => FloppyType::None,
+            1 => FloppyType::Kb360,
+            2 => FloppyType::Mb1_2,
+            3 => FloppyType::Kb720,
+            4 => FloppyType::Mb1_44,
+            5 => FloppyType::Mb2_88,
+            other => FloppyType::Unknown(other)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EquipmentDisplay {
+    /// An EGA/VGA (or newer) adapter with its own BIOS.
+    Ega,
+    Cga40,
+    Cga80,
+    Monochrome
+}
+
+/// The equipment byte as set up by the firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub struct Equipment {
+    pub floppy_drives: u8,
+    pub math_coprocessor: bool,
+    pub display: EquipmentDisplay
+}
+
+/// Status of the last boot, persisted in the scratch area so the next boot can tell whether the
+/// previous one ended cleanly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(dead_code)]
+pub enum BootStatus {
+    Unknown = 0,
+    Booting = 1,
+    Running = 2,
+    ShutDown = 3,
+    Panicked = 4
+} impl BootStatus {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => BootStatus::Booting,
+            2 => BootStatus::Running,
+            3 => BootStatus::ShutDown,
+            4 => BootStatus::Panicked,
+            _ => BootStatus::Unknown
+        }
+    }
+}
+
+/// Kernel settings persisted across reboots in the scratch area of the CMOS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub struct CmosSettings {
+    pub boot_status: BootStatus,
+    pub boot_count: u16,
+    /// Kernel-defined id of the display mode to start in.
+    pub display_mode: u8,
+    /// How fast the real-time clock runs, as estimated from the corrections to it, in parts per million.
+    pub clock_drift_ppm: i16,
+    /// Kernel-defined id of the time zone, one higher than the offset so 0 means it was never set.
+    pub time_offset: u8,
+    /// Kernel-defined id of the log level, 0 if it was never set.
+    pub log_level: u8,
+    /// Kernel-defined id of what to start once booted.
+    pub boot_target: u8
+} impl CmosSettings {
+    pub fn new() -> Self { Self {
+        boot_status: BootStatus::Unknown,
+        boot_count: 0,
+        display_mode: 0,
+        clock_drift_ppm: 0,
+        time_offset: 0,
+        log_level: 0,
+        boot_target: 0
+    } }
+
+    fn from_bytes(bytes: [u8; SCRATCH_SIZE]) -> Self { Self {
+        boot_status: BootStatus::from_u8(bytes[0]),
+        boot_count: u16::from_le_bytes([bytes[1], bytes[2]]),
+        display_mode: bytes[3],
+        clock_drift_ppm: i16::from_le_bytes([bytes[4], bytes[5]]),
+        time_offset: bytes[6],
+        log_level: bytes[7],
+        boot_target: bytes[8]
+    } }
+
+    fn to_bytes(self) -> [u8; SCRATCH_SIZE] {
+        let mut bytes = [0; SCRATCH_SIZE];
+        bytes[0] = self.boot_status as u8;
+        bytes[1..3].copy_from_slice(&self.boot_count.to_le_bytes());
+        bytes[3] = self.display_mode;
+        bytes[4..6].copy_from_slice(&self.clock_drift_ppm.to_le_bytes());
+        bytes[6] = self.time_offset;
+        bytes[7] = self.log_level;
+        bytes[8] = self.boot_target;
+        bytes
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -35,18 +213,18 @@ pub struct Rtc {
 }
 
 pub struct Cmos {
-    port_1: Port<u8>,
-    port_2: Port<u8>,
-    century_register: u8
+    ports: IoPortRange,
+    century_register: u8,
+    nmi_enabled: bool
 } impl Cmos {
     pub(crate) fn global() -> Option<&'static Mutex<Self>> {
         CMOS.get()
     }
 
-    fn new(century_register: u8) -> Self { Self {
-        port_1: Port::new(CMOS_PORT_1),
-        port_2: Port::new(CMOS_PORT_2),
-        century_register
+    fn new(century_register: u8, ports: IoPortRange) -> Self { Self {
+        ports,
+        century_register,
+        nmi_enabled: true
     } }
 
     fn read_date_time(&mut self) -> Rtc {
@@ -71,40 +249,103 @@ pub struct Cmos {
 
         let status_b = self.read_register(CmosRegister::StatusB as u8);
 
-        if status_b & 0x04 == 0 {
-            rtc.seconds = (rtc.seconds & 0x0F) + ((rtc.seconds / 16) * 10);
-            rtc.minutes = (rtc.minutes & 0x0F) + ((rtc.minutes / 16) * 10);
-            rtc.hours = ((rtc.hours & 0x0F) + (((rtc.hours & 0x70) / 16) * 10))
-                | (rtc.hours & 0x80);
-            rtc.day = (rtc.day & 0x0F) + ((rtc.day / 16) * 10);
-            rtc.month = (rtc.month & 0x0F) + ((rtc.month / 16) * 10);
-            rtc.year = (rtc.year & 0x0F) + ((rtc.year / 16) * 10);
+        if status_b & STATUS_B_BINARY == 0 {
+            rtc.seconds = from_bcd(rtc.seconds);
+            rtc.minutes = from_bcd(rtc.minutes);
+            rtc.hours = from_bcd(rtc.hours & !HOURS_PM) | (rtc.hours & HOURS_PM);
+            rtc.day = from_bcd(rtc.day);
+            rtc.month = from_bcd(rtc.month);
+            rtc.year = from_bcd(rtc.year as u8) as u16;
+        }
+
+        if status_b & STATUS_B_24_HOUR == 0 {
+            // 12 AM is midnight and 12 PM is noon
+            let pm = rtc.hours & HOURS_PM != 0;
+            rtc.hours = (rtc.hours & !HOURS_PM) % 12 + if pm { 12 } else { 0 };
         }
 
-        if (status_b & 0x02 == 0) && (rtc.hours & 0x80 == 0) {
-            rtc.hours = ((rtc.hours & 0x07F) + 12) % 24;
+        rtc.year += self.century();
+
+        rtc
+    }
+
+    /// Sets the date and time of the real-time clock, in whatever format the firmware set it up
+    /// with. The clock is stopped while the registers are written, so an update can't mix the old
+    /// and the new time. Fails for years outside the century the clock is read in.
+    pub fn set_rtc(&mut self, date_time: DateTime) -> Result<(), KernelError> {
+        let century = self.century() as i32;
+        if date_time.year() < century || date_time.year() >= century + 100 {
+            return Err(KernelError::InvalidConfiguration("Year is outside the century of the real-time clock"));
         }
 
-        rtc.year += match self.century_register {
+        crate::internal::idt::without_interrupts(|| {
+            self.wait_for_update();
+            self.disable_nmi();
+            let status_b = self.read_register(CmosRegister::StatusB as u8);
+            self.write_register(CmosRegister::StatusB as u8, status_b | STATUS_B_SET);
+
+            let encode = |value: u8| if status_b & STATUS_B_BINARY == 0 { to_bcd(value) } else { value };
+            let hours = if status_b & STATUS_B_24_HOUR == 0 {
+                let pm = if date_time.hours() >= 12 { HOURS_PM } else { 0 };
+                encode(match date_time.hours() % 12 { 0 => 12, hours => hours }) | pm
+            } else { encode(date_time.hours()) };
+            // The clock counts the days of the week from Sunday on, starting at 1
+            let weekday = match date_time.weekday() as u8 { 0 => 7, weekday => weekday };
+
+            self.write_register(CmosRegister::Seconds as u8, encode(date_time.seconds()));
+            self.write_register(CmosRegister::Minutes as u8, encode(date_time.minutes()));
+            self.write_register(CmosRegister::Hours as u8, hours);
+            self.write_register(CmosRegister::Weekday as u8, encode(weekday));
+            self.write_register(CmosRegister::Day as u8, encode(date_time.day()));
+            self.write_register(CmosRegister::Month as u8, encode(date_time.month() as u8));
+            self.write_register(CmosRegister::Year as u8, encode((date_time.year() - century) as u8));
+            if self.century_register != 0 {
+                self.write_register(self.century_register, encode((century / 100) as u8));
+            }
+
+            self.write_register(CmosRegister::StatusB as u8, status_b & !STATUS_B_SET);
+            self.enable_nmi();
+        });
+        Ok(())
+    }
+
+    /// Returns the century the two-digit year of the clock is in.
+    fn century(&self) -> u16 {
+        match self.century_register {
             0 => 1900,
             0x32 => 2000,
             _ => CENTURY
-        };
-
-        rtc
+        }
     }
 
     pub fn enable_interrupts(&mut self) {
         crate::internal::idt::without_interrupts(|| {
             self.disable_nmi();
             let prev = self.read_register(CmosRegister::StatusB as u8);
-            self.write_register(CmosRegister::StatusB, 0x8B);
-            self.write_register(CmosRegister::StatusB, prev | 1 << 6);
+            self.write_register(CmosRegister::StatusB as u8, prev | 1 << 6);
             self.enable_nmi();
             self.notify_end_of_interrupt();
         })
     }
 
+    /// Stops the periodic interrupt again.
+    pub fn disable_interrupts(&mut self) {
+        crate::internal::idt::without_interrupts(|| {
+            self.disable_nmi();
+            let prev = self.read_register(CmosRegister::StatusB as u8);
+            self.write_register(CmosRegister::StatusB as u8, prev & !(1 << 6));
+            self.enable_nmi();
+        })
+    }
+
+    /// Sets the rate of the periodic interrupt.
+    pub fn set_rate(&mut self, rate: RtcRate) {
+        crate::internal::idt::without_interrupts(|| {
+            let status_a = self.read_register(CmosRegister::StatusA as u8);
+            self.write_register(CmosRegister::StatusA as u8, (status_a & 0xF0) | rate as u8);
+        })
+    }
+
     pub fn notify_end_of_interrupt(&mut self) {
         self.read_register(CmosRegister::StatusC as u8);
     }
@@ -117,27 +358,165 @@ pub struct Cmos {
         self.read_register(CmosRegister::StatusA as u8).get_bit(7)
     }
 
-    fn enable_nmi(&mut self) {
-        let status_b = self.read_register(CmosRegister::StatusB as u8) & 0x7F;
-        self.write_register(CmosRegister::StatusB, status_b)
+    /// Returns the drive types of the first and second floppy drive.
+    pub fn floppy_drives(&mut self) -> (FloppyType, FloppyType) {
+        let types = self.read_register(CmosRegister::FloppyTypes as u8);
+        (FloppyType::from_u8(types >> 4), FloppyType::from_u8(types & 0x0F))
     }
 
-    fn disable_nmi(&mut self) {
-        let status_b = self.read_register(CmosRegister::StatusB as u8) | 0x80;
-        self.write_register(CmosRegister::StatusB, status_b)
+    /// Returns the equipment byte as set up by the firmware.
+    pub fn equipment(&mut self) -> Equipment {
+        let equipment = self.read_register(CmosRegister::Equipment as u8);
+        Equipment {
+            floppy_drives: if equipment.get_bit(0) { equipment.get_bits(6..8) + 1 } else { 0 },
+            math_coprocessor: equipment.get_bit(1),
+            display: match equipment.get_bits(4..6) {
+                0 => EquipmentDisplay::Ega,
+                1 => EquipmentDisplay::Cga40,
+                2 => EquipmentDisplay::Cga80,
+                _ => EquipmentDisplay::Monochrome
+            }
+        }
     }
 
-    fn read_register(&mut self, register: u8) -> u8 { unsafe {
-        self.port_1.write(register);
-        self.port_2.read()
-    } }
+    /// Reads the kernel settings from the scratch area.
+    /// Returns None if the checksum doesn't match, e.g. on first boot or after the CMOS was reset.
+    pub fn settings(&mut self) -> Option<CmosSettings> {
+        let mut bytes = [0; SCRATCH_SIZE];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.read_register(SCRATCH_START + index as u8);
+        }
 
-    fn write_register(&mut self, register: CmosRegister, value: u8) { unsafe {
-        self.port_1.write(register as u8);
-        self.port_2.write(value)
-    } }
+        let checksum_start = SCRATCH_START + SCRATCH_SIZE as u8;
+        let checksum = u16::from_le_bytes([
+            self.read_register(checksum_start),
+            self.read_register(checksum_start + 1)
+        ]);
+
+        if checksum == scratch_checksum(&bytes) {
+            Some(CmosSettings::from_bytes(bytes))
+        } else { None }
+    }
+
+    /// Writes the kernel settings to the scratch area.
+    pub fn set_settings(&mut self, settings: CmosSettings) {
+        let bytes = settings.to_bytes();
+        crate::internal::idt::without_interrupts(|| {
+            for (index, byte) in bytes.iter().enumerate() {
+                self.write_register(SCRATCH_START + index as u8, *byte);
+            }
+
+            let checksum_start = SCRATCH_START + SCRATCH_SIZE as u8;
+            let checksum = scratch_checksum(&bytes).to_le_bytes();
+            self.write_register(checksum_start, checksum[0]);
+            self.write_register(checksum_start + 1, checksum[1]);
+        })
+    }
+
+    /// Unmasks non-maskable interrupts.
+    pub fn enable_nmi(&mut self) {
+        self.nmi_enabled = true;
+        self.read_register(CmosRegister::StatusC as u8);
+    }
+
+    /// Masks non-maskable interrupts until `enable_nmi` is called. As the NMI mask shares the port
+    /// used to select a register, every register access keeps it in the requested state.
+    pub fn disable_nmi(&mut self) {
+        self.nmi_enabled = false;
+        self.read_register(CmosRegister::StatusC as u8);
+    }
+
+    fn read_register(&mut self, register: u8) -> u8 {
+        self.ports.write(INDEX_OFFSET, self.register_index(register));
+        self.ports.read(DATA_OFFSET)
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) {
+        self.ports.write(INDEX_OFFSET, self.register_index(register));
+        self.ports.write(DATA_OFFSET, value)
+    }
+
+    fn register_index(&self, register: u8) -> u8 {
+        if self.nmi_enabled { register & !NMI_DISABLE_BIT } else { register | NMI_DISABLE_BIT }
+    }
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value & 0x0F) + (value >> 4) * 10
+}
+
+fn to_bcd(value: u8) -> u8 {
+    (value / 10) << 4 | (value % 10)
+}
+
+pub fn init(century_register: u8) -> Result<(), KernelError> {
+    if CMOS.get().is_none() {
+        let ports = crate::internal::ioport::claim(CMOS_PORT, CMOS_PORT_COUNT, "CMOS")?;
+        CMOS.call_once(|| Mutex::new(Cmos::new(century_register, ports)));
+    }
+    Ok(())
+}
+
+fn scratch_checksum(bytes: &[u8; SCRATCH_SIZE]) -> u16 {
+    bytes.iter().fold(SCRATCH_CHECKSUM_SEED, |sum, byte| sum.wrapping_add(*byte as u16))
+}
+
+/// Marks the start of a new boot in the persisted settings and returns the settings as the
+/// previous boot left them (None if there were no valid settings).
+pub fn record_boot() -> Option<CmosSettings> {
+    let mut cmos = CMOS.get()?.lock();
+    let previous = cmos.settings();
+
+    let mut settings = previous.unwrap_or(CmosSettings::new());
+    settings.boot_status = BootStatus::Booting;
+    settings.boot_count = settings.boot_count.wrapping_add(1);
+    cmos.set_settings(settings);
+
+    previous
+}
+
+/// Updates the boot status in the persisted settings. Doesn't wait for the CMOS lock, so it can
+/// be used on the panic path.
+pub fn set_boot_status(status: BootStatus) {
+    if let Some(mut cmos) = CMOS.get().and_then(|cmos| cmos.try_lock()) {
+        let mut settings = cmos.settings().unwrap_or(CmosSettings::new());
+        settings.boot_status = status;
+        cmos.set_settings(settings);
+    }
+}
+
+/// Returns the clock drift estimated in an earlier boot, zero if there is none.
+pub fn clock_drift_ppm() -> i16 {
+    let Some(cmos) = CMOS.get() else { return 0; };
+    // The real-time clock interrupt takes the lock too
+    crate::internal::idt::without_interrupts(|| cmos.lock().settings())
+        .map_or(0, |settings| settings.clock_drift_ppm)
+}
+
+/// Persists the estimated clock drift for the next boot.
+pub fn set_clock_drift_ppm(drift_ppm: i16) {
+    let Some(cmos) = CMOS.get() else { return; };
+    crate::internal::idt::without_interrupts(|| {
+        let mut cmos = cmos.lock();
+        let mut settings = cmos.settings().unwrap_or(CmosSettings::new());
+        settings.clock_drift_ppm = drift_ppm;
+        cmos.set_settings(settings);
+    });
+}
+
+/// Returns the persisted settings, None if there are no valid ones.
+pub fn settings() -> Option<CmosSettings> {
+    let cmos = CMOS.get()?;
+    crate::internal::idt::without_interrupts(|| cmos.lock().settings())
 }
 
-pub fn init(century_register: u8) {
-    CMOS.call_once(|| Mutex::new(Cmos::new(century_register)));
+/// Changes the persisted settings, starting from the defaults if there are no valid ones.
+pub fn update_settings(update: impl FnOnce(&mut CmosSettings)) {
+    let Some(cmos) = CMOS.get() else { return; };
+    crate::internal::idt::without_interrupts(|| {
+        let mut cmos = cmos.lock();
+        let mut settings = cmos.settings().unwrap_or(CmosSettings::new());
+        update(&mut settings);
+        cmos.set_settings(settings);
+    });
 }
\ No newline at end of file
@@ -0,0 +1,163 @@
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Once;
+
+static MONITOR_MWAIT: Once<bool> = Once::new();
+static MWAIT_INTERRUPT_BREAK: Once<bool> = Once::new();
+static ERMS: Once<bool> = Once::new();
+static AVX: Once<bool> = Once::new();
+static X2APIC: Once<bool> = Once::new();
+static TSC_DEADLINE: Once<bool> = Once::new();
+static HUGE_PAGES: Once<bool> = Once::new();
+static RDRAND: Once<bool> = Once::new();
+static PAT: Once<bool> = Once::new();
+static EXTENDED_FEATURES: Once<u32> = Once::new();
+static MWAIT_STATES: Once<u32> = Once::new();
+static POWER_FEATURES: Once<(u32, u32)> = Once::new();
+
+/// Returns whether the CPU supports the `monitor`/`mwait` instructions.
+pub fn supports_monitor_mwait() -> bool {
+    *MONITOR_MWAIT.call_once(|| {
+        let features = unsafe { core::arch::x86_64::__cpuid(1) };
+        features.ecx & (1 << 3) != 0
+    })
+}
+
+/// Returns whether `mwait` can be woken by interrupts while they are masked. Without it, passing
+/// that extension to `mwait` raises a general protection fault.
+pub fn supports_mwait_interrupt_break() -> bool {
+    *MWAIT_INTERRUPT_BREAK.call_once(|| {
+        let max_leaf = unsafe { core::arch::x86_64::__cpuid(0).eax };
+        if !supports_monitor_mwait() || max_leaf < 5 { return false; }
+        // Bit 0 enumerates the extensions, bit 1 the interrupt break event
+        unsafe { core::arch::x86_64::__cpuid(5) }.ecx & 0b11 == 0b11
+    })
+}
+
+/// Returns whether the CPU has enhanced `rep movsb`/`rep stosb`, making them the fastest way to
+/// copy large buffers.
+pub fn supports_erms() -> bool {
+    *ERMS.call_once(|| {
+        let max_leaf = unsafe { core::arch::x86_64::__cpuid(0).eax };
+        max_leaf >= 7 && unsafe { core::arch::x86_64::__cpuid_count(7, 0).ebx } & (1 << 9) != 0
+    })
+}
+
+/// Returns whether AVX can be used, which needs both CPU support and the OS (here: whoever booted
+/// the kernel) having enabled the YMM state through `xsetbv`.
+pub fn supports_avx() -> bool {
+    *AVX.call_once(|| {
+        let features = unsafe { core::arch::x86_64::__cpuid(1) };
+        let os_xsave = features.ecx & (1 << 27) != 0;
+        if !os_xsave || features.ecx & (1 << 28) == 0 {
+            return false;
+        }
+        let xcr0 = unsafe { core::arch::x86_64::_xgetbv(0) };
+        // Both the SSE and AVX state have to be enabled
+        xcr0 & 0b110 == 0b110
+    })
+}
+
+/// Returns whether the local APIC can be run in x2APIC mode, accessed through MSRs.
+pub fn supports_x2apic() -> bool {
+    *X2APIC.call_once(|| unsafe { core::arch::x86_64::__cpuid(1) }.ecx & (1 << 21) != 0)
+}
+
+/// Returns whether the local APIC timer can fire at a time stamp counter deadline.
+pub fn supports_tsc_deadline() -> bool {
+    *TSC_DEADLINE.call_once(|| unsafe { core::arch::x86_64::__cpuid(1) }.ecx & (1 << 24) != 0)
+}
+
+/// Returns whether pages can be 2 MiB large (page size extension).
+pub fn supports_huge_pages() -> bool {
+    *HUGE_PAGES.call_once(|| unsafe { core::arch::x86_64::__cpuid(1) }.edx & (1 << 3) != 0)
+}
+
+/// Returns whether the CPU has the `rdrand` hardware random number generator.
+pub fn supports_rdrand() -> bool {
+    *RDRAND.call_once(|| unsafe { core::arch::x86_64::__cpuid(1) }.ecx & (1 << 30) != 0)
+}
+
+/// Returns whether memory types can be configured through the page attribute table.
+pub fn supports_pat() -> bool {
+    *PAT.call_once(|| unsafe { core::arch::x86_64::__cpuid(1) }.edx & (1 << 16) != 0)
+}
+
+/// Returns whether pages can be marked as not executable.
+pub fn supports_no_execute() -> bool {
+    extended_features() & (1 << 20) != 0
+}
+
+/// Returns whether the `syscall`/`sysret` instructions can be enabled.
+pub fn supports_syscall() -> bool {
+    extended_features() & (1 << 11) != 0
+}
+
+/// The EDX of the extended processor features leaf, zero if the CPU doesn't have it.
+fn extended_features() -> u32 {
+    *EXTENDED_FEATURES.call_once(|| {
+        let max_leaf = unsafe { core::arch::x86_64::__cpuid(0x8000_0000).eax };
+        if max_leaf < 0x8000_0001 { return 0; }
+        unsafe { core::arch::x86_64::__cpuid(0x8000_0001) }.edx
+    })
+}
+
+/// Returns the sub-state counts of the `mwait` C-states, four bits per C-state starting with C0.
+/// Zero if the CPU doesn't enumerate them or can't be woken from `mwait` by masked interrupts.
+pub fn mwait_states() -> u32 {
+    *MWAIT_STATES.call_once(|| {
+        if !supports_mwait_interrupt_break() { return 0; }
+        unsafe { core::arch::x86_64::__cpuid(5) }.edx
+    })
+}
+
+/// The EAX and ECX of the thermal and power management leaf, zero if the CPU doesn't have it.
+fn power_features() -> (u32, u32) {
+    *POWER_FEATURES.call_once(|| {
+        let max_leaf = unsafe { core::arch::x86_64::__cpuid(0).eax };
+        if max_leaf < 6 { return (0, 0); }
+        let leaf = unsafe { core::arch::x86_64::__cpuid(6) };
+        (leaf.eax, leaf.ecx)
+    })
+}
+
+/// Returns whether the local APIC timer keeps running in deep C-states.
+pub fn supports_always_running_apic_timer() -> bool {
+    power_features().0 & (1 << 2) != 0
+}
+
+/// Returns whether the CPU has the APERF/MPERF counters to measure its actual frequency.
+pub fn supports_aperf_mperf() -> bool {
+    power_features().1 & (1 << 0) != 0
+}
+
+/// Puts the CPU to sleep until an interrupt arrives or, if `mwait` can be woken by masked
+/// interrupts, until `wake_flag` gets written to.
+///
+/// Has to be called with interrupts disabled and returns with interrupts enabled, after any
+/// interrupt that woke the CPU has been handled.
+pub fn sleep(wake_flag: &AtomicBool) {
+    sleep_with_hint(wake_flag, 0);
+}
+
+/// Like `sleep`, but passes the hint to `mwait`, selecting the C-state to sleep in.
+pub fn sleep_with_hint(wake_flag: &AtomicBool, hint: u32) {
+    if supports_mwait_interrupt_break() {
+        unsafe {
+            asm!(
+                "monitor",
+                in("rax") wake_flag as *const AtomicBool as u64, in("ecx") 0, in("edx") 0,
+                options(nostack, preserves_flags)
+            );
+            if !wake_flag.load(Ordering::SeqCst) {
+                // ECX bit 0 lets interrupts break out of mwait even though they are masked
+                asm!("mwait", in("eax") hint, in("ecx") 1, options(nostack, preserves_flags));
+            }
+        }
+        x86_64::instructions::interrupts::enable();
+        // Pending interrupts are only delivered after the instruction following sti
+        x86_64::instructions::nop();
+    } else {
+        x86_64::instructions::interrupts::enable_and_hlt();
+    }
+}
\ No newline at end of file
@@ -0,0 +1,67 @@
+use alloc::vec::Vec;
+use aml::{AmlValue, LevelType};
+use spin::{Mutex, Once};
+use crate::internal::acpi::AcpiNamespace;
+
+static PROCESSOR_ID: &str = "ACPI0007";
+
+static PERFORMANCE_STATES: Once<Vec<PerformanceState>> = Once::new();
+/// The APERF and MPERF counters of the last frequency estimate.
+static LAST_SAMPLE: Mutex<Option<(u64, u64)>> = Mutex::new(None);
+
+/// A performance state of the CPU from the ACPI `_PSS` object, fastest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerformanceState {
+    pub frequency_mhz: u64,
+    pub power_mw: u64
+}
+
+/// Reads the performance states of the first processor in the namespace that has any, returns
+/// how many there are.
+pub fn init(aml: Option<&AcpiNamespace>) -> usize {
+    PERFORMANCE_STATES.call_once(|| {
+        let Some(aml) = aml else { return Vec::new() };
+        let mut aml = aml.lock();
+
+        // Older firmware declares processors as `Processor` objects, newer as devices
+        let mut processors = crate::internal::acpi::find_levels(&mut aml, LevelType::Processor);
+        for path in crate::internal::acpi::find_levels(&mut aml, LevelType::Device) {
+            let id = crate::internal::acpi::evaluate(&mut aml, &path, "_HID").ok()
+                .and_then(|id| crate::internal::acpi::hardware_id(&id));
+            if id.as_deref() == Some(PROCESSOR_ID) {
+                processors.push(path);
+            }
+        }
+
+        processors.iter().find_map(|path| {
+            let AmlValue::Package(states) = crate::internal::acpi::evaluate(&mut aml, path, "_PSS").ok()? else {
+                return None;
+            };
+            Some(states.iter().filter_map(|state| match state {
+                AmlValue::Package(fields) => match (fields.first(), fields.get(1)) {
+                    (Some(AmlValue::Integer(frequency_mhz)), Some(AmlValue::Integer(power_mw))) => {
+                        Some(PerformanceState { frequency_mhz: *frequency_mhz, power_mw: *power_mw })
+                    }, _ => None
+                }, _ => None
+            }).collect())
+        }).unwrap_or_default()
+    }).len()
+}
+
+pub fn performance_states() -> &'static [PerformanceState] {
+    PERFORMANCE_STATES.get().map_or(&[], |states| states.as_slice())
+}
+
+/// Estimates the average frequency the CPU ran at since the last estimate (or since it was
+/// reset), in kHz. MPERF counts at the time stamp counter's rate, so the frequency is that rate
+/// scaled by how much faster APERF counted. None without the counters or a calibrated TSC.
+pub fn estimate_khz() -> Option<u64> {
+    let (aperf, mperf) = crate::internal::msr::performance_counters()?;
+    let tsc_khz = crate::internal::tsc::khz();
+    let (last_aperf, last_mperf) = LAST_SAMPLE.lock().replace((aperf, mperf)).unwrap_or((0, 0));
+
+    let aperf = aperf.wrapping_sub(last_aperf);
+    let mperf = mperf.wrapping_sub(last_mperf);
+    if mperf == 0 || tsc_khz == 0 { return None; }
+    Some((tsc_khz as u128 * aperf as u128 / mperf as u128) as u64)
+}
\ No newline at end of file
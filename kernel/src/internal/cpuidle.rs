@@ -0,0 +1,80 @@
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spin::Once;
+
+const MAX_STATES: usize = 8;
+
+static STATES: Once<Vec<CState>> = Once::new();
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_ENTRIES: AtomicU64 = AtomicU64::new(0);
+static ENTRIES: [AtomicU64; MAX_STATES] = [NO_ENTRIES; MAX_STATES];
+
+/// An idle state of the CPU. Deeper states save more power but take longer to wake up from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CState {
+    /// The number of the state, 1 for C1.
+    pub number: u32,
+    /// The `mwait` hint selecting the state, None if the state is entered with `hlt`.
+    pub hint: Option<u32>,
+    /// The fewest ticks the CPU has to stay idle for the state to be worth entering.
+    pub min_ticks: u64
+} impl Display for CState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self.hint {
+            Some(hint) => write!(f, "C{} (mwait {:#04X})", self.number, hint),
+            None => write!(f, "C{} (hlt)", self.number)
+        }
+    }
+}
+
+/// Finds the C-states the CPU can enter through `mwait`, returns how many there are. Without
+/// `mwait` C-states only C1 through `hlt` is used.
+pub fn init() -> usize {
+    STATES.call_once(|| {
+        let sub_states = crate::internal::cpu::mwait_states();
+        // Without an always running APIC timer, deep states would stop the timer waking the CPU
+        let deepest = if crate::internal::apic::timer_active()
+            && !crate::internal::cpu::supports_always_running_apic_timer() { 1 } else { MAX_STATES as u32 - 1 };
+        let mut states: Vec<CState> = (1..=deepest)
+            .filter(|number| (sub_states >> (number * 4)) & 0xF != 0)
+            .map(|number| CState {
+                number,
+                // The hint selects the state by its number minus one, using its first sub-state
+                hint: Some((number - 1) << 4),
+                // Wakeup latency grows with depth, so deeper states need longer idle periods
+                min_ticks: 1 << (number - 1)
+            }).collect();
+        if states.is_empty() {
+            states.push(CState { number: 1, hint: None, min_ticks: 1 });
+        }
+        states
+    }).len()
+}
+
+pub fn states() -> &'static [CState] {
+    STATES.get().map_or(&[], |states| states.as_slice())
+}
+
+/// Returns how often the state at the index of `states` was entered.
+pub fn entries(index: usize) -> u64 {
+    ENTRIES.get(index).map_or(0, |entries| entries.load(Ordering::Relaxed))
+}
+
+/// Sleeps in the deepest C-state worth entering for the ticks the CPU is expected to be idle.
+/// Like `cpu::sleep`, has to be called with interrupts disabled and returns with them enabled.
+pub fn enter(idle_ticks: u64, wake_flag: &AtomicBool) {
+    let states = states();
+    let Some((index, state)) = states.iter().enumerate().rev()
+        .find(|(.., state)| state.min_ticks <= idle_ticks) else {
+        return crate::internal::cpu::sleep(wake_flag);
+    };
+
+    ENTRIES[index].fetch_add(1, Ordering::Relaxed);
+    match state.hint {
+        // The hint is passed with interrupts as break events, which not every mwait supports
+        Some(hint) if crate::internal::cpu::supports_mwait_interrupt_break() => {
+            crate::internal::cpu::sleep_with_hint(wake_flag, hint)
+        }, _ => crate::internal::cpu::sleep(wake_flag)
+    }
+}
\ No newline at end of file
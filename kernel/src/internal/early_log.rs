@@ -0,0 +1,98 @@
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use log::{Level, Record};
+use spin::Mutex;
+use crate::internal::log_buffer::FixedText;
+use crate::internal::serial::SerialLoggingLevel;
+
+const MAX_RECORDS: usize = 64;
+const TARGET_LENGTH: usize = 48;
+const MESSAGE_LENGTH: usize = 160;
+
+/// Logging goes through here from the start until the global allocator is switched to the main
+/// heap, afterwards through the main logger.
+static ACTIVE: AtomicBool = AtomicBool::new(true);
+static RECORDS: Mutex<EarlyRecords> = Mutex::new(EarlyRecords::new());
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// A record logged before the heap, kept as text of fixed capacity until it is replayed.
+#[derive(Clone, Copy)]
+struct EarlyRecord {
+    level: Level,
+    target: FixedText<TARGET_LENGTH>,
+    message: FixedText<MESSAGE_LENGTH>,
+    file: Option<&'static str>,
+    line: Option<u32>
+}
+
+struct EarlyRecords {
+    records: [EarlyRecord; MAX_RECORDS],
+    length: usize
+} impl EarlyRecords {
+    const fn new() -> Self { Self {
+        records: [EarlyRecord {
+            level: Level::Trace, target: FixedText::new(), message: FixedText::new(), file: None, line: None
+        }; MAX_RECORDS],
+        length: 0
+    } }
+}
+
+/// Returns whether logging still goes through the early logger.
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+/// Writes the record straight to the serial port and keeps it for the log buffer. Neither the
+/// transmit buffer nor the log buffer is involved, so nothing here can allocate or wait for a lock.
+pub fn log(record: &Record) {
+    let level = SerialLoggingLevel::from(record.level());
+    unsafe {
+        // Only this logger writes to the port until it hands over
+        crate::internal::serial::write_unlocked(format_args!(
+            "\n[{}#{} | {}]: {}", record.file().unwrap_or("_"), record.line().unwrap_or(0), level.as_str(),
+            crate::internal::log_fields::Plain(*record.args())
+        ));
+    }
+
+    let mut target = FixedText::new();
+    let _ = target.write_str(record.target());
+    let mut message = FixedText::new();
+    let _ = message.write_fmt(*record.args());
+    let early = EarlyRecord { level: record.level(), target, message, file: record.file_static(), line: record.line() };
+
+    crate::internal::idt::without_interrupts(|| {
+        match RECORDS.try_lock() {
+            Some(mut records) if records.length < MAX_RECORDS => {
+                let index = records.length;
+                records.records[index] = early;
+                records.length += 1;
+            }, _ => { DROPPED.fetch_add(1, Ordering::Relaxed); }
+        }
+    })
+}
+
+/// Hands logging over to the main logger and replays the early records into the log buffer.
+/// They were already sent to the serial port, so they aren't sent again.
+pub fn finish() {
+    if !ACTIVE.swap(false, Ordering::SeqCst) { return; }
+
+    let count = crate::internal::idt::without_interrupts(|| {
+        let records = RECORDS.lock();
+        for record in records.records[..records.length].iter() {
+            crate::internal::log_buffer::record(&Record::builder()
+                .level(record.level)
+                .target(record.target.as_str())
+                .args(format_args!("{}", record.message.as_str()))
+                .file_static(record.file)
+                .line(record.line)
+                .build());
+        }
+        records.length
+    });
+
+    log::info!("Early logger handed over after {} records.", count);
+    let dropped = DROPPED.load(Ordering::Relaxed);
+    if dropped > 0 {
+        log::warn!("{} early log records weren't kept for the log buffer.", dropped);
+    }
+}
\ No newline at end of file
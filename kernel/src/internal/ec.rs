@@ -0,0 +1,112 @@
+use alloc::format;
+use alloc::vec;
+use aml::AmlValue;
+use spin::{Mutex, Once};
+use crate::api::error::KernelError;
+use crate::internal::acpi::AcpiNamespace;
+use crate::internal::ioport::IoPortRange;
+use crate::internal::platform::PlatformDeviceKind;
+
+/// Status register bits: the EC has a byte for the host in the data register, or hasn't taken the
+/// last byte from the host yet.
+static OUTPUT_FULL_BIT: u8 = 0b0000_0001;
+static INPUT_FULL_BIT: u8 = 0b0000_0010;
+
+static READ_COMMAND: u8 = 0x80;
+static WRITE_COMMAND: u8 = 0x81;
+
+static POLL_LIMIT: usize = 100_000;
+
+/// The `_REG` arguments telling the firmware the EC address space can be used now.
+static EC_SPACE_ID: u64 = 3;
+static REGION_CONNECTED: u64 = 1;
+
+/// The EC address space is 256 bytes.
+pub const SPACE_SIZE: usize = 256;
+
+static EC: Once<Mutex<EmbeddedController>> = Once::new();
+
+/// The embedded controller of a laptop, talked to through a data and a command/status port as
+/// described in chapter 12 of the ACPI specification.
+struct EmbeddedController {
+    data: IoPortRange,
+    command: IoPortRange
+} impl EmbeddedController {
+    fn read(&self, offset: u8) -> Result<u8, KernelError> {
+        self.send_command(READ_COMMAND)?;
+        self.send_data(offset)?;
+        self.wait_for_output()?;
+        Ok(self.data.read(0))
+    }
+
+    fn write(&self, offset: u8, value: u8) -> Result<(), KernelError> {
+        self.send_command(WRITE_COMMAND)?;
+        self.send_data(offset)?;
+        self.send_data(value)?;
+        self.wait_for_input()
+    }
+
+    fn send_command(&self, command: u8) -> Result<(), KernelError> {
+        self.wait_for_input()?;
+        self.command.write(0, command);
+        Ok(())
+    }
+
+    fn send_data(&self, value: u8) -> Result<(), KernelError> {
+        self.wait_for_input()?;
+        self.data.write(0, value);
+        Ok(())
+    }
+
+    fn wait_for_input(&self) -> Result<(), KernelError> {
+        (0..POLL_LIMIT).any(|_| self.status() & INPUT_FULL_BIT == 0)
+            .then_some(()).ok_or(KernelError::Busy("Embedded controller"))
+    }
+
+    fn wait_for_output(&self) -> Result<(), KernelError> {
+        (0..POLL_LIMIT).any(|_| self.status() & OUTPUT_FULL_BIT != 0)
+            .then_some(()).ok_or(KernelError::Busy("Embedded controller"))
+    }
+
+    fn status(&self) -> u8 {
+        self.command.read(0)
+    }
+}
+
+/// Binds to the embedded controller if the firmware describes one, returns whether there is one.
+/// Its `_CRS` lists the data port first and the command/status port second. Once bound, the
+/// `_REG` method of the EC is run, as most firmware only touches its EC regions after that.
+pub fn init(namespace: Option<&AcpiNamespace>) -> Result<bool, KernelError> {
+    let Some(device) = crate::internal::platform::find(PlatformDeviceKind::EmbeddedController).next() else {
+        return Ok(false);
+    };
+    let mut ports = device.io_ports();
+    let (Some((data, _)), Some((command, _))) = (ports.next(), ports.next()) else {
+        return Err(KernelError::InvalidConfiguration("Embedded controller without data and command ports"));
+    };
+
+    let data = crate::internal::ioport::claim(data, 1, "EC data")?;
+    let command = crate::internal::ioport::claim(command, 1, "EC command")?;
+    EC.call_once(|| Mutex::new(EmbeddedController { data, command }));
+
+    if let Some(namespace) = namespace {
+        let args = vec![AmlValue::Integer(EC_SPACE_ID), AmlValue::Integer(REGION_CONNECTED)];
+        match namespace.evaluate(&format!("{}._REG", device.path), args) {
+            Ok(_) | Err(KernelError::HardwareMissing(_)) => {},
+            Err(err) => log::warn!("Failed to run _REG of the embedded controller: {}", err)
+        }
+    }
+    Ok(true)
+}
+
+/// Reads a byte from the EC address space.
+pub fn read(offset: u8) -> Result<u8, KernelError> {
+    let ec = EC.get().ok_or(KernelError::HardwareMissing("Embedded controller"))?;
+    ec.lock().read(offset)
+}
+
+/// Writes a byte to the EC address space.
+pub fn write(offset: u8, value: u8) -> Result<(), KernelError> {
+    let ec = EC.get().ok_or(KernelError::HardwareMissing("Embedded controller"))?;
+    ec.lock().write(offset, value)
+}
\ No newline at end of file
@@ -0,0 +1,255 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use spin::{Mutex, Once};
+use x86_64::{PhysAddr, VirtAddr};
+use x86_64::structures::paging::{FrameAllocator, Mapper, OffsetPageTable, Page, PageSize, PageTableFlags, PhysFrame, Size4KiB, Translate};
+use crate::api::error::KernelError;
+use crate::internal::protocol::EfiInformation;
+
+static SYSTEM_TABLE_SIGNATURE: u64 = 0x5453_5953_2049_4249; // "IBI SYST"
+static RUNTIME_SERVICES_SIGNATURE: u64 = 0x5652_4553_544E_5552; // "RUNTSERV"
+/// The offsets of the firmware vendor, its revision and the runtime services in the system table.
+static SYSTEM_TABLE_VENDOR: u64 = 24;
+static SYSTEM_TABLE_REVISION: u64 = 32;
+static SYSTEM_TABLE_RUNTIME_SERVICES: u64 = 88;
+/// Set on the memory map entries the firmware still needs after the boot services are gone.
+static MEMORY_RUNTIME: u64 = 1 << 63;
+static MEMORY_MAPPED_IO: u32 = 11;
+static MEMORY_MAPPED_IO_PORT_SPACE: u32 = 12;
+/// Errors have the highest bit of the status set.
+const STATUS_ERROR: u64 = 1 << 63;
+static STATUS_BUFFER_TOO_SMALL: u64 = STATUS_ERROR | 5;
+/// Vendor names are cut off here, in case the string isn't terminated.
+static MAX_VENDOR_LENGTH: usize = 256;
+
+pub static VARIABLE_NON_VOLATILE: u32 = 0x1;
+pub static VARIABLE_BOOTSERVICE_ACCESS: u32 = 0x2;
+pub static VARIABLE_RUNTIME_ACCESS: u32 = 0x4;
+
+static RUNTIME: Once<Mutex<RuntimeServices>> = Once::new();
+static FIRMWARE: Once<FirmwareInfo> = Once::new();
+
+/// Identifies the owner of an EFI variable.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Guid {
+    pub data1: u32,
+    pub data2: u16,
+    pub data3: u16,
+    pub data4: [u8; 8]
+}
+
+/// The owner of the variables defined by the UEFI specification, like `BootOrder`.
+pub static GLOBAL_VARIABLE: Guid = Guid {
+    data1: 0x8BE4DF61, data2: 0x93CA, data3: 0x11D2,
+    data4: [0xAA, 0x0D, 0x00, 0xE0, 0x98, 0x03, 0x2B, 0x8C]
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareInfo {
+    pub vendor: String,
+    pub revision: u32,
+    /// The UEFI version the firmware implements, as major and minor version.
+    pub uefi_version: (u16, u16),
+    /// The amount of pages the runtime services needed mapped.
+    pub runtime_pages: u64
+}
+
+/// The time as the firmware's clock reports it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EfiTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pad1: u8,
+    pub nanosecond: u32,
+    /// The offset to UTC in minutes, 0x7FF if the time is local time.
+    pub time_zone: i16,
+    pub daylight: u8,
+    pad2: u8
+}
+
+#[repr(C)]
+struct TableHeader {
+    signature: u64,
+    revision: u32,
+    header_size: u32,
+    crc32: u32,
+    reserved: u32
+}
+
+#[repr(C)]
+struct MemoryDescriptor {
+    kind: u32,
+    physical_start: u64,
+    virtual_start: u64,
+    page_count: u64,
+    attributes: u64
+}
+
+/// The runtime services table, with the services the kernel doesn't use left as plain pointers.
+#[repr(C)]
+struct RuntimeServicesTable {
+    header: TableHeader,
+    get_time: extern "efiapi" fn(*mut EfiTime, *mut c_void) -> u64,
+    set_time: extern "efiapi" fn(*const EfiTime) -> u64,
+    get_wakeup_time: usize,
+    set_wakeup_time: usize,
+    set_virtual_address_map: usize,
+    convert_pointer: usize,
+    get_variable: extern "efiapi" fn(*const u16, *const Guid, *mut u32, *mut usize, *mut c_void) -> u64,
+    get_next_variable_name: usize,
+    set_variable: extern "efiapi" fn(*const u16, *const Guid, u32, usize, *const c_void) -> u64
+}
+
+struct RuntimeServices {
+    table: &'static RuntimeServicesTable
+} unsafe impl Send for RuntimeServices {}
+
+/// Identity maps the memory the firmware's runtime services need, which is where the firmware
+/// expects it as the kernel never asks it to move (`SetVirtualAddressMap`), and makes the services
+/// available. Fails if the kernel wasn't booted through UEFI or the bootloader didn't pass the
+/// memory map the firmware handed over.
+pub fn init(
+    mapper: &mut OffsetPageTable, frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    physical_memory_offset: VirtAddr, efi: Option<EfiInformation>
+) -> Result<&'static FirmwareInfo, KernelError> {
+    let efi = efi.ok_or(KernelError::HardwareMissing("EFI system table"))?;
+    if efi.memory_map == 0 || efi.descriptor_size == 0 {
+        return Err(KernelError::HardwareMissing("EFI memory map"));
+    }
+    let virt = |address: u64| crate::internal::memory::phys_to_virt(physical_memory_offset, PhysAddr::new(address));
+
+    let system_table = virt(efi.system_table);
+    let header = unsafe { &*system_table.as_ptr::<TableHeader>() };
+    if header.signature != SYSTEM_TABLE_SIGNATURE {
+        return Err(KernelError::InvalidConfiguration("EFI system table has an invalid signature"));
+    }
+    let runtime_address = unsafe { *(system_table + SYSTEM_TABLE_RUNTIME_SERVICES).as_ptr::<u64>() };
+    let runtime_header = unsafe { &*virt(runtime_address).as_ptr::<TableHeader>() };
+    if runtime_header.signature != RUNTIME_SERVICES_SIGNATURE {
+        return Err(KernelError::InvalidConfiguration("EFI runtime services table has an invalid signature"));
+    }
+
+    let mut runtime_pages = 0;
+    for offset in (0..efi.memory_map_size).step_by(efi.descriptor_size as usize) {
+        let descriptor = unsafe { &*virt(efi.memory_map + offset).as_ptr::<MemoryDescriptor>() };
+        if descriptor.attributes & MEMORY_RUNTIME == 0 { continue; }
+        let mut flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        if descriptor.kind == MEMORY_MAPPED_IO || descriptor.kind == MEMORY_MAPPED_IO_PORT_SPACE {
+            flags |= PageTableFlags::NO_CACHE;
+        }
+        runtime_pages += identity_map(mapper, frame_allocator, descriptor.physical_start, descriptor.page_count, flags)?;
+    }
+
+    let vendor_address = unsafe { *(system_table + SYSTEM_TABLE_VENDOR).as_ptr::<u64>() };
+    let vendor = if vendor_address == 0 { String::new() } else {
+        let vendor = virt(vendor_address).as_ptr::<u16>();
+        let length = (0..MAX_VENDOR_LENGTH).find(|&index| unsafe { *vendor.add(index) } == 0).unwrap_or(MAX_VENDOR_LENGTH);
+        String::from_utf16_lossy(unsafe { core::slice::from_raw_parts(vendor, length) })
+    };
+
+    RUNTIME.call_once(|| Mutex::new(RuntimeServices {
+        // Reached through the identity mapping, like the firmware itself reaches it
+        table: unsafe { &*(runtime_address as *const RuntimeServicesTable) }
+    }));
+    Ok(FIRMWARE.call_once(|| FirmwareInfo {
+        vendor,
+        revision: unsafe { *(system_table + SYSTEM_TABLE_REVISION).as_ptr::<u32>() },
+        uefi_version: ((header.revision >> 16) as u16, (header.revision & 0xFFFF) as u16),
+        runtime_pages
+    }))
+}
+
+/// Reads the firmware's clock.
+pub fn time() -> Result<EfiTime, KernelError> {
+    let mut time = EfiTime::default();
+    with_runtime(|runtime| (runtime.table.get_time)(&mut time, core::ptr::null_mut()))?;
+    Ok(time)
+}
+
+/// Sets the firmware's clock.
+#[allow(dead_code)]
+pub fn set_time(time: &EfiTime) -> Result<(), KernelError> {
+    with_runtime(|runtime| (runtime.table.set_time)(time))
+}
+
+/// Reads a variable together with its attributes.
+pub fn variable(name: &str, vendor: &Guid) -> Result<(Vec<u8>, u32), KernelError> {
+    let name = encode_name(name);
+    let mut attributes = 0;
+    let mut data = Vec::new();
+    loop {
+        let mut size = data.len();
+        let result = with_runtime(|runtime| (runtime.table.get_variable)(
+            name.as_ptr(), vendor, &mut attributes, &mut size, data.as_mut_ptr() as *mut c_void
+        ));
+        match result {
+            Ok(()) => {
+                data.truncate(size);
+                return Ok((data, attributes));
+            }, Err(KernelError::Firmware(status)) if status == STATUS_BUFFER_TOO_SMALL && size > data.len() => {
+                data.resize(size, 0);
+            }, Err(err) => return Err(err)
+        }
+    }
+}
+
+/// Writes a variable, an empty one gets deleted.
+pub fn set_variable(name: &str, vendor: &Guid, attributes: u32, data: &[u8]) -> Result<(), KernelError> {
+    let name = encode_name(name);
+    with_runtime(|runtime| (runtime.table.set_variable)(
+        name.as_ptr(), vendor, attributes, data.len(), data.as_ptr() as *const c_void
+    ))
+}
+
+/// Returns the name of an EFI status code.
+pub fn status_name(status: u64) -> &'static str {
+    match status & !STATUS_ERROR {
+        0 => "Success",
+        1 => "Load error",
+        2 => "Invalid parameter",
+        3 => "Unsupported",
+        4 => "Bad buffer size",
+        5 => "Buffer too small",
+        6 => "Not ready",
+        7 => "Device error",
+        8 => "Write protected",
+        9 => "Out of resources",
+        14 => "Not found",
+        15 => "Access denied",
+        26 => "Security violation",
+        _ => "Unknown error"
+    }
+}
+
+/// Calls into the firmware, which isn't reentrant and must not be interrupted by the kernel's
+/// handlers, so interrupts are off for the whole call.
+fn with_runtime(func: impl FnOnce(&RuntimeServices) -> u64) -> Result<(), KernelError> {
+    let runtime = RUNTIME.get().ok_or(KernelError::HardwareMissing("EFI runtime services"))?;
+    let status = crate::internal::idt::without_interrupts(|| func(&runtime.lock()));
+    if status & STATUS_ERROR != 0 { Err(KernelError::Firmware(status)) } else { Ok(()) }
+}
+
+/// Maps the pages at their physical addresses, skipping the ones already mapped that way.
+fn identity_map(
+    mapper: &mut OffsetPageTable, frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    start: u64, page_count: u64, flags: PageTableFlags
+) -> Result<u64, KernelError> {
+    for index in 0..page_count {
+        let address = PhysAddr::new(start + index * Size4KiB::SIZE);
+        if mapper.translate_addr(VirtAddr::new(address.as_u64())) == Some(address) { continue; }
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(address.as_u64()));
+        unsafe { mapper.map_to(page, PhysFrame::containing_address(address), flags, frame_allocator)?.flush() };
+    }
+    Ok(page_count)
+}
+
+fn encode_name(name: &str) -> Vec<u16> {
+    name.encode_utf16().chain(core::iter::once(0)).collect()
+}
\ No newline at end of file
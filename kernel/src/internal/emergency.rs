@@ -0,0 +1,164 @@
+use core::arch::asm;
+use core::fmt::{Arguments, Write};
+use core::ptr::addr_of_mut;
+use core::sync::atomic::{AtomicBool, Ordering};
+use embedded_graphics::image::GetPixel;
+use embedded_graphics::mono_font::MonoFont;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::{OriginDimensions, Point};
+use crate::api::display::{Color, Colors, Fonts};
+use crate::internal::cmos::BootStatus;
+use crate::internal::framebuffer::FrameBufferInfo;
+
+const STACK_SIZE: usize = 4096 * 16;
+const MESSAGE_SIZE: usize = 2048;
+
+static HEADER: &str = "Kernel Panic -- please reboot your machine! See message below:";
+
+/// Set by the first abort, any abort after that (like a panic while rendering) just halts.
+static ABORTING: AtomicBool = AtomicBool::new(false);
+
+#[repr(C, align(16))]
+struct EmergencyStack([u8; STACK_SIZE]);
+
+static mut STACK: EmergencyStack = EmergencyStack([0; STACK_SIZE]);
+static mut MESSAGE: MessageBuffer = MessageBuffer { bytes: [0; MESSAGE_SIZE], length: 0 };
+
+/// Stops the kernel and shows the message on the serial port and the screen. Switches to its own
+/// stack and only uses pre-allocated buffers, without taking any locks, so it still works when the
+/// heap is corrupted, the stack is exhausted or the interrupted code holds the frame buffer.
+pub fn abort(message: Arguments) -> ! {
+    x86_64::instructions::interrupts::disable();
+    if ABORTING.swap(true, Ordering::SeqCst) {
+        halt();
+    }
+
+    // The message is only formatted on the emergency stack, the arguments stay where they are
+    unsafe {
+        let stack_top = addr_of_mut!(STACK) as usize + STACK_SIZE;
+        asm!(
+            "mov rsp, {stack_top}",
+            "call {main}",
+            stack_top = in(reg) stack_top,
+            main = sym emergency_main,
+            in("rdi") &message as *const Arguments,
+            options(noreturn)
+        );
+    }
+}
+
+extern "C" fn emergency_main(message: *const Arguments) -> ! {
+    let buffer = unsafe { &mut *addr_of_mut!(MESSAGE) };
+    // Messages longer than the buffer are cut off, which is better than not showing them
+    let _ = buffer.write_fmt(unsafe { *message });
+    let message = buffer.as_str();
+    // What was logged before is sent first, unless the logger is what got interrupted
+    crate::internal::serial::flush();
+    unsafe { crate::internal::serial::write_unlocked(format_args!(
+        "\n[PANIC]: Kernel panicked with message '{}'\n[PANIC]: {}\n", message, crate::build_info()
+    )) }
+    crate::internal::cmos::set_boot_status(BootStatus::Panicked);
+
+    let _ = unsafe { crate::internal::framebuffer::with_framebuffer_unlocked(|fb, info| {
+        let mut blitter = Blitter { fb, info, font: Fonts::Font9x18.into() };
+        blitter.fill(Colors::Blue.into());
+        blitter.draw_text(HEADER, 0, 0, Colors::White.into());
+        blitter.draw_text(message, 0, blitter.font.character_size.height as usize, Colors::White.into());
+        blitter.draw_build_info();
+    }) };
+
+    halt();
+}
+
+fn halt() -> ! {
+    loop { x86_64::instructions::hlt(); }
+}
+
+/// Fixed-size buffer the abort message is formatted into, the heap might be what is broken.
+struct MessageBuffer {
+    bytes: [u8; MESSAGE_SIZE],
+    length: usize
+} impl MessageBuffer {
+    fn as_str(&self) -> &str {
+        // The cut off might have split a character, only the valid part is shown
+        match core::str::from_utf8(&self.bytes[..self.length]) {
+            Ok(message) => message,
+            Err(err) => core::str::from_utf8(&self.bytes[..err.valid_up_to()]).unwrap_or("")
+        }
+    }
+} impl Write for MessageBuffer {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let count = s.len().min(self.bytes.len() - self.length);
+        self.bytes[self.length..self.length + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.length += count;
+        Ok(())
+    }
+}
+
+/// Draws text by copying the glyphs of a monospace font straight into the frame buffer.
+struct Blitter<'a> {
+    fb: &'a mut [u8],
+    info: FrameBufferInfo,
+    font: MonoFont<'static>
+} impl Blitter<'_> {
+    fn fill(&mut self, color: Color) {
+        for y in 0..self.info.height {
+            for x in 0..self.info.width {
+                self.set_pixel(x, y, color);
+            }
+        }
+    }
+
+    /// Draws the text starting at the pixel position, wrapping at newlines and the screen edge.
+    fn draw_text(&mut self, text: &str, x: usize, y: usize, color: Color) {
+        let (width, height) = (self.font.character_size.width as usize, self.font.character_size.height as usize);
+        let (mut column, mut row) = (x, y);
+        for character in text.chars() {
+            if character == '\n' || column + width > self.info.width {
+                column = x;
+                row += height;
+                if character == '\n' { continue; }
+            }
+            if row + height > self.info.height { return; }
+            self.draw_char(character, column, row, color);
+            column += width + self.font.character_spacing as usize;
+        }
+    }
+
+    /// Draws what the kernel was built from on the last row, so screenshots of a panic tell the build.
+    fn draw_build_info(&mut self) {
+        let mut text = MessageBuffer { bytes: [0; MESSAGE_SIZE], length: 0 };
+        let _ = write!(text, "{}", crate::build_info());
+        let height = self.font.character_size.height as usize;
+        self.draw_text(text.as_str(), 0, self.info.height.saturating_sub(height), Colors::White.into());
+    }
+
+    fn draw_char(&mut self, character: char, x: usize, y: usize, color: Color) {
+        let size = self.font.character_size;
+        let glyphs_per_row = (self.font.image.size().width / size.width).max(1);
+        let glyph = self.font.glyph_mapping.index(character) as u32;
+        let origin = Point::new(
+            ((glyph % glyphs_per_row) * size.width) as i32,
+            ((glyph / glyphs_per_row) * size.height) as i32
+        );
+
+        for glyph_y in 0..size.height {
+            for glyph_x in 0..size.width {
+                let pixel = self.font.image.pixel(origin + Point::new(glyph_x as i32, glyph_y as i32));
+                if pixel == Some(BinaryColor::On) {
+                    self.set_pixel(x + glyph_x as usize, y + glyph_y as usize, color);
+                }
+            }
+        }
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        if x >= self.info.width || y >= self.info.height { return; }
+        let offset = (y * self.info.stride + x) * self.info.bytes_per_pixel;
+        let Some(pixel) = self.fb.get_mut(offset..offset + self.info.bytes_per_pixel) else { return; };
+        if let Some(value) = self.info.pixel_format.encode(color.red, color.green, color.blue) {
+            let length = pixel.len().min(4);
+            pixel[..length].copy_from_slice(&value.to_le_bytes()[..length]);
+        }
+    }
+}
\ No newline at end of file
@@ -1,37 +1,297 @@
-use bootloader_api::info::FrameBufferInfo;
-use spin::Lazy;
+use spin::{Lazy, Once};
 use spin::lock_api::Mutex;
+use x86_64::VirtAddr;
+use x86_64::structures::paging::{FrameAllocator, Mapper, OffsetPageTable, Page, PageTableFlags, Size4KiB, Translate};
+use x86_64::structures::paging::mapper::TranslateResult;
+use crate::api::error::KernelError;
+use crate::internal::memory::MappingStats;
 
-static FRAMEBUFFER: Lazy<Mutex<Option<&'static mut [u8]>>> = Lazy::new(|| {
-    Mutex::new(None)
+/// Where `remap` maps the frame buffer, aligned to 2 MiB.
+static FRAMEBUFFER_START: u64 = 0x_5555_5540_0000;
+
+/// How many screens can be driven at once. Output 0 is the primary one the bootloader set up first.
+pub const MAX_OUTPUTS: usize = 4;
+
+/// Describes the layout of the frame buffer, independent of the boot protocol that provided it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameBufferInfo {
+    /// The total size of the frame buffer in bytes.
+    pub byte_len: usize,
+    /// The visible width in pixels.
+    pub width: usize,
+    /// The visible height in pixels.
+    pub height: usize,
+    pub pixel_format: PixelFormat,
+    pub bytes_per_pixel: usize,
+    /// The amount of pixels (not bytes) between the starts of two lines.
+    pub stride: usize
+}
+
+/// The order of the color channels in a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum PixelFormat {
+    Rgb,
+    Bgr,
+    /// One byte of grayscale per pixel.
+    U8,
+    /// Channels at arbitrary bit positions and sizes, like the 15 and 16 bit modes of VGA devices.
+    BitMask { red: ChannelMask, green: ChannelMask, blue: ChannelMask },
+    /// Every pixel is an index into the palette set with `set_palette`.
+    Indexed,
+    Unknown
+} #[allow(dead_code)] impl PixelFormat {
+    /// Builds a bit-masked format, using the faster fixed formats where they match.
+    pub fn from_masks(red: ChannelMask, green: ChannelMask, blue: ChannelMask) -> Self {
+        let byte = |shift| ChannelMask::new(shift, 8);
+        match (red, green, blue) {
+            (red, green, blue) if red == byte(0) && green == byte(8) && blue == byte(16) => PixelFormat::Rgb,
+            (red, green, blue) if red == byte(16) && green == byte(8) && blue == byte(0) => PixelFormat::Bgr,
+            _ => PixelFormat::BitMask { red, green, blue }
+        }
+    }
+
+    /// Encodes the color as the little endian value of a pixel, None if the format is unknown.
+    #[inline]
+    pub fn encode(&self, red: u8, green: u8, blue: u8) -> Option<u32> {
+        match self {
+            PixelFormat::Rgb => Some(red as u32 | (green as u32) << 8 | (blue as u32) << 16),
+            PixelFormat::Bgr => Some(blue as u32 | (green as u32) << 8 | (red as u32) << 16),
+            PixelFormat::U8 => Some((red as u32 + green as u32 + blue as u32) / 3),
+            PixelFormat::BitMask { red: red_mask, green: green_mask, blue: blue_mask } => Some(
+                red_mask.encode(red) | green_mask.encode(green) | blue_mask.encode(blue)
+            ), PixelFormat::Indexed => PALETTE.get().map(|palette| palette.index_of(red, green, blue) as u32),
+            PixelFormat::Unknown => None
+        }
+    }
+
+    /// Decodes the little endian value of a pixel into its color channels.
+    #[inline]
+    pub fn decode(&self, value: u32) -> Option<(u8, u8, u8)> {
+        match self {
+            PixelFormat::Rgb => Some((value as u8, (value >> 8) as u8, (value >> 16) as u8)),
+            PixelFormat::Bgr => Some(((value >> 16) as u8, (value >> 8) as u8, value as u8)),
+            PixelFormat::U8 => Some((value as u8, value as u8, value as u8)),
+            PixelFormat::BitMask { red, green, blue } => Some((
+                red.decode(value), green.decode(value), blue.decode(value)
+            )), PixelFormat::Indexed => PALETTE.get().map(|palette| palette.color_of(value as u8)),
+            PixelFormat::Unknown => None
+        }
+    }
+}
+
+/// Position and size of a color channel within a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelMask {
+    pub shift: u8,
+    /// The amount of bits, at most 8.
+    pub size: u8
+} impl ChannelMask {
+    pub fn new(shift: u8, size: u8) -> Self { Self {
+        shift, size: size.min(8)
+    } }
+
+    #[inline]
+    fn encode(&self, value: u8) -> u32 {
+        if self.size == 0 { return 0; }
+        ((value >> (8 - self.size)) as u32) << self.shift
+    }
+
+    #[inline]
+    fn decode(&self, value: u32) -> u8 {
+        if self.size == 0 { return 0; }
+        let max = (1u32 << self.size) - 1;
+        let channel = (value >> self.shift) & max;
+        // Scaled, so the largest value of a smaller channel still maps to 255
+        ((channel * 255 + max / 2) / max) as u8
+    }
+}
+
+static PALETTE: Once<Palette> = Once::new();
+
+/// The colors of an indexed frame buffer, with a table mapping colors to their closest index.
+struct Palette {
+    colors: [(u8, u8, u8); 256],
+    /// Closest palette index for every color with 4 bits per channel.
+    lookup: [u8; 4096]
+} #[allow(dead_code)] impl Palette {
+    fn new(colors: &[(u8, u8, u8)]) -> Self {
+        let mut palette = Self { colors: [(0, 0, 0); 256], lookup: [0; 4096] };
+        let count = colors.len().min(256);
+        palette.colors[..count].copy_from_slice(&colors[..count]);
+
+        for (key, entry) in palette.lookup.iter_mut().enumerate() {
+            let expand = |nibble: usize| (nibble as i32) * 17;
+            let (red, green, blue) = (expand(key >> 8), expand((key >> 4) & 0xF), expand(key & 0xF));
+            *entry = colors[..count].iter().enumerate()
+                .min_by_key(|(.., color)| {
+                    let (dr, dg, db) = (color.0 as i32 - red, color.1 as i32 - green, color.2 as i32 - blue);
+                    dr * dr + dg * dg + db * db
+                })
+                .map_or(0, |(index, ..)| index as u8);
+        }
+        palette
+    }
+
+    #[inline]
+    fn index_of(&self, red: u8, green: u8, blue: u8) -> u8 {
+        self.lookup[((red as usize >> 4) << 8) | ((green as usize >> 4) << 4) | (blue as usize >> 4)]
+    }
+
+    #[inline]
+    fn color_of(&self, index: u8) -> (u8, u8, u8) {
+        self.colors[index as usize]
+    }
+}
+
+/// Sets the palette of an indexed frame buffer. Builds the color lookup table, so it can only be
+/// set once, at frame buffer initialization.
+#[allow(dead_code)]
+pub fn set_palette(colors: &[(u8, u8, u8)]) {
+    PALETTE.call_once(|| Palette::new(colors));
+}
+
+/// Maps the frame buffer again at `FRAMEBUFFER_START`, using 2 MiB pages where its physical address
+/// allows it, as the bootloader maps it with 4 KiB pages. The new mapping is write-combining where
+/// the page attribute table allows it, so writes to the screen get merged instead of going out one
+/// by one. The bootloader's mapping is removed. `capacity` is how much video memory follows the
+/// start of the buffer, the returned buffer covers it all so the resolution can be raised later.
+/// Returns the original buffer if it isn't physically contiguous or mapping fails.
+pub fn remap(
+    mapper: &mut OffsetPageTable, frame_allocator: &mut impl FrameAllocator<Size4KiB>, buffer: &'static mut [u8],
+    capacity: usize
+) -> (&'static mut [u8], Result<MappingStats, KernelError>) {
+    let (pointer, length) = (buffer.as_mut_ptr(), buffer.len());
+    let mapped_length = capacity.max(length);
+    let start = VirtAddr::from_ptr(pointer);
+
+    let TranslateResult::Mapped { frame, offset, flags } = mapper.translate(start) else {
+        return (buffer, Err(KernelError::InvalidConfiguration("Frame buffer is not mapped")));
+    };
+    let physical_start = frame.start_address() + offset;
+    let contiguous = (0..length as u64).step_by(4096)
+        .all(|offset| mapper.translate_addr(start + offset) == Some(physical_start + offset));
+    if !contiguous {
+        return (buffer, Err(KernelError::InvalidConfiguration("Frame buffer is not physically contiguous")));
+    }
+
+    let flags = crate::internal::memory::write_combining(flags & (PageTableFlags::PRESENT | PageTableFlags::WRITABLE
+        | PageTableFlags::WRITE_THROUGH | PageTableFlags::NO_CACHE | PageTableFlags::GLOBAL | PageTableFlags::NO_EXECUTE));
+    // At the same offset into a 2 MiB page as the physical address, so both get aligned at once
+    let first_frame = physical_start.align_down(4096u64);
+    let target = VirtAddr::new(FRAMEBUFFER_START) + first_frame.as_u64() % (2 * 1024 * 1024);
+    let frame_offset = physical_start - first_frame;
+    let result = crate::internal::memory::map_range(
+        mapper, frame_allocator, target, frame_offset + mapped_length as u64, flags,
+        |_, offset, _| Some(first_frame + offset)
+    );
+    match result {
+        Ok(stats) => {
+            // The old mapping would alias the new one with a different memory type
+            let pages = Page::<Size4KiB>::range_inclusive(
+                Page::containing_address(start), Page::containing_address(start + (length as u64 - 1))
+            );
+            for page in pages {
+                if let Ok((_, flush)) = mapper.unmap(page) {
+                    flush.flush();
+                }
+            }
+            let buffer = unsafe { core::slice::from_raw_parts_mut((target + frame_offset).as_mut_ptr(), mapped_length) };
+            (buffer, Ok(stats))
+        }, Err(err) => (unsafe { core::slice::from_raw_parts_mut(pointer, length) }, Err(err.into()))
+    }
+}
+
+/// Clears the buffer and returns how many time stamp counter cycles that took, to compare mappings.
+pub fn measure_clear(buffer: &mut [u8]) -> u64 {
+    let start = crate::internal::tsc::read();
+    buffer.fill(0);
+    crate::internal::tsc::read().saturating_sub(start)
+}
+
+static FRAMEBUFFERS: Lazy<[Mutex<Option<&'static mut [u8]>>; MAX_OUTPUTS]> = Lazy::new(|| {
+    core::array::from_fn(|_| Mutex::new(None))
 });
 
-static FRAMEBUFFER_INFO: Lazy<Mutex<Option<FrameBufferInfo>>> = Lazy::new(|| {
-    Mutex::new(None)
+static FRAMEBUFFER_INFOS: Lazy<[Mutex<Option<FrameBufferInfo>>; MAX_OUTPUTS]> = Lazy::new(|| {
+    core::array::from_fn(|_| Mutex::new(None))
 });
 
+/// Sets the primary frame buffer up. The buffer can be longer than the visible screen, the rest is
+/// used when `set_info` raises the resolution.
 pub fn init(frame_buffer_info: FrameBufferInfo, frame_buffer: &'static mut [u8]) {
-    let mut fb_guard = FRAMEBUFFER.lock();
-    *fb_guard = Some(frame_buffer);
+    init_output(0, frame_buffer_info, frame_buffer);
+}
 
-    let mut info_guard = FRAMEBUFFER_INFO.lock();
-    *info_guard = Some(frame_buffer_info);
+/// Sets the frame buffer of the output up. Outputs past `MAX_OUTPUTS` are ignored.
+pub fn init_output(output: usize, frame_buffer_info: FrameBufferInfo, frame_buffer: &'static mut [u8]) {
+    let (Some(fb), Some(info)) = (FRAMEBUFFERS.get(output), FRAMEBUFFER_INFOS.get(output)) else { return; };
+    *fb.lock() = Some(frame_buffer);
+    *info.lock() = Some(frame_buffer_info);
+}
+
+/// Returns the outputs that have a frame buffer.
+pub fn outputs() -> impl Iterator<Item = usize> {
+    (0..MAX_OUTPUTS).filter(|&output| FRAMEBUFFER_INFOS[output].lock().is_some())
+}
+
+pub fn with_framebuffer<F, R>(func: F) -> Result<R, KernelError>
+    where F: FnOnce(&mut [u8], FrameBufferInfo) -> R {
+    with_output(0, func)
 }
 
-pub fn with_framebuffer<F, R>(func: F) -> Option<R>
+/// Calls the function with the frame buffer of the output and its layout.
+pub fn with_output<F, R>(output: usize, func: F) -> Result<R, KernelError>
     where F: FnOnce(&mut [u8], FrameBufferInfo) -> R {
 
-    let mut fb_guard = FRAMEBUFFER.lock();
-    let info_guard = FRAMEBUFFER_INFO.lock();
+    let (Some(fb), Some(info)) = (FRAMEBUFFERS.get(output), FRAMEBUFFER_INFOS.get(output)) else {
+        return Err(KernelError::NoFrameBuffer);
+    };
+    let mut fb_guard = fb.lock();
+    let info_guard = info.lock();
 
     if let (Some(fb), Some(info)) = (&mut *fb_guard, &*info_guard) {
-        Some(func(fb, *info))
-    } else { None }
+        let length = info.byte_len.min(fb.len());
+        Ok(func(&mut fb[..length], *info))
+    } else { Err(KernelError::NoFrameBuffer) }
+}
+
+/// Replaces the layout of the primary frame buffer after the display hardware switched its mode.
+/// Fails if the new layout needs more memory than is mapped.
+pub fn set_info(frame_buffer_info: FrameBufferInfo) -> Result<(), KernelError> {
+    let fb_guard = FRAMEBUFFERS[0].lock();
+    let mut info_guard = FRAMEBUFFER_INFOS[0].lock();
+
+    let capacity = fb_guard.as_ref().ok_or(KernelError::NoFrameBuffer)?.len();
+    if frame_buffer_info.byte_len > capacity {
+        return Err(KernelError::InvalidConfiguration("Resolution needs more video memory than is mapped"));
+    }
+    *info_guard = Some(frame_buffer_info);
+    Ok(())
+}
+
+/// Returns how many bytes of video memory are mapped, at least the size of the visible screen.
+pub fn capacity() -> usize {
+    FRAMEBUFFERS[0].lock().as_ref().map_or(0, |fb| fb.len())
 }
 
 pub fn is_initialized() -> bool {
-    let fb_guard = FRAMEBUFFER.lock();
-    let info_guard = FRAMEBUFFER_INFO.lock();
+    let fb_guard = FRAMEBUFFERS[0].lock();
+    let info_guard = FRAMEBUFFER_INFOS[0].lock();
 
     fb_guard.is_some() && info_guard.is_some()
+}
+
+/// Accesses the primary frame buffer without taking its locks. Only meant for fatal paths (e.g. a
+/// double fault) where the interrupted code might still be holding them.
+pub unsafe fn with_framebuffer_unlocked<F, R>(func: F) -> Result<R, KernelError>
+    where F: FnOnce(&mut [u8], FrameBufferInfo) -> R {
+
+    let fb = &mut *FRAMEBUFFERS[0].data_ptr();
+    let info = &*FRAMEBUFFER_INFOS[0].data_ptr();
+
+    if let (Some(fb), Some(info)) = (fb, info) {
+        let length = info.byte_len.min(fb.len());
+        Ok(func(&mut fb[..length], *info))
+    } else { Err(KernelError::NoFrameBuffer) }
 }
\ No newline at end of file
@@ -1,18 +1,32 @@
 use alloc::collections::VecDeque;
 use core::alloc::{GlobalAlloc, Layout};
-use core::sync::atomic::{AtomicBool, Ordering};
-use bootloader_api::info::MemoryRegions;
+use core::fmt::{Display, Formatter};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use linked_list_allocator::LockedHeap;
 use x86_64::VirtAddr;
-use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB};
+use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageSize, PageTableFlags, PhysFrame, Size2MiB, Size4KiB};
 use x86_64::structures::paging::mapper::MapToError;
+use crate::api::error::KernelError;
+use crate::api::event::Event;
+use crate::internal::protocol::MemoryRegion;
+use crate::internal::symbols::Symbol;
 
 pub const INITIAL_HEAP_START: usize = 0x_1111_1111_0000;
 pub const INITIAL_HEAP_SIZE: usize = 1024 * 1024 * 2; // 2 MiB
 
-pub const MAIN_HEAP_START: usize = 0x_4444_4444_0000;
 pub const MAIN_HEAP_SIZE: usize = 1024 * 1024 * 128; // 128 MiB
 
+static LOW_WATERMARK_PERCENT: usize = 75;
+static CRITICAL_WATERMARK_PERCENT: usize = 90;
+/// Return addresses kept for a failed allocation, enough to get past the allocation error path.
+const ALLOC_ERROR_FRAMES: usize = 12;
+static PRESSURE: AtomicU8 = AtomicU8::new(MemoryPressure::Normal as u8);
+
+/// Returns where the main heap starts, which is randomized at boot unless KASLR is turned off.
+pub fn main_heap_start() -> usize {
+    crate::internal::kaslr::layout().main_heap
+}
+
 #[global_allocator]
 static ALLOCATOR: HeapManager = HeapManager::new();
 
@@ -20,19 +34,82 @@ pub fn init_allocator() {
     ALLOCATOR.init();
 }
 
+/// How close the heap is to running out, by the watermarks it crossed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum MemoryPressure {
+    Normal = 0,
+    /// Caches should shrink.
+    Low = 1,
+    /// Everything that can be freed should be, the next allocations might fail.
+    Critical = 2
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapUsage {
+    pub used: usize,
+    pub size: usize
+} impl HeapUsage {
+    pub fn pressure(&self) -> MemoryPressure {
+        let percent = self.used * 100 / self.size.max(1);
+        if percent >= CRITICAL_WATERMARK_PERCENT {
+            MemoryPressure::Critical
+        } else if percent >= LOW_WATERMARK_PERCENT {
+            MemoryPressure::Low
+        } else { MemoryPressure::Normal }
+    }
+}
+
+/// Returns how much of the current heap is in use.
+pub fn usage() -> HeapUsage {
+    let heap = ALLOCATOR.current_heap().lock();
+    HeapUsage { used: heap.used(), size: heap.size() }
+}
+
+/// Returns how much of the current heap is in use like `usage`, or None if the heap is locked. For
+/// interrupt handlers, which may have interrupted an allocation.
+pub fn try_usage() -> Option<HeapUsage> {
+    let heap = ALLOCATOR.current_heap().try_lock()?;
+    Some(HeapUsage { used: heap.used(), size: heap.size() })
+}
+
+/// Pushes a memory pressure event if the heap crossed a watermark since the last check. The
+/// allocator can't push events itself, as queueing the event allocates.
+pub fn check_pressure() {
+    let pressure = usage().pressure();
+    if PRESSURE.swap(pressure as u8, Ordering::SeqCst) != pressure as u8 {
+        crate::api::event::EventDispatcher::global().push(Event::MemoryPressure(pressure));
+    }
+}
+
 pub struct HeapManager {
     initial_heap: LockedHeap,
     main_heap: LockedHeap,
+    /// Set once the initial heap is mapped, allocating before is a bug.
+    ready: AtomicBool,
     initialized: AtomicBool,
 } impl HeapManager {
     const fn new() -> Self { Self {
         initial_heap: LockedHeap::empty(),
         main_heap: LockedHeap::empty(),
+        ready: AtomicBool::new(false),
         initialized: AtomicBool::new(false),
     } }
 
     unsafe fn init_initial_heap(&self, start: usize, size: usize) {
         self.initial_heap.lock().init(start as *mut u8, size);
+        self.ready.store(true, Ordering::SeqCst);
+    }
+
+    /// Stops the kernel at an allocation made before there is a heap, naming where it came from.
+    /// Without this it would only fail as an out of memory error of the empty initial heap.
+    fn assert_ready(&self, layout: Layout) {
+        if !self.ready.load(Ordering::SeqCst) {
+            crate::internal::emergency::abort(format_args!(
+                "Allocated {} bytes before the heap exists for {}",
+                layout.size(), AllocationSite::<ALLOC_ERROR_FRAMES>::capture()
+            ))
+        }
     }
 
     unsafe fn init_main_heap(&self, start: usize, size: usize) {
@@ -42,29 +119,105 @@ pub struct HeapManager {
     fn init(&self) {
         self.initialized.store(true, Ordering::SeqCst);
     }
-} unsafe impl GlobalAlloc for HeapManager {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+
+    fn current_heap(&self) -> &LockedHeap {
         if self.initialized.load(Ordering::SeqCst) {
-            self.main_heap.alloc(layout)
+            &self.main_heap
         } else {
-            self.initial_heap.alloc(layout)
+            &self.initial_heap
         }
     }
+} unsafe impl GlobalAlloc for HeapManager {
+    #[cfg(not(feature = "heap-debug"))]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.assert_ready(layout);
+        self.current_heap().alloc(layout)
+    }
 
+    #[cfg(not(feature = "heap-debug"))]
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        if self.initialized.load(Ordering::SeqCst) {
-            self.main_heap.dealloc(ptr, layout)
-        } else {
-            self.initial_heap.dealloc(ptr, layout)
+        self.current_heap().dealloc(ptr, layout)
+    }
+
+    #[cfg(feature = "heap-debug")]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.assert_ready(layout);
+        crate::internal::heap_debug::alloc(self.current_heap(), layout)
+    }
+
+    #[cfg(feature = "heap-debug")]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        crate::internal::heap_debug::dealloc(self.current_heap(), ptr, layout)
+    }
+}
+
+/// The return addresses of the frames that led to an allocation.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct AllocationSite<const FRAMES: usize>([u64; FRAMES]);
+
+impl<const FRAMES: usize> AllocationSite<FRAMES> {
+    /// Walks the frame pointer chain, which is why the kernel is built with frame pointers.
+    #[inline(always)]
+    pub fn capture() -> Self {
+        let mut site = [0; FRAMES];
+        let mut frame: *const u64;
+        unsafe { core::arch::asm!("mov {}, rbp", out(reg) frame, options(nomem, nostack)) };
+        for return_address in site.iter_mut() {
+            if (frame as u64) < 0x1000 || frame as u64 % 8 != 0 { break; }
+            unsafe {
+                *return_address = *frame.add(1);
+                frame = *frame as *const u64;
+            }
         }
+        Self(site)
     }
+
+    /// Returns the first frame outside of the allocator.
+    fn caller(&self) -> Option<(u64, Option<Symbol>)> {
+        self.0.iter().filter(|address| **address != 0)
+            .map(|address| (*address, crate::internal::symbols::resolve(*address)))
+            .find(|(.., symbol)| !symbol.is_some_and(|symbol| is_allocator(symbol.name)))
+    }
+} impl<const FRAMES: usize> Display for AllocationSite<FRAMES> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self.caller() {
+            Some((address, Some(symbol))) => write!(f, "{} ({:#X})", symbol, address),
+            Some((address, None)) => write!(f, "{:#X}", address),
+            None => write!(f, "an unknown site")
+        }
+    }
+}
+
+fn is_allocator(name: &str) -> bool {
+    name.starts_with("__r") || ["5alloc", "alloc..", "alloc_error", "8internal4heap", "heap_debug", "HeapManager"]
+        .iter().any(|part| name.contains(part))
+}
+
+/// Reports an allocation that failed without its caller handling it and stops the kernel. There
+/// are no user processes an OOM policy could kill to free memory yet, so there is nothing else
+/// left to do. Must not allocate, the heap just ran out.
+pub fn alloc_error(layout: Layout) -> ! {
+    let site = AllocationSite::<ALLOC_ERROR_FRAMES>::capture();
+    let heap = if ALLOCATOR.initialized.load(Ordering::SeqCst) { "main" } else { "initial" };
+    // The heap might still be locked, if the error came from inside the allocator
+    let Some((used, size)) = ALLOCATOR.current_heap().try_lock().map(|heap| (heap.used(), heap.size())) else {
+        crate::internal::emergency::abort(format_args!(
+            "Failed to allocate {} bytes aligned to {} from the {} heap for {}",
+            layout.size(), layout.align(), heap, site
+        ))
+    };
+    crate::internal::emergency::abort(format_args!(
+        "Failed to allocate {} bytes aligned to {} from the {} heap for {}, {} of {} bytes are in use",
+        layout.size(), layout.align(), heap, site, used, size
+    ))
 }
 
 pub struct SimpleHeapFrameAllocator {
-    memory_regions: &'static MemoryRegions,
+    memory_regions: &'static [MemoryRegion],
     next: usize,
 } impl SimpleHeapFrameAllocator {
-    pub unsafe fn new(memory_regions: &'static MemoryRegions, next: usize) -> Self { Self {
+    pub unsafe fn new(memory_regions: &'static [MemoryRegion], next: usize) -> Self { Self {
         memory_regions, next
     } }
 
@@ -83,7 +236,7 @@ pub struct HeapFrameAllocator {
     usable_frames: VecDeque<PhysFrame>,
     next: usize,
 } impl HeapFrameAllocator {
-    pub unsafe fn new(memory_regions: &'static MemoryRegions, next: usize) -> Self {
+    pub unsafe fn new(memory_regions: &'static [MemoryRegion], next: usize) -> Self {
         let usable_frames: VecDeque<_> = crate::internal::memory::get_usable_regions(memory_regions, next).collect();
         Self { next, usable_frames }
     }
@@ -92,12 +245,37 @@ pub struct HeapFrameAllocator {
         self.next += 1;
         self.usable_frames.pop_front()
     }
+} unsafe impl FrameAllocator<Size2MiB> for HeapFrameAllocator {
+    /// Takes the first 512 physically contiguous frames starting at a 2 MiB boundary.
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        let frames_per_page = (Size2MiB::SIZE / Size4KiB::SIZE) as usize;
+        let frames = self.usable_frames.make_contiguous();
+
+        let mut index = 0;
+        while index + frames_per_page <= frames.len() {
+            let start = frames[index].start_address();
+            if !start.is_aligned(Size2MiB::SIZE) {
+                index += 1;
+                continue;
+            }
+            let run = frames[index..index + frames_per_page].iter().enumerate()
+                .take_while(|(offset, frame)| frame.start_address() == start + *offset as u64 * Size4KiB::SIZE)
+                .count();
+            if run == frames_per_page {
+                self.usable_frames.drain(index..index + frames_per_page);
+                self.next += frames_per_page;
+                return PhysFrame::from_start_address(start).ok();
+            }
+            index += run.max(1);
+        }
+        None
+    }
 }
 
 pub fn init_initial_heap(
     mapper: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut SimpleHeapFrameAllocator,
-) -> Result<usize, MapToError<Size4KiB>> {
+) -> Result<usize, KernelError> {
     init_heap_range(mapper, frame_allocator, INITIAL_HEAP_START, INITIAL_HEAP_SIZE)?;
 
     unsafe { ALLOCATOR.init_initial_heap(INITIAL_HEAP_START, INITIAL_HEAP_SIZE); }
@@ -105,13 +283,27 @@ pub fn init_initial_heap(
     Ok(frame_allocator.next)
 }
 
+/// Maps the main heap with 2 MiB pages where possible, they cut down on page tables and TLB misses.
 pub fn init_main_heap(
-    mapper: &mut impl Mapper<Size4KiB>,
+    mapper: &mut (impl Mapper<Size4KiB> + Mapper<Size2MiB>),
     frame_allocator: &mut HeapFrameAllocator,
-) -> Result<usize, MapToError<Size4KiB>> {
-    init_heap_range(mapper, frame_allocator, MAIN_HEAP_START, MAIN_HEAP_SIZE)?;
+) -> Result<usize, KernelError> {
+    let main_heap_start = main_heap_start();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    let stats = crate::internal::memory::map_range(
+        mapper, frame_allocator, VirtAddr::new(main_heap_start as u64), MAIN_HEAP_SIZE as u64, flags,
+        |frame_allocator, _, huge| if huge {
+            FrameAllocator::<Size2MiB>::allocate_frame(frame_allocator).map(|frame| frame.start_address())
+        } else {
+            FrameAllocator::<Size4KiB>::allocate_frame(frame_allocator).map(|frame| frame.start_address())
+        }
+    )?;
+    log::info!(
+        "Initialized heap range: {:#x?} - {:#x?} with {} 2MiB and {} 4KiB pages",
+        main_heap_start, main_heap_start + MAIN_HEAP_SIZE, stats.huge_pages, stats.pages
+    );
 
-    unsafe { ALLOCATOR.init_main_heap(MAIN_HEAP_START, MAIN_HEAP_SIZE); }
+    unsafe { ALLOCATOR.init_main_heap(main_heap_start, MAIN_HEAP_SIZE); }
 
     Ok(frame_allocator.next)
 }
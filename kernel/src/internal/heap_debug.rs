@@ -0,0 +1,128 @@
+use core::alloc::{GlobalAlloc, Layout};
+use spin::Mutex;
+use crate::internal::heap::AllocationSite;
+
+static HEADER_SIZE: usize = 64;
+static CANARY_SIZE: usize = 8;
+static CANARY: u64 = 0xCA4A_27CA_4A27_CA4A;
+static STATE_ALLOCATED: u64 = 0xA110_CA7E_DA11_0CA7;
+static STATE_FREED: u64 = 0xF4EE_DF4E_EDF4_EEDF;
+/// Fresh allocations are filled with this, so reads of uninitialized memory stand out.
+static UNINITIALIZED_POISON: u8 = 0xAA;
+/// Freed allocations are filled with this, so use-after-free reads stand out.
+static FREED_POISON: u8 = 0xDD;
+
+/// Return addresses kept per allocation, the first one outside the allocator is the site.
+const SITE_FRAMES: usize = 4;
+/// Freed allocations are only given back to the heap once this many others have been freed after
+/// them. Until then a double free or a write to freed memory is detected.
+const QUARANTINE_SIZE: usize = 64;
+
+static QUARANTINE: Mutex<Quarantine> = Mutex::new(Quarantine::new());
+
+/// Placed right in front of every allocation.
+#[repr(C)]
+struct Header {
+    state: u64,
+    size: usize,
+    site: AllocationSite<SITE_FRAMES>,
+    canary: u64
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FreedBlock {
+    pointer: usize,
+    size: usize,
+    align: usize
+}
+
+struct Quarantine {
+    blocks: [FreedBlock; QUARANTINE_SIZE],
+    head: usize,
+    length: usize
+} impl Quarantine {
+    const fn new() -> Self { Self {
+        blocks: [FreedBlock { pointer: 0, size: 0, align: 0 }; QUARANTINE_SIZE],
+        head: 0,
+        length: 0
+    } }
+
+    /// Adds the block, returns the oldest one if the quarantine is full.
+    fn push(&mut self, block: FreedBlock) -> Option<FreedBlock> {
+        let oldest = (self.length == QUARANTINE_SIZE).then(|| self.blocks[self.head]);
+        self.blocks[self.head] = block;
+        self.head = (self.head + 1) % QUARANTINE_SIZE;
+        self.length = (self.length + 1).min(QUARANTINE_SIZE);
+        oldest
+    }
+}
+
+/// Returns the layout of the whole block, the offset of the allocation in it.
+fn outer_layout(layout: Layout) -> Option<(Layout, usize)> {
+    let offset = HEADER_SIZE.next_multiple_of(layout.align());
+    let size = offset.checked_add(layout.size())?.checked_add(CANARY_SIZE)?;
+    Some((Layout::from_size_align(size, layout.align().max(16)).ok()?, offset))
+}
+
+/// Allocates from the heap with a header and canaries around the allocation.
+pub unsafe fn alloc(heap: &impl GlobalAlloc, layout: Layout) -> *mut u8 {
+    let site = AllocationSite::capture();
+    let Some((outer, offset)) = outer_layout(layout) else { return core::ptr::null_mut() };
+    let block = heap.alloc(outer);
+    if block.is_null() { return block; }
+
+    let pointer = block.add(offset);
+    (pointer.sub(core::mem::size_of::<Header>()) as *mut Header).write(Header {
+        state: STATE_ALLOCATED, size: layout.size(), site, canary: CANARY
+    });
+    core::ptr::write_bytes(pointer, UNINITIALIZED_POISON, layout.size());
+    (pointer.add(layout.size()) as *mut u64).write_unaligned(CANARY);
+    pointer
+}
+
+/// Checks the header and canaries of the allocation, poisons it and puts it into the quarantine.
+/// Panics with the allocation site if anything was overwritten or the allocation was freed before.
+pub unsafe fn dealloc(heap: &impl GlobalAlloc, pointer: *mut u8, layout: Layout) {
+    let header = &mut *(pointer.sub(core::mem::size_of::<Header>()) as *mut Header);
+    if header.state == STATE_FREED {
+        panic!(
+            "Heap corruption: double free of {} bytes at {:p} allocated by {}",
+            layout.size(), pointer, header.site
+        );
+    } else if header.state != STATE_ALLOCATED || header.canary != CANARY {
+        panic!(
+            "Heap corruption: header in front of {} bytes at {:p} was overwritten (freed with {:?})",
+            layout.size(), pointer, layout
+        );
+    } else if header.size != layout.size() {
+        panic!(
+            "Heap corruption: {} bytes at {:p} allocated by {} were freed as {} bytes",
+            header.size, pointer, header.site, layout.size()
+        );
+    } else if (pointer.add(layout.size()) as *const u64).read_unaligned() != CANARY {
+        panic!(
+            "Heap corruption: write past the end of {} bytes at {:p} allocated by {}",
+            layout.size(), pointer, header.site
+        );
+    }
+
+    header.state = STATE_FREED;
+    core::ptr::write_bytes(pointer, FREED_POISON, layout.size());
+
+    let freed = FreedBlock { pointer: pointer as usize, size: layout.size(), align: layout.align() };
+    let Some(oldest) = QUARANTINE.lock().push(freed) else { return };
+
+    let pointer = oldest.pointer as *mut u8;
+    let contents = core::slice::from_raw_parts(pointer, oldest.size);
+    if let Some(index) = contents.iter().position(|byte| *byte != FREED_POISON) {
+        let header = &*(pointer.sub(core::mem::size_of::<Header>()) as *const Header);
+        panic!(
+            "Heap corruption: byte {} of {} freed bytes at {:p} allocated by {} was written after the free",
+            index, oldest.size, pointer, header.site
+        );
+    }
+    let layout = Layout::from_size_align_unchecked(oldest.size, oldest.align);
+    if let Some((outer, offset)) = outer_layout(layout) {
+        heap.dealloc(pointer.sub(offset), outer);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,244 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use spin::Once;
+use x86_64::{PhysAddr, VirtAddr};
+use crate::api::error::KernelError;
+use crate::api::event::Event;
+use crate::internal::acpi::Acpi;
+use crate::drivers::timer::TimerTick;
+use crate::internal::pic::PicInterrupts;
+
+static REGISTERS_SIZE: u64 = 0x400;
+static CAPABILITIES_REGISTER: usize = 0x000;
+static CONFIGURATION_REGISTER: usize = 0x010;
+static COUNTER_REGISTER: usize = 0x0F0;
+static TIMER_CONFIGURATION_REGISTER: usize = 0x100;
+static TIMER_COMPARATOR_REGISTER: usize = 0x108;
+static TIMER_REGISTERS_STRIDE: usize = 0x20;
+
+static COUNTER_64_BIT: u64 = 1 << 13;
+static ENABLE_COUNTER: u64 = 1 << 0;
+/// Legacy replacement routing takes over the PIT and RTC lines, the RTC is still in use so it stays off.
+static LEGACY_ROUTING: u64 = 1 << 1;
+
+static TIMER_LEVEL_TRIGGERED: u64 = 1 << 1;
+static TIMER_INTERRUPT_ENABLE: u64 = 1 << 2;
+static TIMER_PERIODIC: u64 = 1 << 3;
+static TIMER_64_BIT: u64 = 1 << 5;
+static TIMER_32_BIT_MODE: u64 = 1 << 8;
+static TIMER_ROUTE_SHIFT: u64 = 9;
+static TIMER_ROUTE_MASK: u64 = 0x1F << 9;
+
+static FEMTOSECONDS_PER_SECOND: u64 = 1_000_000_000_000_000;
+/// The longest counter period the specification allows, 100 ns.
+static MAX_PERIOD: u64 = 100_000_000;
+
+/// The vector the HPET comparator interrupts on.
+pub const TIMER_VECTOR: u8 = 0x31;
+
+/// The CPU wakes up at least this often, so a 32-bit counter is read before it wraps twice.
+static MAX_IDLE_TICKS: u64 = 1000;
+/// How far ahead of the counter a missed deadline is moved, doubled until the comparator is set in time.
+static MIN_ARM_COUNTS: u64 = 64;
+
+static HPET: Once<Hpet> = Once::new();
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static COUNTS_PER_TICK: AtomicU64 = AtomicU64::new(0);
+/// Counter reading up to which ticks were reported.
+static LAST_TICK_COUNT: AtomicU64 = AtomicU64::new(0);
+static TIMER_FIRED: AtomicBool = AtomicBool::new(false);
+static IDLE: AtomicBool = AtomicBool::new(false);
+
+/// The comparator that drives the timer and where its interrupt goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HpetTimer {
+    pub comparator: usize,
+    pub gsi: u32,
+    pub khz: u64,
+    /// Whether counter and comparator are 64 bits wide, otherwise the counter is extended in software.
+    pub wide: bool
+}
+
+struct Hpet {
+    registers: VirtAddr,
+    comparator: usize,
+    wide: bool,
+    /// How often the low 32 bits of the counter wrapped, and what they were when last read.
+    wraps: AtomicU64,
+    last_low: AtomicU32
+} impl Hpet {
+    fn read(&self, register: usize) -> u64 {
+        unsafe { core::ptr::read_volatile((self.registers.as_u64() as usize + register) as *const u64) }
+    }
+
+    fn write(&self, register: usize, value: u64) {
+        unsafe { core::ptr::write_volatile((self.registers.as_u64() as usize + register) as *mut u64, value) }
+    }
+
+    fn timer_register(&self, register: usize) -> usize {
+        register + self.comparator * TIMER_REGISTERS_STRIDE
+    }
+
+    /// Reads the main counter. A 32-bit counter wraps after a few minutes at most, so it is
+    /// extended to 64 bits by counting the wraps, which works as long as it is read more often.
+    fn counter(&self) -> u64 {
+        if self.wide {
+            return self.read(COUNTER_REGISTER);
+        }
+        crate::internal::idt::without_interrupts(|| {
+            let low = self.read(COUNTER_REGISTER) as u32;
+            let mut wraps = self.wraps.load(Ordering::SeqCst);
+            if low < self.last_low.swap(low, Ordering::SeqCst) {
+                wraps += 1;
+                self.wraps.store(wraps, Ordering::SeqCst);
+            }
+            wraps << 32 | low as u64
+        })
+    }
+
+    /// Sets the comparator to the counter value. A 32-bit comparator only takes the low half,
+    /// which fires at the right time as long as the value is less than a wrap ahead.
+    fn set_comparator(&self, value: u64) {
+        let value = if self.wide { value } else { value as u32 as u64 };
+        self.write(self.timer_register(TIMER_COMPARATOR_REGISTER), value);
+    }
+
+    fn set_interrupt_enabled(&self, enabled: bool) {
+        let register = self.timer_register(TIMER_CONFIGURATION_REGISTER);
+        let config = self.read(register);
+        self.write(register, if enabled { config | TIMER_INTERRUPT_ENABLE } else { config & !TIMER_INTERRUPT_ENABLE });
+    }
+}
+
+/// Switches the timer to one-shot interrupts of an HPET comparator, routed through the IOAPIC to
+/// the first input it can use that no ISA interrupt is wired to. Meant for when the local APIC
+/// can't fire at TSC deadlines; needs the local APIC and IOAPICs to be set up. The PIT timer
+/// interrupt is masked once the HPET drives the timer.
+pub fn init_timer(acpi: &Acpi, physical_memory_offset: VirtAddr) -> Result<HpetTimer, KernelError> {
+    let info = acpi.hpet_info().map_err(|_| KernelError::HardwareMissing("HPET"))?;
+    let registers = crate::internal::memory::map_mmio(
+        physical_memory_offset, PhysAddr::new(info.base_address as u64), REGISTERS_SIZE, "HPET"
+    )?;
+    let mut hpet = Hpet { registers, comparator: 0, wide: false, wraps: AtomicU64::new(0), last_low: AtomicU32::new(0) };
+
+    let capabilities = hpet.read(CAPABILITIES_REGISTER);
+    let period = capabilities >> 32;
+    if period == 0 || period > MAX_PERIOD {
+        return Err(KernelError::InvalidConfiguration("HPET counter period out of range"));
+    }
+    let khz = FEMTOSECONDS_PER_SECOND / period / 1000;
+    let counts_per_tick = khz * 1000 / crate::drivers::timer::tick_hz();
+    if counts_per_tick == 0 {
+        return Err(KernelError::InvalidConfiguration("HPET counter too slow for the tick rate"));
+    }
+
+    // Find a comparator that can interrupt through a free IOAPIC input
+    let comparators = ((capabilities >> 8) & 0x1F) as usize + 1;
+    let (comparator, gsi) = (0..comparators).find_map(|comparator| {
+        hpet.comparator = comparator;
+        let routes = (hpet.read(hpet.timer_register(TIMER_CONFIGURATION_REGISTER)) >> 32) as u32;
+        (0..32).find(|gsi| routes & (1 << gsi) != 0 && crate::internal::ioapic::is_free(*gsi))
+            .map(|gsi| (comparator, gsi))
+    }).ok_or(KernelError::InvalidConfiguration("No HPET comparator can be routed to a free IOAPIC input"))?;
+    hpet.comparator = comparator;
+
+    crate::internal::idt::without_interrupts(|| {
+        // One-shot and edge triggered, with the interrupt off until the comparator is set
+        let register = hpet.timer_register(TIMER_CONFIGURATION_REGISTER);
+        let mut config = hpet.read(register)
+            & !(TIMER_LEVEL_TRIGGERED | TIMER_INTERRUPT_ENABLE | TIMER_PERIODIC | TIMER_32_BIT_MODE | TIMER_ROUTE_MASK);
+        config |= (gsi as u64) << TIMER_ROUTE_SHIFT;
+        hpet.wide = capabilities & COUNTER_64_BIT != 0 && config & TIMER_64_BIT != 0;
+        if !hpet.wide && config & TIMER_64_BIT != 0 {
+            config |= TIMER_32_BIT_MODE;
+        }
+        hpet.write(register, config);
+        crate::internal::ioapic::route_gsi(gsi, TIMER_VECTOR)?;
+
+        let config = hpet.read(CONFIGURATION_REGISTER) & !LEGACY_ROUTING;
+        hpet.write(CONFIGURATION_REGISTER, config | ENABLE_COUNTER);
+
+        let hpet = HPET.call_once(|| hpet);
+        COUNTS_PER_TICK.store(counts_per_tick, Ordering::SeqCst);
+        LAST_TICK_COUNT.store(hpet.counter(), Ordering::SeqCst);
+        ACTIVE.store(true, Ordering::SeqCst);
+        arm(1);
+        hpet.set_interrupt_enabled(true);
+        crate::internal::pic::mask(PicInterrupts::Timer);
+        Ok(HpetTimer { comparator, gsi, khz, wide: hpet.wide })
+    })
+}
+
+/// Turns the comparator interrupt off, so it doesn't fire anymore.
+pub fn stop_timer() {
+    if !ACTIVE.swap(false, Ordering::SeqCst) { return; }
+    if let Some(hpet) = HPET.get() {
+        hpet.set_interrupt_enabled(false);
+    }
+}
+
+/// Returns whether an HPET comparator drives the timer ticks instead of the PIT.
+pub fn timer_active() -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+/// Arms the comparator to fire the given amount of ticks after the last reported one. The
+/// comparator only fires when the counter reaches it, so a deadline the counter already passed
+/// (or passes while it is set) is moved just ahead of the counter.
+fn arm(ticks: u64) {
+    let Some(hpet) = HPET.get() else { return; };
+    let counts_per_tick = COUNTS_PER_TICK.load(Ordering::SeqCst);
+    let mut deadline = LAST_TICK_COUNT.load(Ordering::SeqCst).saturating_add(ticks.saturating_mul(counts_per_tick));
+    let mut margin = MIN_ARM_COUNTS;
+    loop {
+        hpet.set_comparator(deadline);
+        let now = hpet.counter();
+        if now < deadline { break; }
+        deadline = now + margin;
+        margin *= 2;
+    }
+}
+
+/// Takes the whole ticks that passed since the last reported one, the rest of a tick is kept.
+fn take_elapsed_ticks() -> u64 {
+    let Some(hpet) = HPET.get() else { return 0; };
+    let counts_per_tick = COUNTS_PER_TICK.load(Ordering::SeqCst).max(1);
+    let last = LAST_TICK_COUNT.load(Ordering::SeqCst);
+    let ticks = hpet.counter().saturating_sub(last) / counts_per_tick;
+    LAST_TICK_COUNT.store(last + ticks * counts_per_tick, Ordering::SeqCst);
+    ticks
+}
+
+/// Gets called by the HPET interrupt handler to find out what the interrupt stands for. Arms the
+/// comparator for the next tick.
+pub fn timer_tick() -> TimerTick {
+    TIMER_FIRED.store(true, Ordering::SeqCst);
+    let ticks = take_elapsed_ticks();
+    arm(1);
+    TimerTick { ticks, idle: IDLE.load(Ordering::SeqCst) }
+}
+
+/// Like `apic::idle`, but sleeps at most `MAX_IDLE_TICKS` so the counter keeps being read.
+pub fn idle(deadline: Option<u64>, wake_flag: &AtomicBool) {
+    let ticks = deadline.unwrap_or(MAX_IDLE_TICKS).clamp(1, MAX_IDLE_TICKS);
+    if ticks > 1 { arm(ticks); }
+
+    TIMER_FIRED.store(false, Ordering::SeqCst);
+    IDLE.store(true, Ordering::SeqCst);
+    crate::internal::cpuidle::enter(ticks, wake_flag);
+    x86_64::instructions::interrupts::disable();
+    IDLE.store(false, Ordering::SeqCst);
+
+    if ticks > 1 {
+        if !TIMER_FIRED.load(Ordering::SeqCst) {
+            let elapsed = take_elapsed_ticks();
+            if elapsed > 0 {
+                crate::api::event::EventDispatcher::global().push(Event::Timer(TimerTick {
+                    ticks: elapsed, idle: true
+                }));
+            }
+        }
+        arm(1);
+    }
+
+    x86_64::instructions::interrupts::enable();
+}
\ No newline at end of file
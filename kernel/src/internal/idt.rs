@@ -1,20 +1,25 @@
 use alloc::format;
-use spin::Once;
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use core::fmt::Write;
+use spin::{Lazy, Mutex};
+use x86_64::instructions::port::PortReadOnly;
+use x86_64::registers::control::{Cr0, Cr2, Cr3, Cr4};
+use x86_64::structures::idt::{Entry, HandlerFunc, InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use crate::api::display::{Colors, DisplayApi, Fonts, Position, TextAlignment, TextBaseline, TextLineHeight};
 use crate::api::event::{ErrorEvent, Event};
 use crate::internal::pic::PicInterrupts;
+use crate::internal::trace::TraceCategory;
+use crate::systems::display::SimpleDisplay;
 
-static IDT: Once<InterruptDescriptorTable> = Once::new();
+/// Lives behind a lock so handlers can be added while it is loaded, see `interrupts::register`.
+static IDT: Lazy<Mutex<InterruptDescriptorTable>> = Lazy::new(|| Mutex::new(InterruptDescriptorTable::new()));
+static SYSTEM_CONTROL_PORT_B: u16 = 0x61;
 
 pub fn load() {
-    IDT.call_once(|| {
-        let mut idt = InterruptDescriptorTable::new();
-
-        // Hardware Interrupt Handlers
-        idt[PicInterrupts::Timer.into_values().1 as usize].set_handler_fn(timer_interrupt_handler);
-        idt[PicInterrupts::RTC.into_values().1 as usize].set_handler_fn(rtc_interrupt_handler);
+    crate::internal::idt::without_interrupts(|| {
+        let mut idt = IDT.lock();
 
         // Exception Handlers
+        idt.non_maskable_interrupt.set_handler_fn(non_maskable_interrupt_handler);
         idt.breakpoint.set_handler_fn(breakpoint_handler);
         idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
         idt.invalid_tss.set_handler_fn(invalid_tss_handler);
@@ -26,16 +31,53 @@ pub fn load() {
                 .set_stack_index(super::gdt::PAGE_FAULT_IST_INDEX);
             idt.general_protection_fault.set_handler_fn(general_protection_fault_handler)
                 .set_stack_index(super::gdt::GENERAL_PROTECTION_FAULT_IST_INDEX);
-        }
 
-        idt
+            // The table is in a static, so it stays where it is for as long as it is loaded
+            idt.load_unsafe();
+        }
     });
 
-    IDT.get().unwrap_or_else(|| panic!("Interrupt descriptor table not found!")).load();
+    // Hardware Interrupt Handlers
+    let handlers: [(PicInterrupts, HandlerFunc); 5] = [
+        (PicInterrupts::Timer, timer_interrupt_handler),
+        (PicInterrupts::RTC, rtc_interrupt_handler),
+        (PicInterrupts::COM1, com1_interrupt_handler),
+        (PicInterrupts::COM2, com2_interrupt_handler),
+        (PicInterrupts::Keyboard, keyboard_interrupt_handler)
+    ];
+    for (irq, handler) in handlers {
+        super::interrupts::register_irq(irq, handler)
+            .unwrap_or_else(|err| panic!("Failed to register interrupt handler: {}", err));
+    }
+    // The last lines of both PICs stay masked, but they still receive spurious interrupts
+    for (irq, handler) in [
+        (PicInterrupts::LPT1, pic1_spurious_interrupt_handler as HandlerFunc),
+        (PicInterrupts::SecondaryATA, pic2_spurious_interrupt_handler)
+    ] {
+        super::interrupts::register_irq_masked(irq, handler)
+            .unwrap_or_else(|err| panic!("Failed to register spurious interrupt handler: {}", err));
+    }
+    super::interrupts::register(super::apic::TIMER_VECTOR, apic_timer_interrupt_handler)
+        .unwrap_or_else(|err| panic!("Failed to register APIC timer handler: {}", err));
+    super::interrupts::register(super::hpet::TIMER_VECTOR, hpet_timer_interrupt_handler)
+        .unwrap_or_else(|err| panic!("Failed to register HPET timer handler: {}", err));
+    super::interrupts::register(super::apic::SPURIOUS_VECTOR, apic_spurious_interrupt_handler)
+        .unwrap_or_else(|err| panic!("Failed to register APIC spurious handler: {}", err));
 
     x86_64::instructions::interrupts::enable();
 }
 
+/// Sets or (with None) removes the handler of a vector in the loaded table.
+pub(super) fn set_handler(vector: u8, handler: Option<HandlerFunc>) {
+    crate::internal::idt::without_interrupts(|| {
+        let mut idt = IDT.lock();
+        match handler {
+            Some(handler) => { idt[vector as usize].set_handler_fn(handler); },
+            None => idt[vector as usize] = Entry::missing()
+        }
+    })
+}
+
 pub fn without_interrupts<F, R>(func: F) -> R
     where F: FnOnce() -> R {
     x86_64::instructions::interrupts::without_interrupts(func)
@@ -45,18 +87,72 @@ pub fn disable_interrupts() {
     x86_64::instructions::interrupts::disable();
 }
 
+pub fn enable_interrupts() {
+    x86_64::instructions::interrupts::enable();
+}
+
 // Hardware Interrupt Handlers
 
 extern "x86-interrupt" fn timer_interrupt_handler(
     _stack_frame: InterruptStackFrame
 ) {
-    crate::api::event::EventDispatcher::global().push(Event::Timer);
+    crate::trace!(TraceCategory::Interrupt, PicInterrupts::Timer.into_values().1);
+    let _guard = crate::internal::interrupts::enter(PicInterrupts::Timer.into_values().1);
+    crate::api::event::EventDispatcher::global().push(Event::Timer(crate::drivers::timer::pit::timer_tick()));
     crate::internal::pic::end_of_interrupt(PicInterrupts::Timer);
 }
 
+extern "x86-interrupt" fn apic_timer_interrupt_handler(
+    _stack_frame: InterruptStackFrame
+) {
+    crate::trace!(TraceCategory::Interrupt, super::apic::TIMER_VECTOR);
+    let _guard = crate::internal::interrupts::enter(super::apic::TIMER_VECTOR);
+    crate::api::event::EventDispatcher::global().push(Event::Timer(crate::internal::apic::timer_tick()));
+    crate::internal::apic::end_of_interrupt();
+}
+
+extern "x86-interrupt" fn hpet_timer_interrupt_handler(
+    _stack_frame: InterruptStackFrame
+) {
+    crate::trace!(TraceCategory::Interrupt, super::hpet::TIMER_VECTOR);
+    let _guard = crate::internal::interrupts::enter(super::hpet::TIMER_VECTOR);
+    crate::api::event::EventDispatcher::global().push(Event::Timer(crate::internal::hpet::timer_tick()));
+    crate::internal::apic::end_of_interrupt();
+}
+
+extern "x86-interrupt" fn apic_spurious_interrupt_handler(
+    _stack_frame: InterruptStackFrame
+) {
+    // Spurious interrupts are not in service, so they must not be acknowledged
+    crate::internal::interrupts::spurious();
+}
+
+extern "x86-interrupt" fn pic1_spurious_interrupt_handler(
+    _stack_frame: InterruptStackFrame
+) { pic_spurious_interrupt(PicInterrupts::LPT1) }
+
+extern "x86-interrupt" fn pic2_spurious_interrupt_handler(
+    _stack_frame: InterruptStackFrame
+) { pic_spurious_interrupt(PicInterrupts::SecondaryATA) }
+
+/// Handles the last line of a PIC, which no driver uses but which also gets raised for spurious
+/// interrupts.
+fn pic_spurious_interrupt(interrupt: PicInterrupts) {
+    if crate::internal::pic::in_service(interrupt) {
+        crate::trace!(TraceCategory::Interrupt, interrupt.into_values().1);
+        let _guard = crate::internal::interrupts::enter(interrupt.into_values().1);
+        crate::internal::pic::end_of_interrupt(interrupt);
+    } else {
+        crate::internal::interrupts::spurious();
+        crate::internal::pic::end_of_spurious_interrupt(interrupt);
+    }
+}
+
 extern "x86-interrupt" fn rtc_interrupt_handler(
     _stack_frame: InterruptStackFrame
 ) {
+    crate::trace!(TraceCategory::Interrupt, PicInterrupts::RTC.into_values().1);
+    let _guard = crate::internal::interrupts::enter(PicInterrupts::RTC.into_values().1);
     let date_time = crate::internal::cmos::Cmos::global()
         .unwrap_or_else(|| panic!("CMOS not found!"))
         .lock().rtc();
@@ -64,8 +160,50 @@ extern "x86-interrupt" fn rtc_interrupt_handler(
     crate::internal::pic::end_of_interrupt(PicInterrupts::RTC);
 }
 
+extern "x86-interrupt" fn com1_interrupt_handler(
+    _stack_frame: InterruptStackFrame
+) {
+    crate::trace!(TraceCategory::Interrupt, PicInterrupts::COM1.into_values().1);
+    let _guard = crate::internal::interrupts::enter(PicInterrupts::COM1.into_values().1);
+    crate::internal::serial::on_interrupt(PicInterrupts::COM1);
+    crate::internal::pic::end_of_interrupt(PicInterrupts::COM1);
+}
+
+extern "x86-interrupt" fn com2_interrupt_handler(
+    _stack_frame: InterruptStackFrame
+) {
+    crate::trace!(TraceCategory::Interrupt, PicInterrupts::COM2.into_values().1);
+    let _guard = crate::internal::interrupts::enter(PicInterrupts::COM2.into_values().1);
+    crate::internal::serial::on_interrupt(PicInterrupts::COM2);
+    crate::internal::pic::end_of_interrupt(PicInterrupts::COM2);
+}
+
+extern "x86-interrupt" fn keyboard_interrupt_handler(
+    _stack_frame: InterruptStackFrame
+) {
+    crate::trace!(TraceCategory::Interrupt, PicInterrupts::Keyboard.into_values().1);
+    let _guard = crate::internal::interrupts::enter(PicInterrupts::Keyboard.into_values().1);
+    // Replies to keyboard commands are polled for with interrupts disabled, so by the time this runs
+    // the output buffer may already be empty again.
+    if let Some(scancode) = crate::internal::keyboard::try_read() {
+        crate::internal::keyboard::handle_scancode(scancode);
+    }
+    crate::internal::pic::end_of_interrupt(PicInterrupts::Keyboard);
+}
+
 // Exception Handlers
 
+extern "x86-interrupt" fn non_maskable_interrupt_handler(
+    stack_frame: InterruptStackFrame
+) {
+    // Bit 7 signals a memory parity error and bit 6 an I/O channel check
+    let mut system_control_port: PortReadOnly<u8> = PortReadOnly::new(SYSTEM_CONTROL_PORT_B);
+    let status = unsafe { system_control_port.read() };
+    crate::api::event::EventDispatcher::global().push(Event::error(ErrorEvent::NonMaskableInterrupt(
+        format!("{:#?}", stack_frame), status
+    )))
+}
+
 extern "x86-interrupt" fn breakpoint_handler(
     stack_frame: InterruptStackFrame
 ) { crate::api::event::EventDispatcher::global().push(Event::error(ErrorEvent::Breakpoint(
@@ -100,8 +238,80 @@ extern "x86-interrupt" fn general_protection_fault_handler(
 extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame, error_code: u64
 ) -> ! {
-    crate::api::event::EventDispatcher::global().push(Event::error(ErrorEvent::DoubleFault(
-        format!("{:#?}", stack_frame), error_code
-    )));
+    // The main loop is not going to run anymore and whatever we interrupted might hold the heap,
+    // logger or frame buffer locks, so everything here has to be lock- and allocation-free.
+    let cr0 = Cr0::read_raw();
+    let cr2 = Cr2::read().as_u64();
+    let cr3 = Cr3::read_raw().0.start_address().as_u64();
+    let cr4 = Cr4::read_raw();
+
+    unsafe { crate::internal::serial::write_unlocked(format_args!(
+        "\n[DOUBLE FAULT | PANIC]: Error code {:#X}\n{:#?}\nCR0: {:#018X}\nCR2: {:#018X}\nCR3: {:#018X}\nCR4: {:#018X}\n",
+        error_code, stack_frame, cr0, cr2, cr3, cr4
+    )) }
+    if let Some(symbol) = crate::internal::symbols::resolve(stack_frame.instruction_pointer.as_u64()) {
+        unsafe { crate::internal::serial::write_unlocked(format_args!("RIP is in {}\n", symbol)) }
+    }
+
+    let mut display = unsafe { SimpleDisplay::emergency() };
+    let initialized = unsafe {
+        crate::internal::framebuffer::with_framebuffer_unlocked(|_, _| ()).is_ok()
+    };
+    if initialized {
+        let registers = [
+            ("RIP", stack_frame.instruction_pointer.as_u64()),
+            ("RSP", stack_frame.stack_pointer.as_u64()),
+            ("RFLAGS", stack_frame.cpu_flags),
+            ("CS", stack_frame.code_segment),
+            ("SS", stack_frame.stack_segment),
+            ("ERROR", error_code),
+            ("CR0", cr0),
+            ("CR2", cr2),
+            ("CR3", cr3),
+            ("CR4", cr4)
+        ];
+
+        // Nothing can be done about drawing errors here, the dump on the serial port has to do
+        let _ = display.clear(Colors::Blue.into());
+        let _ = display.draw_text(
+            "Double fault -- please reboot your machine! Register dump:", Position::new(0, 0),
+            Colors::White.into(), None,
+            Fonts::default().into(), false, false,
+            TextBaseline::Top, TextAlignment::Left, TextLineHeight::Full
+        );
+        for (index, (name, value)) in registers.iter().enumerate() {
+            let mut line = LineBuffer::new();
+            let _ = write!(line, "{:<6} {:#018X}", name, value);
+            let _ = display.draw_text(
+                line.as_str(), Position::new(0, 18 * (index + 2)),
+                Colors::White.into(), None,
+                Fonts::Font9x18.into(), false, false,
+                TextBaseline::Top, TextAlignment::Left, TextLineHeight::Full
+            );
+        }
+    }
+
     loop { x86_64::instructions::hlt(); }
+}
+
+/// Fixed-size, stack-allocated line used to format text where the heap can't be used.
+struct LineBuffer {
+    bytes: [u8; 64],
+    length: usize
+} impl LineBuffer {
+    fn new() -> Self { Self {
+        bytes: [0; 64],
+        length: 0
+    } }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.length]).unwrap_or("")
+    }
+} impl Write for LineBuffer {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let count = s.len().min(self.bytes.len() - self.length);
+        self.bytes[self.length..self.length + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.length += count;
+        Ok(())
+    }
 }
\ No newline at end of file
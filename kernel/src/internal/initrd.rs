@@ -0,0 +1,45 @@
+static NEWC_MAGIC: &[u8] = b"070701";
+static HEADER_SIZE: usize = 110;
+static TRAILER: &str = "TRAILER!!!";
+static FILE_TYPE_MASK: u32 = 0o170000;
+static REGULAR_FILE: u32 = 0o100000;
+
+/// Returns whether the data is a `newc` cpio archive, the format of initial ramdisks.
+pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(NEWC_MAGIC)
+}
+
+/// Iterates over the regular files of a `newc` cpio archive as their paths and contents.
+/// Stops at the trailer or at the first malformed entry.
+pub fn files(data: &'static [u8]) -> impl Iterator<Item = (&'static str, &'static [u8])> {
+    let mut offset = 0;
+    core::iter::from_fn(move || loop {
+        let header = data.get(offset..offset + HEADER_SIZE)?;
+        if !header.starts_with(NEWC_MAGIC) { return None; }
+        let mode = hex_field(header, 14)?;
+        let file_size = hex_field(header, 54)? as usize;
+        let name_size = hex_field(header, 94)? as usize;
+
+        let name_start = offset + HEADER_SIZE;
+        let name = data.get(name_start..name_start + name_size.saturating_sub(1))?;
+        let name = core::str::from_utf8(name).ok()?;
+        if name == TRAILER { return None; }
+
+        let data_start = align(name_start + name_size);
+        let contents = data.get(data_start..data_start + file_size)?;
+        offset = align(data_start + file_size);
+
+        if mode & FILE_TYPE_MASK == REGULAR_FILE {
+            return Some((name.trim_start_matches("./"), contents));
+        }
+    })
+}
+
+fn hex_field(header: &[u8], offset: usize) -> Option<u32> {
+    let field = core::str::from_utf8(header.get(offset..offset + 8)?).ok()?;
+    u32::from_str_radix(field, 16).ok()
+}
+
+fn align(offset: usize) -> usize {
+    (offset + 3) & !3
+}
\ No newline at end of file
@@ -0,0 +1,232 @@
+use alloc::format;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::structures::idt::HandlerFunc;
+use crate::api::error::KernelError;
+use crate::api::event::{ErrorEvent, Event};
+use crate::internal::pic::PicInterrupts;
+
+/// Vectors below are exceptions and the legacy PIC interrupts, which are only reachable through
+/// `register_irq`.
+pub const FIRST_DYNAMIC_VECTOR: u8 = 0x30;
+
+/// Interrupts of a single vector within one storm window that make it count as a storm.
+static STORM_THRESHOLD: u32 = 2000;
+static STORM_WINDOW_MILLIS: u64 = 10;
+/// How long a storming vector stays masked before it gets another chance.
+static STORM_COOLDOWN_MILLIS: u64 = 1000;
+
+static VECTORS: Mutex<VectorAllocator> = Mutex::new(VectorAllocator::new());
+static STATS: Mutex<InterruptStats> = Mutex::new(InterruptStats::new());
+static SPURIOUS: AtomicU64 = AtomicU64::new(0);
+static DEPTH: AtomicU32 = AtomicU32::new(0);
+static MAX_DEPTH: AtomicU32 = AtomicU32::new(0);
+
+/// Keeps track of which vectors have a handler, one bit per vector.
+struct VectorAllocator {
+    used: [u64; 4]
+} impl VectorAllocator {
+    const fn new() -> Self { Self {
+        used: [0; 4]
+    } }
+
+    fn is_used(&self, vector: u8) -> bool {
+        self.used[vector as usize / 64] & (1 << (vector % 64)) != 0
+    }
+
+    fn set_used(&mut self, vector: u8, used: bool) {
+        if used {
+            self.used[vector as usize / 64] |= 1 << (vector % 64);
+        } else {
+            self.used[vector as usize / 64] &= !(1 << (vector % 64));
+        }
+    }
+
+    fn find_free(&self) -> Option<u8> {
+        (FIRST_DYNAMIC_VECTOR..=u8::MAX).find(|vector| !self.is_used(*vector))
+    }
+}
+
+/// What has been counted for a single vector.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VectorStats {
+    pub count: u64,
+    /// How many times the vector was found storming.
+    pub storms: u32,
+    window_start: u64,
+    window_count: u32,
+    /// TSC value until which the vector stays masked (or for vectors that can't be masked, until
+    /// which it isn't reported again), 0 if it isn't.
+    masked_until: u64
+}
+
+struct InterruptStats {
+    vectors: [VectorStats; 256],
+    /// Amount of vectors with a `masked_until`.
+    masked: usize
+} impl InterruptStats {
+    const fn new() -> Self { Self {
+        vectors: [VectorStats {
+            count: 0, storms: 0, window_start: 0, window_count: 0, masked_until: 0
+        }; 256],
+        masked: 0
+    } }
+
+    /// Counts the interrupt and returns whether it just started a storm.
+    fn count(&mut self, vector: u8, tsc: u64, tsc_khz: u64) -> bool {
+        let stats = &mut self.vectors[vector as usize];
+        stats.count += 1;
+        if tsc.wrapping_sub(stats.window_start) > STORM_WINDOW_MILLIS * tsc_khz {
+            stats.window_start = tsc;
+            stats.window_count = 0;
+        }
+        stats.window_count += 1;
+
+        if stats.window_count < STORM_THRESHOLD || stats.masked_until != 0 {
+            return false;
+        }
+        stats.storms += 1;
+        stats.masked_until = tsc + STORM_COOLDOWN_MILLIS * tsc_khz;
+        self.masked += 1;
+        true
+    }
+
+    /// Unmasks the vectors whose cooldown is over.
+    fn expire(&mut self, tsc: u64) {
+        if self.masked == 0 { return; }
+        for (vector, stats) in self.vectors.iter_mut().enumerate() {
+            if stats.masked_until == 0 || stats.masked_until > tsc { continue; }
+            stats.masked_until = 0;
+            stats.window_count = 0;
+            self.masked -= 1;
+            if let Some(irq) = PicInterrupts::from_vector(vector as u8) {
+                crate::internal::pic::unmask(irq);
+            }
+        }
+    }
+}
+
+/// Tracks the nesting of interrupt handlers, dropped when the handler is done.
+pub struct InterruptGuard;
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        DEPTH.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Gets called first by every hardware interrupt handler. Counts the interrupt and masks its
+/// vector for a while if it fires so often that it would starve the rest of the kernel.
+pub fn enter(vector: u8) -> InterruptGuard {
+    let depth = DEPTH.fetch_add(1, Ordering::SeqCst) + 1;
+    MAX_DEPTH.fetch_max(depth, Ordering::SeqCst);
+
+    let tsc_khz = crate::internal::tsc::khz();
+    if tsc_khz == 0 {
+        STATS.lock().vectors[vector as usize].count += 1;
+        return InterruptGuard;
+    }
+
+    let tsc = crate::internal::tsc::read();
+    let mut stats = STATS.lock();
+    stats.expire(tsc);
+    if stats.count(vector, tsc, tsc_khz) {
+        let irq = PicInterrupts::from_vector(vector);
+        if let Some(irq) = irq {
+            crate::internal::pic::mask(irq);
+        }
+        crate::api::event::EventDispatcher::global().push(Event::error(ErrorEvent::InterruptStorm(format!(
+            "Vector {:#04X} fired {} times within {}ms, {} for {}ms",
+            vector, STORM_THRESHOLD, STORM_WINDOW_MILLIS,
+            if irq.is_some() { "masked" } else { "can't be masked, not reporting it again" },
+            STORM_COOLDOWN_MILLIS
+        ), vector)));
+    }
+    InterruptGuard
+}
+
+/// Counts an interrupt that turned out to be spurious.
+pub fn spurious() {
+    SPURIOUS.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Returns the statistics of the vector.
+pub fn stats(vector: u8) -> VectorStats {
+    crate::internal::idt::without_interrupts(|| STATS.lock().vectors[vector as usize])
+}
+
+/// Returns how many spurious interrupts were received.
+pub fn spurious_count() -> u64 {
+    SPURIOUS.load(Ordering::SeqCst)
+}
+
+/// Returns how deep interrupt handlers have been nested at most.
+pub fn max_depth() -> u32 {
+    MAX_DEPTH.load(Ordering::SeqCst)
+}
+
+/// Installs the handler for a vector from `FIRST_DYNAMIC_VECTOR` up, e.g. one an MSI was
+/// configured with. Fails if the vector already has a handler.
+pub fn register(vector: u8, handler: HandlerFunc) -> Result<(), KernelError> {
+    if vector < FIRST_DYNAMIC_VECTOR {
+        return Err(KernelError::InvalidConfiguration("Vector is reserved for exceptions and legacy interrupts"));
+    }
+    crate::internal::idt::without_interrupts(|| {
+        let mut vectors = VECTORS.lock();
+        if vectors.is_used(vector) {
+            return Err(KernelError::Busy("Interrupt vector"));
+        }
+        vectors.set_used(vector, true);
+        crate::internal::idt::set_handler(vector, Some(handler));
+        Ok(())
+    })
+}
+
+/// Installs the handler at the first free vector and returns it.
+#[allow(dead_code)]
+pub fn register_any(handler: HandlerFunc) -> Result<u8, KernelError> {
+    crate::internal::idt::without_interrupts(|| {
+        let mut vectors = VECTORS.lock();
+        let vector = vectors.find_free().ok_or(KernelError::Busy("All interrupt vectors"))?;
+        vectors.set_used(vector, true);
+        crate::internal::idt::set_handler(vector, Some(handler));
+        Ok(vector)
+    })
+}
+
+/// Installs the handler for a legacy interrupt and unmasks it at the PIC.
+pub fn register_irq(irq: PicInterrupts, handler: HandlerFunc) -> Result<(), KernelError> {
+    register_irq_masked(irq, handler)?;
+    crate::internal::pic::unmask(irq);
+    Ok(())
+}
+
+/// Installs the handler for a legacy interrupt, leaving its mask as it is. Meant for the lines
+/// that can raise spurious interrupts even while masked.
+pub fn register_irq_masked(irq: PicInterrupts, handler: HandlerFunc) -> Result<(), KernelError> {
+    let vector = irq.into_values().1;
+    crate::internal::idt::without_interrupts(|| {
+        let mut vectors = VECTORS.lock();
+        if vectors.is_used(vector) {
+            return Err(KernelError::Busy("Interrupt vector"));
+        }
+        vectors.set_used(vector, true);
+        crate::internal::idt::set_handler(vector, Some(handler));
+        Ok(())
+    })
+}
+
+/// Removes the handler of the vector, so it can be handed out again.
+#[allow(dead_code)]
+pub fn unregister(vector: u8) {
+    crate::internal::idt::without_interrupts(|| {
+        VECTORS.lock().set_used(vector, false);
+        crate::internal::idt::set_handler(vector, None);
+    })
+}
+
+/// Returns how many vectors from `FIRST_DYNAMIC_VECTOR` up are still free.
+pub fn free_vectors() -> usize {
+    let vectors = VECTORS.lock();
+    (FIRST_DYNAMIC_VECTOR..=u8::MAX).filter(|vector| !vectors.is_used(*vector)).count()
+}
\ No newline at end of file
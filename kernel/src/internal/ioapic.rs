@@ -0,0 +1,223 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+use acpi::InterruptModel;
+use acpi::platform::interrupt::{Polarity, TriggerMode};
+use spin::{Mutex, Once};
+use x86_64::{PhysAddr, VirtAddr};
+use crate::api::error::KernelError;
+use crate::internal::acpi::Acpi;
+use crate::internal::pic::PicInterrupts;
+
+static REGISTER_SELECT: usize = 0x00;
+static REGISTER_WINDOW: usize = 0x10;
+static REGISTERS_SIZE: u64 = 0x20;
+static VERSION_REGISTER: u32 = 0x01;
+static REDIRECTION_TABLE: u32 = 0x10;
+
+static REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+static REDIRECTION_LEVEL: u64 = 1 << 15;
+static REDIRECTION_MASKED: u64 = 1 << 16;
+
+static ROUTER: Once<Mutex<IoApicRouter>> = Once::new();
+/// One bit per legacy interrupt that is delivered through an IOAPIC instead of the PIC.
+static ROUTED: AtomicU16 = AtomicU16::new(0);
+
+/// How an ISA interrupt is wired to the IOAPICs. Without a source override the ISA interrupt is
+/// the global system interrupt of the same number, edge triggered and active high.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsaRoute {
+    pub gsi: u32,
+    pub active_low: bool,
+    pub level_triggered: bool
+} impl IsaRoute {
+    fn new(isa_irq: u8) -> Self { Self {
+        gsi: isa_irq as u32,
+        active_low: false,
+        level_triggered: false
+    } }
+}
+
+struct IoApic {
+    registers: VirtAddr,
+    gsi_base: u32,
+    redirection_entries: u32
+} impl IoApic {
+    fn new(physical_memory_offset: VirtAddr, address: u32, gsi_base: u32) -> Result<Self, KernelError> {
+        let registers = crate::internal::memory::map_mmio(
+            physical_memory_offset, PhysAddr::new(address as u64), REGISTERS_SIZE, "IOAPIC"
+        )?;
+        let mut io_apic = Self { registers, gsi_base, redirection_entries: 0 };
+        io_apic.redirection_entries = ((io_apic.read(VERSION_REGISTER) >> 16) & 0xFF) + 1;
+        Ok(io_apic)
+    }
+
+    fn read(&self, register: u32) -> u32 {
+        unsafe {
+            core::ptr::write_volatile((self.registers.as_u64() as usize + REGISTER_SELECT) as *mut u32, register);
+            core::ptr::read_volatile((self.registers.as_u64() as usize + REGISTER_WINDOW) as *const u32)
+        }
+    }
+
+    fn write(&mut self, register: u32, value: u32) {
+        unsafe {
+            core::ptr::write_volatile((self.registers.as_u64() as usize + REGISTER_SELECT) as *mut u32, register);
+            core::ptr::write_volatile((self.registers.as_u64() as usize + REGISTER_WINDOW) as *mut u32, value);
+        }
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.redirection_entries
+    }
+
+    fn read_redirection(&self, gsi: u32) -> u64 {
+        let register = REDIRECTION_TABLE + (gsi - self.gsi_base) * 2;
+        self.read(register) as u64 | (self.read(register + 1) as u64) << 32
+    }
+
+    fn write_redirection(&mut self, gsi: u32, entry: u64) {
+        let register = REDIRECTION_TABLE + (gsi - self.gsi_base) * 2;
+        // Masked while the upper half is written, so a half-written entry never gets used
+        self.write(register, (entry as u32) | REDIRECTION_MASKED as u32);
+        self.write(register + 1, (entry >> 32) as u32);
+        self.write(register, entry as u32);
+    }
+}
+
+struct IoApicRouter {
+    io_apics: Vec<IoApic>,
+    isa_routes: [IsaRoute; 16]
+} impl IoApicRouter {
+    fn io_apic(&mut self, gsi: u32) -> Result<&mut IoApic, KernelError> {
+        self.io_apics.iter_mut().find(|io_apic| io_apic.handles(gsi))
+            .ok_or(KernelError::InvalidConfiguration("No IOAPIC handles the global system interrupt"))
+    }
+}
+
+/// Finds the IOAPICs and the ISA interrupt source overrides in the MADT and masks every
+/// redirection entry. Returns the amount of IOAPICs found.
+pub fn init(acpi: &Acpi, physical_memory_offset: VirtAddr) -> Result<usize, KernelError> {
+    let platform_info = acpi.platform_info()
+        .map_err(|_| KernelError::HardwareMissing("MADT"))?;
+    let InterruptModel::Apic(apic) = platform_info.interrupt_model() else {
+        return Err(KernelError::HardwareMissing("IOAPIC"));
+    };
+
+    let mut io_apics: Vec<IoApic> = apic.io_apics.iter()
+        .map(|io_apic| IoApic::new(physical_memory_offset, io_apic.address, io_apic.global_system_interrupt_base))
+        .collect::<Result<_, _>>()?;
+    if io_apics.is_empty() {
+        return Err(KernelError::HardwareMissing("IOAPIC"));
+    }
+    for io_apic in io_apics.iter_mut() {
+        for gsi in io_apic.gsi_base..io_apic.gsi_base + io_apic.redirection_entries {
+            io_apic.write_redirection(gsi, REDIRECTION_MASKED);
+        }
+    }
+
+    let mut isa_routes: [IsaRoute; 16] = core::array::from_fn(|isa_irq| IsaRoute::new(isa_irq as u8));
+    for source_override in apic.interrupt_source_overrides.iter() {
+        let Some(route) = isa_routes.get_mut(source_override.isa_source as usize) else { continue; };
+        // ISA is edge triggered and active high, which is what "same as bus" stands for here
+        route.gsi = source_override.global_system_interrupt;
+        route.active_low = source_override.polarity == Polarity::ActiveLow;
+        route.level_triggered = source_override.trigger_mode == TriggerMode::Level;
+        log::debug!("ISA interrupt {} is overridden to {:?}.", source_override.isa_source, route);
+    }
+
+    let count = io_apics.len();
+    ROUTER.call_once(|| Mutex::new(IoApicRouter { io_apics, isa_routes }));
+    Ok(count)
+}
+
+/// Returns how the legacy interrupt is wired to the IOAPICs.
+#[allow(dead_code)]
+pub fn isa_route(irq: PicInterrupts) -> Option<IsaRoute> {
+    let router = ROUTER.get()?;
+    Some(crate::internal::idt::without_interrupts(|| router.lock().isa_routes[irq.line() as usize]))
+}
+
+/// Delivers the legacy interrupt through its IOAPIC to the current CPU, at the same vector the
+/// PIC used, and masks it at the PIC. Needs the local APIC to be enabled, as it has to acknowledge
+/// the interrupt then.
+pub fn route_irq(irq: PicInterrupts) -> Result<IsaRoute, KernelError> {
+    let router = ROUTER.get().ok_or(KernelError::HardwareMissing("IOAPIC"))?;
+    let destination = crate::internal::apic::id().ok_or(KernelError::HardwareMissing("Local APIC"))?;
+    if destination > 0xFF {
+        return Err(KernelError::InvalidConfiguration("Local APIC id can't be targeted by an IOAPIC"));
+    }
+
+    crate::internal::idt::without_interrupts(|| {
+        let mut router = router.lock();
+        let route = router.isa_routes[irq.line() as usize];
+
+        let mut entry = irq.into_values().1 as u64 | (destination as u64) << 56;
+        if route.active_low { entry |= REDIRECTION_ACTIVE_LOW; }
+        if route.level_triggered { entry |= REDIRECTION_LEVEL; }
+
+        let io_apic = router.io_apic(route.gsi)?;
+        crate::internal::pic::mask(irq);
+        io_apic.write_redirection(route.gsi, entry);
+        ROUTED.fetch_or(1 << irq.line(), Ordering::SeqCst);
+        Ok(route)
+    })
+}
+
+/// Returns whether a device can be routed to the global system interrupt: an IOAPIC handles it,
+/// no ISA interrupt is wired to it and it isn't delivered to a vector yet.
+pub fn is_free(gsi: u32) -> bool {
+    let Some(router) = ROUTER.get() else { return false; };
+    crate::internal::idt::without_interrupts(|| {
+        let mut router = router.lock();
+        if router.isa_routes.iter().any(|route| route.gsi == gsi) {
+            return false;
+        }
+        router.io_apic(gsi).is_ok_and(|io_apic| io_apic.read_redirection(gsi) & REDIRECTION_MASKED != 0)
+    })
+}
+
+/// Delivers the global system interrupt, edge triggered and active high, through its IOAPIC to
+/// the vector on the current CPU.
+pub fn route_gsi(gsi: u32, vector: u8) -> Result<(), KernelError> {
+    let router = ROUTER.get().ok_or(KernelError::HardwareMissing("IOAPIC"))?;
+    let destination = crate::internal::apic::id().ok_or(KernelError::HardwareMissing("Local APIC"))?;
+    if destination > 0xFF {
+        return Err(KernelError::InvalidConfiguration("Local APIC id can't be targeted by an IOAPIC"));
+    }
+
+    crate::internal::idt::without_interrupts(|| {
+        let mut router = router.lock();
+        router.io_apic(gsi)?.write_redirection(gsi, vector as u64 | (destination as u64) << 56);
+        Ok(())
+    })
+}
+
+/// Returns whether the legacy interrupt is delivered through an IOAPIC.
+pub fn is_routed(irq: PicInterrupts) -> bool {
+    ROUTED.load(Ordering::SeqCst) & (1 << irq.line()) != 0
+}
+
+/// Masks or unmasks a routed legacy interrupt at its IOAPIC.
+pub fn set_masked(irq: PicInterrupts, masked: bool) {
+    let Some(router) = ROUTER.get() else { return; };
+    crate::internal::idt::without_interrupts(|| {
+        let mut router = router.lock();
+        let gsi = router.isa_routes[irq.line() as usize].gsi;
+        if let Ok(io_apic) = router.io_apic(gsi) {
+            let entry = io_apic.read_redirection(gsi);
+            io_apic.write_redirection(gsi, if masked { entry | REDIRECTION_MASKED } else { entry & !REDIRECTION_MASKED });
+        }
+    })
+}
+
+/// Masks every redirection entry, the routed interrupts go back to the (masked) PIC.
+pub fn mask_all() {
+    let Some(router) = ROUTER.get() else { return; };
+    crate::internal::idt::without_interrupts(|| {
+        for io_apic in router.lock().io_apics.iter_mut() {
+            for gsi in io_apic.gsi_base..io_apic.gsi_base + io_apic.redirection_entries {
+                io_apic.write_redirection(gsi, REDIRECTION_MASKED);
+            }
+        }
+        ROUTED.store(0, Ordering::SeqCst);
+    })
+}
\ No newline at end of file
@@ -0,0 +1,119 @@
+use spin::Mutex;
+use x86_64::instructions::port::{PortReadOnly, PortWriteOnly};
+use x86_64::structures::port::{PortRead, PortWrite};
+use crate::api::error::KernelError;
+
+/// Claims are kept in a fixed list, as the serial port is claimed before there is a heap.
+const MAX_CLAIMS: usize = 32;
+
+static CLAIMS: Mutex<Claims> = Mutex::new(Claims::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Claim {
+    start: u16,
+    /// Exclusive, a `u32` so the last port can be claimed.
+    end: u32,
+    owner: &'static str
+}
+
+struct Claims {
+    claims: [Claim; MAX_CLAIMS],
+    length: usize
+} impl Claims {
+    const fn new() -> Self { Self {
+        claims: [Claim { start: 0, end: 0, owner: "" }; MAX_CLAIMS],
+        length: 0
+    } }
+
+    fn overlapping(&self, start: u16, end: u32) -> Option<Claim> {
+        self.claims[..self.length].iter().find(|claim| (claim.start as u32) < end && (start as u32) < claim.end).copied()
+    }
+}
+
+/// A range of I/O ports owned by a driver. Only the owner can get at the ports, so accessing
+/// them is safe; the range is given back to the registry with `release`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct IoPortRange {
+    start: u16,
+    length: u16,
+    owner: &'static str
+} #[allow(dead_code)] impl IoPortRange {
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    pub fn owner(&self) -> &'static str {
+        self.owner
+    }
+
+    /// Reads the port at the offset into the range. Panics if the access doesn't fit in the range.
+    pub fn read<T: PortRead>(&self, offset: u16) -> T {
+        let mut port = PortReadOnly::<T>::new(self.port::<T>(offset));
+        unsafe { port.read() }
+    }
+
+    /// Writes the port at the offset into the range. Panics if the access doesn't fit in the range.
+    pub fn write<T: PortWrite>(&self, offset: u16, value: T) {
+        let mut port = PortWriteOnly::<T>::new(self.port::<T>(offset));
+        unsafe { port.write(value) }
+    }
+
+    fn port<T>(&self, offset: u16) -> u16 {
+        if offset as usize + core::mem::size_of::<T>() > self.length as usize {
+            panic!("Port offset {:#X} is outside of the ports claimed by {}", offset, self.owner);
+        }
+        self.start + offset
+    }
+}
+
+/// Claims `length` ports starting at `start` for the owner. Fails if any of them is already
+/// claimed by someone else, naming the owner and the first conflicting port.
+pub fn claim(start: u16, length: u16, owner: &'static str) -> Result<IoPortRange, KernelError> {
+    let end = start as u32 + length as u32;
+    if length == 0 || end > u16::MAX as u32 + 1 {
+        return Err(KernelError::InvalidConfiguration("I/O port range out of bounds"));
+    }
+
+    crate::internal::idt::without_interrupts(|| {
+        let mut claims = CLAIMS.lock();
+        if let Some(claim) = claims.overlapping(start, end) {
+            return Err(KernelError::IoPortConflict(claim.owner, start.max(claim.start)));
+        }
+        if claims.length == MAX_CLAIMS {
+            return Err(KernelError::Busy("I/O port claim list"));
+        }
+        let index = claims.length;
+        claims.claims[index] = Claim { start, end, owner };
+        claims.length += 1;
+        Ok(IoPortRange { start, length, owner })
+    })
+}
+
+/// Gives a claimed range back to the registry.
+#[allow(dead_code)]
+pub fn release(range: IoPortRange) {
+    crate::internal::idt::without_interrupts(|| {
+        let mut claims = CLAIMS.lock();
+        let length = claims.length;
+        if let Some(index) = claims.claims[..length].iter()
+            .position(|claim| claim.start == range.start && claim.owner == range.owner) {
+            claims.claims.copy_within(index + 1..length, index);
+            claims.length -= 1;
+        }
+    })
+}
+
+/// Passes the first port, the amount of ports and the owner of every claimed range to the function.
+pub fn claims(func: &mut dyn FnMut(u16, u16, &'static str)) {
+    let claims = crate::internal::idt::without_interrupts(|| {
+        let claims = CLAIMS.lock();
+        (claims.claims, claims.length)
+    });
+    for claim in claims.0[..claims.1].iter() {
+        func(claim.start, (claim.end - claim.start as u32) as u16, claim.owner);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,82 @@
+use core::fmt::{Display, Formatter};
+use spin::Once;
+use x86_64::structures::paging::PageTable;
+
+/// The randomized regions are placed within the level 4 entries of this window (0x4000_0000_0000
+/// to 0x5000_0000_0000), away from the initial heap and the frame buffer.
+const WINDOW_FIRST_ENTRY: usize = 0x80;
+const WINDOW_LAST_ENTRY: usize = 0x9F;
+/// The address space covered by a single level 4 entry (512 GiB).
+static ENTRY_SIZE: u64 = 1 << 39;
+/// How much space each region may grow into from its base.
+static REGION_SIZE: u64 = 1 << 36; // 64 GiB
+/// Bases are 2 MiB aligned, so the regions can still be mapped with huge pages.
+static BASE_ALIGNMENT: u64 = 1 << 21;
+
+/// The layout used when randomization is turned off.
+static FIXED_LAYOUT: AddressLayout = AddressLayout {
+    main_heap: 0x_4444_4444_0000,
+    stacks: 0x_4800_0000_0000,
+    mmap: 0x_4C00_0000_0000,
+    randomized: false
+};
+
+static LAYOUT: Once<AddressLayout> = Once::new();
+
+/// Where the regions of the kernel's address space that are not set up by the bootloader start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressLayout {
+    pub main_heap: usize,
+    /// Where the stacks of tasks get allocated from.
+    pub stacks: usize,
+    /// Where memory mapped on request gets placed.
+    pub mmap: usize,
+    pub randomized: bool
+} impl Display for AddressLayout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f, "main heap at {:#X}, stacks at {:#X}, mmap at {:#X}",
+            self.main_heap, self.stacks, self.mmap
+        )
+    }
+}
+
+/// Picks the layout, randomized if `enabled`. Each region gets its own level 4 entry that nothing
+/// is mapped in yet, so none of them can run into the mappings of the bootloader. Falls back to
+/// the fixed layout if there aren't enough free entries.
+pub fn init(level_4_table: &PageTable, enabled: bool) -> AddressLayout {
+    *LAYOUT.call_once(|| {
+        if !enabled { return FIXED_LAYOUT; }
+
+        let mut free_entries = [0usize; WINDOW_LAST_ENTRY - WINDOW_FIRST_ENTRY + 1];
+        let mut free_count = 0;
+        for index in WINDOW_FIRST_ENTRY..=WINDOW_LAST_ENTRY {
+            if level_4_table[index].is_unused() {
+                free_entries[free_count] = index;
+                free_count += 1;
+            }
+        }
+        if free_count < 3 { return FIXED_LAYOUT; }
+
+        let mut bases = [0usize; 3];
+        for base in bases.iter_mut() {
+            // Take a random free entry out of the list, so no two regions share one
+            let picked = crate::internal::random::below(free_count as u64) as usize;
+            let entry = free_entries[picked];
+            free_count -= 1;
+            free_entries[picked] = free_entries[free_count];
+
+            let slots = (ENTRY_SIZE - REGION_SIZE) / BASE_ALIGNMENT;
+            let offset = crate::internal::random::below(slots) * BASE_ALIGNMENT;
+            // The window lies in the lower half, so the address needs no sign extension
+            *base = (entry as u64 * ENTRY_SIZE + offset) as usize;
+        }
+
+        AddressLayout { main_heap: bases[0], stacks: bases[1], mmap: bases[2], randomized: true }
+    })
+}
+
+/// Returns the layout picked by `init`, or the fixed one if it didn't run yet.
+pub fn layout() -> AddressLayout {
+    LAYOUT.get().copied().unwrap_or(FIXED_LAYOUT)
+}
\ No newline at end of file
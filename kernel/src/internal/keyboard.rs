@@ -0,0 +1,132 @@
+use x86_64::instructions::port::{Port, PortReadOnly};
+use crate::api::event::Event;
+use crate::api::keyboard::TypematicDelay;
+
+static DATA_PORT: u16 = 0x60;
+static STATUS_PORT: u16 = 0x64;
+static OUTPUT_FULL_BIT: u8 = 0b0000_0001;
+static INPUT_FULL_BIT: u8 = 0b0000_0010;
+
+static SET_LEDS_COMMAND: u8 = 0xED;
+static SET_TYPEMATIC_COMMAND: u8 = 0xF3;
+static ACKNOWLEDGE: u8 = 0xFA;
+static RESEND: u8 = 0xFE;
+
+static COMMAND_RETRIES: usize = 3;
+/// How long the keyboard gets to take a byte and to reply to it, it has to within 20 ms.
+static REPLY_TIMEOUT_MILLIS: u64 = 20;
+/// The frequency assumed before the time stamp counter is calibrated, high enough not to cut the
+/// wait short on any CPU.
+static UNCALIBRATED_KHZ: u64 = 10_000_000;
+
+pub static SCROLL_LOCK_LED: u8 = 0b001;
+pub static NUM_LOCK_LED: u8 = 0b010;
+pub static CAPS_LOCK_LED: u8 = 0b100;
+
+/// Reads a byte from the keyboard if one is waiting in the controller's output buffer.
+pub fn try_read() -> Option<u8> {
+    let mut status_port: PortReadOnly<u8> = PortReadOnly::new(STATUS_PORT);
+    let mut data_port: PortReadOnly<u8> = PortReadOnly::new(DATA_PORT);
+
+    unsafe {
+        if status_port.read() & OUTPUT_FULL_BIT != 0 {
+            Some(data_port.read())
+        } else { None }
+    }
+}
+
+/// Hands a byte read from the keyboard on as a scancode event, unless it is part of a SysRq
+/// combination.
+pub fn handle_scancode(scancode: u8) {
+    crate::boot::on_scancode(scancode);
+    if !crate::internal::sysrq::on_scancode(scancode) {
+        crate::api::event::EventDispatcher::global().push(Event::Scancode(scancode));
+    }
+}
+
+/// Sets the keyboard LEDs to the given combination of `SCROLL_LOCK_LED`, `NUM_LOCK_LED` and `CAPS_LOCK_LED`.
+/// Has to be called with interrupts disabled, otherwise the keyboard interrupt steals the acknowledgement.
+pub fn set_leds(leds: u8) -> bool {
+    send_command(SET_LEDS_COMMAND) && send_command(leds & 0b111)
+}
+
+/// Configures how long a key has to be held before it repeats and how many times per second it
+/// repeats afterwards. The rate is rounded to the closest one the keyboard supports (2 to 30).
+/// Has to be called with interrupts disabled, otherwise the keyboard interrupt steals the acknowledgement.
+pub fn set_typematic(delay: TypematicDelay, rate: u32) -> bool {
+    send_command(SET_TYPEMATIC_COMMAND) && send_command(((delay as u8) << 5) | typematic_bits(rate))
+}
+
+/// Returns the bits selecting the repeat rate closest to the given one (in characters per second).
+const fn typematic_bits(rate: u32) -> u8 {
+    let target = rate.saturating_mul(100);
+    let (mut bits, mut closest) = (0, 0);
+    while bits < 32 {
+        if typematic_rate(bits).abs_diff(target) < typematic_rate(closest).abs_diff(target) {
+            closest = bits;
+        }
+        bits += 1;
+    }
+    closest
+}
+
+/// Returns the repeat rate (in hundredths of a character per second) selected by the given bits.
+const fn typematic_rate(bits: u8) -> u32 {
+    // Period = (8 + B) * 2^D * 4.17 ms, with B in bits 0-2 and D in bits 3-4, here in 10 µs units
+    let period = (8 + (bits & 0b111) as u32) * (1 << ((bits >> 3) & 0b11)) * 417;
+    10_000_000 / period
+}
+
+// The fastest, the default and the slowest rate have to pick the bits from the rate table
+const _: () = {
+    assert!(typematic_bits(30) == 0x00);
+    assert!(typematic_bits(20) == 0x04);
+    assert!(typematic_bits(2) == 0x1F);
+};
+
+fn send_command(byte: u8) -> bool {
+    for _ in 0..COMMAND_RETRIES {
+        if !wait_for_input() { return false; }
+        let mut data_port: Port<u8> = Port::new(DATA_PORT);
+        unsafe { data_port.write(byte); }
+
+        match wait_for_reply() {
+            Some(reply) if reply == ACKNOWLEDGE => return true,
+            Some(_) => continue,
+            None => return false
+        }
+    }
+    false
+}
+
+/// Returns the time stamp counter value after which the keyboard is considered to not reply.
+fn reply_deadline() -> u64 {
+    let khz = match crate::internal::tsc::khz() {
+        0 => UNCALIBRATED_KHZ,
+        khz => khz
+    };
+    crate::internal::tsc::read() + khz * REPLY_TIMEOUT_MILLIS
+}
+
+fn wait_for_input() -> bool {
+    let mut status_port: PortReadOnly<u8> = PortReadOnly::new(STATUS_PORT);
+    let deadline = reply_deadline();
+    loop {
+        if unsafe { status_port.read() } & INPUT_FULL_BIT == 0 { return true; }
+        if crate::internal::tsc::read() >= deadline { return false; }
+    }
+}
+
+/// Waits for the keyboard to acknowledge the byte or to ask for it again. Keys pressed in the
+/// meantime arrive before the reply, they are handed on as the interrupt would have.
+fn wait_for_reply() -> Option<u8> {
+    let deadline = reply_deadline();
+    while crate::internal::tsc::read() < deadline {
+        match try_read() {
+            Some(reply) if reply == ACKNOWLEDGE || reply == RESEND => return Some(reply),
+            Some(scancode) => handle_scancode(scancode),
+            None => {}
+        }
+    }
+    None
+}
\ No newline at end of file
@@ -0,0 +1,129 @@
+use core::fmt;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU64, Ordering};
+use log::{Level, Record};
+use spin::Mutex;
+
+const BUFFER_CAPACITY: usize = 256;
+const TARGET_LENGTH: usize = 48;
+const MESSAGE_LENGTH: usize = 160;
+
+static BUFFER: Mutex<LogBuffer> = Mutex::new(LogBuffer::new());
+/// Amount of records ever logged, lets readers notice new ones without taking the lock.
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Text of a fixed capacity, longer text is cut off at a character boundary.
+#[derive(Clone, Copy)]
+pub(crate) struct FixedText<const N: usize> {
+    bytes: [u8; N],
+    length: usize
+} impl<const N: usize> FixedText<N> {
+    pub(crate) const fn new() -> Self { Self {
+        bytes: [0; N],
+        length: 0
+    } }
+
+    pub(crate) fn as_str(&self) -> &str {
+        // Only whole characters are ever written
+        core::str::from_utf8(&self.bytes[..self.length]).unwrap_or("")
+    }
+} impl<const N: usize> Write for FixedText<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for character in s.chars() {
+            let length = character.len_utf8();
+            if self.length + length > N { break; }
+            character.encode_utf8(&mut self.bytes[self.length..]);
+            self.length += length;
+        }
+        Ok(())
+    }
+}
+
+/// A log message kept for reading it back later, e.g. on screen.
+#[derive(Clone, Copy)]
+pub struct LogRecord {
+    /// Position of the record among all records logged since boot.
+    pub sequence: u64,
+    pub level: Level,
+    target: FixedText<TARGET_LENGTH>,
+    message: FixedText<MESSAGE_LENGTH>
+} impl LogRecord {
+    /// The module the record was logged from, e.g. `kernel::managers::display`.
+    pub fn target(&self) -> &str {
+        self.target.as_str()
+    }
+
+    /// The message without its fields, cut off if it was too long to keep.
+    pub fn message(&self) -> &str {
+        crate::internal::log_fields::split(self.message.as_str()).0
+    }
+
+    /// The key/value fields logged with the message, as far as they were kept.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        crate::internal::log_fields::split(self.message.as_str()).1
+    }
+}
+
+struct LogBuffer {
+    records: [LogRecord; BUFFER_CAPACITY],
+    head: usize,
+    length: usize
+} impl LogBuffer {
+    const fn new() -> Self { Self {
+        records: [LogRecord {
+            sequence: 0, level: Level::Trace,
+            target: FixedText::new(), message: FixedText::new()
+        }; BUFFER_CAPACITY],
+        head: 0,
+        length: 0
+    } }
+
+    fn push(&mut self, record: LogRecord) {
+        self.records[self.head] = record;
+        self.head = (self.head + 1) % BUFFER_CAPACITY;
+        self.length = (self.length + 1).min(BUFFER_CAPACITY);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &LogRecord> {
+        let start = (self.head + BUFFER_CAPACITY - self.length) % BUFFER_CAPACITY;
+        (0..self.length).map(move |index| &self.records[(start + index) % BUFFER_CAPACITY])
+    }
+}
+
+/// Keeps the record in the ring buffer, overwriting the oldest one once it is full.
+/// Never blocks: if the buffer is currently being read the record is dropped.
+pub fn record(record: &Record) {
+    let mut target = FixedText::new();
+    let _ = target.write_str(record.target());
+    let mut message = FixedText::new();
+    let _ = message.write_fmt(*record.args());
+
+    crate::internal::idt::without_interrupts(|| {
+        if let Some(mut buffer) = BUFFER.try_lock() {
+            let sequence = SEQUENCE.fetch_add(1, Ordering::SeqCst);
+            buffer.push(LogRecord { sequence, level: record.level(), target, message });
+        } else {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    })
+}
+
+/// Returns how many records were kept since boot, changes whenever a new record arrives.
+pub fn sequence() -> u64 {
+    SEQUENCE.load(Ordering::SeqCst)
+}
+
+/// Returns how many records were dropped because the buffer was being read.
+pub fn dropped() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// Passes the kept records to the function, oldest first. Records logged by the function itself
+/// are dropped, as the buffer is locked meanwhile.
+pub fn for_each<F>(func: F)
+    where F: FnMut(&LogRecord) {
+
+    let buffer = BUFFER.lock();
+    buffer.iter().for_each(func);
+}
\ No newline at end of file
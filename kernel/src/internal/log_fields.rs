@@ -0,0 +1,60 @@
+use core::fmt;
+use core::fmt::{Display, Write};
+
+/// Marks where a field starts in a formatted message. The `log` crate carries only the
+/// message without its key/value support, so fields travel inside it behind this separator.
+pub static FIELD_SEPARATOR: char = '\u{1F}';
+
+/// Logs a message with key/value fields, shown as `key=value` after the message.
+///
+/// Usage: `log_fields!(log::Level::Warn, "Handler is locked."; handler = id, queued = 3)`.
+#[macro_export]
+macro_rules! log_fields {
+    ($level:expr, $($message:expr),+; $($key:ident = $value:expr),+ $(,)?) => {
+        log::log!($level, "{}{}", format_args!($($message),+), $crate::internal::log_fields::Fields(&[
+            $((stringify!($key), &$value as &dyn core::fmt::Display)),+
+        ]))
+    };
+}
+
+/// Key/value fields formatted the way the log records carry them.
+pub struct Fields<'a>(pub &'a [(&'a str, &'a dyn Display)]);
+impl Display for Fields<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in self.0 {
+            write!(f, "{}{}={}", FIELD_SEPARATOR, key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits a formatted message into the message itself and its fields.
+pub fn split(message: &str) -> (&str, impl Iterator<Item = (&str, &str)>) {
+    let mut parts = message.split(FIELD_SEPARATOR);
+    let text = parts.next().unwrap_or("");
+    (text, parts.map(|field| field.split_once('=').unwrap_or((field, ""))))
+}
+
+/// Shows a formatted message with its fields as ` key=value`, e.g. for the serial log.
+pub struct Plain<'a>(pub fmt::Arguments<'a>);
+impl Display for Plain<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        PlainFields(f).write_fmt(self.0)
+    }
+}
+
+/// Passes text on with the field separators turned into spaces.
+struct PlainFields<'a, W: Write>(&'a mut W);
+impl<W: Write> Write for PlainFields<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut parts = s.split(FIELD_SEPARATOR);
+        if let Some(first) = parts.next() {
+            self.0.write_str(first)?;
+        }
+        for part in parts {
+            self.0.write_char(' ')?;
+            self.0.write_str(part)?;
+        }
+        Ok(())
+    }
+}
\ No newline at end of file
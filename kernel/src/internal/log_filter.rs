@@ -0,0 +1,186 @@
+use core::fmt::Write;
+use log::{Level, LevelFilter, Metadata, Record};
+use spin::Mutex;
+use crate::internal::log_buffer::FixedText;
+
+const MAX_FILTERS: usize = 16;
+const TARGET_LENGTH: usize = 48;
+
+/// How often a message that keeps repeating is reported, in milliseconds.
+static REPEAT_REPORT_MILLIS: u64 = 1000;
+
+/// Debug and trace records are only logged by kernels built with the verbose-debug feature.
+#[cfg(feature = "verbose-debug")]
+static MAX_LEVEL: LevelFilter = LevelFilter::Trace;
+#[cfg(not(feature = "verbose-debug"))]
+static MAX_LEVEL: LevelFilter = LevelFilter::Info;
+
+static FILTERS: Mutex<Filters> = Mutex::new(Filters::new());
+static LAST_RECORD: Mutex<LastRecord> = Mutex::new(LastRecord::new());
+
+/// The level of a module and everything below it, e.g. `kernel::api` also covers `kernel::api::event`.
+#[derive(Clone, Copy)]
+struct TargetFilter {
+    target: FixedText<TARGET_LENGTH>,
+    level: LevelFilter
+} impl TargetFilter {
+    fn matches(&self, target: &str) -> bool {
+        let prefix = self.target.as_str();
+        target.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    }
+}
+
+struct Filters {
+    default: LevelFilter,
+    targets: [Option<TargetFilter>; MAX_FILTERS]
+} impl Filters {
+    const fn new() -> Self { Self {
+        default: LevelFilter::Trace,
+        targets: [None; MAX_FILTERS]
+    } }
+
+    /// Returns the level of the most specific filter covering the target.
+    fn level(&self, target: &str) -> LevelFilter {
+        let level = self.targets.iter().flatten()
+            .filter(|filter| filter.matches(target))
+            .max_by_key(|filter| filter.target.as_str().len())
+            .map_or(self.default, |filter| filter.level);
+        level.min(MAX_LEVEL)
+    }
+
+    /// The `log` macros skip anything above the maximum level before asking the logger, so it
+    /// has to let through the most verbose level any module is set to.
+    fn apply(&self) {
+        let level = self.targets.iter().flatten()
+            .map(|filter| filter.level)
+            .fold(self.default, Ord::max);
+        log::set_max_level(level.min(MAX_LEVEL));
+    }
+}
+
+/// The message logged last and how often it was repeated since.
+struct LastRecord {
+    hash: u64,
+    level: Level,
+    target: FixedText<TARGET_LENGTH>,
+    repeated: u32,
+    reported: u64
+} impl LastRecord {
+    const fn new() -> Self { Self {
+        hash: 0,
+        level: Level::Trace,
+        target: FixedText::new(),
+        repeated: 0,
+        reported: 0
+    } }
+}
+
+/// Whether a record is logged and which held back repeats have to be reported first.
+pub struct RepeatCheck {
+    /// Logs the record, false while it repeats the one before.
+    pub log: bool,
+    /// How many repeats were held back, reported as logged from the level and target below.
+    pub repeated: u32,
+    pub level: Level,
+    target: FixedText<TARGET_LENGTH>
+} impl RepeatCheck {
+    pub fn target(&self) -> &str {
+        self.target.as_str()
+    }
+}
+
+/// Hashes what makes two records identical with FNV-1a, so comparing needs no copy of the message.
+struct RecordHasher(u64);
+impl Write for RecordHasher {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for byte in s.bytes() {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100000001B3);
+        }
+        Ok(())
+    }
+}
+
+/// Sets the level of every module without a filter of its own.
+pub fn set_default_level(level: LevelFilter) {
+    crate::internal::idt::without_interrupts(|| {
+        let mut filters = FILTERS.lock();
+        filters.default = level;
+        filters.apply();
+    })
+}
+
+/// Sets the level of the module and everything below it, or removes its filter if None.
+pub fn set_target_level(target: &str, level: Option<LevelFilter>) -> Result<(), &'static str> {
+    if target.len() > TARGET_LENGTH {
+        return Err("Module path is too long");
+    }
+
+    crate::internal::idt::without_interrupts(|| {
+        let mut filters = FILTERS.lock();
+        let existing = filters.targets.iter_mut()
+            .position(|filter| filter.is_some_and(|filter| filter.target.as_str() == target));
+        match (existing, level) {
+            (Some(index), Some(level)) => if let Some(filter) = filters.targets[index].as_mut() {
+                filter.level = level;
+            },
+            (Some(index), None) => filters.targets[index] = None,
+            (None, Some(level)) => {
+                let slot = filters.targets.iter_mut().find(|filter| filter.is_none()).ok_or("Too many module filters")?;
+                let mut text = FixedText::new();
+                let _ = text.write_str(target);
+                *slot = Some(TargetFilter { target: text, level });
+            },
+            (None, None) => return Err("Module has no filter")
+        }
+        filters.apply();
+        Ok(())
+    })
+}
+
+/// Passes the default level and then every module filter to the function.
+pub fn for_each<F>(mut func: F)
+    where F: FnMut(&str, LevelFilter) {
+
+    let filters = crate::internal::idt::without_interrupts(|| {
+        let filters = FILTERS.lock();
+        (filters.default, filters.targets)
+    });
+    func("*", filters.0);
+    filters.1.iter().flatten().for_each(|filter| func(filter.target.as_str(), filter.level));
+}
+
+/// Returns whether records of this level from this module are logged.
+pub fn enabled(metadata: &Metadata) -> bool {
+    crate::internal::idt::without_interrupts(|| {
+        metadata.level() <= FILTERS.lock().level(metadata.target())
+    })
+}
+
+/// Compares the record against the one logged before it, holding back identical repeats. Those
+/// are reported once a different record arrives, or every second while they keep coming.
+pub fn check_repeat(record: &Record) -> RepeatCheck {
+    let mut hasher = RecordHasher(0xCBF29CE484222325);
+    let _ = write!(hasher, "{}\0{}\0{}", record.level(), record.target(), record.args());
+    let now = crate::internal::tsc::read();
+    let report_cycles = crate::internal::tsc::khz() * REPEAT_REPORT_MILLIS;
+
+    crate::internal::idt::without_interrupts(|| {
+        // Logged from within logging, better to log it twice than not at all
+        let Some(mut last) = LAST_RECORD.try_lock() else {
+            return RepeatCheck { log: true, repeated: 0, level: record.level(), target: FixedText::new() };
+        };
+
+        if last.hash == hasher.0 {
+            last.repeated += 1;
+            let due = now.wrapping_sub(last.reported) >= report_cycles;
+            let repeated = if due { last.reported = now; core::mem::take(&mut last.repeated) } else { 0 };
+            return RepeatCheck { log: false, repeated, level: last.level, target: last.target };
+        }
+
+        let check = RepeatCheck { log: true, repeated: last.repeated, level: last.level, target: last.target };
+        let mut target = FixedText::new();
+        let _ = target.write_str(record.target());
+        *last = LastRecord { hash: hasher.0, level: record.level(), target, repeated: 0, reported: now };
+        check
+    })
+}
\ No newline at end of file
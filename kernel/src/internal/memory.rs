@@ -1,8 +1,21 @@
-use bootloader_api::info::{MemoryRegionKind, MemoryRegions};
-use x86_64::structures::paging::{OffsetPageTable, PageTable, PhysFrame};
+use x86_64::structures::paging::{FrameAllocator, Mapper, OffsetPageTable, Page, PageSize, PageTable, PageTableFlags, PhysFrame, Size2MiB, Size4KiB};
+use x86_64::structures::paging::mapper::MapToError;
+use spin::Once;
 use x86_64::{PhysAddr, VirtAddr};
+use crate::api::error::KernelError;
+use crate::internal::protocol::{MemoryRegion, MemoryRegionKind};
+
+/// How many pages of each size a range was mapped with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MappingStats {
+    pub huge_pages: usize,
+    pub pages: usize
+}
+
+static PHYSICAL_MEMORY_OFFSET: Once<VirtAddr> = Once::new();
 
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    PHYSICAL_MEMORY_OFFSET.call_once(|| physical_memory_offset);
     let level_4_table = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
@@ -19,20 +32,88 @@ unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut
     &mut *page_table_ptr
 }
 
-pub fn get_usable_regions(memory_regions: &'static MemoryRegions, skip: usize) -> impl Iterator<Item = PhysFrame> {
+pub fn get_usable_regions(memory_regions: &'static [MemoryRegion], skip: usize) -> impl Iterator<Item = PhysFrame> {
     memory_regions.iter()
         .filter(|region| region.kind == MemoryRegionKind::Usable)
         .filter(|region| region.start % 4096 == 0 && region.end % 4096 == 0)
         .map(|region| region.start..region.end)
         .flat_map(|region_range| region_range.step_by(4096))
+        // The memory map might have overlapping regions and drivers might have claimed memory
+        .filter(|addr| crate::internal::reserved::reserved_by(*addr, addr + 4096).is_none())
         .map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
         .skip(skip)
 }
 
+/// Returns the virtual address all of the physical memory is mapped at, None before `init`.
+pub fn physical_memory_offset() -> Option<VirtAddr> {
+    PHYSICAL_MEMORY_OFFSET.get().copied()
+}
+
 pub fn phys_to_virt(physical_memory_offset: VirtAddr, physical_address: PhysAddr) -> VirtAddr {
     physical_memory_offset + physical_address.as_u64()
 }
 
+/// Maps `size` bytes from `start` on, using 2 MiB pages where the virtual address is aligned to
+/// them (and the CPU supports them) and 4 KiB pages elsewhere. `frames` is asked for the physical
+/// address backing the page at an offset into the range, for 2 MiB pages it may return None (or an
+/// unaligned address) to fall back to 4 KiB pages.
+pub fn map_range<A: FrameAllocator<Size4KiB>>(
+    mapper: &mut (impl Mapper<Size4KiB> + Mapper<Size2MiB>),
+    frame_allocator: &mut A,
+    start: VirtAddr, size: u64, flags: PageTableFlags,
+    mut frames: impl FnMut(&mut A, u64, bool) -> Option<PhysAddr>
+) -> Result<MappingStats, MapToError<Size4KiB>> {
+    let mut stats = MappingStats::default();
+    let mut offset = 0;
+    while offset < size {
+        let address = start + offset;
+        let huge_fits = crate::internal::cpu::supports_huge_pages()
+            && address.is_aligned(Size2MiB::SIZE) && size - offset >= Size2MiB::SIZE;
+        let huge_frame = huge_fits.then(|| frames(frame_allocator, offset, true)).flatten()
+            .and_then(|frame| PhysFrame::<Size2MiB>::from_start_address(frame).ok());
+
+        if let Some(frame) = huge_frame {
+            let page = Page::<Size2MiB>::containing_address(address);
+            unsafe { mapper.map_to(page, frame, flags, frame_allocator).map_err(small_map_error)?.flush() };
+            stats.huge_pages += 1;
+            offset += Size2MiB::SIZE;
+        } else {
+            let frame = frames(frame_allocator, offset, false).ok_or(MapToError::FrameAllocationFailed)?;
+            let page = Page::<Size4KiB>::containing_address(address);
+            unsafe { mapper.map_to(page, PhysFrame::containing_address(frame), flags, frame_allocator)?.flush() };
+            stats.pages += 1;
+            offset += Size4KiB::SIZE;
+        }
+    }
+    Ok(stats)
+}
+
+fn small_map_error(error: MapToError<Size2MiB>) -> MapToError<Size4KiB> {
+    match error {
+        MapToError::FrameAllocationFailed => MapToError::FrameAllocationFailed,
+        MapToError::ParentEntryHugePage => MapToError::ParentEntryHugePage,
+        MapToError::PageAlreadyMapped(frame) => MapToError::PageAlreadyMapped(PhysFrame::containing_address(frame.start_address()))
+    }
+}
+
+/// Claims the memory mapped registers of a device for the owner and returns where they can be
+/// accessed through the physical memory mapping. Fails if the range is RAM, firmware data or
+/// claimed by another driver.
+pub fn map_mmio(
+    physical_memory_offset: VirtAddr, address: PhysAddr, size: u64, owner: &'static str
+) -> Result<VirtAddr, KernelError> {
+    crate::internal::reserved::claim_mmio(address.as_u64(), address.as_u64() + size, owner)?;
+    Ok(phys_to_virt(physical_memory_offset, address))
+}
+
+/// Returns the flags with the caching bits selecting write-combining, or unchanged if the page
+/// attribute table has no such entry. Meant for frame buffers and GPU apertures, which are written
+/// a lot and hardly ever read back.
+pub fn write_combining(flags: PageTableFlags) -> PageTableFlags {
+    if !crate::internal::msr::write_combining() { return flags; }
+    (flags - PageTableFlags::NO_CACHE) | PageTableFlags::WRITE_THROUGH
+}
+
 #[allow(dead_code)]
 pub fn read_address<T>(address: usize) -> T where T: Copy {
     let virt_addr = VirtAddr::new(address as u64);
@@ -7,4 +7,38 @@ pub mod aml;
 pub mod idt;
 pub mod pic;
 pub mod cmos;
-pub mod framebuffer;
\ No newline at end of file
+pub mod framebuffer;
+pub mod tsc;
+pub mod trace;
+pub mod cpu;
+pub mod keyboard;
+pub mod protocol;
+pub mod initrd;
+pub mod emergency;
+pub mod blit;
+pub mod log_buffer;
+pub mod log_filter;
+pub mod log_fields;
+pub mod early_log;
+pub mod apic;
+pub mod interrupts;
+pub mod ioapic;
+pub mod symbols;
+#[cfg(feature = "heap-debug")]
+pub mod heap_debug;
+pub mod reserved;
+pub mod vmmap;
+pub mod random;
+pub mod kaslr;
+pub mod ioport;
+pub mod msr;
+pub mod cpuidle;
+pub mod cpufreq;
+pub mod smbios;
+pub mod efi;
+pub mod build_info;
+pub mod platform;
+pub mod ec;
+pub mod hpet;
+pub mod sysrq;
+pub mod pci;
\ No newline at end of file
@@ -0,0 +1,242 @@
+use core::fmt::{Display, Formatter};
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::{PhysAddr, VirtAddr};
+use x86_64::registers::model_specific::{Efer, FsBase, GsBase, KernelGsBase, Msr};
+use crate::api::error::KernelError;
+
+pub use x86_64::registers::model_specific::EferFlags;
+
+static IA32_APIC_BASE: u32 = 0x1B;
+static IA32_TSC_DEADLINE: u32 = 0x6E0;
+static IA32_PAT: u32 = 0x277;
+static IA32_MPERF: u32 = 0xE7;
+static IA32_APERF: u32 = 0xE8;
+
+/// The power-on defaults, except for entry 1 (PWT) which becomes write-combining. Write-through
+/// stays available through entry 5 (PAT and PWT).
+static KERNEL_PAT: PageAttributeTable = PageAttributeTable([
+    MemoryType::WriteBack, MemoryType::WriteCombining, MemoryType::UncachedMinus, MemoryType::Uncacheable,
+    MemoryType::WriteBack, MemoryType::WriteThrough, MemoryType::UncachedMinus, MemoryType::Uncacheable
+]);
+static WRITE_COMBINING: AtomicBool = AtomicBool::new(false);
+
+static APIC_BASE_BSP: u64 = 1 << 8;
+static APIC_BASE_X2APIC: u64 = 1 << 10;
+static APIC_BASE_ENABLE: u64 = 1 << 11;
+static APIC_BASE_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// The contents of IA32_APIC_BASE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApicBase {
+    /// The physical address of the local APIC registers in xAPIC mode.
+    pub address: PhysAddr,
+    /// Whether this is the CPU the firmware booted on.
+    pub bootstrap: bool,
+    pub x2apic: bool,
+    pub enabled: bool
+}
+
+/// A memory type the page attribute table can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(dead_code)]
+pub enum MemoryType {
+    Uncacheable = 0,
+    WriteCombining = 1,
+    WriteThrough = 4,
+    WriteProtected = 5,
+    WriteBack = 6,
+    /// Uncacheable, but can be overridden to write-combining by the MTRRs.
+    UncachedMinus = 7
+} impl MemoryType {
+    fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0 => Some(MemoryType::Uncacheable),
+            1 => Some(MemoryType::WriteCombining),
+            4 => Some(MemoryType::WriteThrough),
+            5 => Some(MemoryType::WriteProtected),
+            6 => Some(MemoryType::WriteBack),
+            7 => Some(MemoryType::UncachedMinus),
+            _ => None
+        }
+    }
+
+    fn short_name(self) -> &'static str {
+        match self {
+            MemoryType::Uncacheable => "UC",
+            MemoryType::WriteCombining => "WC",
+            MemoryType::WriteThrough => "WT",
+            MemoryType::WriteProtected => "WP",
+            MemoryType::WriteBack => "WB",
+            MemoryType::UncachedMinus => "UC-"
+        }
+    }
+}
+
+/// The eight entries of the page attribute table, selected by the PAT, PCD and PWT bits of a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageAttributeTable(pub [MemoryType; 8]);
+
+impl Display for PageAttributeTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        for (index, memory_type) in self.0.iter().enumerate() {
+            if index > 0 { write!(f, " ")?; }
+            write!(f, "{}", memory_type.short_name())?;
+        }
+        Ok(())
+    }
+}
+
+pub fn efer() -> EferFlags {
+    Efer::read()
+}
+
+/// Sets the given EFER flags, keeping the others. Fails if the CPU doesn't support no-execute
+/// pages or `syscall` and those are asked for.
+pub fn enable_efer(flags: EferFlags) -> Result<(), KernelError> {
+    if flags.contains(EferFlags::NO_EXECUTE_ENABLE) && !crate::internal::cpu::supports_no_execute() {
+        return Err(KernelError::HardwareMissing("No-execute pages"));
+    }
+    if flags.contains(EferFlags::SYSTEM_CALL_EXTENSIONS) && !crate::internal::cpu::supports_syscall() {
+        return Err(KernelError::HardwareMissing("Syscall instruction"));
+    }
+    // Only adds features, the long mode flags the kernel runs with stay set
+    unsafe { Efer::update(|efer| efer.insert(flags)); }
+    Ok(())
+}
+
+pub fn apic_base() -> ApicBase {
+    let value = unsafe { Msr::new(IA32_APIC_BASE).read() };
+    ApicBase {
+        address: PhysAddr::new(value & APIC_BASE_ADDRESS_MASK),
+        bootstrap: value & APIC_BASE_BSP != 0,
+        x2apic: value & APIC_BASE_X2APIC != 0,
+        enabled: value & APIC_BASE_ENABLE != 0
+    }
+}
+
+/// Writes IA32_APIC_BASE, the bootstrap flag is read-only and ignored. The local APIC can only
+/// go from xAPIC to x2APIC mode while enabled, so coming from disabled it is enabled in xAPIC mode
+/// first. Switching back from x2APIC to xAPIC needs disabling it in between and is refused.
+pub fn set_apic_base(apic_base: ApicBase) -> Result<(), KernelError> {
+    if apic_base.x2apic && !crate::internal::cpu::supports_x2apic() {
+        return Err(KernelError::HardwareMissing("x2APIC"));
+    }
+    if apic_base.x2apic && !apic_base.enabled {
+        return Err(KernelError::InvalidConfiguration("x2APIC mode needs the local APIC enabled"));
+    }
+    let current = crate::internal::msr::apic_base();
+    if current.enabled && current.x2apic && apic_base.enabled && !apic_base.x2apic {
+        return Err(KernelError::InvalidConfiguration("x2APIC mode can only be left by disabling the local APIC"));
+    }
+
+    let mut value = unsafe { Msr::new(IA32_APIC_BASE).read() } & !(APIC_BASE_ADDRESS_MASK | APIC_BASE_X2APIC | APIC_BASE_ENABLE);
+    value |= apic_base.address.as_u64() & APIC_BASE_ADDRESS_MASK;
+    if apic_base.enabled { value |= APIC_BASE_ENABLE; }
+    if apic_base.x2apic && !current.enabled {
+        unsafe { Msr::new(IA32_APIC_BASE).write(value); }
+    }
+    if apic_base.x2apic { value |= APIC_BASE_X2APIC; }
+    unsafe { Msr::new(IA32_APIC_BASE).write(value); }
+    Ok(())
+}
+
+pub fn fs_base() -> VirtAddr {
+    FsBase::read()
+}
+
+#[allow(dead_code)]
+pub fn set_fs_base(address: VirtAddr) {
+    FsBase::write(address)
+}
+
+pub fn gs_base() -> VirtAddr {
+    GsBase::read()
+}
+
+#[allow(dead_code)]
+pub fn set_gs_base(address: VirtAddr) {
+    GsBase::write(address)
+}
+
+/// The GS base `swapgs` swaps in, used for per-CPU data while in the kernel.
+pub fn kernel_gs_base() -> VirtAddr {
+    KernelGsBase::read()
+}
+
+#[allow(dead_code)]
+pub fn set_kernel_gs_base(address: VirtAddr) {
+    KernelGsBase::write(address)
+}
+
+/// Sets the time stamp counter value the local APIC timer fires at, zero disarms it. The caller
+/// has to check for TSC-deadline support once, as this gets called from the timer interrupt.
+pub fn set_tsc_deadline(deadline: u64) {
+    unsafe { Msr::new(IA32_TSC_DEADLINE).write(deadline); }
+}
+
+/// Returns the APERF and MPERF counters, None if the CPU doesn't have them. MPERF counts at a
+/// fixed rate while the CPU runs, APERF at the actual clock rate.
+pub fn performance_counters() -> Option<(u64, u64)> {
+    if !crate::internal::cpu::supports_aperf_mperf() { return None; }
+    Some(unsafe { (Msr::new(IA32_APERF).read(), Msr::new(IA32_MPERF).read()) })
+}
+
+/// Returns the page attribute table, None if the CPU doesn't have one.
+pub fn pat() -> Option<PageAttributeTable> {
+    if !crate::internal::cpu::supports_pat() { return None; }
+    let value = unsafe { Msr::new(IA32_PAT).read() };
+    let mut entries = [MemoryType::Uncacheable; 8];
+    for (index, entry) in entries.iter_mut().enumerate() {
+        // Reserved encodings can't be written, so reading one back is impossible
+        *entry = MemoryType::from_bits((value >> (index * 8)) as u8 & 0b111).unwrap_or(MemoryType::Uncacheable);
+    }
+    Some(PageAttributeTable(entries))
+}
+
+/// Replaces the page attribute table. Mappings using an entry whose type changes have to be
+/// flushed from the caches and TLBs by the caller.
+pub fn set_pat(table: PageAttributeTable) -> Result<(), KernelError> {
+    if !crate::internal::cpu::supports_pat() {
+        return Err(KernelError::HardwareMissing("Page attribute table"));
+    }
+    let value = table.0.iter().enumerate().fold(0u64, |value, (index, memory_type)| {
+        value | (*memory_type as u64) << (index * 8)
+    });
+    unsafe { Msr::new(IA32_PAT).write(value); }
+    Ok(())
+}
+
+/// Programs the kernel's page attribute table, making write-combining mappings possible. The
+/// bootloader's mappings don't use entry 1, so none of them change their memory type.
+pub fn init_pat() -> Result<(), KernelError> {
+    crate::internal::idt::without_interrupts(|| {
+        unsafe { core::arch::asm!("wbinvd", options(nostack, preserves_flags)); }
+        set_pat(KERNEL_PAT)?;
+        unsafe { core::arch::asm!("wbinvd", options(nostack, preserves_flags)); }
+        x86_64::instructions::tlb::flush_all();
+        Ok::<(), KernelError>(())
+    })?;
+    WRITE_COMBINING.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Returns whether the page attribute table has a write-combining entry, selected by the PWT bit.
+pub fn write_combining() -> bool {
+    WRITE_COMBINING.load(Ordering::SeqCst)
+}
+
+/// Logs the values the bootloader left in the MSRs the kernel manages.
+pub fn log_values() {
+    log::info!("EFER: {:?}", efer());
+    let apic_base = apic_base();
+    log::info!(
+        "APIC base: {:#X} (enabled: {}, x2APIC: {}, bootstrap CPU: {})",
+        apic_base.address, apic_base.enabled, apic_base.x2apic, apic_base.bootstrap
+    );
+    log::info!("FS base: {:#X}, GS base: {:#X}, kernel GS base: {:#X}", fs_base(), gs_base(), kernel_gs_base());
+    match pat() {
+        Some(pat) => log::info!("PAT: {}", pat),
+        None => log::info!("PAT: not supported")
+    }
+}
\ No newline at end of file
@@ -0,0 +1,64 @@
+use spin::{Mutex, Once};
+use crate::internal::ioport::IoPortRange;
+
+/// The address port, followed by the data port at 0xCFC.
+static CONFIG_PORT: u16 = 0xCF8;
+static CONFIG_PORT_COUNT: u16 = 8;
+static DATA_OFFSET: u16 = 4;
+static ENABLE_BIT: u32 = 1 << 31;
+
+/// Configuration mechanism 1 only reaches the first 256 bytes of every function.
+static CONFIG_SPACE_SIZE: u16 = 256;
+
+static PORTS: Once<Mutex<IoPortRange>> = Once::new();
+
+/// Returns the configuration ports, claiming them on first use.
+fn ports() -> &'static Mutex<IoPortRange> {
+    PORTS.call_once(|| Mutex::new(crate::internal::ioport::claim(CONFIG_PORT, CONFIG_PORT_COUNT, "PCI")
+        .unwrap_or_else(|err| panic!("Failed to claim the PCI configuration ports: {}", err))))
+}
+
+/// Selects the dword of the configuration space and calls the function with the ports and the
+/// offset of the data port the addressed byte is at. Returns None for what mechanism 1 can't
+/// reach: other segments than 0 and the extended configuration space.
+fn with_config<R>(
+    segment: u16, bus: u8, device: u8, function: u8, offset: u16, func: impl FnOnce(&IoPortRange, u16) -> R
+) -> Option<R> {
+    if segment != 0 || offset >= CONFIG_SPACE_SIZE || device >= 32 || function >= 8 {
+        return None;
+    }
+    let address = ENABLE_BIT | (bus as u32) << 16 | (device as u32) << 11 | (function as u32) << 8 | (offset as u32 & 0xFC);
+    // The address and data accesses must not be split by someone else selecting another dword
+    crate::internal::idt::without_interrupts(|| {
+        let ports = ports().lock();
+        ports.write(0, address);
+        Some(func(&ports, DATA_OFFSET + (offset & 3)))
+    })
+}
+
+/// Reads the configuration space of a function through configuration mechanism 1. What can't be
+/// reached reads as all ones, like a missing device.
+pub fn read_u8(segment: u16, bus: u8, device: u8, function: u8, offset: u16) -> u8 {
+    with_config(segment, bus, device, function, offset, |ports, data| ports.read(data)).unwrap_or(u8::MAX)
+}
+
+pub fn read_u16(segment: u16, bus: u8, device: u8, function: u8, offset: u16) -> u16 {
+    with_config(segment, bus, device, function, offset & !1, |ports, data| ports.read(data)).unwrap_or(u16::MAX)
+}
+
+pub fn read_u32(segment: u16, bus: u8, device: u8, function: u8, offset: u16) -> u32 {
+    with_config(segment, bus, device, function, offset & !3, |ports, data| ports.read(data)).unwrap_or(u32::MAX)
+}
+
+/// Writes the configuration space of a function, writes to what can't be reached are dropped.
+pub fn write_u8(segment: u16, bus: u8, device: u8, function: u8, offset: u16, value: u8) {
+    with_config(segment, bus, device, function, offset, |ports, data| ports.write(data, value));
+}
+
+pub fn write_u16(segment: u16, bus: u8, device: u8, function: u8, offset: u16, value: u16) {
+    with_config(segment, bus, device, function, offset & !1, |ports, data| ports.write(data, value));
+}
+
+pub fn write_u32(segment: u16, bus: u8, device: u8, function: u8, offset: u16, value: u32) {
+    with_config(segment, bus, device, function, offset & !3, |ports, data| ports.write(data, value));
+}
\ No newline at end of file
@@ -1,19 +1,21 @@
 use pic8259::ChainedPics;
 use spin::{Mutex, Once};
-use x86_64::instructions::port::Port;
 use bit_field::BitField;
-
-static DATA_PORT: u16 = 0x40;
-static COMMAND_PORT: u16 = 0x43;
-static OPERATING_MODE: u8 = 0b0011_0100; // 16-bit binary, rate generator, lo/hi byte, channel 0
-pub static TIMER_HZ: u64 = 1000; // 1000Hz (min 19Hz, max 1193180Hz) - 1ms interval
-pub static TIMER_DIVISOR: u64 = 1193180 / TIMER_HZ;
+use crate::api::error::KernelError;
+use crate::internal::ioport::IoPortRange;
 
 static PIC1_OFFSET: u8 = 0x20;
 static PIC2_OFFSET: u8 = 0x28;
+static PIC1_COMMAND_PORT: u16 = 0x20;
+static PIC2_COMMAND_PORT: u16 = 0xA0;
+static PIC_PORT_COUNT: u16 = 2;
+static READ_ISR_COMMAND: u8 = 0x0B;
 
 static PICS: Once<Mutex<ChainedPics>> = Once::new();
+/// The command and data ports of both PICs, accessed through `ChainedPics` apart from reading the ISR.
+static PIC_PORTS: Once<(IoPortRange, IoPortRange)> = Once::new();
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(dead_code)]
 pub enum PicInterrupts {
     Timer, Keyboard, PassThrough,
@@ -40,6 +42,21 @@ pub enum PicInterrupts {
             PicInterrupts::LPT1 => (7, PIC1_OFFSET + 7)
         }
     }
+
+    /// Returns the number of the interrupt line, 0 to 7 on the master and 8 to 15 on the slave.
+    pub fn line(self) -> u8 {
+        self.into_values().1 - PIC1_OFFSET
+    }
+
+    /// Returns the interrupt delivered at the vector, None if it isn't one of the PIC vectors.
+    pub fn from_vector(vector: u8) -> Option<Self> {
+        [
+            PicInterrupts::Timer, PicInterrupts::Keyboard, PicInterrupts::PassThrough,
+            PicInterrupts::RTC, PicInterrupts::ACPI, PicInterrupts::PCI1, PicInterrupts::PCI2,
+            PicInterrupts::Mouse, PicInterrupts::FPU, PicInterrupts::PrimaryATA, PicInterrupts::SecondaryATA,
+            PicInterrupts::COM2, PicInterrupts::COM1, PicInterrupts::LPT2, PicInterrupts::Floppy, PicInterrupts::LPT1
+        ].into_iter().find(|interrupt| interrupt.into_values().1 == vector)
+    }
 }
 
 pub struct PicMask {
@@ -66,28 +83,73 @@ pub struct PicMask {
     }
 }
 
-pub fn init(mask: PicMask) {
+pub fn init(mask: PicMask) -> Result<(), KernelError> {
+    if PIC_PORTS.get().is_none() {
+        let pic1 = crate::internal::ioport::claim(PIC1_COMMAND_PORT, PIC_PORT_COUNT, "PIC")?;
+        let pic2 = crate::internal::ioport::claim(PIC2_COMMAND_PORT, PIC_PORT_COUNT, "PIC")?;
+        PIC_PORTS.call_once(|| (pic1, pic2));
+    }
     PICS.call_once(|| unsafe {
         Mutex::new(ChainedPics::new(PIC1_OFFSET, PIC2_OFFSET))
     });
     mask.apply();
     unsafe {
-        let mut pics = PICS.get().unwrap_or_else(|| panic!("PIC not loaded!")).lock();
+        PICS.get().unwrap_or_else(|| panic!("PIC not loaded!")).lock().initialize();
+    }
+    Ok(())
+}
 
-        let mut data_port: Port<u8> = Port::new(DATA_PORT);
-        let mut command_port: Port<u8> = Port::new(COMMAND_PORT);
+/// Masks a single interrupt, keeping the others as they are.
+pub fn mask(interrupt: PicInterrupts) {
+    set_masked(interrupt, true);
+}
 
-        let low_byte = (TIMER_DIVISOR & 0xFF) as u8;
-        let high_byte = ((TIMER_DIVISOR >> 8) & 0xFF) as u8;
+/// Unmasks a single interrupt, keeping the others as they are.
+pub fn unmask(interrupt: PicInterrupts) {
+    set_masked(interrupt, false);
+}
 
-        command_port.write(OPERATING_MODE);
-        data_port.write(low_byte);
-        data_port.write(high_byte);
+fn set_masked(interrupt: PicInterrupts, masked: bool) {
+    if crate::internal::ioapic::is_routed(interrupt) {
+        return crate::internal::ioapic::set_masked(interrupt, masked);
+    }
+    let (bit, offset) = interrupt.into_values();
+    crate::internal::idt::without_interrupts(|| unsafe {
+        let mut pics = PICS.get().unwrap_or_else(|| panic!("PIC not loaded!")).lock();
+        let [mut pic1, mut pic2] = pics.read_masks();
+        if offset < PIC2_OFFSET {
+            pic1.set_bit(bit as usize, masked);
+        } else {
+            pic2.set_bit(bit as usize, masked);
+        }
+        pics.write_masks(pic1, pic2);
+    })
+}
+
+/// Returns whether the interrupt is really in service. The last line of each PIC (LPT1 and
+/// SecondaryATA) also gets raised for interrupts that went away before they could be delivered,
+/// those are spurious and are not in service.
+pub fn in_service(interrupt: PicInterrupts) -> bool {
+    let (bit, offset) = interrupt.into_values();
+    let (pic1, pic2) = PIC_PORTS.get().unwrap_or_else(|| panic!("PIC not loaded!"));
+    let ports = if offset < PIC2_OFFSET { pic1 } else { pic2 };
 
-        pics.initialize();
+    let _pics = PICS.get().unwrap_or_else(|| panic!("PIC not loaded!")).lock();
+    ports.write(0, READ_ISR_COMMAND);
+    ports.read::<u8>(0).get_bit(bit as usize)
+}
+
+/// Acknowledges a spurious interrupt. Only a spurious SecondaryATA needs one, and only at the
+/// master, which did see the cascade line go up.
+pub fn end_of_spurious_interrupt(interrupt: PicInterrupts) {
+    if interrupt.into_values().1 >= PIC2_OFFSET {
+        end_of_interrupt(PicInterrupts::PassThrough);
     }
 }
 
 pub fn end_of_interrupt(interrupt: PicInterrupts) {
+    if crate::internal::ioapic::is_routed(interrupt) {
+        return crate::internal::apic::end_of_interrupt();
+    }
     unsafe { PICS.get().unwrap_or_else(|| panic!("PIC not loaded!")).lock().notify_end_of_interrupt(interrupt.into_values().1) }
 }
\ No newline at end of file
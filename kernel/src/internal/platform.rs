@@ -0,0 +1,193 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::fmt::{Display, Formatter};
+use aml::{AmlContext, AmlValue, LevelType};
+use spin::Once;
+use crate::internal::acpi::AcpiNamespace;
+
+/// `_STA` bit telling the device is present.
+static STATUS_PRESENT: u64 = 1 << 0;
+
+static DEVICES: Once<Option<Vec<PlatformDevice>>> = Once::new();
+
+/// What a platform device is, by the IDs the drivers of this kernel know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlatformDeviceKind {
+    Ps2Keyboard,
+    Ps2Mouse,
+    SerialPort,
+    Hpet,
+    Rtc,
+    EmbeddedController,
+    Other
+} impl PlatformDeviceKind {
+    fn from_id(id: &str) -> Self {
+        match id {
+            "PNP0303" | "PNP030B" | "PNP0320" => Self::Ps2Keyboard,
+            "PNP0F03" | "PNP0F0B" | "PNP0F0E" | "PNP0F12" | "PNP0F13" => Self::Ps2Mouse,
+            "PNP0500" | "PNP0501" => Self::SerialPort,
+            "PNP0103" => Self::Hpet,
+            "PNP0B00" | "PNP0B01" | "PNP0B02" => Self::Rtc,
+            "PNP0C09" => Self::EmbeddedController,
+            _ => Self::Other
+        }
+    }
+}
+
+/// A resource a platform device uses, from its `_CRS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlatformResource {
+    IoPorts { base: u16, length: u16 },
+    Memory { base: u64, length: u64 },
+    Irq(u32)
+} impl Display for PlatformResource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoPorts { base, length } => write!(f, "io {:#X}+{}", base, length),
+            Self::Memory { base, length } => write!(f, "memory {:#X}+{:#X}", base, length),
+            Self::Irq(irq) => write!(f, "irq {}", irq)
+        }
+    }
+}
+
+/// A device the firmware describes in the ACPI namespace, rather than one found on a bus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlatformDevice {
+    pub path: String,
+    /// The `_HID`, or the first `_CID` if the device has no hardware ID of its own.
+    pub id: String,
+    pub kind: PlatformDeviceKind,
+    pub uid: Option<u64>,
+    pub resources: Vec<PlatformResource>
+} #[allow(dead_code)] impl PlatformDevice {
+    /// Returns the I/O port ranges of the device in the order the firmware lists them.
+    pub fn io_ports(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        self.resources.iter().filter_map(|resource| match resource {
+            PlatformResource::IoPorts { base, length } => Some((*base, *length)),
+            _ => None
+        })
+    }
+} impl Display for PlatformDevice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {:?}", self.path, self.id, self.kind)?;
+        if let Some(uid) = self.uid {
+            write!(f, " uid {}", uid)?;
+        }
+        for resource in self.resources.iter() {
+            write!(f, " {}", resource)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the present devices with a hardware or compatible ID from the namespace, returns how
+/// many there are. Without a namespace there is no list and the legacy devices are assumed.
+pub fn init(namespace: Option<&AcpiNamespace>) -> Option<usize> {
+    DEVICES.call_once(|| {
+        let namespace = namespace?;
+        let mut aml = namespace.lock();
+        let paths = crate::internal::acpi::find_levels(&mut aml, LevelType::Device);
+        Some(paths.into_iter().filter_map(|path| read_device(&mut aml, path)).collect())
+    }).as_ref().map(Vec::len)
+}
+
+/// Returns the devices the firmware describes, empty without an ACPI namespace.
+pub fn devices() -> &'static [PlatformDevice] {
+    DEVICES.get().and_then(Option::as_ref).map_or(&[], Vec::as_slice)
+}
+
+/// Returns the described devices of the kind.
+#[allow(dead_code)]
+pub fn find(kind: PlatformDeviceKind) -> impl Iterator<Item = &'static PlatformDevice> {
+    devices().iter().filter(move |device| device.kind == kind)
+}
+
+/// Returns whether a driver should bind to a device of the kind: only if the firmware describes
+/// one, or if there is no description to go by and the device is one every PC has.
+pub fn is_present(kind: PlatformDeviceKind) -> bool {
+    match DEVICES.get() {
+        Some(Some(devices)) => devices.iter().any(|device| device.kind == kind),
+        _ => true
+    }
+}
+
+fn read_device(aml: &mut AmlContext, path: String) -> Option<PlatformDevice> {
+    let status = crate::internal::acpi::evaluate(aml, &path, "_STA").ok()
+        .and_then(|status| status.as_integer(aml).ok());
+    if status.is_some_and(|status| status & STATUS_PRESENT == 0) {
+        return None;
+    }
+
+    let hid = crate::internal::acpi::evaluate(aml, &path, "_HID").ok()
+        .and_then(|id| crate::internal::acpi::hardware_id(&id));
+    let cids = match crate::internal::acpi::evaluate(aml, &path, "_CID") {
+        Ok(AmlValue::Package(ids)) => ids.iter().filter_map(crate::internal::acpi::hardware_id).collect(),
+        Ok(id) => crate::internal::acpi::hardware_id(&id).into_iter().collect(),
+        Err(_) => Vec::new()
+    };
+
+    // A compatible ID tells the kind when the hardware ID is vendor specific
+    let kind = hid.iter().chain(cids.iter())
+        .map(|id| PlatformDeviceKind::from_id(id))
+        .find(|kind| *kind != PlatformDeviceKind::Other)
+        .unwrap_or(PlatformDeviceKind::Other);
+    let id = hid.or_else(|| cids.into_iter().next())?;
+
+    let uid = crate::internal::acpi::evaluate(aml, &path, "_UID").ok()
+        .and_then(|uid| uid.as_integer(aml).ok());
+    let resources = match crate::internal::acpi::evaluate(aml, &path, "_CRS") {
+        Ok(AmlValue::Buffer(bytes)) => parse_resources(&bytes.lock()),
+        _ => Vec::new()
+    };
+
+    Some(PlatformDevice { path, id, kind, uid, resources })
+}
+
+/// Reads the I/O port, memory and interrupt descriptors of a resource template, skipping the
+/// ones no driver here needs. Stops at the end tag or at a descriptor running past the buffer.
+fn parse_resources(bytes: &[u8]) -> Vec<PlatformResource> {
+    let mut resources = Vec::new();
+    let mut offset = 0;
+    let read_u16 = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+    let read_u32 = |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+
+    while offset < bytes.len() {
+        let tag = bytes[offset];
+        if tag & 0x80 == 0 {
+            // Small item: name in bits 3 to 6, length in bits 0 to 2
+            let (name, length) = ((tag >> 3) & 0x0F, (tag & 0x07) as usize);
+            let item = offset + 1;
+            if item + length > bytes.len() { break; }
+            match (name, length) {
+                (0x04, 2..) => {
+                    let mask = read_u16(item);
+                    resources.extend((0..16).filter(|irq| mask & (1 << irq) != 0).map(PlatformResource::Irq));
+                },
+                (0x08, 7) => resources.push(PlatformResource::IoPorts { base: read_u16(item + 1), length: bytes[item + 6] as u16 }),
+                (0x09, 3) => resources.push(PlatformResource::IoPorts { base: read_u16(item) & 0x3FF, length: bytes[item + 2] as u16 }),
+                (0x0F, _) => break,
+                _ => {}
+            }
+            offset = item + length;
+        } else {
+            // Large item: name in bits 0 to 6, 16-bit length after the tag
+            if offset + 3 > bytes.len() { break; }
+            let (name, length) = (tag & 0x7F, read_u16(offset + 1) as usize);
+            let item = offset + 3;
+            if item + length > bytes.len() { break; }
+            match name {
+                0x06 if length >= 9 => resources.push(PlatformResource::Memory {
+                    base: read_u32(item + 1) as u64, length: read_u32(item + 5) as u64
+                }),
+                0x09 if length >= 2 => {
+                    let count = (bytes[item + 1] as usize).min((length - 2) / 4);
+                    resources.extend((0..count).map(|index| PlatformResource::Irq(read_u32(item + 2 + index * 4))));
+                },
+                _ => {}
+            }
+            offset = item + length;
+        }
+    }
+    resources
+}
\ No newline at end of file
@@ -0,0 +1,105 @@
+use bootloader_api::{BootInfo, BootloaderConfig};
+use bootloader_api::config::Mapping;
+use bootloader_api::info::{MemoryRegionKind as BootloaderRegionKind, PixelFormat as BootloaderPixelFormat};
+use crate::internal::framebuffer::{ChannelMask, FrameBufferInfo, PixelFormat};
+use crate::internal::protocol::{BootModule, BootProtocol, EfiInformation, KernelFile, MemoryRegion, MemoryRegionKind};
+
+static UEFI_ACPI_RECLAIM_MEMORY: u32 = 9;
+static BIOS_ACPI_RECLAIM_MEMORY: u32 = 3;
+
+const BOOTLOADER_CONFIG: BootloaderConfig = {
+    let mut config = BootloaderConfig::new_default();
+    config.mappings.physical_memory = Some(Mapping::Dynamic);
+    config.kernel_stack_size = 1024 * 1024;
+    config
+};
+bootloader_api::entry_point!(bootloader_main, config = &BOOTLOADER_CONFIG);
+
+fn bootloader_main(boot_info: &'static mut BootInfo) -> ! {
+    crate::kernel_main(crate::internal::protocol::collect(BootloaderProtocol { boot_info }))
+}
+
+/// Boot protocol of the `bootloader` crate, which builds the BIOS and UEFI disk images.
+pub struct BootloaderProtocol {
+    boot_info: &'static mut BootInfo
+} impl BootProtocol for BootloaderProtocol {
+    fn name(&self) -> &'static str { "bootloader" }
+
+    fn physical_memory_offset(&self) -> Option<u64> {
+        self.boot_info.physical_memory_offset.into_option()
+    }
+
+    fn memory_regions(&self, func: &mut dyn FnMut(MemoryRegion)) {
+        for region in self.boot_info.memory_regions.iter() {
+            func(MemoryRegion {
+                start: region.start,
+                end: region.end,
+                kind: match region.kind {
+                    BootloaderRegionKind::Usable => MemoryRegionKind::Usable,
+                    BootloaderRegionKind::Bootloader => MemoryRegionKind::Bootloader,
+                    BootloaderRegionKind::UnknownUefi(kind) if kind == UEFI_ACPI_RECLAIM_MEMORY => MemoryRegionKind::AcpiReclaimable,
+                    BootloaderRegionKind::UnknownBios(kind) if kind == BIOS_ACPI_RECLAIM_MEMORY => MemoryRegionKind::AcpiReclaimable,
+                    _ => MemoryRegionKind::Reserved
+                }
+            });
+        }
+    }
+
+    fn framebuffer(&mut self, output: usize) -> Option<(FrameBufferInfo, &'static mut [u8])> {
+        if output > 0 { return None; }
+        let frame_buffer = self.boot_info.framebuffer.take()?;
+        let info = frame_buffer.info();
+
+        Some((FrameBufferInfo {
+            byte_len: info.byte_len,
+            width: info.width,
+            height: info.height,
+            pixel_format: match info.pixel_format {
+                BootloaderPixelFormat::Rgb => PixelFormat::Rgb,
+                BootloaderPixelFormat::Bgr => PixelFormat::Bgr,
+                BootloaderPixelFormat::U8 => PixelFormat::U8,
+                BootloaderPixelFormat::Unknown { red_position, green_position, blue_position } => PixelFormat::from_masks(
+                    ChannelMask::new(red_position, 8),
+                    ChannelMask::new(green_position, 8),
+                    ChannelMask::new(blue_position, 8)
+                ), _ => PixelFormat::Unknown
+            },
+            bytes_per_pixel: info.bytes_per_pixel,
+            stride: info.stride
+        }, frame_buffer.into_buffer()))
+    }
+
+    fn rsdp_address(&self) -> Option<u64> {
+        self.boot_info.rsdp_addr.into_option()
+    }
+
+    fn smbios_address(&self) -> Option<u64> {
+        // Not passed by the bootloader, the entry point is searched for in the BIOS area instead
+        None
+    }
+
+    fn efi(&self) -> Option<EfiInformation> {
+        // The bootloader neither passes the system table nor the memory map it got from the firmware
+        None
+    }
+
+    fn modules(&self, func: &mut dyn FnMut(BootModule)) {
+        if let Some(address) = self.boot_info.ramdisk_addr.into_option() {
+            func(BootModule { name: "ramdisk", address, size: self.boot_info.ramdisk_len });
+        }
+    }
+
+    fn kernel_file(&self) -> Option<KernelFile> {
+        let physical_memory_offset = self.physical_memory_offset()?;
+        Some(KernelFile {
+            address: physical_memory_offset + self.boot_info.kernel_addr,
+            size: self.boot_info.kernel_len,
+            load_offset: self.boot_info.kernel_image_offset
+        })
+    }
+
+    fn command_line(&self) -> Option<&'static str> {
+        // The bootloader crate has no way to pass a command line
+        None
+    }
+}
\ No newline at end of file
@@ -0,0 +1,282 @@
+use core::ffi::{c_char, CStr};
+use core::ptr;
+use crate::internal::framebuffer::{ChannelMask, FrameBufferInfo, MAX_OUTPUTS, PixelFormat};
+use crate::internal::protocol::{BootModule, BootProtocol, EfiInformation, KernelFile, MemoryRegion, MemoryRegionKind};
+
+const COMMON_MAGIC: [u64; 2] = [0xc7b1dd30df4c8b88, 0x0a82e883a194f07b];
+
+static MEMORY_MAP_USABLE: u64 = 0;
+static MEMORY_MAP_ACPI_RECLAIMABLE: u64 = 2;
+static MEMORY_MAP_BOOTLOADER_RECLAIMABLE: u64 = 5;
+static MEMORY_MODEL_RGB: u8 = 1;
+
+#[used]
+#[link_section = ".requests"]
+static BASE_REVISION: [u64; 3] = [0xf9562b2d5c95a6c8, 0x6a7b384944536bdc, 1];
+
+#[used]
+#[link_section = ".requests"]
+static HHDM_REQUEST: Request<HhdmResponse> = Request::new([0x48dcf1cb8ad2b852, 0x63984e959a98244b]);
+#[used]
+#[link_section = ".requests"]
+static MEMORY_MAP_REQUEST: Request<MemoryMapResponse> = Request::new([0x67cf3d9d378a806f, 0xe304acdfc50c3c62]);
+#[used]
+#[link_section = ".requests"]
+static FRAMEBUFFER_REQUEST: Request<FramebufferResponse> = Request::new([0x9d5827dcd881dd75, 0xa3148604f6fab11b]);
+#[used]
+#[link_section = ".requests"]
+static RSDP_REQUEST: Request<RsdpResponse> = Request::new([0xc5e77b6b397e7b43, 0x27637845accdcf3c]);
+#[used]
+#[link_section = ".requests"]
+static SMBIOS_REQUEST: Request<SmbiosResponse> = Request::new([0x9e9046f11e095391, 0xaa4a520fefbde5ee]);
+#[used]
+#[link_section = ".requests"]
+static EFI_SYSTEM_TABLE_REQUEST: Request<EfiSystemTableResponse> = Request::new([0x5ceba5163eaaf6d6, 0x0a6981610cf65fcc]);
+#[used]
+#[link_section = ".requests"]
+static EFI_MEMORY_MAP_REQUEST: Request<EfiMemoryMapResponse> = Request::new([0x7df62a431d6872d5, 0xa4fcdfb3e57306c8]);
+#[used]
+#[link_section = ".requests"]
+static MODULE_REQUEST: Request<ModuleResponse> = Request::new([0x3e7e279702be32af, 0xca1c4f3bd1280cee]);
+#[used]
+#[link_section = ".requests"]
+static KERNEL_FILE_REQUEST: Request<KernelFileResponse> = Request::new([0xad97e90e83f1ed67, 0x31eb5d1c5ff23b69]);
+
+/// Limine enters the kernel in long mode with the higher half direct map and a stack set up.
+#[no_mangle]
+extern "C" fn _start() -> ! {
+    crate::kernel_main(crate::internal::protocol::collect(LimineProtocol {
+        framebuffers_taken: [false; MAX_OUTPUTS]
+    }))
+}
+
+/// A request Limine finds by scanning the kernel image for its id and answers by filling in the
+/// response pointer before jumping to the kernel.
+#[repr(C)]
+struct Request<T> {
+    id: [u64; 4],
+    revision: u64,
+    response: *const T
+} impl<T> Request<T> {
+    const fn new(id: [u64; 2]) -> Self { Self {
+        id: [COMMON_MAGIC[0], COMMON_MAGIC[1], id[0], id[1]],
+        revision: 0,
+        response: ptr::null()
+    } }
+
+    fn response(&self) -> Option<&'static T> {
+        // Written by the bootloader behind the compiler's back
+        unsafe { ptr::read_volatile(&self.response).as_ref() }
+    }
+} unsafe impl<T> Sync for Request<T> {}
+
+#[repr(C)]
+struct HhdmResponse {
+    revision: u64,
+    offset: u64
+}
+
+#[repr(C)]
+struct MemoryMapResponse {
+    revision: u64,
+    entry_count: u64,
+    entries: *const *const MemoryMapEntry
+}
+
+#[repr(C)]
+struct MemoryMapEntry {
+    base: u64,
+    length: u64,
+    kind: u64
+}
+
+#[repr(C)]
+struct FramebufferResponse {
+    revision: u64,
+    framebuffer_count: u64,
+    framebuffers: *const *const Framebuffer
+}
+
+#[repr(C)]
+struct Framebuffer {
+    address: *mut u8,
+    width: u64,
+    height: u64,
+    pitch: u64,
+    bpp: u16,
+    memory_model: u8,
+    red_mask_size: u8,
+    red_mask_shift: u8,
+    green_mask_size: u8,
+    green_mask_shift: u8,
+    blue_mask_size: u8,
+    blue_mask_shift: u8
+}
+
+#[repr(C)]
+struct RsdpResponse {
+    revision: u64,
+    address: u64
+}
+
+#[repr(C)]
+struct SmbiosResponse {
+    revision: u64,
+    /// The 32-bit entry point, zero if there is none.
+    entry_32: u64,
+    /// The 64-bit entry point, zero if there is none.
+    entry_64: u64
+}
+
+#[repr(C)]
+struct EfiSystemTableResponse {
+    revision: u64,
+    address: u64
+}
+
+#[repr(C)]
+struct EfiMemoryMapResponse {
+    revision: u64,
+    memory_map: u64,
+    memory_map_size: u64,
+    descriptor_size: u64,
+    descriptor_version: u64
+}
+
+#[repr(C)]
+struct ModuleResponse {
+    revision: u64,
+    module_count: u64,
+    modules: *const *const File
+}
+
+#[repr(C)]
+struct KernelFileResponse {
+    revision: u64,
+    kernel_file: *const File
+}
+
+#[repr(C)]
+struct File {
+    revision: u64,
+    address: *mut u8,
+    size: u64,
+    path: *const c_char,
+    cmdline: *const c_char
+}
+
+/// Boot protocol of the Limine bootloader.
+pub struct LimineProtocol {
+    framebuffers_taken: [bool; MAX_OUTPUTS]
+} impl BootProtocol for LimineProtocol {
+    fn name(&self) -> &'static str { "limine" }
+
+    fn physical_memory_offset(&self) -> Option<u64> {
+        HHDM_REQUEST.response().map(|response| response.offset)
+    }
+
+    fn memory_regions(&self, func: &mut dyn FnMut(MemoryRegion)) {
+        let Some(response) = MEMORY_MAP_REQUEST.response() else { return; };
+        for index in 0..response.entry_count as usize {
+            let entry = unsafe { &**response.entries.add(index) };
+            func(MemoryRegion {
+                start: entry.base,
+                end: entry.base + entry.length,
+                kind: match entry.kind {
+                    kind if kind == MEMORY_MAP_USABLE => MemoryRegionKind::Usable,
+                    kind if kind == MEMORY_MAP_ACPI_RECLAIMABLE => MemoryRegionKind::AcpiReclaimable,
+                    kind if kind == MEMORY_MAP_BOOTLOADER_RECLAIMABLE => MemoryRegionKind::Bootloader,
+                    _ => MemoryRegionKind::Reserved
+                }
+            });
+        }
+    }
+
+    fn framebuffer(&mut self, output: usize) -> Option<(FrameBufferInfo, &'static mut [u8])> {
+        if *self.framebuffers_taken.get(output)? { return None; }
+        let response = FRAMEBUFFER_REQUEST.response()?;
+        if output as u64 >= response.framebuffer_count { return None; }
+        let framebuffer = unsafe { &**response.framebuffers.add(output) };
+        self.framebuffers_taken[output] = true;
+
+        let bytes_per_pixel = (framebuffer.bpp as usize + 7) / 8;
+        let byte_len = (framebuffer.pitch * framebuffer.height) as usize;
+        let pixel_format = if framebuffer.memory_model == MEMORY_MODEL_RGB {
+            PixelFormat::from_masks(
+                ChannelMask::new(framebuffer.red_mask_shift, framebuffer.red_mask_size),
+                ChannelMask::new(framebuffer.green_mask_shift, framebuffer.green_mask_size),
+                ChannelMask::new(framebuffer.blue_mask_shift, framebuffer.blue_mask_size)
+            )
+        } else { PixelFormat::Unknown };
+
+        Some((FrameBufferInfo {
+            byte_len,
+            width: framebuffer.width as usize,
+            height: framebuffer.height as usize,
+            pixel_format,
+            bytes_per_pixel,
+            stride: framebuffer.pitch as usize / bytes_per_pixel
+        }, unsafe { core::slice::from_raw_parts_mut(framebuffer.address, byte_len) }))
+    }
+
+    fn rsdp_address(&self) -> Option<u64> {
+        // Older base revisions hand out the address in the higher half direct map
+        let address = RSDP_REQUEST.response()?.address;
+        match self.physical_memory_offset() {
+            Some(offset) if address >= offset => Some(address - offset),
+            _ => Some(address)
+        }
+    }
+
+    fn smbios_address(&self) -> Option<u64> {
+        let response = SMBIOS_REQUEST.response()?;
+        let address = if response.entry_64 != 0 { response.entry_64 } else { response.entry_32 };
+        match self.physical_memory_offset() {
+            _ if address == 0 => None,
+            Some(offset) if address >= offset => Some(address - offset),
+            _ => Some(address)
+        }
+    }
+
+    fn efi(&self) -> Option<EfiInformation> {
+        let physical = |address: u64| match self.physical_memory_offset() {
+            Some(offset) if address >= offset => address - offset,
+            _ => address
+        };
+        let system_table = EFI_SYSTEM_TABLE_REQUEST.response()?.address;
+        let memory_map = EFI_MEMORY_MAP_REQUEST.response();
+        Some(EfiInformation {
+            system_table: physical(system_table),
+            memory_map: memory_map.map_or(0, |response| physical(response.memory_map)),
+            memory_map_size: memory_map.map_or(0, |response| response.memory_map_size),
+            descriptor_size: memory_map.map_or(0, |response| response.descriptor_size)
+        })
+    }
+
+    fn modules(&self, func: &mut dyn FnMut(BootModule)) {
+        let Some(response) = MODULE_REQUEST.response() else { return; };
+        for index in 0..response.module_count as usize {
+            let file = unsafe { &**response.modules.add(index) };
+            let path = unsafe { CStr::from_ptr(file.path) }.to_str().unwrap_or("");
+            func(BootModule {
+                name: path.rsplit('/').next().unwrap_or(path),
+                address: file.address as u64,
+                size: file.size
+            });
+        }
+    }
+
+    fn kernel_file(&self) -> Option<KernelFile> {
+        let response = KERNEL_FILE_REQUEST.response()?;
+        let file = unsafe { response.kernel_file.as_ref()? };
+        // Limine loads the kernel at the addresses it was linked at
+        Some(KernelFile { address: file.address as u64, size: file.size, load_offset: 0 })
+    }
+
+    fn command_line(&self) -> Option<&'static str> {
+        let response = KERNEL_FILE_REQUEST.response()?;
+        let file = unsafe { response.kernel_file.as_ref()? };
+        if file.cmdline.is_null() { return None; }
+        unsafe { CStr::from_ptr(file.cmdline) }.to_str().ok()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,189 @@
+use spin::Once;
+use crate::internal::framebuffer::{FrameBufferInfo, MAX_OUTPUTS};
+
+#[cfg(feature = "bootloader")]
+pub mod bootloader;
+#[cfg(feature = "limine")]
+pub mod limine;
+#[cfg(feature = "multiboot2")]
+pub mod multiboot2;
+
+#[cfg(not(any(feature = "bootloader", feature = "limine", feature = "multiboot2")))]
+compile_error!("One of the boot protocol features (bootloader, limine, multiboot2) has to be enabled.");
+#[cfg(any(
+    all(feature = "bootloader", feature = "limine"),
+    all(feature = "bootloader", feature = "multiboot2"),
+    all(feature = "limine", feature = "multiboot2")
+))]
+compile_error!("Only one of the boot protocol features (bootloader, limine, multiboot2) can be enabled.");
+
+const MAX_MEMORY_REGIONS: usize = 256;
+const MAX_MODULES: usize = 16;
+
+static MEMORY_REGIONS: Once<FixedList<MemoryRegion, MAX_MEMORY_REGIONS>> = Once::new();
+static MODULES: Once<FixedList<BootModule, MAX_MODULES>> = Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryRegionKind {
+    /// Free memory the kernel can use.
+    Usable,
+    /// Memory used by the bootloader, like the page tables and the kernel stack.
+    Bootloader,
+    /// Memory holding ACPI tables, usable once they have been read.
+    AcpiReclaimable,
+    /// Memory that must not be touched.
+    Reserved
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRegion {
+    /// The physical start address of the region.
+    pub start: u64,
+    /// The physical end address of the region (exclusive).
+    pub end: u64,
+    pub kind: MemoryRegionKind
+}
+
+/// A file the bootloader loaded alongside the kernel, like an initial ramdisk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootModule {
+    pub name: &'static str,
+    /// The virtual address the module is mapped at.
+    pub address: u64,
+    pub size: u64
+} impl BootModule {
+    /// Returns the contents of the module.
+    pub fn data(&self) -> &'static [u8] {
+        unsafe { core::slice::from_raw_parts(self.address as *const u8, self.size as usize) }
+    }
+}
+
+/// The kernel's own ELF file, as the bootloader loaded it from disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelFile {
+    /// The virtual address the file is mapped at.
+    pub address: u64,
+    pub size: u64,
+    /// What got added to the addresses of the file's symbols when the kernel was loaded.
+    pub load_offset: u64
+} impl KernelFile {
+    /// Returns the contents of the file.
+    pub fn data(&self) -> &'static [u8] {
+        unsafe { core::slice::from_raw_parts(self.address as *const u8, self.size as usize) }
+    }
+}
+
+/// Where the UEFI firmware left its system table and the memory map it handed over on exit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EfiInformation {
+    /// The physical address of the EFI system table.
+    pub system_table: u64,
+    /// The physical address of the EFI memory map, zero if the bootloader didn't pass it.
+    pub memory_map: u64,
+    pub memory_map_size: u64,
+    /// The size of one descriptor in the memory map, which may be larger than the UEFI structure.
+    pub descriptor_size: u64
+}
+
+/// Everything the kernel needs to know from the bootloader, independent of the boot protocol.
+pub struct BootInformation {
+    /// The name of the boot protocol the kernel was loaded with.
+    pub protocol: &'static str,
+    /// The virtual address all of the physical memory is mapped at.
+    pub physical_memory_offset: Option<u64>,
+    pub memory_regions: &'static [MemoryRegion],
+    /// The frame buffers of the screens, indexed by output. Only the primary one is remapped.
+    pub framebuffers: [Option<(FrameBufferInfo, &'static mut [u8])>; MAX_OUTPUTS],
+    /// The physical address of the ACPI root system description pointer.
+    pub rsdp_address: Option<u64>,
+    /// The physical address of the SMBIOS entry point.
+    pub smbios_address: Option<u64>,
+    /// Only present when the kernel was booted through UEFI.
+    pub efi: Option<EfiInformation>,
+    pub modules: &'static [BootModule],
+    pub kernel_file: Option<KernelFile>,
+    /// The command line the kernel was started with, empty if the bootloader doesn't pass one.
+    pub command_line: &'static str
+} impl BootInformation {
+    /// Returns whether the flag was given on the kernel command line.
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.command_line.split_whitespace().any(|argument| argument == flag)
+    }
+
+    /// Returns the value of a `name=value` option on the kernel command line.
+    pub fn option(&self, name: &str) -> Option<&'static str> {
+        self.command_line.split_whitespace()
+            .find_map(|argument| argument.strip_prefix(name)?.strip_prefix('='))
+    }
+}
+
+/// Reads the information the kernel needs from a bootloader.
+pub trait BootProtocol {
+    fn name(&self) -> &'static str;
+    fn physical_memory_offset(&self) -> Option<u64>;
+    /// Passes every region of the memory map to the given function.
+    fn memory_regions(&self, func: &mut dyn FnMut(MemoryRegion));
+    /// Takes the frame buffer of the output, can only succeed once for each.
+    fn framebuffer(&mut self, output: usize) -> Option<(FrameBufferInfo, &'static mut [u8])>;
+    fn rsdp_address(&self) -> Option<u64>;
+    fn smbios_address(&self) -> Option<u64>;
+    fn efi(&self) -> Option<EfiInformation>;
+    /// Passes every module loaded with the kernel to the given function.
+    fn modules(&self, func: &mut dyn FnMut(BootModule));
+    fn kernel_file(&self) -> Option<KernelFile>;
+    fn command_line(&self) -> Option<&'static str>;
+}
+
+/// Collects the boot information from the protocol. Runs before there is a heap, so the memory map
+/// and the modules are copied into fixed-size lists; entries beyond their capacity are dropped.
+pub fn collect(mut protocol: impl BootProtocol) -> BootInformation {
+    let memory_regions = MEMORY_REGIONS.call_once(|| {
+        let mut regions = FixedList::new(MemoryRegion { start: 0, end: 0, kind: MemoryRegionKind::Reserved });
+        protocol.memory_regions(&mut |region| regions.push(region));
+        regions
+    });
+    let modules = MODULES.call_once(|| {
+        let mut modules = FixedList::new(BootModule { name: "", address: 0, size: 0 });
+        protocol.modules(&mut |module| modules.push(module));
+        modules
+    });
+
+    BootInformation {
+        protocol: protocol.name(),
+        physical_memory_offset: protocol.physical_memory_offset(),
+        memory_regions: memory_regions.as_slice(),
+        framebuffers: core::array::from_fn(|output| protocol.framebuffer(output)),
+        rsdp_address: protocol.rsdp_address(),
+        smbios_address: protocol.smbios_address(),
+        efi: protocol.efi(),
+        modules: modules.as_slice(),
+        kernel_file: protocol.kernel_file(),
+        command_line: protocol.command_line().unwrap_or("")
+    }
+}
+
+/// Returns the modules the bootloader loaded, empty before the boot information was collected.
+pub fn modules() -> &'static [BootModule] {
+    MODULES.get().map_or(&[], |modules| modules.as_slice())
+}
+
+struct FixedList<T: Copy, const N: usize> {
+    items: [T; N],
+    length: usize
+} impl<T: Copy, const N: usize> FixedList<T, N> {
+    fn new(empty: T) -> Self { Self {
+        items: [empty; N],
+        length: 0
+    } }
+
+    fn push(&mut self, item: T) {
+        if self.length < N {
+            self.items[self.length] = item;
+            self.length += 1;
+        }
+    }
+
+    fn as_slice(&self) -> &[T] {
+        &self.items[..self.length]
+    }
+}
\ No newline at end of file
@@ -0,0 +1,359 @@
+use core::ffi::{c_char, CStr};
+use crate::internal::framebuffer::{ChannelMask, FrameBufferInfo, PixelFormat};
+use crate::internal::protocol::{BootModule, BootProtocol, EfiInformation, KernelFile, MemoryRegion, MemoryRegionKind};
+
+static BOOTLOADER_MAGIC: u32 = 0x36d76289;
+
+static TAG_END: u32 = 0;
+static TAG_COMMAND_LINE: u32 = 1;
+static TAG_MODULE: u32 = 3;
+static TAG_MEMORY_MAP: u32 = 6;
+static TAG_FRAMEBUFFER: u32 = 8;
+static TAG_EFI_SYSTEM_TABLE: u32 = 12;
+static TAG_SMBIOS: u32 = 13;
+static TAG_RSDP_V1: u32 = 14;
+static TAG_RSDP_V2: u32 = 15;
+static TAG_EFI_MEMORY_MAP: u32 = 17;
+
+static MEMORY_AVAILABLE: u32 = 1;
+static MEMORY_ACPI_RECLAIMABLE: u32 = 3;
+static FRAMEBUFFER_TYPE_INDEXED: u8 = 0;
+static FRAMEBUFFER_TYPE_RGB: u8 = 1;
+
+/// The end of the identity map the trampoline sets up, nothing above it can be accessed.
+static IDENTITY_MAPPED_END: u64 = 0x1_0000_0000;
+
+extern "C" {
+    // Provided by the linker script, the whole image has to stay out of the usable memory
+    static __kernel_start: u8;
+    static __kernel_end: u8;
+}
+
+// GRUB enters the kernel in 32-bit protected mode without paging. The header asks for a linear
+// frame buffer, the trampoline identity maps the first 4 GiB with 2 MiB pages, switches to long
+// mode and calls `multiboot2_main` with the magic value and the physical address of the boot
+// information.
+core::arch::global_asm!(r#"
+.section .multiboot_header, "a"
+.align 8
+multiboot2_header_start:
+    .long 0xe85250d6
+    .long 0
+    .long multiboot2_header_end - multiboot2_header_start
+    .long -(0xe85250d6 + (multiboot2_header_end - multiboot2_header_start))
+    .align 8
+    .short 5
+    .short 0
+    .long 20
+    .long 0
+    .long 0
+    .long 32
+    .align 8
+    .short 0
+    .short 0
+    .long 8
+multiboot2_header_end:
+
+.section .bss
+.align 4096
+multiboot2_pml4:
+    .skip 4096
+multiboot2_pdpt:
+    .skip 4096
+multiboot2_pd:
+    .skip 4096 * 4
+multiboot2_stack_bottom:
+    .skip 1024 * 1024
+multiboot2_stack_top:
+
+.section .rodata
+.align 8
+multiboot2_gdt:
+    .quad 0
+    .quad 0x00af9a000000ffff
+    .quad 0x00cf92000000ffff
+multiboot2_gdt_pointer:
+    .short multiboot2_gdt_pointer - multiboot2_gdt - 1
+    .long multiboot2_gdt
+
+.section .text
+.code32
+.global _start
+_start:
+    mov $multiboot2_stack_top, %esp
+    mov %eax, %edi
+    mov %ebx, %esi
+
+    mov $multiboot2_pdpt, %eax
+    or $3, %eax
+    mov %eax, multiboot2_pml4
+
+    xor %ecx, %ecx
+1:
+    mov %ecx, %eax
+    shl $12, %eax
+    add $multiboot2_pd, %eax
+    or $3, %eax
+    mov %eax, multiboot2_pdpt(, %ecx, 8)
+    inc %ecx
+    cmp $4, %ecx
+    jne 1b
+
+    xor %ecx, %ecx
+2:
+    mov %ecx, %eax
+    shl $21, %eax
+    or $0x83, %eax
+    mov %eax, multiboot2_pd(, %ecx, 8)
+    inc %ecx
+    cmp $2048, %ecx
+    jne 2b
+
+    mov $multiboot2_pml4, %eax
+    mov %eax, %cr3
+    mov %cr4, %eax
+    or $0x20, %eax
+    mov %eax, %cr4
+    mov $0xc0000080, %ecx
+    rdmsr
+    or $0x100, %eax
+    wrmsr
+    mov %cr0, %eax
+    or $0x80000001, %eax
+    mov %eax, %cr0
+
+    lgdt multiboot2_gdt_pointer
+    ljmp $0x08, $multiboot2_long_mode
+
+.code64
+multiboot2_long_mode:
+    mov $0x10, %ax
+    mov %ax, %ds
+    mov %ax, %es
+    mov %ax, %fs
+    mov %ax, %gs
+    mov %ax, %ss
+    mov %edi, %edi
+    mov %esi, %esi
+    call multiboot2_main
+3:
+    hlt
+    jmp 3b
+"#, options(att_syntax));
+
+#[no_mangle]
+extern "C" fn multiboot2_main(magic: u32, info_address: u32) -> ! {
+    if magic != BOOTLOADER_MAGIC {
+        panic!("Kernel was not loaded by a Multiboot2 compliant bootloader.");
+    }
+    crate::kernel_main(crate::internal::protocol::collect(Multiboot2Protocol {
+        info_address: info_address as u64,
+        framebuffer_taken: false
+    }))
+}
+
+/// Boot protocol of Multiboot2 compliant bootloaders like GRUB. The first 4 GiB are identity
+/// mapped, so the physical addresses in the boot information can be used as they are. Memory above
+/// is reported as reserved, as the kernel can't reach it through the identity map.
+pub struct Multiboot2Protocol {
+    info_address: u64,
+    framebuffer_taken: bool
+} impl Multiboot2Protocol {
+    /// Calls the function with the address and the size of every tag of the given type.
+    fn tags(&self, tag_type: u32, func: &mut dyn FnMut(u64, u32)) {
+        let total_size = unsafe { read::<u32>(self.info_address) } as u64;
+        let mut address = self.info_address + 8;
+        while address < self.info_address + total_size {
+            let (kind, size) = unsafe { (read::<u32>(address), read::<u32>(address + 4)) };
+            if kind == TAG_END || size < 8 { break; }
+            if kind == tag_type {
+                func(address, size);
+            }
+            address += (size as u64 + 7) & !7;
+        }
+    }
+
+    fn module_bounds(&self, func: &mut dyn FnMut(u64, u64, &'static str)) {
+        self.tags(TAG_MODULE, &mut |address, _| unsafe {
+            let command_line = CStr::from_ptr((address + 16) as *const c_char).to_str().unwrap_or("");
+            let path = command_line.split(' ').next().unwrap_or("");
+            func(
+                read::<u32>(address + 8) as u64, read::<u32>(address + 12) as u64,
+                path.rsplit('/').next().unwrap_or(path)
+            );
+        });
+    }
+
+    /// Passes the parts of the given usable range to the function that do not overlap with the
+    /// kernel image, the boot information or a module; those parts are passed as bootloader memory.
+    fn split_usable(&self, start: u64, end: u64, func: &mut dyn FnMut(MemoryRegion)) {
+        let (kernel_start, kernel_end) = unsafe {
+            (&__kernel_start as *const u8 as u64, &__kernel_end as *const u8 as u64)
+        };
+        let info_end = self.info_address + unsafe { read::<u32>(self.info_address) } as u64;
+
+        let mut current = start;
+        while current < end {
+            // Finds the closest reserved range that is not entirely before the current address
+            let mut next: Option<(u64, u64)> = None;
+            let mut consider = |reserved_start: u64, reserved_end: u64| {
+                if reserved_end <= current || reserved_start >= end { return; }
+                if next.map_or(true, |(next_start, ..)| reserved_start < next_start) {
+                    next = Some((reserved_start, reserved_end));
+                }
+            };
+            consider(kernel_start, kernel_end);
+            consider(self.info_address, info_end);
+            self.module_bounds(&mut |module_start, module_end, _| consider(module_start, module_end));
+
+            let Some((reserved_start, reserved_end)) = next else {
+                func(MemoryRegion { start: current, end, kind: MemoryRegionKind::Usable });
+                return;
+            };
+            if reserved_start > current {
+                func(MemoryRegion { start: current, end: reserved_start, kind: MemoryRegionKind::Usable });
+            }
+            let reserved_end = reserved_end.min(end);
+            func(MemoryRegion { start: reserved_start.max(current), end: reserved_end, kind: MemoryRegionKind::Bootloader });
+            current = reserved_end;
+        }
+    }
+} impl BootProtocol for Multiboot2Protocol {
+    fn name(&self) -> &'static str { "multiboot2" }
+
+    fn physical_memory_offset(&self) -> Option<u64> {
+        Some(0)
+    }
+
+    fn memory_regions(&self, func: &mut dyn FnMut(MemoryRegion)) {
+        self.tags(TAG_MEMORY_MAP, &mut |address, size| {
+            let entry_size = unsafe { read::<u32>(address + 8) } as u64;
+            if entry_size == 0 { return; }
+            let mut entry = address + 16;
+            while entry + entry_size <= address + size as u64 {
+                let (base, length, kind) = unsafe {
+                    (read::<u64>(entry), read::<u64>(entry + 8), read::<u32>(entry + 16))
+                };
+                let (end, mapped_end) = (base + length, (base + length).min(IDENTITY_MAPPED_END));
+                if base < mapped_end {
+                    if kind == MEMORY_AVAILABLE {
+                        self.split_usable(base, mapped_end, func);
+                    } else {
+                        func(MemoryRegion {
+                            start: base,
+                            end: mapped_end,
+                            kind: if kind == MEMORY_ACPI_RECLAIMABLE {
+                                MemoryRegionKind::AcpiReclaimable
+                            } else { MemoryRegionKind::Reserved }
+                        });
+                    }
+                }
+                if end > IDENTITY_MAPPED_END {
+                    func(MemoryRegion { start: base.max(IDENTITY_MAPPED_END), end, kind: MemoryRegionKind::Reserved });
+                }
+                entry += entry_size;
+            }
+        });
+    }
+
+    fn framebuffer(&mut self, output: usize) -> Option<(FrameBufferInfo, &'static mut [u8])> {
+        if output > 0 || self.framebuffer_taken { return None; }
+        let mut framebuffer = None;
+        self.tags(TAG_FRAMEBUFFER, &mut |address, _| unsafe {
+            let buffer_address = read::<u64>(address + 8);
+            let pitch = read::<u32>(address + 16) as usize;
+            let width = read::<u32>(address + 20) as usize;
+            let height = read::<u32>(address + 24) as usize;
+            let bytes_per_pixel = (read::<u8>(address + 28) as usize + 7) / 8;
+            let kind = read::<u8>(address + 29);
+            let pixel_format = if kind == FRAMEBUFFER_TYPE_RGB {
+                PixelFormat::from_masks(
+                    ChannelMask::new(read::<u8>(address + 32), read::<u8>(address + 33)),
+                    ChannelMask::new(read::<u8>(address + 34), read::<u8>(address + 35)),
+                    ChannelMask::new(read::<u8>(address + 36), read::<u8>(address + 37))
+                )
+            } else if kind == FRAMEBUFFER_TYPE_INDEXED {
+                // The palette follows as a count and RGB triples
+                let count = (read::<u16>(address + 32) as usize).min(256);
+                let mut colors = [(0, 0, 0); 256];
+                for (index, color) in colors.iter_mut().take(count).enumerate() {
+                    let entry = address + 34 + index as u64 * 3;
+                    *color = (read::<u8>(entry), read::<u8>(entry + 1), read::<u8>(entry + 2));
+                }
+                crate::internal::framebuffer::set_palette(&colors[..count]);
+                PixelFormat::Indexed
+            } else { PixelFormat::Unknown };
+            if bytes_per_pixel == 0 { return; }
+
+            // A frame buffer above the identity map can't be drawn to
+            let byte_len = pitch * height;
+            if buffer_address + byte_len as u64 > IDENTITY_MAPPED_END { return; }
+            framebuffer = Some((FrameBufferInfo {
+                byte_len,
+                width,
+                height,
+                pixel_format,
+                bytes_per_pixel,
+                stride: pitch / bytes_per_pixel
+            }, core::slice::from_raw_parts_mut(buffer_address as *mut u8, byte_len)));
+        });
+        self.framebuffer_taken = framebuffer.is_some();
+        framebuffer
+    }
+
+    fn rsdp_address(&self) -> Option<u64> {
+        // The bootloader hands out a copy of the RSDP, which is just as good as the original
+        let mut rsdp_address = None;
+        self.tags(TAG_RSDP_V1, &mut |address, _| rsdp_address = Some(address + 8));
+        self.tags(TAG_RSDP_V2, &mut |address, _| rsdp_address = Some(address + 8));
+        rsdp_address
+    }
+
+    fn smbios_address(&self) -> Option<u64> {
+        // A copy of the entry point follows the version and some reserved bytes
+        let mut smbios_address = None;
+        self.tags(TAG_SMBIOS, &mut |address, _| smbios_address = Some(address + 16));
+        smbios_address
+    }
+
+    fn efi(&self) -> Option<EfiInformation> {
+        let mut efi = None;
+        self.tags(TAG_EFI_SYSTEM_TABLE, &mut |address, _| efi = Some(EfiInformation {
+            system_table: unsafe { read::<u64>(address + 8) },
+            memory_map: 0,
+            memory_map_size: 0,
+            descriptor_size: 0
+        }));
+        // Only passed when the bootloader exited the boot services itself
+        let mut efi = efi?;
+        self.tags(TAG_EFI_MEMORY_MAP, &mut |address, size| {
+            efi.memory_map = address + 16;
+            efi.memory_map_size = (size as u64).saturating_sub(16);
+            efi.descriptor_size = unsafe { read::<u32>(address + 8) } as u64;
+        });
+        Some(efi)
+    }
+
+    fn modules(&self, func: &mut dyn FnMut(BootModule)) {
+        self.module_bounds(&mut |start, end, name| {
+            func(BootModule { name, address: start, size: end.saturating_sub(start) });
+        });
+    }
+
+    fn kernel_file(&self) -> Option<KernelFile> {
+        // Only the loaded segments are kept, not the file with its symbol table
+        None
+    }
+
+    fn command_line(&self) -> Option<&'static str> {
+        let mut command_line = None;
+        self.tags(TAG_COMMAND_LINE, &mut |address, _| {
+            command_line = unsafe { CStr::from_ptr((address + 8) as *const c_char) }.to_str().ok();
+        });
+        command_line
+    }
+}
+
+unsafe fn read<T: Copy>(address: u64) -> T {
+    core::ptr::read_unaligned(address as *const T)
+}
\ No newline at end of file
@@ -0,0 +1,46 @@
+use core::arch::asm;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+static RDRAND_RETRIES: u32 = 10;
+
+/// State of the fallback generator, used when the CPU has no `rdrand` or it keeps failing.
+static FALLBACK_STATE: AtomicU64 = AtomicU64::new(0);
+
+/// Returns a random number from the CPU's hardware generator if it has one. Otherwise the number
+/// is derived from the time stamp counter, which is good enough to vary addresses between boots
+/// but must not be used for anything secret.
+pub fn next_u64() -> u64 {
+    if crate::internal::cpu::supports_rdrand() {
+        for _ in 0..RDRAND_RETRIES {
+            if let Some(value) = rdrand() {
+                return value;
+            }
+        }
+    }
+    fallback()
+}
+
+/// Returns a random number below the bound, which must not be zero.
+pub fn below(bound: u64) -> u64 {
+    // Multiplying instead of taking the remainder keeps the bias small for any bound
+    ((next_u64() as u128 * bound as u128) >> 64) as u64
+}
+
+/// Reads the hardware generator, which reports with the carry flag whether it had a number ready.
+fn rdrand() -> Option<u64> {
+    let value: u64;
+    let ready: u8;
+    unsafe {
+        asm!("rdrand {}", "setc {}", out(reg) value, out(reg_byte) ready, options(nomem, nostack));
+    }
+    (ready != 0).then_some(value)
+}
+
+/// Splitmix64 over a state that also takes in the time stamp counter on every call.
+fn fallback() -> u64 {
+    let increment = 0x9E37_79B9_7F4A_7C15 ^ crate::internal::tsc::read().rotate_left(32);
+    let mut value = FALLBACK_STATE.fetch_add(increment, Ordering::Relaxed).wrapping_add(increment);
+    value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    value ^ (value >> 31)
+}
\ No newline at end of file
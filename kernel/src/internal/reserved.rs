@@ -0,0 +1,100 @@
+use spin::{Mutex, Once};
+use crate::api::error::KernelError;
+use crate::internal::protocol::{MemoryRegion, MemoryRegionKind};
+
+/// Claims are kept in a fixed list, as the frame allocators check against them before there is a heap.
+const MAX_CLAIMS: usize = 64;
+
+static MEMORY_MAP: Once<&'static [MemoryRegion]> = Once::new();
+static CLAIMS: Mutex<Claims> = Mutex::new(Claims::new());
+
+/// A physical memory range taken by a driver, like the registers of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Claim {
+    pub start: u64,
+    /// Exclusive.
+    pub end: u64,
+    pub owner: &'static str
+}
+
+struct Claims {
+    claims: [Claim; MAX_CLAIMS],
+    length: usize
+} impl Claims {
+    const fn new() -> Self { Self {
+        claims: [Claim { start: 0, end: 0, owner: "" }; MAX_CLAIMS],
+        length: 0
+    } }
+
+    fn overlapping(&self, start: u64, end: u64) -> Option<Claim> {
+        self.claims[..self.length].iter().find(|claim| claim.start < end && start < claim.end).copied()
+    }
+}
+
+/// Registers the memory map, every region in it that isn't usable counts as reserved. Returns the
+/// amount of reserved regions.
+pub fn init(memory_regions: &'static [MemoryRegion]) -> usize {
+    MEMORY_MAP.call_once(|| memory_regions);
+    memory_regions.iter().filter(|region| region.kind != MemoryRegionKind::Usable).count()
+}
+
+/// Returns what reserves the physical range, None if nothing does.
+pub fn reserved_by(start: u64, end: u64) -> Option<&'static str> {
+    let memory_map = MEMORY_MAP.get().copied().unwrap_or_default();
+    let region = memory_map.iter()
+        .find(|region| region.kind != MemoryRegionKind::Usable && region.start < end && start < region.end);
+    if let Some(region) = region {
+        return Some(region_owner(region.kind));
+    }
+    crate::internal::idt::without_interrupts(|| CLAIMS.lock().overlapping(start, end).map(|claim| claim.owner))
+}
+
+/// Claims the physical range for memory mapped registers. Fails if any of it is RAM, firmware data
+/// like the ACPI tables, or already claimed by someone else. Claiming the same range for the same
+/// owner again succeeds.
+pub fn claim_mmio(start: u64, end: u64, owner: &'static str) -> Result<(), KernelError> {
+    let memory_map = MEMORY_MAP.get().copied().unwrap_or_default();
+    let region = memory_map.iter()
+        .find(|region| region.kind != MemoryRegionKind::Reserved && region.start < end && start < region.end);
+    if let Some(region) = region {
+        return Err(KernelError::MemoryReserved(region_owner(region.kind), start.max(region.start)));
+    }
+
+    crate::internal::idt::without_interrupts(|| {
+        let mut claims = CLAIMS.lock();
+        match claims.overlapping(start, end) {
+            Some(claim) if claim.owner == owner && claim.start <= start && end <= claim.end => return Ok(()),
+            Some(claim) => return Err(KernelError::MemoryReserved(claim.owner, start.max(claim.start))),
+            None => {}
+        }
+        if claims.length == MAX_CLAIMS {
+            return Err(KernelError::Busy("Memory claim list"));
+        }
+        let length = claims.length;
+        claims.claims[length] = Claim { start, end, owner };
+        claims.length += 1;
+        Ok(())
+    })
+}
+
+/// Gives a claimed range back.
+#[allow(dead_code)]
+pub fn release(start: u64, owner: &'static str) {
+    crate::internal::idt::without_interrupts(|| {
+        let mut claims = CLAIMS.lock();
+        let length = claims.length;
+        if let Some(index) = claims.claims[..length].iter().position(|claim| claim.start == start && claim.owner == owner) {
+            claims.claims.copy_within(index + 1..length, index);
+            claims.length -= 1;
+        }
+    })
+}
+
+fn region_owner(kind: MemoryRegionKind) -> &'static str {
+    match kind {
+        MemoryRegionKind::Usable => "RAM",
+        MemoryRegionKind::Bootloader => "the bootloader",
+        MemoryRegionKind::AcpiReclaimable => "ACPI tables",
+        MemoryRegionKind::Reserved => "firmware"
+    }
+}
\ No newline at end of file
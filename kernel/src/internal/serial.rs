@@ -1,10 +1,63 @@
 use core::fmt;
-use core::fmt::{Arguments, Write};
+use core::fmt::{Arguments, Display, Formatter, Write};
 use log::{Log, Metadata, Record, SetLoggerError};
-use spin::RwLock;
+use spin::{Mutex, Once};
 use uart_16550::SerialPort;
+use x86_64::instructions::port::Port;
+use crate::api::error::KernelError;
+use crate::api::event::Event;
+use crate::internal::ioport::IoPortRange;
+use crate::internal::pic::PicInterrupts;
 
-static LOGGER: RwLock<Option<SerialPortLogger>> = RwLock::new(None);
+/// The legacy serial ports the kernel probes for, COM1 and COM3 share an interrupt line, as do
+/// COM2 and COM4.
+const PORT_COUNT: usize = 4;
+static LEGACY_PORTS: [(&str, u16, PicInterrupts); PORT_COUNT] = [
+    ("COM1", 0x3F8, PicInterrupts::COM1),
+    ("COM2", 0x2F8, PicInterrupts::COM2),
+    ("COM3", 0x3E8, PicInterrupts::COM1),
+    ("COM4", 0x2E8, PicInterrupts::COM2)
+];
+/// The command line options with the line settings of each port.
+static LINE_OPTIONS: [&str; PORT_COUNT] = ["com1", "com2", "com3", "com4"];
+/// The ports logging and the control channel go to unless the command line says otherwise.
+static DEFAULT_LOG_PORT: usize = 0;
+static DEFAULT_CONTROL_PORT: usize = 1;
+
+static SERIAL_PORT_COUNT: u16 = 8;
+static DATA_OFFSET: u16 = 0;
+static INTERRUPT_ENABLE_OFFSET: u16 = 1;
+static DIVISOR_HIGH_OFFSET: u16 = 1;
+static INTERRUPT_ID_OFFSET: u16 = 2;
+static LINE_CONTROL_OFFSET: u16 = 3;
+static LINE_STATUS_OFFSET: u16 = 5;
+static SCRATCH_OFFSET: u16 = 7;
+
+/// Line control bits: access the baud rate divisor instead of data and interrupt enable, two
+/// stop bits, parity enabled and even instead of odd parity.
+static DIVISOR_LATCH_ACCESS: u8 = 0x80;
+static TWO_STOP_BITS: u8 = 0x04;
+static PARITY_ENABLE: u8 = 0x08;
+static EVEN_PARITY: u8 = 0x10;
+/// The baud rate with a divisor of one.
+static BASE_BAUD: u32 = 115200;
+/// Values written to the scratch register to find out whether there is a UART behind the ports.
+static SCRATCH_PATTERNS: [u8; 2] = [0x5A, 0xA5];
+
+/// Interrupt enable bit for an empty transmit holding register, and the line status bit telling
+/// it is empty.
+static INTERRUPT_TX_EMPTY: u8 = 0x02;
+static LINE_STATUS_TX_EMPTY: u8 = 0x20;
+/// Bytes the transmit FIFO of a 16550 takes at once.
+static TX_FIFO_SIZE: usize = 16;
+/// Log output waiting to be sent, sized to take a burst of verbose logging without blocking.
+const TX_BUFFER_SIZE: usize = 16 * 1024;
+
+/// The ports found at boot, the ones in use stay claimed for as long as the kernel runs.
+static DEVICES: Once<[SerialDevice; PORT_COUNT]> = Once::new();
+
+static LOGGER: Mutex<Option<SerialPortLogger>> = Mutex::new(None);
+static CONTROL_PORT: Mutex<Option<SerialPort>> = Mutex::new(None);
 
 struct LoggerWrapper;
 
@@ -25,61 +78,540 @@ pub enum SerialLoggingLevel {
             Self::Panic => "PANIC"
         }
     }
+} impl From<log::Level> for SerialLoggingLevel {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Trace => Self::Debug,
+            log::Level::Debug => Self::Debug,
+            log::Level::Info => Self::Info,
+            log::Level::Warn => Self::Warning,
+            log::Level::Error => Self::Error
+        }
+    }
+}
+
+/// What logging does when the transmit buffer has no room for a message. Warnings and errors
+/// are never dropped, they always wait.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum OverflowPolicy {
+    /// Drops the message and logs how much was dropped once there is room again.
+    Drop,
+    /// Sends enough of the buffer right away to make room, stalling like unbuffered logging.
+    Block
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even
+}
+
+/// The line settings of a serial port, written like `115200,8n1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialConfig {
+    pub baud: u32,
+    /// 5 to 8.
+    pub data_bits: u8,
+    pub parity: Parity,
+    /// 1 or 2.
+    pub stop_bits: u8
+} impl SerialConfig {
+    /// What the ports are set to unless configured otherwise, the same as the firmware usually uses.
+    const fn new() -> Self { Self {
+        baud: 38400,
+        data_bits: 8,
+        parity: Parity::None,
+        stop_bits: 1
+    } }
+
+    /// Parses a baud rate optionally followed by data bits, parity and stop bits, like `9600` or
+    /// `115200,7e2`. The baud rate has to divide 115200.
+    pub fn parse(text: &str) -> Result<Self, &'static str> {
+        let (baud, frame) = text.split_once(',').unwrap_or((text, "8n1"));
+        let baud: u32 = baud.parse().map_err(|_| "Baud rate is not a number")?;
+        if baud == 0 || BASE_BAUD % baud != 0 {
+            return Err("Baud rate doesn't divide 115200");
+        }
+
+        let mut chars = frame.chars();
+        let (Some(data_bits), Some(parity), Some(stop_bits), None) = (chars.next(), chars.next(), chars.next(), chars.next()) else {
+            return Err("Expected data bits, parity and stop bits like 8n1");
+        };
+        let data_bits = match data_bits.to_digit(10) {
+            Some(bits @ 5..=8) => bits as u8,
+            _ => return Err("Data bits must be 5 to 8")
+        };
+        let parity = match parity.to_ascii_lowercase() {
+            'n' => Parity::None,
+            'o' => Parity::Odd,
+            'e' => Parity::Even,
+            _ => return Err("Parity must be n, o or e")
+        };
+        let stop_bits = match stop_bits {
+            '1' => 1,
+            '2' => 2,
+            _ => return Err("Stop bits must be 1 or 2")
+        };
+        Ok(Self { baud, data_bits, parity, stop_bits })
+    }
+
+    /// Returns the line control register value for the settings.
+    fn line_control(&self) -> u8 {
+        let mut value = self.data_bits - 5;
+        if self.stop_bits == 2 { value |= TWO_STOP_BITS; }
+        match self.parity {
+            Parity::None => {},
+            Parity::Odd => value |= PARITY_ENABLE,
+            Parity::Even => value |= PARITY_ENABLE | EVEN_PARITY
+        }
+        value
+    }
+} impl Display for SerialConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E'
+        };
+        write!(f, "{} {}{}{}", self.baud, self.data_bits, parity, self.stop_bits)
+    }
+}
+
+/// What the kernel uses a serial port for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialRole {
+    Unused,
+    /// The kernel log goes to it.
+    Log,
+    /// Control commands are received and answered on it.
+    Control
 }
 
+/// One of the legacy serial ports.
+#[derive(Debug)]
+pub struct SerialDevice {
+    pub name: &'static str,
+    pub base: u16,
+    pub irq: PicInterrupts,
+    /// Whether a UART answered at the ports. A role can still be given to a port that didn't.
+    pub present: bool,
+    pub config: SerialConfig,
+    pub role: SerialRole,
+    /// Claimed if the port is present or has a role.
+    ports: Option<IoPortRange>
+} impl SerialDevice {
+    fn ports(&self) -> Option<&IoPortRange> {
+        self.ports.as_ref()
+    }
+
+    /// Sets the baud rate and line settings, after `SerialPort::init` set up the rest.
+    fn configure(&self) {
+        let Some(ports) = self.ports() else { return; };
+        let divisor = (BASE_BAUD / self.config.baud) as u16;
+        ports.write(LINE_CONTROL_OFFSET, DIVISOR_LATCH_ACCESS);
+        ports.write(DATA_OFFSET, (divisor & 0xFF) as u8);
+        ports.write(DIVISOR_HIGH_OFFSET, (divisor >> 8) as u8);
+        ports.write(LINE_CONTROL_OFFSET, self.config.line_control());
+    }
+} impl Display for SerialDevice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} io {:#X} irq {} {}", self.name, self.base, self.irq.line(), self.config)?;
+        match self.role {
+            SerialRole::Unused => {},
+            SerialRole::Log => write!(f, " log")?,
+            SerialRole::Control => write!(f, " control")?
+        }
+        if !self.present {
+            write!(f, " (not detected)")?;
+        }
+        Ok(())
+    }
+}
+
+/// What the kernel command line says about the serial ports: the port each role goes to, as a
+/// name like `com2` or `none`, and the line settings of each port.
+#[derive(Debug, Default)]
+pub struct SerialOptions<'a> {
+    pub log: Option<&'a str>,
+    pub control: Option<&'a str>,
+    pub lines: [Option<&'a str>; PORT_COUNT]
+} impl<'a> SerialOptions<'a> {
+    /// Collects the `log=`, `control=` and `com1=` to `com4=` options through the function.
+    pub fn new(option: impl Fn(&str) -> Option<&'a str>) -> Self { Self {
+        log: option("log"),
+        control: option("control"),
+        lines: LINE_OPTIONS.map(option)
+    } }
+}
+
+/// The bytes waiting to be sent, in a ring.
+struct TxBuffer {
+    bytes: [u8; TX_BUFFER_SIZE],
+    head: usize,
+    length: usize
+} impl TxBuffer {
+    const fn new() -> Self { Self {
+        bytes: [0; TX_BUFFER_SIZE],
+        head: 0,
+        length: 0
+    } }
+
+    fn free(&self) -> usize {
+        TX_BUFFER_SIZE - self.length
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.length == 0 { return None; }
+        let byte = self.bytes[self.head];
+        self.head = (self.head + 1) % TX_BUFFER_SIZE;
+        self.length -= 1;
+        Some(byte)
+    }
+} impl Write for TxBuffer {
+    /// Fails if the text doesn't fit, with the part that did fit written.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if self.length == TX_BUFFER_SIZE { return Err(fmt::Error); }
+            self.bytes[(self.head + self.length) % TX_BUFFER_SIZE] = byte;
+            self.length += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Counts the bytes formatted text takes up, to check it fits before writing it.
+struct LengthCounter(usize);
+
+impl Write for LengthCounter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+/// Logs to the serial port with the log role. Until `enable_interrupts` it sends every byte right away;
+/// after that messages go into a buffer the port takes from whenever its transmit FIFO ran empty,
+/// so logging doesn't wait for the port.
 pub struct SerialPortLogger {
-    port: RwLock<SerialPort>
+    port: SerialPort,
+    ports: &'static IoPortRange,
+    buffer: TxBuffer,
+    policy: OverflowPolicy,
+    /// Whether the port interrupts once it sent its FIFO.
+    interrupts: bool,
+    /// Bytes of messages dropped since the last message that made it into the buffer.
+    dropped: usize
 } #[allow(dead_code)] impl SerialPortLogger {
-    pub fn init() -> Self {
-        let mut port = unsafe { SerialPort::new(0x3F8) };
+    fn init(device: &'static SerialDevice) -> Option<Self> {
+        let ports = device.ports()?;
+        let mut port = unsafe { SerialPort::new(device.base) };
         port.init();
-        Self { port: RwLock::new(port) }
+        device.configure();
+        Some(Self { port, ports, buffer: TxBuffer::new(), policy: OverflowPolicy::Drop, interrupts: false, dropped: 0 })
     }
 
     pub fn log_args(&mut self, args: &Arguments, level: SerialLoggingLevel, file: &str, line: u32) {
-        self.port.write().write_fmt(
-            format_args!("\n[{}#{} | {}]: {}", file, line, level.as_str(), args)
-        ).unwrap();
+        let must_send = matches!(level, SerialLoggingLevel::Warning | SerialLoggingLevel::Error | SerialLoggingLevel::Panic);
+        self.write_message(format_args!("\n[{}#{} | {}]: {}", file, line, level.as_str(), args), must_send);
     }
-} impl Write for SerialPortLogger {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        self.port.write().write_str(s)
+
+    fn write_message(&mut self, args: Arguments, must_send: bool) {
+        if !self.interrupts {
+            let _ = self.port.write_fmt(args);
+            return;
+        }
+
+        let mut length = LengthCounter(0);
+        let _ = length.write_fmt(args);
+        let length = length.0;
+        if self.buffer.free() < length + dropped_notice_length(self.dropped) {
+            if self.policy == OverflowPolicy::Drop && !must_send {
+                self.dropped += length;
+                return;
+            }
+            while self.buffer.free() < length + dropped_notice_length(self.dropped) {
+                let Some(byte) = self.buffer.pop() else { break; };
+                self.port.send_raw(byte);
+            }
+        }
+
+        if self.dropped > 0 {
+            let _ = self.buffer.write_fmt(format_args!(
+                "\n[serial | WARNING]: {} bytes of log output dropped", core::mem::take(&mut self.dropped)
+            ));
+        }
+        // Only messages longer than the whole buffer don't fit, those are sent right away
+        if self.buffer.free() < length {
+            self.flush();
+            let _ = self.port.write_fmt(args);
+        } else {
+            let _ = self.buffer.write_fmt(args);
+        }
+        self.transmit();
+    }
+
+    /// Fills the transmit FIFO from the buffer if the port sent everything it had.
+    fn transmit(&mut self) {
+        let ports = self.ports;
+        if ports.read::<u8>(LINE_STATUS_OFFSET) & LINE_STATUS_TX_EMPTY == 0 { return; }
+        for _ in 0..TX_FIFO_SIZE {
+            let Some(byte) = self.buffer.pop() else { break; };
+            ports.write(DATA_OFFSET, byte);
+        }
+    }
+
+    /// Sends everything in the buffer, waiting for the port.
+    fn flush(&mut self) {
+        while let Some(byte) = self.buffer.pop() {
+            self.port.send_raw(byte);
+        }
     }
 
-    fn write_char(&mut self, c: char) -> fmt::Result {
-        self.port.write().write_char(c)
+    fn set_interrupts(&mut self, enabled: bool) {
+        if !enabled { self.flush(); }
+        self.ports.write(INTERRUPT_ENABLE_OFFSET, if enabled { INTERRUPT_TX_EMPTY } else { 0u8 });
+        self.interrupts = enabled;
+    }
+} impl Write for SerialPortLogger {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_message(format_args!("{}", s), true);
+        Ok(())
     }
+} impl LoggerWrapper {
+    fn write(&self, record: &Record) {
+        crate::internal::log_buffer::record(record);
+        let level = SerialLoggingLevel::from(record.level());
 
-    fn write_fmt(&mut self, args: Arguments<'_>) -> fmt::Result {
-        self.port.write().write_fmt(args)
+        // The transmit interrupt takes the logger too
+        crate::internal::idt::without_interrupts(|| {
+            if let Some(logger) = LOGGER.lock().as_mut() {
+                logger.log_args(
+                    &format_args!("{}", crate::internal::log_fields::Plain(*record.args())),
+                    level, record.file().unwrap_or("_"), record.line().unwrap_or(0)
+                );
+            }
+        });
     }
 } impl Log for LoggerWrapper {
-    fn enabled(&self, _metadata: &Metadata) -> bool { true }
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        crate::internal::log_filter::enabled(metadata)
+    }
 
     fn log(&self, record: &Record) {
-        let level = match record.level() {
-            log::Level::Trace => SerialLoggingLevel::Debug,
-            log::Level::Debug => SerialLoggingLevel::Debug,
-            log::Level::Info => SerialLoggingLevel::Info,
-            log::Level::Warn => SerialLoggingLevel::Warning,
-            log::Level::Error => SerialLoggingLevel::Error
-        };
+        if !self.enabled(record.metadata()) { return; }
+        if crate::internal::early_log::is_active() {
+            crate::internal::early_log::log(record);
+            return;
+        }
 
-        if let Some(logger) = LOGGER.write().as_mut() {
-            logger.log_args(record.args(), level, record.file().unwrap_or("_"), record.line().unwrap_or(0));
+        let check = crate::internal::log_filter::check_repeat(record);
+        if check.repeated > 0 {
+            self.write(&Record::builder()
+                .level(check.level)
+                .target(check.target())
+                .args(format_args!("last message repeated {} times", check.repeated))
+                .build());
+        }
+        if check.log {
+            self.write(record);
         }
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        flush();
+    }
 }
 
-pub fn init() -> Result<(), SetLoggerError> {
-    let mut logger = LOGGER.write();
+/// Probes for the legacy serial ports, gives them their roles and line settings from the options
+/// and starts logging to the one with the log role. Logging is set up even without a log port,
+/// the log buffer still keeps the messages.
+pub fn init(options: &SerialOptions) -> Result<(), SetLoggerError> {
+    let mut errors: [Option<(&'static str, &'static str)>; 2 + PORT_COUNT] = [None; 2 + PORT_COUNT];
+    if DEVICES.get().is_none() {
+        let log = parse_role(options.log, DEFAULT_LOG_PORT).unwrap_or_else(|err| {
+            errors[0] = Some(("log", err));
+            Some(DEFAULT_LOG_PORT)
+        });
+        let mut control = parse_role(options.control, DEFAULT_CONTROL_PORT).unwrap_or_else(|err| {
+            errors[1] = Some(("control", err));
+            Some(DEFAULT_CONTROL_PORT)
+        });
+        if control.is_some() && control == log {
+            errors[1] = Some(("control", "Port is already used for the log"));
+            control = None;
+        }
+
+        DEVICES.call_once(|| core::array::from_fn(|index| {
+            let (name, base, irq) = LEGACY_PORTS[index];
+            let role = if Some(index) == log { SerialRole::Log }
+                else if Some(index) == control { SerialRole::Control }
+                else { SerialRole::Unused };
+            let config = options.lines[index].map_or(Ok(SerialConfig::new()), SerialConfig::parse)
+                .unwrap_or_else(|err| {
+                    errors[2 + index] = Some((LINE_OPTIONS[index], err));
+                    SerialConfig::new()
+                });
+            // Nothing can have claimed these ports before the logger, so claiming can't fail
+            let present = probe(base);
+            let ports = (present || role != SerialRole::Unused).then(|| crate::internal::ioport::claim(base, SERIAL_PORT_COUNT, name)
+                .unwrap_or_else(|err| panic!("Failed to claim the serial port: {}", err)));
+            SerialDevice { name, base, irq, present, config, role, ports }
+        }));
+    }
+
+    let mut logger = LOGGER.lock();
     if logger.is_none() {
-        *logger = Some(SerialPortLogger::init());
+        *logger = device(SerialRole::Log).and_then(SerialPortLogger::init);
     }
     drop(logger);
 
     log::set_logger(&LoggerWrapper)
-        .map(|()| log::set_max_level(log::LevelFilter::Trace))
+        .map(|()| crate::internal::log_filter::set_default_level(log::LevelFilter::Trace))?;
+    for (option, err) in errors.iter().flatten() {
+        log::warn!("Ignoring serial option {}: {}", option, err);
+    }
+    Ok(())
+}
+
+/// Returns the legacy serial ports, empty before `init`.
+pub fn devices() -> &'static [SerialDevice] {
+    DEVICES.get().map_or(&[], |devices| devices.as_slice())
+}
+
+/// Returns the serial port with the role.
+fn device(role: SerialRole) -> Option<&'static SerialDevice> {
+    devices().iter().find(|device| device.role == role)
+}
+
+/// Parses the port a role goes to, None if it goes nowhere.
+fn parse_role(option: Option<&str>, default: usize) -> Result<Option<usize>, &'static str> {
+    match option {
+        None => Ok(Some(default)),
+        Some(name) if name.eq_ignore_ascii_case("none") => Ok(None),
+        Some(name) => LEGACY_PORTS.iter().position(|(port, _, _)| port.eq_ignore_ascii_case(name))
+            .map(Some).ok_or("Unknown serial port")
+    }
+}
+
+/// Returns whether a UART answers at the ports, by checking its scratch register keeps what is
+/// written to it.
+fn probe(base: u16) -> bool {
+    let mut scratch: Port<u8> = Port::new(base + SCRATCH_OFFSET);
+    SCRATCH_PATTERNS.iter().all(|pattern| unsafe {
+        scratch.write(*pattern);
+        scratch.read() == *pattern
+    })
+}
+
+/// Switches the logger to sending from its buffer on the transmit interrupt, which has to be
+/// registered and unmasked by now.
+pub fn enable_interrupts() {
+    crate::internal::idt::without_interrupts(|| {
+        if let Some(logger) = LOGGER.lock().as_mut() {
+            logger.set_interrupts(true);
+        }
+    });
+}
+
+/// Sends what is left in the buffer and switches back to sending every byte right away, for when
+/// interrupts stop being handled.
+pub fn disable_interrupts() {
+    crate::internal::idt::without_interrupts(|| {
+        if let Some(logger) = LOGGER.lock().as_mut() {
+            logger.set_interrupts(false);
+        }
+    });
+}
+
+pub fn set_overflow_policy(policy: OverflowPolicy) {
+    crate::internal::idt::without_interrupts(|| {
+        if let Some(logger) = LOGGER.lock().as_mut() {
+            logger.policy = policy;
+        }
+    });
+}
+
+/// Sends everything still in the buffer, waiting for the port. Gives up if the logger is locked,
+/// so the panic paths can use it even if they interrupted a write.
+pub fn flush() {
+    crate::internal::idt::without_interrupts(|| {
+        if let Some(logger) = LOGGER.try_lock().as_mut().and_then(|logger| logger.as_mut()) {
+            logger.flush();
+        }
+    });
+}
+
+/// Handles the interrupt line shared by two of the serial ports. The log port only interrupts
+/// once its transmit FIFO is empty and the logger is never waited for, it is only locked here while
+/// another processor writes to it, and writing starts sending by itself. Bytes received on the
+/// control port become control input events.
+pub fn on_interrupt(irq: PicInterrupts) {
+    if let Some(ports) = device(SerialRole::Log).filter(|device| device.irq == irq).and_then(SerialDevice::ports) {
+        // Reading the interrupt identification acknowledges the interrupt
+        let _ = ports.read::<u8>(INTERRUPT_ID_OFFSET);
+        if let Some(logger) = LOGGER.try_lock().as_mut().and_then(|logger| logger.as_mut()) {
+            logger.transmit();
+        }
+    }
+    if device(SerialRole::Control).is_some_and(|device| device.irq == irq) {
+        while let Some(byte) = try_receive_control() {
+            crate::api::event::EventDispatcher::global().push(Event::ControlInput(byte));
+        }
+    }
+}
+
+fn dropped_notice_length(dropped: usize) -> usize {
+    if dropped == 0 { 0 } else { 64 }
+}
+
+/// Writes directly to the log port, bypassing the logger and its lock. Only meant for fatal
+/// paths where the interrupted code might still be holding the logger lock, and for the early
+/// logger which has the port to itself.
+pub unsafe fn write_unlocked(args: Arguments) {
+    let Some(device) = device(SerialRole::Log) else { return; };
+    let mut port = SerialPort::new(device.base);
+    let _ = port.write_fmt(args);
+}
+
+/// Initializes the serial port with the control role as the control channel, returns its name or
+/// None if no port has the role.
+pub fn init_control() -> Result<Option<&'static str>, KernelError> {
+    let Some(device) = device(SerialRole::Control) else { return Ok(None); };
+    if device.ports().is_none() {
+        return Err(KernelError::HardwareMissing("Control serial port"));
+    }
+    let mut port = unsafe { SerialPort::new(device.base) };
+    port.init();
+    device.configure();
+    *CONTROL_PORT.lock() = Some(port);
+    Ok(Some(device.name))
+}
+
+/// Reads the next byte received on the control port, if there is one. Never blocks, so it is
+/// safe to call from the interrupt handler even while a write to the control port is interrupted.
+pub fn try_receive_control() -> Option<u8> {
+    let mut port = CONTROL_PORT.try_lock()?;
+    let port = port.as_mut()?;
+
+    if device(SerialRole::Control)?.ports()?.read::<u8>(LINE_STATUS_OFFSET) & 1 == 0 { return None; }
+
+    Some(port.receive())
+}
+
+/// Writes formatted text to the control port.
+pub fn write_control(args: Arguments) {
+    if let Some(port) = CONTROL_PORT.lock().as_mut() {
+        let _ = port.write_fmt(args);
+    }
+}
+
+/// Writes raw binary data to the control port.
+pub fn write_control_raw(bytes: &[u8]) {
+    if let Some(port) = CONTROL_PORT.lock().as_mut() {
+        for byte in bytes {
+            port.send_raw(*byte);
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,186 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use spin::Once;
+use x86_64::{PhysAddr, VirtAddr};
+
+/// Where the entry point is on BIOS systems, on a 16-byte boundary.
+static BIOS_AREA_START: u64 = 0xF0000;
+static BIOS_AREA_END: u64 = 0x100000;
+static ANCHOR_2: &[u8] = b"_SM_";
+static ANCHOR_3: &[u8] = b"_SM3_";
+static TYPE_BIOS: u8 = 0;
+static TYPE_SYSTEM: u8 = 1;
+static TYPE_PROCESSOR: u8 = 4;
+static TYPE_MEMORY_DEVICE: u8 = 17;
+static TYPE_END: u8 = 127;
+
+static SYSTEM_INFO: Once<SystemInfo> = Once::new();
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessorInfo {
+    /// The label of the socket, like `CPU 0`.
+    pub socket: String,
+    pub manufacturer: String,
+    pub version: String,
+    pub max_speed_mhz: u16,
+    /// The cores of the processor, zero if unknown.
+    pub cores: u8,
+    /// Whether a processor is plugged into the socket.
+    pub populated: bool
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryDevice {
+    /// The label of the slot, like `DIMM 0`.
+    pub locator: String,
+    /// Zero if the slot is empty.
+    pub size_mib: u64,
+    /// The speed in megatransfers per second, the configured one if known, zero if unknown.
+    pub speed_mts: u16
+}
+
+/// The hardware inventory from the SMBIOS tables.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SystemInfo {
+    /// The SMBIOS version as major and minor.
+    pub version: (u8, u8),
+    pub manufacturer: String,
+    pub product: String,
+    pub bios_vendor: String,
+    pub bios_version: String,
+    pub bios_date: String,
+    pub processors: Vec<ProcessorInfo>,
+    pub memory_devices: Vec<MemoryDevice>
+}
+
+/// A structure of the table: its type, the formatted part and the strings it refers to by index.
+struct Structure<'a> {
+    kind: u8,
+    data: &'a [u8],
+    strings: Vec<&'a str>
+} impl<'a> Structure<'a> {
+    fn byte(&self, offset: usize) -> Option<u8> {
+        self.data.get(offset).copied()
+    }
+
+    fn word(&self, offset: usize) -> Option<u16> {
+        Some(u16::from_le_bytes(self.data.get(offset..offset + 2)?.try_into().ok()?))
+    }
+
+    fn dword(&self, offset: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(self.data.get(offset..offset + 4)?.try_into().ok()?))
+    }
+
+    /// Returns the string the byte at the offset refers to, empty if it refers to none.
+    fn string(&self, offset: usize) -> String {
+        self.byte(offset)
+            .and_then(|index| self.strings.get((index as usize).checked_sub(1)?))
+            .map_or(String::new(), |string| string.trim().to_string())
+    }
+}
+
+/// Splits the structure table into its structures.
+fn structures(table: &[u8]) -> Vec<Structure> {
+    let mut structures = Vec::new();
+    let mut offset = 0;
+    while offset + 4 <= table.len() {
+        let kind = table[offset];
+        let length = table[offset + 1] as usize;
+        if length < 4 || offset + length > table.len() { break; }
+        let data = &table[offset..offset + length];
+
+        // The strings follow the formatted part and end with two zero bytes
+        let strings_start = offset + length;
+        let Some(strings_length) = table[strings_start..].windows(2).position(|pair| pair == [0, 0]) else { break; };
+        let strings = table[strings_start..strings_start + strings_length].split(|byte| *byte == 0)
+            .filter(|string| !string.is_empty())
+            .map(|string| core::str::from_utf8(string).unwrap_or("?"))
+            .collect();
+        structures.push(Structure { kind, data, strings });
+
+        if kind == TYPE_END { break; }
+        offset = strings_start + strings_length + 2;
+    }
+    structures
+}
+
+fn parse(version: (u8, u8), table: &[u8]) -> SystemInfo {
+    let mut info = SystemInfo { version, ..SystemInfo::default() };
+    for structure in structures(table) {
+        match structure.kind {
+            kind if kind == TYPE_BIOS => {
+                info.bios_vendor = structure.string(0x04);
+                info.bios_version = structure.string(0x05);
+                info.bios_date = structure.string(0x08);
+            }, kind if kind == TYPE_SYSTEM => {
+                info.manufacturer = structure.string(0x04);
+                info.product = structure.string(0x05);
+            }, kind if kind == TYPE_PROCESSOR => info.processors.push(ProcessorInfo {
+                socket: structure.string(0x04),
+                manufacturer: structure.string(0x07),
+                version: structure.string(0x10),
+                max_speed_mhz: structure.word(0x14).unwrap_or(0),
+                cores: structure.byte(0x23).unwrap_or(0),
+                populated: structure.byte(0x18).is_some_and(|status| status & (1 << 6) != 0)
+            }), kind if kind == TYPE_MEMORY_DEVICE => {
+                let size_mib = match structure.word(0x0C).unwrap_or(0) {
+                    0 | 0xFFFF => 0,
+                    // Too large for the field, the size is in the extended field in MiB
+                    0x7FFF => structure.dword(0x1C).unwrap_or(0) as u64 & 0x7FFF_FFFF,
+                    // Bit 15 set means the size is in KiB
+                    size if size & 0x8000 != 0 => (size & 0x7FFF) as u64 / 1024,
+                    size => size as u64
+                };
+                info.memory_devices.push(MemoryDevice {
+                    locator: structure.string(0x10),
+                    size_mib,
+                    speed_mts: structure.word(0x20).filter(|speed| *speed != 0).or(structure.word(0x15)).unwrap_or(0)
+                });
+            }, _ => {}
+        }
+    }
+    info
+}
+
+fn physical_slice(physical_memory_offset: VirtAddr, address: u64, length: usize) -> &'static [u8] {
+    let virtual_address = crate::internal::memory::phys_to_virt(physical_memory_offset, PhysAddr::new(address));
+    unsafe { core::slice::from_raw_parts(virtual_address.as_ptr(), length) }
+}
+
+fn checksum_valid(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+/// Reads the entry point at the address, returns the version and the structure table.
+fn read_entry_point(physical_memory_offset: VirtAddr, address: u64) -> Option<((u8, u8), &'static [u8])> {
+    let header = physical_slice(physical_memory_offset, address, 0x20);
+    if header.starts_with(ANCHOR_3) {
+        let length = header[0x06] as usize;
+        if length > 0x20 || !checksum_valid(&header[..length]) { return None; }
+        let table_length = u32::from_le_bytes(header[0x0C..0x10].try_into().ok()?) as usize;
+        let table_address = u64::from_le_bytes(header[0x10..0x18].try_into().ok()?);
+        Some(((header[0x07], header[0x08]), physical_slice(physical_memory_offset, table_address, table_length)))
+    } else if header.starts_with(ANCHOR_2) {
+        let length = header[0x05] as usize;
+        if length > 0x20 || !checksum_valid(&header[..length]) { return None; }
+        let table_length = u16::from_le_bytes(header[0x16..0x18].try_into().ok()?) as usize;
+        let table_address = u32::from_le_bytes(header[0x18..0x1C].try_into().ok()?) as u64;
+        Some(((header[0x06], header[0x07]), physical_slice(physical_memory_offset, table_address, table_length)))
+    } else { None }
+}
+
+/// Parses the SMBIOS tables at the entry point the bootloader passed, or searches the BIOS area
+/// for one. Returns None if there are no tables, like on UEFI systems booted without one passed.
+pub fn init(physical_memory_offset: VirtAddr, entry_point: Option<u64>) -> Option<&'static SystemInfo> {
+    let (version, table) = match entry_point {
+        Some(address) => read_entry_point(physical_memory_offset, address)?,
+        None => (BIOS_AREA_START..BIOS_AREA_END).step_by(16)
+            .find_map(|address| read_entry_point(physical_memory_offset, address))?
+    };
+    Some(SYSTEM_INFO.call_once(|| parse(version, table)))
+}
+
+/// Returns the hardware inventory, None if there were no SMBIOS tables.
+pub fn system_info() -> Option<&'static SystemInfo> {
+    SYSTEM_INFO.get()
+}
\ No newline at end of file
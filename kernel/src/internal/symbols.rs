@@ -0,0 +1,148 @@
+use core::fmt::{Display, Formatter, Write};
+use spin::Once;
+use crate::api::error::KernelError;
+use crate::internal::protocol::KernelFile;
+
+static ELF_MAGIC: &[u8] = b"\x7fELF";
+static SECTION_HEADER_SIZE: usize = 64;
+static SECTION_SYMTAB: u32 = 2;
+static SYMBOL_SIZE: usize = 24;
+static SYMBOL_TYPE_FUNCTION: u8 = 2;
+
+/// Escapes of the legacy Rust symbol mangling and what they stand for.
+static ESCAPES: &[(&str, &str)] = &[
+    ("$LT$", "<"), ("$GT$", ">"), ("$RF$", "&"), ("$BP$", "*"), ("$C$", ","), ("$SP$", "@"),
+    ("$u20$", " "), ("$u22$", "\""), ("$u27$", "'"), ("$u2b$", "+"), ("$u3b$", ";"),
+    ("$u5b$", "["), ("$u5d$", "]"), ("$u7b$", "{"), ("$u7d$", "}"), ("$u7e$", "~"), ("..", "::")
+];
+
+static SYMBOLS: Once<SymbolTable> = Once::new();
+
+/// The function symbols of the kernel's own ELF file. Resolving neither allocates nor locks, so it
+/// can be used from the allocator and from fatal paths.
+struct SymbolTable {
+    symbols: &'static [u8],
+    names: &'static [u8],
+    load_offset: u64
+} impl SymbolTable {
+    fn parse(file: &'static [u8], load_offset: u64) -> Result<Self, KernelError> {
+        if file.get(..4) != Some(ELF_MAGIC) {
+            return Err(KernelError::InvalidConfiguration("Kernel file is not an ELF file"));
+        }
+        let section_offset = read_u64(file, 0x28)? as usize;
+        let section_count = read_u16(file, 0x3C)? as usize;
+
+        let section = |index: usize| -> Result<(u32, u32, &'static [u8]), KernelError> {
+            let header = section_offset + index * SECTION_HEADER_SIZE;
+            let kind = read_u32(file, header + 0x04)?;
+            let offset = read_u64(file, header + 0x18)? as usize;
+            let size = read_u64(file, header + 0x20)? as usize;
+            let link = read_u32(file, header + 0x28)?;
+            let contents = file.get(offset..offset + size)
+                .ok_or(KernelError::InvalidConfiguration("Kernel file section out of bounds"))?;
+            Ok((kind, link, contents))
+        };
+
+        for index in 0..section_count {
+            let (kind, link, symbols) = section(index)?;
+            if kind == SECTION_SYMTAB {
+                let (.., names) = section(link as usize)?;
+                return Ok(Self { symbols, names, load_offset });
+            }
+        }
+        Err(KernelError::InvalidConfiguration("Kernel file has no symbol table"))
+    }
+
+    fn count(&self) -> usize {
+        self.symbols.len() / SYMBOL_SIZE
+    }
+
+    fn resolve(&self, address: u64) -> Option<Symbol> {
+        self.symbols.chunks_exact(SYMBOL_SIZE)
+            .filter(|symbol| symbol[4] & 0xF == SYMBOL_TYPE_FUNCTION)
+            .find_map(|symbol| {
+                let start = read_u64(symbol, 8).ok()?.wrapping_add(self.load_offset);
+                let size = read_u64(symbol, 16).ok()?;
+                if address < start || address >= start + size.max(1) { return None; }
+
+                let name_offset = read_u32(symbol, 0).ok()? as usize;
+                let name = self.names.get(name_offset..)?;
+                let name = &name[..name.iter().position(|byte| *byte == 0)?];
+                Some(Symbol { name: core::str::from_utf8(name).ok()?, offset: address - start })
+            })
+    }
+}
+
+/// A function of the kernel and how far into it an address is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    /// The mangled name, `Display` shows it demangled.
+    pub name: &'static str,
+    pub offset: u64
+} impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write_demangled(f, self.name)?;
+        write!(f, "+{:#X}", self.offset)
+    }
+}
+
+/// Loads the symbol table of the kernel file, returns the amount of symbols in it.
+pub fn init(kernel_file: KernelFile) -> Result<usize, KernelError> {
+    let table = SymbolTable::parse(kernel_file.data(), kernel_file.load_offset)?;
+    let count = table.count();
+    SYMBOLS.call_once(|| table);
+    Ok(count)
+}
+
+/// Finds the function the address belongs to, None if there is no symbol table or no function
+/// contains the address.
+pub fn resolve(address: u64) -> Option<Symbol> {
+    SYMBOLS.get()?.resolve(address)
+}
+
+/// Writes a legacy mangled Rust symbol (`_ZN...E`) as path without the trailing hash, anything
+/// else as it is.
+fn write_demangled(f: &mut Formatter<'_>, name: &str) -> core::fmt::Result {
+    let Some(mut rest) = name.strip_prefix("_ZN") else { return f.write_str(name) };
+    let mut first = true;
+    while let Some(length_end) = rest.find(|char: char| !char.is_ascii_digit()).filter(|end| *end > 0) {
+        let Ok(length) = rest[..length_end].parse::<usize>() else { break };
+        let Some(segment) = rest.get(length_end..length_end + length) else { break };
+        rest = &rest[length_end + length..];
+
+        let is_hash = segment.len() == 17 && segment.starts_with('h')
+            && segment[1..].chars().all(|char| char.is_ascii_hexdigit());
+        if is_hash && rest.starts_with('E') { break; }
+
+        if !first { f.write_str("::")?; }
+        first = false;
+        // Segments starting with an escape get an underscore in front
+        let mut segment = if segment.starts_with("_$") { &segment[1..] } else { segment };
+        while !segment.is_empty() {
+            if let Some((escape, replacement)) = ESCAPES.iter().find(|(escape, ..)| segment.starts_with(escape)) {
+                f.write_str(replacement)?;
+                segment = &segment[escape.len()..];
+            } else {
+                let char = segment.chars().next().unwrap_or_default();
+                f.write_char(char)?;
+                segment = &segment[char.len_utf8()..];
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, KernelError> {
+    bytes.get(offset..offset + 2).and_then(|bytes| bytes.try_into().ok()).map(u16::from_le_bytes)
+        .ok_or(KernelError::InvalidConfiguration("Kernel file is truncated"))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, KernelError> {
+    bytes.get(offset..offset + 4).and_then(|bytes| bytes.try_into().ok()).map(u32::from_le_bytes)
+        .ok_or(KernelError::InvalidConfiguration("Kernel file is truncated"))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, KernelError> {
+    bytes.get(offset..offset + 8).and_then(|bytes| bytes.try_into().ok()).map(u64::from_le_bytes)
+        .ok_or(KernelError::InvalidConfiguration("Kernel file is truncated"))
+}
\ No newline at end of file
@@ -0,0 +1,126 @@
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use crate::api::keyboard::KeyCode;
+use crate::systems::keyboard::Ps2Keyboard;
+
+static EXTENDED_PREFIX: u8 = 0xE0;
+static RELEASE_BIT: u8 = 0x80;
+
+/// Set 1 make codes of the keys making up the combo, the same for the left and right modifiers.
+static CONTROL_SCANCODE: u8 = 0x1D;
+static ALT_SCANCODE: u8 = 0x38;
+static TRIGGER_SCANCODE: u8 = 0x58;
+
+static EXTENDED: AtomicBool = AtomicBool::new(false);
+static CONTROL: AtomicBool = AtomicBool::new(false);
+static ALT: AtomicBool = AtomicBool::new(false);
+static ARMED: AtomicBool = AtomicBool::new(false);
+/// The key whose release is swallowed, as its press never made it to the keyboard system.
+static SWALLOW_RELEASE: AtomicU8 = AtomicU8::new(0);
+static DISPLAY_RESET: AtomicBool = AtomicBool::new(false);
+
+/// The actions and the keys choosing them after the combo.
+static ACTIONS: [(KeyCode, &str, fn()); 6] = [
+    (KeyCode::H, "show this help", help),
+    (KeyCode::T, "dump the work and event queues", dump_tasks),
+    (KeyCode::M, "dump the heap usage", dump_memory),
+    (KeyCode::D, "re-initialize the display", reset_display),
+    (KeyCode::S, "flush the serial log", sync),
+    (KeyCode::C, "panic the kernel", crash)
+];
+
+/// Looks for the Ctrl+Alt+F12 combo in the scancodes straight from the keyboard interrupt, the next
+/// key pressed after it picks a debug action. Runs before the scancodes are queued, so the actions
+/// still work when the kernel loop is stuck. Returns whether the scancode was part of the combo and
+/// shouldn't reach the keyboard system.
+pub fn on_scancode(scancode: u8) -> bool {
+    if scancode == EXTENDED_PREFIX {
+        EXTENDED.store(true, Ordering::SeqCst);
+        return false;
+    }
+    let extended = EXTENDED.swap(false, Ordering::SeqCst);
+    let pressed = scancode & RELEASE_BIT == 0;
+    let code = scancode & !RELEASE_BIT;
+
+    if code == CONTROL_SCANCODE {
+        CONTROL.store(pressed, Ordering::SeqCst);
+        return false;
+    } else if code == ALT_SCANCODE {
+        ALT.store(pressed, Ordering::SeqCst);
+        return false;
+    } else if extended {
+        return false;
+    }
+
+    if !pressed {
+        return SWALLOW_RELEASE.compare_exchange(code, 0, Ordering::SeqCst, Ordering::SeqCst).is_ok();
+    }
+    if code == TRIGGER_SCANCODE && CONTROL.load(Ordering::SeqCst) && ALT.load(Ordering::SeqCst) {
+        ARMED.store(true, Ordering::SeqCst);
+        SWALLOW_RELEASE.store(code, Ordering::SeqCst);
+        log::warn!("SysRq: press a key for an action, h for help.");
+        return true;
+    }
+    if !ARMED.swap(false, Ordering::SeqCst) {
+        return false;
+    }
+
+    SWALLOW_RELEASE.store(code, Ordering::SeqCst);
+    let key = Ps2Keyboard::decode(code, false);
+    match ACTIONS.iter().find(|(action_key, _, _)| *action_key == key) {
+        Some((_, description, action)) => {
+            log::warn!("SysRq: {}", description);
+            action();
+        }, None => log::warn!("SysRq: no action for {:?}, h for help.", key)
+    }
+    true
+}
+
+/// Returns whether the display should be re-initialized and clears the request.
+pub fn take_display_reset() -> bool {
+    DISPLAY_RESET.swap(false, Ordering::SeqCst)
+}
+
+fn help() {
+    for (key, description, _) in ACTIONS.iter() {
+        log::info!("SysRq {:?}: {}", key, description);
+    }
+}
+
+fn dump_tasks() {
+    // The interrupt might have come in while either queue was locked
+    match crate::systems::worker::try_stats() {
+        Some(workers) => log::info!(
+            "Work queue: {} queued, {} at most, {} completed.",
+            workers.queued, workers.high_water_mark, workers.completed
+        ),
+        None => log::warn!("Work queue is busy, locked by the interrupted code.")
+    }
+    match crate::api::event::EventDispatcher::global().try_stats() {
+        Some(events) => log::info!(
+            "Event queue: {} at most, {} dropped, {} coalesced.",
+            events.high_water_mark, events.dropped, events.coalesced
+        ),
+        None => log::warn!("Event queue is busy, locked by the interrupted code.")
+    }
+}
+
+fn dump_memory() {
+    // The interrupt might have come in while the heap was locked
+    match crate::internal::heap::try_usage() {
+        Some(usage) => log::info!("Heap: {} of {} bytes in use ({:?}).", usage.used, usage.size, usage.pressure()),
+        None => log::warn!("Heap is locked by the interrupted code.")
+    }
+}
+
+/// The display belongs to the kernel loop, it picks the request up on its next tick.
+fn reset_display() {
+    DISPLAY_RESET.store(true, Ordering::SeqCst);
+}
+
+fn sync() {
+    crate::internal::serial::flush();
+}
+
+fn crash() {
+    panic!("Crash requested through SysRq");
+}
\ No newline at end of file
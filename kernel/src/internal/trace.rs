@@ -0,0 +1,125 @@
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use spin::{Mutex, MutexGuard};
+
+const MAX_CPUS: usize = 8;
+const BUFFER_CAPACITY: usize = 2048;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_BUFFER: Mutex<TraceBuffer> = Mutex::new(TraceBuffer::new());
+static BUFFERS: [Mutex<TraceBuffer>; MAX_CPUS] = [EMPTY_BUFFER; MAX_CPUS];
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+static CPU: AtomicUsize = AtomicUsize::new(0);
+
+/// Records a tracepoint into the ring buffer of the current CPU.
+///
+/// Usage: `trace!(TraceCategory::Interrupt, vector)` or just `trace!(TraceCategory::DispatchEnd)`.
+#[macro_export]
+macro_rules! trace {
+    ($category:expr) => {
+        $crate::internal::trace::record($category, 0)
+    };
+    ($category:expr, $payload:expr) => {
+        $crate::internal::trace::record($category, $payload as u64)
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(dead_code)]
+pub enum TraceCategory {
+    /// A hardware interrupt was received. The payload is the interrupt vector.
+    Interrupt = 0,
+    /// The event dispatcher started dispatching. The payload is the amount of queued events.
+    DispatchBegin = 1,
+    /// The event dispatcher finished dispatching.
+    DispatchEnd = 2,
+    /// The display driver started drawing.
+    DrawBegin = 3,
+    /// The display driver finished drawing.
+    DrawEnd = 4,
+    /// Free to use for ad-hoc tracepoints while debugging.
+    Custom = 5
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TraceRecord {
+    pub tsc: u64,
+    pub payload: u64,
+    pub category: TraceCategory
+}
+
+struct TraceBuffer {
+    records: [TraceRecord; BUFFER_CAPACITY],
+    head: usize,
+    length: usize
+} impl TraceBuffer {
+    const fn new() -> Self { Self {
+        records: [TraceRecord { tsc: 0, payload: 0, category: TraceCategory::Custom }; BUFFER_CAPACITY],
+        head: 0,
+        length: 0
+    } }
+
+    fn push(&mut self, record: TraceRecord) {
+        self.records[self.head] = record;
+        self.head = (self.head + 1) % BUFFER_CAPACITY;
+        self.length = (self.length + 1).min(BUFFER_CAPACITY);
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = TraceRecord> + '_ {
+        let start = (self.head + BUFFER_CAPACITY - self.length) % BUFFER_CAPACITY;
+        let length = self.length;
+        self.length = 0;
+        (0..length).map(move |index| self.records[(start + index) % BUFFER_CAPACITY])
+    }
+}
+
+/// Reads the index of the CPU the kernel runs on from its initial APIC id. Done once, as `cpuid`
+/// on every tracepoint would be a VM exit under virtualization and distort the recorded timings.
+pub fn init() {
+    let apic_id = unsafe { core::arch::x86_64::__cpuid(1).ebx >> 24 } as usize;
+    CPU.store(apic_id % MAX_CPUS, Ordering::Relaxed);
+}
+
+/// Returns the index of the current CPU, 0 before `init`.
+fn current_cpu() -> usize {
+    CPU.load(Ordering::Relaxed)
+}
+
+/// Records a tracepoint. Use the `trace!` macro instead of calling this directly.
+/// Never blocks: if the buffer is currently being exported the record is dropped.
+pub fn record(category: TraceCategory, payload: u64) {
+    let record = TraceRecord { tsc: super::tsc::read(), payload, category };
+
+    crate::internal::idt::without_interrupts(|| {
+        if let Some(mut buffer) = BUFFERS[current_cpu()].try_lock() {
+            buffer.push(record);
+        } else {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    })
+}
+
+/// Dumps and clears all trace buffers over the control port.
+///
+/// The dump starts with a `TRACE <tsc khz> <record count> <dropped>` line, followed by the records
+/// as 18 bytes each: cpu (u8), category (u8), tsc (u64 LE), payload (u64 LE).
+/// Use the `trace-convert` tool to turn a dump into Chrome trace JSON.
+pub fn export() {
+    // Held for the whole dump, so the count matches the records; tracepoints meanwhile are dropped
+    let mut buffers: [MutexGuard<TraceBuffer>; MAX_CPUS] = core::array::from_fn(|cpu| BUFFERS[cpu].lock());
+    let count: usize = buffers.iter().map(|buffer| buffer.length).sum();
+    crate::internal::serial::write_control(format_args!(
+        "TRACE {} {} {}\n", super::tsc::khz(), count, DROPPED.swap(0, Ordering::Relaxed)
+    ));
+
+    for (cpu, buffer) in buffers.iter_mut().enumerate() {
+        for record in buffer.drain() {
+            let mut bytes = [0u8; 18];
+            bytes[0] = cpu as u8;
+            bytes[1] = record.category as u8;
+            bytes[2..10].copy_from_slice(&record.tsc.to_le_bytes());
+            bytes[10..18].copy_from_slice(&record.payload.to_le_bytes());
+            crate::internal::serial::write_control_raw(&bytes);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,48 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::instructions::port::Port;
+use crate::drivers::timer::pit::{PIT_FREQUENCY, PitChannel, PitMode};
+
+static PIT_GATE_PORT: u16 = 0x61;
+static CALIBRATION_MILLIS: u64 = 10;
+
+static TSC_KHZ: AtomicU64 = AtomicU64::new(0);
+
+/// Reads the current value of the time stamp counter.
+#[inline]
+pub fn read() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Measures the frequency of the time stamp counter against a one-shot countdown of PIT channel 2.
+/// Has to be called with interrupts disabled as it busy-waits for the countdown to finish.
+pub fn calibrate() -> u64 {
+    let mut gate: Port<u8> = Port::new(PIT_GATE_PORT);
+    let divisor = PIT_FREQUENCY * CALIBRATION_MILLIS / 1000;
+
+    let khz = unsafe {
+        // Enable the channel 2 gate but keep the speaker off
+        let value = gate.read();
+        gate.write((value & 0xFD) | 0x01);
+
+        crate::drivers::timer::pit::program(PitChannel::Channel2, PitMode::OneShot, divisor as u16);
+
+        // Restart the countdown by toggling the gate
+        let value = gate.read();
+        gate.write(value & 0xFE);
+        gate.write(value | 0x01);
+
+        let start = read();
+        while gate.read() & 0x20 == 0 { core::hint::spin_loop() }
+        let end = read();
+
+        (end - start) / CALIBRATION_MILLIS
+    };
+
+    TSC_KHZ.store(khz, Ordering::SeqCst);
+    khz
+}
+
+/// Returns the calibrated frequency of the time stamp counter in kHz, or 0 if not calibrated yet.
+pub fn khz() -> u64 {
+    TSC_KHZ.load(Ordering::SeqCst)
+}
\ No newline at end of file
@@ -0,0 +1,95 @@
+use core::fmt::{Display, Formatter};
+use x86_64::{PhysAddr, VirtAddr};
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+
+/// The flags that are compared when merging pages into regions and shown in the listing.
+static SHOWN_FLAGS: PageTableFlags = PageTableFlags::from_bits_truncate(
+    PageTableFlags::WRITABLE.bits() | PageTableFlags::USER_ACCESSIBLE.bits() | PageTableFlags::WRITE_THROUGH.bits()
+        | PageTableFlags::NO_CACHE.bits() | PageTableFlags::GLOBAL.bits() | PageTableFlags::NO_EXECUTE.bits()
+);
+
+/// Virtually and physically contiguous pages of the same size with the same effective flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappedRegion {
+    pub start: VirtAddr,
+    pub physical_start: PhysAddr,
+    pub size: u64,
+    /// The flags of the pages, combined with the ones of the tables above them: writable and user
+    /// accessible only if every level allows it, not executable if any level forbids it.
+    pub flags: PageTableFlags,
+    pub page_size: u64
+} impl MappedRegion {
+    pub fn writable(&self) -> bool {
+        self.flags.contains(PageTableFlags::WRITABLE)
+    }
+
+    pub fn executable(&self) -> bool {
+        !self.flags.contains(PageTableFlags::NO_EXECUTE)
+    }
+
+    /// Appends the page if it continues this region.
+    fn extend(&mut self, page: &MappedRegion) -> bool {
+        let continues = self.start.as_u64().wrapping_add(self.size) == page.start.as_u64()
+            && self.physical_start.as_u64() + self.size == page.physical_start.as_u64()
+            && self.flags == page.flags && self.page_size == page.page_size;
+        if continues { self.size += page.size; }
+        continues
+    }
+} impl Display for MappedRegion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let flag = |flag: PageTableFlags, set: char| if self.flags.contains(flag) { set } else { '-' };
+        write!(
+            f, "{:#018X}-{:#018X} {:#014X} r{}{}{}{}{}{} {}",
+            self.start.as_u64(), self.start.as_u64().wrapping_add(self.size), self.physical_start.as_u64(),
+            flag(PageTableFlags::WRITABLE, 'w'), if self.executable() { 'x' } else { '-' },
+            flag(PageTableFlags::USER_ACCESSIBLE, 'u'), flag(PageTableFlags::GLOBAL, 'g'),
+            flag(PageTableFlags::WRITE_THROUGH, 't'), flag(PageTableFlags::NO_CACHE, 'c'),
+            match self.page_size { 0x1000 => "4K", 0x200000 => "2M", _ => "1G" }
+        )
+    }
+}
+
+/// Walks the active page tables and passes the mapped regions to the function, in ascending order
+/// of their virtual addresses.
+pub fn walk(func: &mut dyn FnMut(MappedRegion)) {
+    let Some(physical_memory_offset) = crate::internal::memory::physical_memory_offset() else { return; };
+    let level_4_table = Cr3::read().0.start_address();
+
+    let mut current: Option<MappedRegion> = None;
+    walk_table(physical_memory_offset, level_4_table, 4, 0, PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE,
+        &mut |page| {
+            if current.as_mut().is_some_and(|region| region.extend(&page)) { return; }
+            if let Some(region) = current.replace(page) { func(region); }
+        }
+    );
+    if let Some(region) = current { func(region); }
+}
+
+fn walk_table(
+    physical_memory_offset: VirtAddr, table: PhysAddr, level: u8, base: u64, parent_flags: PageTableFlags,
+    func: &mut dyn FnMut(MappedRegion)
+) {
+    let table = unsafe {
+        &*crate::internal::memory::phys_to_virt(physical_memory_offset, table).as_ptr::<PageTable>()
+    };
+    let entry_size = 1u64 << (12 + 9 * (level as u64 - 1));
+
+    for (index, entry) in table.iter().enumerate() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) { continue; }
+
+        // The upper half of the address space needs the sign extension of bit 47
+        let start = VirtAddr::new_truncate(base + index as u64 * entry_size);
+        let inherited = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+        let mut effective = (flags & SHOWN_FLAGS & !inherited) | (flags & parent_flags & inherited);
+        if parent_flags.contains(PageTableFlags::NO_EXECUTE) { effective |= PageTableFlags::NO_EXECUTE; }
+        if level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            func(MappedRegion {
+                start, physical_start: entry.addr(), size: entry_size, flags: effective, page_size: entry_size
+            });
+        } else {
+            walk_table(physical_memory_offset, entry.addr(), level - 1, start.as_u64(), effective, func);
+        }
+    }
+}
\ No newline at end of file
@@ -1,60 +1,574 @@
 use alloc::format;
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
 use core::sync::atomic::Ordering;
-use crate::api::event::{ErrorEvent, EventErrorLevel};
+use aml::AmlValue;
+use crate::api::control::{AcpiArgument, ControlCommand};
+use crate::drivers::timer::pit::PitMode;
+use crate::api::event::{ErrorEvent, Event, EventErrorLevel};
+use crate::api::input::{Input, InputEvent, InputSource};
 use crate::{KernelRuntime, Kernel};
-use crate::api::display::{Fonts, Size};
-use crate::api::time::TimeOffset;
-use crate::drivers::display::DisplayDriverType;
+use crate::api::display::{DisplayError, Fonts, Position, Region, Size};
+use crate::api::error::KernelError;
+use crate::drivers::display::console::ConsoleDisplayDriver;
+use crate::drivers::display::layout::{FieldAlignment, TextLayout};
+#[cfg(feature = "gui")]
+use crate::drivers::display::log_viewer::LogViewerDisplayDriver;
+#[cfg(feature = "gui")]
+use crate::drivers::display::settings::{SettingsDisplayDriver, SettingsResult};
+use crate::drivers::display::text::TextDisplayDriver;
+use crate::internal::heap::MemoryPressure;
+use crate::api::thermal::ThermalTrip;
 use crate::managers::display::DisplayMode;
+use crate::systems::settings::{BootSettings, BootTarget};
+
+/// How often the thermal zones and batteries are read, evaluating their AML is too slow to do it every
+/// frame, so it runs as a background job.
+static SENSOR_POLL_SECONDS: u64 = 10;
 
 impl KernelRuntime for Kernel {
-    fn init(&mut self) {
-        self.display_manager.set_mode(DisplayMode::Text(
-            Size::new(80, 25),
-            Fonts::default()
-        ));
+    fn init(&mut self) -> Result<(), KernelError> {
+        self.settings = BootSettings::load();
+        crate::internal::log_filter::set_default_level(self.settings.log_level);
+        log::info!(
+            "Booting into {} on {} in {} with log level {}.", self.settings.target.name(),
+            self.settings.display.name(), self.settings.time_offset, self.settings.log_level
+        );
+
+        // Without a display the settings screen can be shown on, boot as if it wasn't asked for
+        let settings_mode = DisplayMode::Settings(Size::new(80, 25), Fonts::default());
+        if !cfg!(feature = "gui") || !crate::boot::setup_requested() || self.set_display_mode(settings_mode) != Some(settings_mode) {
+            self.start_target();
+        }
+        Ok(())
     }
 
-    fn tick(&mut self) {
+    fn tick(&mut self, ticks: u64) {
         let current_tick = self.tick.load(Ordering::SeqCst);
+        let previous_tick = current_tick - ticks;
+
+        #[cfg(feature = "gui")]
+        {
+            let settings_result = self.display_manager.as_mut()
+                .and_then(|display_manager| display_manager.get_driver::<SettingsDisplayDriver>())
+                .and_then(|driver| driver.controls().lock().take_result());
+            if let Some(result) = settings_result {
+                self.on_settings_left(result);
+            }
+        }
+        if crate::internal::sysrq::take_display_reset() {
+            if let Some(mode) = self.display_mode {
+                log::info!("Re-initializing the display in mode {:?}.", mode);
+                self.set_display_mode(mode);
+            }
+        }
+        let time_offset = self.settings.time_offset;
 
-        match self.display_manager.get_driver() {
-            DisplayDriverType::Text(driver, ..) => {
-                driver.clear_buffer();
-                driver.write_string(format!(
-                    "Tick {} at {}",
-                    current_tick, self.time_manager.with_clock(
-                        |clock| clock.with_offset(TimeOffset::A).to_string()
-                    ).unwrap_or("N/A".to_string())
-                ).as_str());
-
-                if current_tick % 500 == 0 {
+        if let Some(display_manager) = self.display_manager.as_mut() {
+            let blink_ticks = crate::drivers::timer::tick_hz() / 2;
+            if current_tick / blink_ticks != previous_tick / blink_ticks {
+                if let Some(driver) = display_manager.get_driver::<TextDisplayDriver>() {
                     driver.blink();
                 }
-            }, _ => {}
+            }
+
+            if display_manager.frame_due(current_tick) {
+                let time = self.time_manager.with_clock(
+                    |clock| clock.with_offset(time_offset).to_string()
+                ).unwrap_or("N/A".to_string());
+                let idle = self.time_manager.with_accounting(|accounting| accounting.idle_percent())
+                    .unwrap_or(0);
+                let load = match self.power_manager.lock().summary() {
+                    Some(power) => format!("{}% idle, battery {}", idle, power),
+                    None => format!("{}% idle", idle)
+                };
+                if let Some(driver) = display_manager.get_driver::<TextDisplayDriver>() {
+                    // Tick in the left half, time and load right-aligned in the right half
+                    let width = driver.get_buffer_size().width;
+                    let half = Size::new(width / 2, 1);
+                    TextLayout::new(Region::new(Position::new(0, 0), half))
+                        .write_row(driver, 0, format!("Tick {}", current_tick).as_str());
+                    let mut right = TextLayout::new(Region::new(Position::new(width / 2, 0), half));
+                    right.set_alignment(FieldAlignment::Right);
+                    right.write_row(driver, 0, format!("{} ({})", time, load).as_str());
+                } else if let Some(driver) = display_manager.get_driver::<ConsoleDisplayDriver>() {
+                    driver.home();
+                    let status = format!("Tick {} at {} ({})", current_tick, time, load);
+                    if let Err(err) = driver.write_line(status.as_str()) {
+                        log::debug!("Failed to write console line: {:?}", err);
+                    }
+                }
+            }
+
+            match display_manager.draw_frame(current_tick) {
+                Ok(..) => {},
+                Err(KernelError::Display(DisplayError::Busy)) => log::debug!("Display busy, deferring draw to next frame."),
+                Err(err) => log::warn!("Failed to draw display: {}", err)
+            }
         }
-        self.display_manager.draw_all();
 
-        if current_tick >= 10000 {
+        let sensor_poll_ticks = SENSOR_POLL_SECONDS * crate::drivers::timer::tick_hz();
+        if current_tick / sensor_poll_ticks != previous_tick / sensor_poll_ticks {
+            let (thermal_manager, power_manager) = (self.thermal_manager.clone(), self.power_manager.clone());
+            crate::systems::worker::spawn_blocking(move || {
+                thermal_manager.lock().poll();
+                power_manager.lock().refresh();
+            });
+        }
+
+        if current_tick >= 10 * crate::drivers::timer::tick_hz() {
             self.running.store(false, Ordering::SeqCst);
         }
     }
 
+    fn next_deadline(&self) -> Option<u64> {
+        // Only the cursor blink, the next frame, the sensor poll and the shutdown need a specific tick
+        let current_tick = self.tick.load(Ordering::SeqCst);
+        let next_frame = self.display_manager.as_ref()
+            .map_or(u64::MAX, |display_manager| display_manager.next_frame_in(current_tick).max(1));
+        let sensor_poll_ticks = SENSOR_POLL_SECONDS * crate::drivers::timer::tick_hz();
+        let next_sensor_poll = sensor_poll_ticks - current_tick % sensor_poll_ticks;
+        let blink_ticks = crate::drivers::timer::tick_hz() / 2;
+        Some((blink_ticks - current_tick % blink_ticks).min(next_frame).min(next_sensor_poll))
+    }
+
     fn on_error(&mut self, event: ErrorEvent) {
         match event.level() {
-            EventErrorLevel::Fault => {
+            EventErrorLevel::Interrupt => {
+                log::error!("Kernel received a non-maskable interrupt: {}", event.message());
+            }, EventErrorLevel::Fault => {
                 log::error!("Kernel encountered a fault: {}", event.message());
             }, EventErrorLevel::Abort => {
-                crate::abort(&format!(
-                    "\n Kernel encountered an unrecoverable error: {}",
+                crate::internal::emergency::abort(format_args!(
+                    "Kernel encountered an unrecoverable error: {}",
                     event.message()
-                ), Some(&mut self.display_manager))
+                ))
             }, _ => {}
         }
     }
 
+    fn on_control(&mut self, command: ControlCommand) {
+        match command {
+            ControlCommand::Shutdown => {
+                self.running.store(false, Ordering::SeqCst);
+            }, ControlCommand::LogLevel(level) => {
+                crate::internal::log_filter::set_default_level(level);
+            }, ControlCommand::LogFilter(None) => {
+                crate::internal::log_filter::for_each(|target, level| {
+                    crate::internal::serial::write_control(format_args!("{}={}\n", target, level));
+                });
+            }, ControlCommand::LogFilter(Some((target, level))) => {
+                if let Err(err) = crate::internal::log_filter::set_target_level(&target, level) {
+                    crate::internal::serial::write_control(format_args!("ERR {}\n", err));
+                    return;
+                }
+            }, ControlCommand::Screenshot => {
+                if !crate::systems::control::send_screenshot() {
+                    crate::internal::serial::write_control(format_args!("ERR No frame buffer available\n"));
+                    return;
+                }
+            }, ControlCommand::InjectKey(key) => {
+                crate::api::event::EventDispatcher::global().push(Event::Input(
+                    Input::new(InputSource::Serial, InputEvent::Text(key))
+                ));
+            }, ControlCommand::TraceDump => {
+                crate::internal::trace::export();
+            }, ControlCommand::BootChart => {
+                let mut chart = String::new();
+                let _ = crate::boot::write_chart(&mut chart);
+                crate::internal::serial::write_control(format_args!("{}", chart));
+            }, ControlCommand::ListModules => {
+                let loaded = self.module_manager.loaded();
+                for module in loaded.iter() {
+                    crate::internal::serial::write_control(format_args!(
+                        "{} loaded {:#X} {}\n", module.name, module.base, module.size
+                    ));
+                }
+                for name in self.module_manager.available() {
+                    if !loaded.iter().any(|module| module.name == name) {
+                        crate::internal::serial::write_control(format_args!("{} available\n", name));
+                    }
+                }
+            }, ControlCommand::LoadModule(name) => {
+                if let Err(err) = self.module_manager.load(&name) {
+                    crate::internal::serial::write_control(format_args!("ERR {}\n", err));
+                    return;
+                }
+            }, ControlCommand::MemoryMap(only_wx) => {
+                crate::internal::vmmap::walk(&mut |region| {
+                    if !only_wx || (region.writable() && region.executable()) {
+                        crate::internal::serial::write_control(format_args!("{}\n", region));
+                    }
+                });
+            }, ControlCommand::Benchmark => {
+                #[cfg(feature = "tests")]
+                crate::systems::bench::run(self.display_manager.as_mut(), &mut |result| {
+                    log::info!("{}", result);
+                    crate::internal::serial::write_control(format_args!("{}\n", result));
+                });
+                #[cfg(not(feature = "tests"))]
+                {
+                    log::warn!("Benchmarks were asked for, but the kernel was built without them.");
+                    crate::internal::serial::write_control(format_args!("ERR Benchmarks need the tests feature\n"));
+                    return;
+                }
+            }, ControlCommand::Sensors => {
+                let thermal_manager = self.thermal_manager.lock();
+                for zone in thermal_manager.zones() {
+                    let temperature = thermal_manager.temperature(zone);
+                    match (temperature, zone.critical) {
+                        (Ok(temperature), Some(critical)) => crate::internal::serial::write_control(format_args!(
+                            "{} {} critical {}\n", zone.path, temperature, critical
+                        )),
+                        (Ok(temperature), None) => crate::internal::serial::write_control(format_args!(
+                            "{} {}\n", zone.path, temperature
+                        )),
+                        (Err(err), ..) => crate::internal::serial::write_control(format_args!(
+                            "{} unavailable: {}\n", zone.path, err
+                        ))
+                    }
+                }
+            }, ControlCommand::CpuInfo => {
+                match crate::internal::cpufreq::estimate_khz() {
+                    Some(khz) => crate::internal::serial::write_control(format_args!("frequency {} kHz\n", khz)),
+                    None => crate::internal::serial::write_control(format_args!("frequency unknown\n"))
+                }
+                crate::internal::serial::write_control(format_args!("tsc {} kHz\n", crate::internal::tsc::khz()));
+                for state in crate::internal::cpufreq::performance_states() {
+                    crate::internal::serial::write_control(format_args!(
+                        "pstate {} MHz {} mW\n", state.frequency_mhz, state.power_mw
+                    ));
+                }
+                for (index, state) in crate::internal::cpuidle::states().iter().enumerate() {
+                    crate::internal::serial::write_control(format_args!(
+                        "cstate {} entered {} times\n", state, crate::internal::cpuidle::entries(index)
+                    ));
+                }
+            }, ControlCommand::HardwareInfo => {
+                let Some(info) = crate::internal::smbios::system_info() else {
+                    crate::internal::serial::write_control(format_args!("ERR No SMBIOS tables found\n"));
+                    return;
+                };
+                crate::internal::serial::write_control(format_args!(
+                    "system {} {}\nbios {} {} {}\n",
+                    info.manufacturer, info.product, info.bios_vendor, info.bios_version, info.bios_date
+                ));
+                for processor in info.processors.iter().filter(|processor| processor.populated) {
+                    crate::internal::serial::write_control(format_args!(
+                        "cpu {}: {} {} {} MHz {} cores\n", processor.socket, processor.manufacturer,
+                        processor.version, processor.max_speed_mhz, processor.cores
+                    ));
+                }
+                for device in info.memory_devices.iter().filter(|device| device.size_mib > 0) {
+                    crate::internal::serial::write_control(format_args!(
+                        "memory {}: {} MiB {} MT/s\n", device.locator, device.size_mib, device.speed_mts
+                    ));
+                }
+            }, ControlCommand::Firmware => {
+                let Some(info) = self.firmware_manager.info() else {
+                    crate::internal::serial::write_control(format_args!("ERR No EFI runtime services available\n"));
+                    return;
+                };
+                crate::internal::serial::write_control(format_args!(
+                    "vendor {} {:#X}\nuefi {}.{}\n", info.vendor, info.revision, info.uefi_version.0, info.uefi_version.1
+                ));
+                match self.firmware_manager.time() {
+                    Ok(time) => crate::internal::serial::write_control(format_args!("time {}\n", time)),
+                    Err(err) => crate::internal::serial::write_control(format_args!("time unavailable: {}\n", err))
+                }
+                match self.firmware_manager.boot_order() {
+                    Ok(order) => {
+                        let current = self.firmware_manager.boot_current().ok();
+                        for entry in order {
+                            let marker = if Some(entry) == current { " (current)" } else { "" };
+                            crate::internal::serial::write_control(format_args!("boot Boot{:04X}{}\n", entry, marker));
+                        }
+                    }, Err(err) => crate::internal::serial::write_control(format_args!("boot order unavailable: {}\n", err))
+                }
+            }, ControlCommand::Date(None) => {
+                match self.time_manager.with_clock(|clock| (clock.now(), clock.status())) {
+                    Ok((now, status)) => crate::internal::serial::write_control(format_args!(
+                        "date {}\nclock offset {} ns pending {} ns drift {} ppm\n",
+                        now, status.offset_nanos, status.pending_nanos, status.drift_ppm
+                    )),
+                    Err(err) => {
+                        crate::internal::serial::write_control(format_args!("ERR {}\n", err));
+                        return;
+                    }
+                }
+            }, ControlCommand::Date(Some(date_time)) => {
+                if let Err(err) = self.time_manager.set_time(date_time) {
+                    crate::internal::serial::write_control(format_args!("ERR {}\n", err));
+                    return;
+                }
+            }, ControlCommand::AdjustDate(correction) => {
+                if let Err(err) = self.time_manager.adjust_time(correction) {
+                    crate::internal::serial::write_control(format_args!("ERR {}\n", err));
+                    return;
+                }
+            }, ControlCommand::LogViewer(show) => {
+                let mode = if show { DisplayMode::LogViewer(Size::new(80, 25), Fonts::default()) }
+                    else { self.settings.display.mode() };
+                match self.set_display_mode(mode) {
+                    None => {
+                        crate::internal::serial::write_control(format_args!("ERR No display available\n"));
+                        return;
+                    }, Some(set) if set != mode => {
+                        crate::internal::serial::write_control(format_args!("ERR Display mode not supported\n"));
+                        return;
+                    }, Some(..) => {}
+                }
+            }, ControlCommand::AcpiDump(scope) => {
+                let Some(namespace) = self.acpi_namespace.as_ref() else {
+                    crate::internal::serial::write_control(format_args!("ERR No ACPI namespace available\n"));
+                    return;
+                };
+                match namespace.objects(scope.as_deref().unwrap_or("\\")) {
+                    Ok(objects) => for (path, kind) in objects {
+                        crate::internal::serial::write_control(format_args!("{} {:?}\n", path, kind));
+                    }, Err(err) => {
+                        crate::internal::serial::write_control(format_args!("ERR {}\n", err));
+                        return;
+                    }
+                }
+            }, ControlCommand::AcpiExec(path, arguments) => {
+                let Some(namespace) = self.acpi_namespace.as_ref() else {
+                    crate::internal::serial::write_control(format_args!("ERR No ACPI namespace available\n"));
+                    return;
+                };
+                let arguments = arguments.into_iter().map(|argument| match argument {
+                    AcpiArgument::Integer(value) => AmlValue::Integer(value),
+                    AcpiArgument::String(value) => AmlValue::String(value)
+                }).collect();
+                match namespace.evaluate(&path, arguments) {
+                    Ok(value) => crate::internal::serial::write_control(format_args!(
+                        "{}\n", crate::internal::acpi::DisplayValue(&value)
+                    )),
+                    Err(err) => {
+                        crate::internal::serial::write_control(format_args!("ERR {}\n", err));
+                        return;
+                    }
+                }
+            }, ControlCommand::Devices => {
+                for device in crate::internal::platform::devices() {
+                    crate::internal::serial::write_control(format_args!("{}\n", device));
+                }
+            }, ControlCommand::EmbeddedController => {
+                let mut bytes = [0u8; crate::internal::ec::SPACE_SIZE];
+                for (offset, byte) in bytes.iter_mut().enumerate() {
+                    match crate::internal::ec::read(offset as u8) {
+                        Ok(value) => *byte = value,
+                        Err(err) => {
+                            crate::internal::serial::write_control(format_args!("ERR {}\n", err));
+                            return;
+                        }
+                    }
+                }
+                for (row, chunk) in bytes.chunks(16).enumerate() {
+                    crate::internal::serial::write_control(format_args!("{:02X}:", row * 16));
+                    for byte in chunk {
+                        crate::internal::serial::write_control(format_args!(" {:02X}", byte));
+                    }
+                    crate::internal::serial::write_control(format_args!("\n"));
+                }
+            }, ControlCommand::SerialPorts => {
+                for device in crate::internal::serial::devices() {
+                    crate::internal::serial::write_control(format_args!("{}\n", device));
+                }
+            }, ControlCommand::TickRate(None) => {
+                let timer = if crate::internal::apic::timer_active() { "apic" }
+                    else if crate::internal::hpet::timer_active() { "hpet" }
+                    else if crate::drivers::timer::pit::mode() == PitMode::OneShot { "pit oneshot" }
+                    else { "pit periodic" };
+                crate::internal::serial::write_control(format_args!(
+                    "rate {} Hz\ntimer {}\n", crate::drivers::timer::tick_hz(), timer
+                ));
+            }, ControlCommand::TickRate(Some(hz)) => {
+                if let Err(err) = crate::drivers::timer::pit::set_frequency(hz) {
+                    crate::internal::serial::write_control(format_args!("ERR {}\n", err));
+                    return;
+                }
+                log::info!("Timer tick rate set to {} Hz.", hz);
+            }, ControlCommand::TimerMode(mode) => {
+                if let Err(err) = crate::drivers::timer::pit::set_mode(mode) {
+                    crate::internal::serial::write_control(format_args!("ERR {}\n", err));
+                    return;
+                }
+                log::info!("PIT switched to {:?} mode.", mode);
+            }, ControlCommand::Resolution(None) => {
+                let Ok(info) = crate::internal::framebuffer::with_framebuffer(|_, info| info) else {
+                    crate::internal::serial::write_control(format_args!("ERR {}\n", KernelError::NoFrameBuffer));
+                    return;
+                };
+                crate::internal::serial::write_control(format_args!(
+                    "resolution {}x{}x{}\n", info.width, info.height, info.bytes_per_pixel * 8
+                ));
+                if let Ok(max) = crate::drivers::vbe::max_mode() {
+                    crate::internal::serial::write_control(format_args!("max {}\n", max));
+                }
+            }, ControlCommand::Resolution(Some(mode)) => {
+                let Some(display_manager) = self.display_manager.as_mut() else {
+                    crate::internal::serial::write_control(format_args!("ERR {}\n", KernelError::NoFrameBuffer));
+                    return;
+                };
+                if let Err(err) = display_manager.set_resolution(mode) {
+                    crate::internal::serial::write_control(format_args!("ERR {}\n", err));
+                    return;
+                }
+                if let Some(display_mode) = self.display_mode {
+                    self.set_display_mode(display_mode);
+                }
+                log::info!("Resolution set to {}.", mode);
+            }, ControlCommand::Output(None) => {
+                let Some(display_manager) = self.display_manager.as_ref() else {
+                    crate::internal::serial::write_control(format_args!("ERR {}\n", KernelError::NoFrameBuffer));
+                    return;
+                };
+                for (index, size, mode) in display_manager.outputs() {
+                    crate::internal::serial::write_control(format_args!(
+                        "output {} {}x{} {}\n", index, size.width, size.height, mode.name()
+                    ));
+                }
+            }, ControlCommand::Output(Some((index, mode))) => {
+                // The primary screen takes the input focus along with its mode
+                if index == 0 {
+                    if self.set_display_mode(mode).is_none() {
+                        crate::internal::serial::write_control(format_args!("ERR {}\n", KernelError::NoFrameBuffer));
+                        return;
+                    }
+                } else {
+                    let Some(display_manager) = self.display_manager.as_mut() else {
+                        crate::internal::serial::write_control(format_args!("ERR {}\n", KernelError::NoFrameBuffer));
+                        return;
+                    };
+                    if let Err(err) = display_manager.set_output_mode(index, mode) {
+                        crate::internal::serial::write_control(format_args!("ERR {}\n", err));
+                        return;
+                    }
+                }
+                log::info!("Display output {} switched to {}.", index, mode.name());
+            }, ControlCommand::Version => {
+                let info = crate::build_info();
+                crate::internal::serial::write_control(format_args!(
+                    "version {}\ncommit {}\nbuilt {}\nrustc {}\nprofile {}\nfeatures {}\n",
+                    info.version, info.commit, info.timestamp, info.rustc, info.profile, info.features
+                ));
+            }
+        }
+
+        crate::internal::serial::write_control(format_args!("OK\n"));
+    }
+
+    fn on_memory_pressure(&mut self, pressure: MemoryPressure) {
+        let usage = crate::internal::heap::usage();
+        match pressure {
+            MemoryPressure::Normal => log::info!("Memory pressure relieved, {} of {} heap bytes used.", usage.used, usage.size),
+            MemoryPressure::Low => log::warn!("Memory is getting low, {} of {} heap bytes used.", usage.used, usage.size),
+            MemoryPressure::Critical => log::error!("Memory is critically low, {} of {} heap bytes used.", usage.used, usage.size)
+        }
+    }
+
+    fn on_thermal_trip(&mut self, trip: ThermalTrip) {
+        log::error!(
+            "Thermal zone {} reached {}, above its critical {}. Shutting down...",
+            trip.zone, trip.temperature, trip.critical
+        );
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    fn on_tick_rate_changed(&mut self, _hz: u64) {
+        if let Some(display_manager) = self.display_manager.as_mut() {
+            display_manager.on_tick_rate_changed();
+        }
+    }
+
     fn shutdown(&mut self) {
-        self.display_manager.clear_screen();
+        let uptime = self.time_manager.uptime();
+        log::info!("Kernel was up for {}.{:03} seconds.", uptime.seconds(), uptime.millis());
+        if let Ok(Some(drift_ppm)) = self.time_manager.tick_drift_ppm() {
+            log::info!("Timer drifted {} ppm against the real-time clock.", drift_ppm);
+        }
+        if let Ok((busy, idle, interrupts)) = self.time_manager.with_accounting(|accounting| (
+            accounting.busy_ticks(), accounting.idle_ticks(), accounting.interrupts()
+        )) {
+            log::info!("Ran for {} busy and {} idle ticks using {} timer events.", busy, idle, interrupts);
+        }
+        let stats = crate::api::event::EventDispatcher::global().stats();
+        log::info!(
+            "Event queue dropped {} and coalesced {} events, with at most {} queued.",
+            stats.dropped, stats.coalesced, stats.high_water_mark
+        );
+        for vector in 0..=u8::MAX {
+            let stats = crate::internal::interrupts::stats(vector);
+            if stats.count > 0 {
+                log::info!("Vector {:#04X} fired {} times and stormed {} times.", vector, stats.count, stats.storms);
+            }
+        }
+        log::info!(
+            "Received {} spurious interrupts, interrupt handlers were nested at most {} deep.",
+            crate::internal::interrupts::spurious_count(), crate::internal::interrupts::max_depth()
+        );
+        let workers = crate::systems::worker::stats();
+        log::info!(
+            "Worker queue completed {} jobs with at most {} queued, {} left.",
+            workers.completed, workers.high_water_mark, workers.queued
+        );
+        let dropped = crate::internal::log_buffer::dropped();
+        if dropped > 0 {
+            log::info!("Log buffer dropped {} records while being read.", dropped);
+        }
+        if let Some(stats) = self.display_manager.as_ref().map(|display_manager| display_manager.frame_stats()) {
+            log::info!(
+                "Display drew {} frames at {} FPS and skipped {} unchanged ones.",
+                stats.frames, stats.fps, stats.skipped
+            );
+        }
+        if let Some(Err(err)) = self.display_manager.as_mut().map(|display_manager| display_manager.clear_screen()) {
+            log::warn!("Failed to clear screen on shutdown: {}", err);
+        }
+    }
+}
+
+impl Kernel {
+    /// Switches the display to the mode and gives the input focus to its driver if it takes input.
+    /// Returns the mode that was actually set, None without a display.
+    fn set_display_mode(&mut self, mode: DisplayMode) -> Option<DisplayMode> {
+        let display_manager = self.display_manager.as_mut()?;
+        if let Some(focus) = self.display_focus.take() {
+            self.input_manager.unfocus(focus);
+        }
+
+        let set = display_manager.set_mode_or_fallback(mode);
+        #[cfg(feature = "gui")]
+        if let Some(driver) = display_manager.get_driver::<LogViewerDisplayDriver>() {
+            self.display_focus = Some(self.input_manager.focus(driver.controls()));
+        } else if let Some(driver) = display_manager.get_driver::<SettingsDisplayDriver>() {
+            self.display_focus = Some(self.input_manager.focus(driver.controls()));
+        }
+        self.display_mode = Some(set);
+        Some(set)
+    }
+
+    /// Starts what the settings say to boot into.
+    fn start_target(&mut self) {
+        let mode = match self.settings.target {
+            BootTarget::LogViewer => DisplayMode::LogViewer(Size::new(80, 25), Fonts::default()),
+            BootTarget::Status | BootTarget::Benchmark => self.settings.display.mode()
+        };
+        self.set_display_mode(mode);
+        if self.settings.target == BootTarget::Benchmark {
+            crate::api::event::EventDispatcher::global().push(Event::Control(ControlCommand::Benchmark));
+        }
+    }
+
+    /// Persists and applies the settings if they were saved, then continues to the boot target.
+    #[cfg(feature = "gui")]
+    fn on_settings_left(&mut self, result: SettingsResult) {
+        if let SettingsResult::Save(settings) = result {
+            settings.save();
+            self.settings = settings;
+            crate::internal::log_filter::set_default_level(settings.log_level);
+            log::info!("Settings saved, booting into {}.", settings.target.name());
+        }
+        self.start_target();
     }
 }
\ No newline at end of file
@@ -3,175 +3,583 @@
 #![feature(const_mut_refs)]
 #![feature(abi_x86_interrupt)]
 #![feature(allocator_api)]
+#![feature(alloc_error_handler)]
 #![no_std]
 #![no_main]
+// Leaving out subsystems leaves parts of the shared code they use unused
+#![cfg_attr(not(all(feature = "gui", feature = "tests")), allow(dead_code))]
 
 extern crate alloc;
 
 use alloc::string::String;
 use alloc::sync::Arc;
+use core::alloc::Layout;
 use core::panic::PanicInfo;
 use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use bootloader_api::{BootInfo, BootloaderConfig};
-use bootloader_api::config::Mapping;
 use spin::Mutex;
 use x86_64::VirtAddr;
+use crate::api::control::ControlCommand;
+use crate::api::error::KernelError;
 use crate::api::event::{ErrorEvent, Event, EventHandler};
-use crate::drivers::display::DisplayDriverType;
+use crate::api::thermal::ThermalTrip;
+use crate::api::input::FocusId;
+use crate::internal::acpi::AcpiNamespace;
+use crate::internal::cmos::BootStatus;
+use crate::internal::heap::MemoryPressure;
 use crate::internal::pic::{PicInterrupts, PicMask};
+use crate::internal::protocol::BootInformation;
 use crate::managers::display::{DisplayManager, DisplayMode, DisplayType};
-use crate::managers::time::TimeManager;
+use crate::managers::input::InputManager;
+use crate::managers::keyboard::KeyboardManager;
+use crate::managers::module::ModuleManager;
+use crate::managers::power::PowerManager;
+use crate::managers::firmware::FirmwareManager;
+use crate::managers::shutdown::ShutdownManager;
+use crate::managers::thermal::ThermalManager;
+use crate::managers::time::{CLOCK_RTC_RATE, TimeManager};
+use crate::systems::control::ControlChannel;
+use crate::systems::settings::BootSettings;
+
+pub use crate::internal::build_info::build_info;
 
 mod internal;
 mod kernel;
+mod boot;
 
 mod api;
 mod systems;
 mod drivers;
 mod managers;
 
-const BOOTLOADER_CONFIG: BootloaderConfig = {
-    let mut config = BootloaderConfig::new_default();
-    config.mappings.physical_memory = Some(Mapping::Dynamic);
-    config.kernel_stack_size = 1024 * 1024;
-    config
-};
-bootloader_api::entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
+/// Entry point shared by all boot protocols, which call it once they collected the boot information.
+fn kernel_main(mut boot_info: BootInformation) -> ! {
+    boot::begin();
+    if boot_info.has_flag("setup") {
+        boot::request_setup();
+    }
+
+    // Initialize serial logger and control channel
+    boot::stage("Serial", || {
+        internal::serial::init(&internal::serial::SerialOptions::new(|name| boot_info.option(name)))
+            .unwrap_or_else(|err| panic!("Failed to initialize serial logger: {:#?}", err));
+        log::info!("Serial logger initialized. Booting AkjoOS via {}...", boot_info.protocol);
+        log::info!("{}.", build_info());
+        if boot_info.has_flag("logblock") {
+            internal::serial::set_overflow_policy(internal::serial::OverflowPolicy::Block);
+            log::info!("Logging waits for the serial port instead of dropping messages.");
+        }
+
+        for device in internal::serial::devices() {
+            log::debug!("Serial port {}.", device);
+        }
+        match internal::serial::init_control() {
+            Ok(Some(name)) => log::info!("Control channel initialized on {}.", name),
+            Ok(None) => log::info!("No serial port is assigned to the control channel."),
+            Err(err) => log::warn!("Failed to initialize control channel: {}", err)
+        }
+    });
 
-fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
-    // Initialize serial logger
-    internal::serial::init()
-        .unwrap_or_else(|err| panic!("Failed to initialize serial logger: {:#?}", err));
-    log::info!("Serial logger initialized. Booting AkjoOS...");
+    // Calibrate time stamp counter, done first so the other stages can be timed in real time
+    boot::stage("TSC", || {
+        let tsc_khz = internal::idt::without_interrupts(internal::tsc::calibrate);
+        log::info!("Time stamp counter calibrated at {} kHz.", tsc_khz);
+        internal::trace::init();
+    });
 
     // Initialize memory mapper
-    let physical_memory_offset = VirtAddr::new(*boot_info.physical_memory_offset.as_ref()
-        .unwrap_or_else(|| panic!("Physical memory offset not found!")));
-    let mut mapper = unsafe { internal::memory::init(physical_memory_offset) };
-    let usable_region_count = &internal::memory::get_usable_regions(&boot_info.memory_regions, 0).count();
-    log::info!(
-        "Memory mapper initialized at physical memory offset {:#X}.",
-        physical_memory_offset
-    );
-    log::info!(
-        "Detected {} of usable memory regions / frames at 4KiB in size.",
-        &usable_region_count
-    );
-
-    // Initialize simple heap allocator
-    let mut simple_heap_allocator = unsafe {
-        internal::heap::SimpleHeapFrameAllocator::new(&boot_info.memory_regions, 0)
-    };
-    let next = internal::heap::init_initial_heap(&mut mapper, &mut simple_heap_allocator)
-        .unwrap_or_else(|err| panic!("Failed to initialize initial heap: {:#?}", err));
-    log::info!(
-        "Initial heap initialized with {} bytes. Next frame at {}/{}.",
-        internal::heap::INITIAL_HEAP_SIZE, next, &usable_region_count
-    );
-
-    // Initialize main heap allocator
-    let mut frame_allocator = unsafe {
-        internal::heap::HeapFrameAllocator::new(&boot_info.memory_regions, next)
-    };
-    let next = internal::heap::init_main_heap(&mut mapper, &mut frame_allocator)
-        .unwrap_or_else(|err| panic!("Failed to initialize main heap: {:#?}", err));
-    log::info!(
-        "Main heap initialized with {} bytes. Next frame at {}/{}.",
-        internal::heap::MAIN_HEAP_SIZE, next, &usable_region_count
-    );
-
-    // Switch to main heap
-    internal::heap::init_allocator();
-    log::info!("Global allocator switched to main heap.");
+    // Log what the bootloader left in the model specific registers and enable no-execute pages
+    boot::stage("MSR", || {
+        internal::msr::log_values();
+        match internal::msr::enable_efer(internal::msr::EferFlags::NO_EXECUTE_ENABLE) {
+            Ok(()) => log::info!("No-execute pages enabled."),
+            Err(err) => log::warn!("Running without no-execute pages: {}", err)
+        }
+        match internal::msr::init_pat() {
+            Ok(()) => log::info!("Page attribute table programmed with write-combining."),
+            Err(err) => log::warn!("Running without write-combining: {}", err)
+        }
+    });
+
+    let (physical_memory_offset, mut mapper, usable_region_count) = boot::stage("Memory", || {
+        let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset
+            .unwrap_or_else(|| panic!("Physical memory offset not found!")));
+        let reserved_region_count = internal::reserved::init(boot_info.memory_regions);
+        let mapper = unsafe { internal::memory::init(physical_memory_offset) };
+        let usable_region_count = internal::memory::get_usable_regions(boot_info.memory_regions, 0).count();
+        log::info!(
+            "Memory mapper initialized at physical memory offset {:#X}.",
+            physical_memory_offset
+        );
+        log::info!(
+            "Detected {} of usable memory regions / frames at 4KiB in size.",
+            &usable_region_count
+        );
+        log::info!("Registered {} reserved memory regions.", reserved_region_count);
+        for module in boot::modules() {
+            log::info!("Bootloader loaded module '{}' with {} bytes at {:#X}.", module.name, module.size, module.address);
+        }
+        (physical_memory_offset, mapper, usable_region_count)
+    });
+
+    // Load the kernel's symbol table, used to name the functions in error reports
+    boot::stage("Symbols", || {
+        match boot_info.kernel_file.map(internal::symbols::init) {
+            Some(Ok(count)) => log::info!("Loaded {} kernel symbols.", count),
+            Some(Err(err)) => log::warn!("Failed to load kernel symbols: {}", err),
+            None => log::info!("Kernel file not provided by the bootloader, no kernel symbols available.")
+        }
+    });
+
+    // Pick where the heap and the other kernel regions go, before anything gets mapped there
+    boot::stage("KASLR", || {
+        let enabled = !boot_info.has_flag("nokaslr");
+        let layout = internal::kaslr::init(mapper.level_4_table(), enabled);
+        if layout.randomized {
+            log::info!("Randomized address layout: {}.", layout);
+        } else {
+            log::info!("Using fixed address layout: {}.", layout);
+        }
+    });
+
+    let mut frame_allocator = boot::stage("Heap", || {
+        // Initialize simple heap allocator
+        let mut simple_heap_allocator = unsafe {
+            internal::heap::SimpleHeapFrameAllocator::new(boot_info.memory_regions, 0)
+        };
+        let next = internal::heap::init_initial_heap(&mut mapper, &mut simple_heap_allocator)
+            .unwrap_or_else(|err| panic!("Failed to initialize initial heap: {}", err));
+        log::info!(
+            "Initial heap initialized with {} bytes. Next frame at {}/{}.",
+            internal::heap::INITIAL_HEAP_SIZE, next, &usable_region_count
+        );
+
+        // Initialize main heap allocator
+        let mut frame_allocator = unsafe {
+            internal::heap::HeapFrameAllocator::new(boot_info.memory_regions, next)
+        };
+        let next = internal::heap::init_main_heap(&mut mapper, &mut frame_allocator)
+            .unwrap_or_else(|err| panic!("Failed to initialize main heap: {}", err));
+        log::info!(
+            "Main heap initialized with {} bytes. Next frame at {}/{}.",
+            internal::heap::MAIN_HEAP_SIZE, next, &usable_region_count
+        );
+
+        // Switch to main heap
+        internal::heap::init_allocator();
+        internal::early_log::finish();
+        log::info!("Global allocator switched to main heap.");
+        frame_allocator
+    });
+
+    // Collects the teardown of the following stages, to run it in reverse order on shutdown
+    let mut shutdown_manager = ShutdownManager::new();
 
     // Load GDT table
-    internal::gdt::load();
-    log::info!("Global descriptor table loaded.");
-
-    // Load ACPI tables and platform information
-    let acpi = internal::acpi::load(boot_info.rsdp_addr.into_option(), physical_memory_offset);
-    log::info!("ACPI tables loaded.");
-
-    // Load platform info
-    let platform_info = acpi.platform_info()
-        .unwrap_or_else(|err| panic!("Platform info not found: {:#?}", err));
-    let processor_info = platform_info.processor_info()
-        .unwrap_or_else(|| panic!("Processor info not found!"));
-    log::info!(
-        "Platform info loaded with system type '{:?}' and {} processors.",
-        platform_info.platform_type(), processor_info.application_processors.iter().count() + 1
-    );
-
-    // Load FADT table
-    let fadt = acpi.fadt()
-        .unwrap_or_else(|err| panic!("FADT table not found: {:#?}", err));
-    log::info!("FADT table loaded.");
+    boot::stage("GDT", || {
+        internal::gdt::load();
+        log::info!("Global descriptor table loaded.");
+    });
+
+    // Load ACPI tables, platform information and the FADT table
+    let (acpi, century) = boot::stage("ACPI", || {
+        let acpi = internal::acpi::load(boot_info.rsdp_address, physical_memory_offset);
+        log::info!("ACPI tables loaded.");
+
+        {
+            let platform_info = acpi.platform_info()
+                .unwrap_or_else(|err| panic!("Platform info not found: {:#?}", err));
+            let processor_info = platform_info.processor_info()
+                .unwrap_or_else(|| panic!("Processor info not found!"));
+            log::info!(
+                "Platform info loaded with system type '{:?}' and {} processors.",
+                platform_info.platform_type(), processor_info.application_processors.iter().count() + 1
+            );
+        }
+
+        let century = acpi.fadt()
+            .unwrap_or_else(|err| panic!("FADT table not found: {:#?}", err))
+            .century;
+        log::info!("FADT table loaded.");
+        (acpi, century)
+    });
+
+    // Collect the devices the firmware describes, drivers bind to those instead of probing
+    boot::stage("Devices", || {
+        match internal::platform::init(acpi.namespace().as_ref()) {
+            Some(count) => log::info!("Firmware describes {} platform devices.", count),
+            None => log::warn!("No ACPI namespace describes the platform devices, assuming the legacy ones.")
+        }
+        for device in internal::platform::devices() {
+            log::debug!("Platform device {}.", device);
+        }
+        match internal::ec::init(acpi.namespace().as_ref()) {
+            Ok(true) => log::info!("Embedded controller bound."),
+            Ok(false) => log::debug!("Firmware describes no embedded controller."),
+            Err(err) => log::warn!("Could not bind the embedded controller: {}", err)
+        }
+    });
+
+    // Read the hardware inventory from the SMBIOS tables
+    boot::stage("SMBIOS", || {
+        match internal::smbios::init(physical_memory_offset, boot_info.smbios_address) {
+            Some(info) => log::info!(
+                "SMBIOS {}.{} tables describe a {} {} with {} processor sockets and {} memory slots.",
+                info.version.0, info.version.1, info.manufacturer, info.product,
+                info.processors.len(), info.memory_devices.len()
+            ),
+            None => log::warn!("No SMBIOS tables found.")
+        }
+    });
+
+    // Map the UEFI runtime services, so variables and the firmware clock stay usable after boot
+    let firmware_info = boot::stage("EFI", || {
+        match internal::efi::init(&mut mapper, &mut frame_allocator, physical_memory_offset, boot_info.efi) {
+            Ok(info) => {
+                log::info!(
+                    "EFI runtime services of {} (revision {:#X}, UEFI {}.{}) mapped with {} pages.",
+                    info.vendor, info.revision, info.uefi_version.0, info.uefi_version.1, info.runtime_pages
+                );
+                Some(info)
+            }, Err(err) => {
+                log::info!("EFI runtime services not available: {}", err);
+                None
+            }
+        }
+    });
 
     // Initialize PIC8259
-    let mut pic_mask = PicMask::new();
-    pic_mask.enable(PicInterrupts::Timer);
-    pic_mask.enable(PicInterrupts::PassThrough);
-    pic_mask.enable(PicInterrupts::RTC);
-    internal::pic::init(pic_mask);
-    log::info!("Programmable interrupt controller initialized.");
-
-    // Initialize CMOS and enable interrupts
-    internal::cmos::init(fadt.century);
-    internal::cmos::Cmos::global()
-        .unwrap_or_else(|| panic!("CMOS not found!"))
-        .lock().enable_interrupts();
-    log::info!("CMOS initialized and CMOS interrupts enabled.");
+    boot::stage("PIC", || {
+        let mut pic_mask = PicMask::new();
+        pic_mask.enable(PicInterrupts::Timer);
+        pic_mask.enable(PicInterrupts::Keyboard);
+        pic_mask.enable(PicInterrupts::PassThrough);
+        pic_mask.enable(PicInterrupts::RTC);
+        pic_mask.enable(PicInterrupts::COM1);
+        pic_mask.enable(PicInterrupts::COM2);
+        internal::pic::init(pic_mask)
+            .unwrap_or_else(|err| panic!("Failed to initialize PIC: {}", err));
+        drivers::timer::pit::init();
+        log::info!("Programmable interrupt controller initialized.");
+
+        shutdown_manager.register("PIC", || {
+            // Masking everything keeps devices from interrupting the rest of the shutdown
+            PicMask::new().apply();
+            Ok(())
+        });
+    });
+
+    boot::stage("CMOS", || {
+        // Initialize CMOS and enable interrupts
+        internal::cmos::init(century)
+            .unwrap_or_else(|err| panic!("Failed to initialize CMOS: {}", err));
+        internal::cmos::Cmos::global()
+            .unwrap_or_else(|| panic!("CMOS not found!"))
+            .lock().enable_interrupts();
+        log::info!("CMOS initialized and CMOS interrupts enabled.");
+        // The CMOS is the last of the legacy devices to claim its I/O ports
+        internal::ioport::claims(&mut |start, length, owner| log::debug!(
+            "I/O ports {:#X}-{:#X} claimed by {}.", start, start + (length - 1), owner
+        ));
+
+        // Read hardware configuration and persisted settings from CMOS
+        let (cmos_equipment, cmos_floppy_drives) = internal::cmos::Cmos::global()
+            .map(|cmos| { let mut cmos = cmos.lock(); (cmos.equipment(), cmos.floppy_drives()) })
+            .unwrap_or_else(|| panic!("CMOS not found!"));
+        log::info!("CMOS reports equipment {:?} and floppy drives {:?}.", cmos_equipment, cmos_floppy_drives);
+        match internal::cmos::record_boot() {
+            Some(settings) if settings.boot_status != BootStatus::ShutDown => log::warn!(
+                "Previous boot did not shut down cleanly (status {:?}). This is boot number {}.",
+                settings.boot_status, settings.boot_count.wrapping_add(1)
+            ), Some(settings) => log::info!(
+                "Previous boot shut down cleanly. This is boot number {}.", settings.boot_count.wrapping_add(1)
+            ), None => log::info!("No valid settings found in CMOS, starting with defaults.")
+        }
+
+        shutdown_manager.register("CMOS", || {
+            let cmos = internal::cmos::Cmos::global().ok_or(KernelError::HardwareMissing("CMOS"))?;
+            cmos.lock().disable_interrupts();
+            internal::cmos::set_boot_status(BootStatus::ShutDown);
+            Ok(())
+        });
+    });
 
     // Load IDT table
-    internal::idt::load();
-    log::info!("Interrupt descriptor table loaded and interrupts enabled.");
+    boot::stage("IDT", || {
+        internal::idt::load();
+        log::info!(
+            "Interrupt descriptor table loaded and interrupts enabled, {} vectors free for drivers.",
+            internal::interrupts::free_vectors()
+        );
 
-    // Initialize frame buffer
-    if let Some(frame_buffer) = boot_info.framebuffer.as_mut() {
-        let info = frame_buffer.info().clone();
-        let buffer = frame_buffer.buffer_mut();
+        internal::serial::enable_interrupts();
+        log::info!("Serial logger switched to buffered, interrupt-driven transmit.");
+    });
+
+    // Switch the timer to the local APIC where possible, the PIT is then only used for calibration
+    boot::stage("Timer", || {
+        if internal::apic::init_timer() {
+            log::info!("Timer driven by the local APIC in TSC-deadline mode.");
+
+            shutdown_manager.register("APIC timer", || {
+                internal::apic::stop_timer();
+                Ok(())
+            });
+        } else if internal::apic::enable() {
+            log::info!("TSC-deadline mode not supported, local APIC enabled without its timer.");
+        } else {
+            log::info!("Local APIC not supported, timer driven by the PIT.");
+        }
+    });
 
-        internal::framebuffer::init(info, buffer);
+    // Find the idle and performance states, after the timer as it limits how deep the CPU may sleep
+    boot::stage("CPU power", || {
+        let idle_states = internal::cpuidle::init();
+        let performance_states = internal::cpufreq::init(acpi.namespace().as_ref());
         log::info!(
-            "Frame buffer initialized with resolution {}x{} and {}bpp.",
-            info.width, info.height, info.bytes_per_pixel * 8
-        )
-    }
+            "Found {} idle and {} performance states, deepest idle state is {}.",
+            idle_states, performance_states, internal::cpuidle::states().last().map_or(0, |state| state.number)
+        );
+    });
+
+    // With the local APIC enabled, deliver the legacy interrupts through the IOAPICs
+    boot::stage("IOAPIC", || {
+        if !internal::apic::enabled() {
+            log::info!("Local APIC not enabled, legacy interrupts stay with the PIC.");
+            return;
+        }
+        match internal::ioapic::init(&acpi, physical_memory_offset) {
+            Ok(count) => log::info!("Found {} IOAPICs.", count),
+            Err(err) => {
+                log::warn!("Legacy interrupts stay with the PIC: {}", err);
+                return;
+            }
+        }
+
+        // Without the APIC timer the PIT still ticks, until an HPET comparator takes over and masks it
+        let timer = (!internal::apic::timer_active()).then_some(PicInterrupts::Timer);
+        let irqs = [PicInterrupts::Keyboard, PicInterrupts::RTC, PicInterrupts::COM1, PicInterrupts::COM2];
+        for irq in timer.into_iter().chain(irqs) {
+            match internal::ioapic::route_irq(irq) {
+                Ok(route) => log::info!(
+                    "Routed {:?} to global system interrupt {} ({}, {}).", irq, route.gsi,
+                    if route.level_triggered { "level" } else { "edge" },
+                    if route.active_low { "active low" } else { "active high" }
+                ), Err(err) => log::warn!("Failed to route {:?} through the IOAPIC, it stays with the PIC: {}", irq, err)
+            }
+        }
+
+        shutdown_manager.register("IOAPIC", || {
+            internal::ioapic::mask_all();
+            Ok(())
+        });
+    });
+
+    // Without TSC-deadline mode, take the one-shot deadlines from an HPET comparator over the PIT
+    boot::stage("HPET", || {
+        if internal::apic::timer_active() || !internal::apic::enabled() { return; }
+        match internal::hpet::init_timer(&acpi, physical_memory_offset) {
+            Ok(timer) => {
+                log::info!(
+                    "Timer driven by HPET comparator {} at {} kHz on global system interrupt {}{}.",
+                    timer.comparator, timer.khz, timer.gsi, if timer.wide { "" } else { ", 32-bit counter" }
+                );
+                shutdown_manager.register("HPET timer", || {
+                    internal::hpet::stop_timer();
+                    Ok(())
+                });
+            }, Err(err) => log::info!("Timer driven by the PIT, HPET not usable: {}", err)
+        }
+    });
+
+    // Initialize frame buffer
+    boot::stage("Frame buffer", || {
+        if let Some((info, buffer)) = boot_info.framebuffers[0].take() {
+            let before = internal::framebuffer::measure_clear(buffer);
+            // With the Bochs VBE interface all of the video memory is mapped, for higher resolutions
+            let capacity = match drivers::vbe::init() {
+                Ok(_) => drivers::vbe::video_memory().unwrap_or(0),
+                Err(err) => {
+                    log::warn!("Failed to probe for the Bochs VBE display interface: {}", err);
+                    0
+                }
+            };
+            let length = buffer.len();
+            let (buffer, remapped) = internal::framebuffer::remap(&mut mapper, &mut frame_allocator, buffer, capacity);
+            match remapped {
+                Ok(stats) => {
+                    log::info!("Frame buffer remapped with {} 2MiB and {} 4KiB pages.", stats.huge_pages, stats.pages);
+                    let after = internal::framebuffer::measure_clear(&mut buffer[..length]);
+                    log::info!(
+                        "Clearing the frame buffer took {} with the bootloader's mapping and {} remapped.",
+                        boot::Duration(before), boot::Duration(after)
+                    );
+                }, Err(err) => log::warn!("Failed to remap frame buffer, keeping the bootloader's mapping: {}", err)
+            }
+            internal::framebuffer::init(info, buffer);
+            log::info!(
+                "Frame buffer initialized with resolution {}x{} and {}bpp.",
+                info.width, info.height, info.bytes_per_pixel * 8
+            )
+        }
+        // The other screens keep the bootloader's mapping, only one frame buffer fits the remap area
+        for (output, framebuffer) in boot_info.framebuffers.iter_mut().enumerate().skip(1) {
+            if let Some((info, buffer)) = framebuffer.take() {
+                internal::framebuffer::init_output(output, info, buffer);
+                log::info!(
+                    "Frame buffer of output {} initialized with resolution {}x{} and {}bpp.",
+                    output, info.width, info.height, info.bytes_per_pixel * 8
+                )
+            }
+        }
+    });
 
     // Initialize time manager
-    let time_manager = TimeManager::new();
-    log::info!("Time manager initialized.");
+    let time_manager = boot::stage("Time", || {
+        let time_manager = TimeManager::new();
+        log::info!("Time manager initialized.");
+        match time_manager.set_rtc_rate(CLOCK_RTC_RATE) {
+            Ok(()) => log::info!("Real-time clock rate set to {} Hz.", CLOCK_RTC_RATE.frequency()),
+            Err(err) => log::warn!("Clock will only follow timer ticks: {}", err)
+        }
+        time_manager
+    });
+
+    // Initialize input manager
+    let input_manager = boot::stage("Input", || {
+        let input_manager = InputManager::new();
+        log::info!("Input manager initialized.");
+        input_manager
+    });
+
+    // Initialize keyboard manager
+    let keyboard_manager = boot::stage("Keyboard", || {
+        let keyboard_manager = KeyboardManager::new();
+        log::info!("Keyboard manager initialized.");
+        keyboard_manager
+    });
+
+    // Initialize module manager
+    let module_manager = boot::stage("Modules", || {
+        let module_manager = ModuleManager::new(boot_info.modules);
+        if module_manager.has_initrd() {
+            log::info!("Module manager initialized with {} modules in the initial ramdisk.", module_manager.available().len());
+        } else {
+            log::info!("Module manager initialized without an initial ramdisk.");
+        }
+        module_manager
+    });
+
+    // Initialize thermal manager
+    let thermal_manager = boot::stage("Thermal", || {
+        let thermal_manager = ThermalManager::new(acpi.namespace());
+        log::info!("Thermal manager initialized with {} thermal zones.", thermal_manager.zones().len());
+        thermal_manager
+    });
+
+    // Initialize power manager
+    let power_manager = boot::stage("Power", || {
+        let power_manager = PowerManager::new(acpi.namespace());
+        log::info!(
+            "Power manager initialized with {} batteries and {} AC adapters.",
+            power_manager.battery_count(), power_manager.adapter_count()
+        );
+        power_manager
+    });
+
+    // Initialize firmware manager
+    let firmware_manager = boot::stage("Firmware", || {
+        let firmware_manager = FirmwareManager::new(firmware_info);
+        match firmware_manager.boot_current() {
+            Ok(entry) => log::info!("Firmware manager initialized, booted from entry Boot{:04X}.", entry),
+            Err(err) => log::info!("Firmware manager initialized without boot entry: {}", err)
+        }
+        firmware_manager
+    });
 
     // Initialize display manager
-    let mut display_manager = DisplayManager::new(DisplayType::Buffered);
-    display_manager.set_mode(DisplayMode::Dummy);
-    display_manager.clear_screen();
-    log::info!("Display manager initialized.");
+    let display_manager = boot::stage("Display", || {
+        match systems::display::DisplayTransform::parse(boot_info.option("rotate"), boot_info.option("scale")) {
+            Ok(transform) if !transform.is_identity() => {
+                systems::display::set_transform(transform);
+                log::info!("Display {}.", transform);
+            }, Ok(_) => {},
+            Err(err) => log::warn!("Ignoring display transform: {}", err)
+        }
+        match DisplayManager::new(DisplayType::Buffered) {
+            Ok(mut display_manager) => {
+                display_manager.set_mode_or_fallback(DisplayMode::Dummy);
+                if let Err(err) = display_manager.clear_screen() {
+                    log::warn!("Failed to clear screen: {}", err);
+                }
+                log::info!("Display manager initialized.");
+                Some(display_manager)
+            }, Err(err) => {
+                log::warn!("Running without a display: {}", err);
+                None
+            }
+        }
+    });
 
-    // Initialize kernel
-    let kernel = Arc::new(Mutex::new(Kernel::new(
-        time_manager,
-        display_manager
-    )));
-    kernel.lock().init();
-    api::event::EventDispatcher::global().register(kernel.clone());
-    log::info!("Kernel initialized and registered as event handler.");
+    // Initialize kernel and register it and the control channel as event handlers
+    let (kernel, _control_channel) = boot::stage("Kernel", || {
+        let kernel = Arc::new(Mutex::new(Kernel::new(
+            time_manager,
+            input_manager,
+            keyboard_manager,
+            module_manager,
+            thermal_manager,
+            power_manager,
+            acpi.namespace(),
+            firmware_manager,
+            display_manager
+        )));
+        kernel.lock().init()
+            .unwrap_or_else(|err| panic!("Failed to initialize kernel: {}", err));
+        let kernel_handler = api::event::EventDispatcher::global().register(kernel.clone());
+        log::info!("Kernel initialized and registered as event handler.");
+
+        let control_channel = Arc::new(Mutex::new(ControlChannel::new()));
+        api::event::EventDispatcher::global().register(control_channel.clone());
+        log::info!("Control channel registered as event handler.");
+
+        let shutdown_kernel = kernel.clone();
+        shutdown_manager.register("Kernel", move || {
+            api::event::EventDispatcher::global().unregister(kernel_handler);
+            shutdown_kernel.lock().shutdown();
+            Ok(())
+        });
+        (kernel, control_channel)
+    });
+    boot::finish();
+
+    if boot_info.has_flag("bench") {
+        api::event::EventDispatcher::global().push(Event::Control(ControlCommand::Benchmark));
+    }
 
     // Main kernel loop
+    internal::cmos::set_boot_status(BootStatus::Running);
     log::info!("Kernel booted successfully. Entering main loop...");
     while kernel.lock().running.load(Ordering::SeqCst) {
         api::event::EventDispatcher::global().dispatch();
+        internal::heap::check_pressure();
+
+        // Background jobs run between events, the CPU only goes to sleep once they are done
+        if systems::worker::run_pending() > 0 { continue; }
+
+        let deadline = kernel.lock().next_deadline();
+        api::event::EventDispatcher::global().wait(deadline);
     }
 
     log::info!("Kernel needs to stop running. Shutting down...");
 
-    // Disable interrupts
+    // Disable interrupts, the logger sends right away again without them
     internal::idt::disable_interrupts();
+    internal::serial::disable_interrupts();
     log::info!("Interrupts disabled.");
 
-    // Shutdown kernel
-    kernel.lock().shutdown();
-    log::info!("Kernel shut down.");
+    // Tear down the subsystems, last initialized first
+    let failed = shutdown_manager.run();
+    log::info!("Kernel shut down with {} failed shutdown hooks.", failed);
 
     // Initiate shutdown
     acpi.shutdown().unwrap_or_else(|err| panic!("Failed to initiate shutdown: {:#?}", err));
@@ -185,30 +593,72 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
 pub struct Kernel {
     /// Used to manage the time and clock of the kernel.
     time_manager: TimeManager,
+    /// Used to route input to whoever has the focus.
+    input_manager: InputManager,
+    /// Used to manage the keyboard, its LEDs and typematic settings.
+    keyboard_manager: KeyboardManager,
+    /// Used to load kernel modules from the initial ramdisk.
+    module_manager: ModuleManager,
+    /// Used to watch the temperatures of the ACPI thermal zones, shared with the background poll.
+    thermal_manager: Arc<Mutex<ThermalManager>>,
+    /// Used to read the state of the batteries and AC adapters, shared with the background poll.
+    power_manager: Arc<Mutex<PowerManager>>,
+    /// Used to browse the ACPI namespace and evaluate objects in it, None without a DSDT.
+    acpi_namespace: Option<AcpiNamespace>,
+    /// Used to read and write EFI variables and the firmware clock.
+    firmware_manager: FirmwareManager,
     /// Used to manage the display and screen of the kernel.
-    display_manager: DisplayManager,
+    display_manager: Option<DisplayManager>,
+    /// The input focus of the current display driver, if it takes input.
+    display_focus: Option<FocusId>,
+    /// The mode the display was last set to, None without a display.
+    display_mode: Option<DisplayMode>,
+    /// The settings chosen on the settings screen, applied on boot.
+    settings: BootSettings,
     /// The current tick of the kernel (incremented every timer event).
     pub tick: AtomicU64,
     /// Whether the kernel is/should be running or not.
     pub running: AtomicBool
 } impl Kernel {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         time_manager: TimeManager,
-        display_manager: DisplayManager
+        input_manager: InputManager,
+        keyboard_manager: KeyboardManager,
+        module_manager: ModuleManager,
+        thermal_manager: ThermalManager,
+        power_manager: PowerManager,
+        acpi_namespace: Option<AcpiNamespace>,
+        firmware_manager: FirmwareManager,
+        display_manager: Option<DisplayManager>
     ) -> Self { Self {
         time_manager,
+        input_manager,
+        keyboard_manager,
+        module_manager,
+        thermal_manager: Arc::new(Mutex::new(thermal_manager)),
+        power_manager: Arc::new(Mutex::new(power_manager)),
+        acpi_namespace,
+        firmware_manager,
         display_manager,
+        display_focus: None,
+        display_mode: None,
+        settings: BootSettings::default(),
         tick: AtomicU64::new(0),
         running: AtomicBool::new(true)
     } }
 } impl EventHandler for Kernel {
     fn handle(&mut self, event: Event) {
         match event {
-            Event::Timer => {
-                self.tick.fetch_add(1, Ordering::SeqCst);
-                self.tick();
+            Event::Timer(tick) => {
+                self.tick.fetch_add(tick.ticks, Ordering::SeqCst);
+                self.tick(tick.ticks);
             },
             Event::Error(event) => self.on_error(event),
+            Event::Control(command) => self.on_control(command),
+            Event::MemoryPressure(pressure) => self.on_memory_pressure(pressure),
+            Event::ThermalTrip(trip) => self.on_thermal_trip(trip),
+            Event::TickRateChanged(hz) => self.on_tick_rate_changed(hz),
             _ => {}
         }
     }
@@ -216,49 +666,43 @@ pub struct Kernel {
 
 pub trait KernelRuntime {
     /// Gets called when the kernel is initialized.
-    fn init(&mut self);
-    /// Gets called on every timer event for the kernel.
-    fn tick(&mut self);
+    fn init(&mut self) -> Result<(), KernelError>;
+    /// Gets called on every timer event for the kernel with the amount of ticks since the last one.
+    fn tick(&mut self, ticks: u64);
+    /// Returns in how many ticks the kernel needs the next timer event, None if it doesn't care.
+    /// Used to let the CPU sleep through ticks nobody is waiting for.
+    fn next_deadline(&self) -> Option<u64>;
     /// Gets called when the kernel encounters an error.
     fn on_error(&mut self, event: ErrorEvent);
+    /// Gets called when a command was received on the control channel.
+    fn on_control(&mut self, command: ControlCommand);
+    /// Gets called when the heap usage crossed a watermark.
+    fn on_memory_pressure(&mut self, pressure: MemoryPressure);
+    /// Gets called when a thermal zone reached its critical temperature.
+    fn on_thermal_trip(&mut self, trip: ThermalTrip);
+    /// Gets called when the timer was set to a new tick rate, in Hz.
+    fn on_tick_rate_changed(&mut self, hz: u64);
     /// Gets called when the kernel needs to shut down.
     fn shutdown(&mut self);
 }
 
 #[panic_handler]
 fn panic(panic_info: &PanicInfo) -> ! {
-    let payload_message = if let Some(message) = panic_info.message() {
-        message.as_str().unwrap_or("Unknown panic message.")
+    // Nothing here may allocate or lock, the heap or the display might be what panicked
+    if let Some(message) = panic_info.message() {
+        if let Some(location) = panic_info.location() {
+            internal::emergency::abort(format_args!("{} at {}", message, location));
+        }
+        internal::emergency::abort(*message);
     } else if let Some(payload) = panic_info.payload().downcast_ref::<&str>() {
-        payload
+        internal::emergency::abort(format_args!("{}", payload));
     } else if let Some(payload) = panic_info.payload().downcast_ref::<String>() {
-        payload.as_str()
-    } else {
-        "Unknown panic payload."
-    };
-
-    internal::framebuffer::is_initialized().then(|| {
-        let mut display_manager = DisplayManager::new(DisplayType::Simple);
-        display_manager.set_mode(DisplayMode::Dummy);
-        display_manager.clear_screen();
-
-        abort(payload_message, Some(&mut display_manager));
-    });
-
-    abort(payload_message, None);
-}
-
-fn abort(message: &str, display_manager: Option<&mut DisplayManager>) -> ! {
-    log::error!("Kernel panicked with message '{}'", message);
-
-    if let Some(display_manager) = display_manager {
-        match display_manager.get_driver() {
-            DisplayDriverType::Dummy(driver) => {
-                driver.draw_panic(message);
-            }, _ => {}
-        }
-        display_manager.draw_all();
+        internal::emergency::abort(format_args!("{}", payload));
     }
+    internal::emergency::abort(format_args!("Unknown panic payload."));
+}
 
-    loop { x86_64::instructions::hlt(); }
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    internal::heap::alloc_error(layout)
 }
\ No newline at end of file
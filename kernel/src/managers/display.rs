@@ -1,30 +1,95 @@
+use alloc::boxed::Box;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use spin::Mutex;
 use spin::rwlock::RwLock;
 use crate::api::display::{Colors, DisplayApi, Fonts, Size};
-use crate::drivers::display::{CommonDisplayDriver, DisplayDriverManager, DisplayDriverType, DummyDisplayDriver};
+use crate::api::error::KernelError;
+use crate::drivers::display::console::ConsoleDisplayDriver;
+#[cfg(feature = "gui")]
+use crate::drivers::display::log_viewer::LogViewerDisplayDriver;
+#[cfg(feature = "gui")]
+use crate::drivers::display::settings::SettingsDisplayDriver;
+use crate::drivers::display::{CommonDisplayDriver, DisplayDriverExt, DisplayDriverManager, DummyDisplayDriver};
 use crate::drivers::display::text::{TextDisplayDriver, TextDisplayDriverArgs};
+use crate::drivers::vbe::VideoMode;
+use crate::internal::trace::TraceCategory;
 use crate::systems::display::{BufferedDisplay, SimpleDisplay};
 
+static DEFAULT_FPS: u32 = 30;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(dead_code)]
 pub enum DisplayMode {
     Unknown,
     Dummy,
-    Text(Size, Fonts)
+    Text(Size, Fonts),
+    /// Minimal console drawing straight to the display, works with every display type.
+    Console(Fonts),
+    /// Shows the kernel log on top of a text buffer of the given size.
+    LogViewer(Size, Fonts),
+    /// Shows the settings screen on top of a text buffer of the given size.
+    Settings(Size, Fonts)
 } impl DisplayMode {
-    fn get_driver(self) -> DisplayDriverType {
+    /// Returns the mode with the name `name` gives, with the default size and font. The settings
+    /// screen has no name, it is only shown on the primary screen at boot.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(DisplayMode::Dummy),
+            "console" => Some(DisplayMode::Console(Fonts::default())),
+            "text" => Some(DisplayMode::Text(Size::new(80, 25), Fonts::default())),
+            "logview" => Some(DisplayMode::LogViewer(Size::new(80, 25), Fonts::default())),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DisplayMode::Unknown => "unknown",
+            DisplayMode::Dummy => "off",
+            DisplayMode::Text(..) => "text",
+            DisplayMode::Console(..) => "console",
+            DisplayMode::LogViewer(..) => "logview",
+            DisplayMode::Settings(..) => "settings"
+        }
+    }
+
+    fn get_driver(self) -> Option<Box<dyn DisplayDriverExt>> {
         match self {
-            DisplayMode::Unknown => DisplayDriverType::Unknown,
-            DisplayMode::Dummy => DisplayDriverType::Dummy(
-                DummyDisplayDriver::new()
-            ), DisplayMode::Text(size, font) => DisplayDriverType::Text(
-                TextDisplayDriver::new(),
-                TextDisplayDriverArgs::new(
+            DisplayMode::Unknown => None,
+            DisplayMode::Dummy => Some(Box::new(DummyDisplayDriver::new())),
+            DisplayMode::Text(size, font) => {
+                let mut driver = TextDisplayDriver::new();
+                driver.init(&mut TextDisplayDriverArgs::new(
                     Arc::new(RwLock::new(size)),
                     Arc::new(RwLock::new(font))
-                )
-            )
+                ));
+                Some(Box::new(driver))
+            }, DisplayMode::Console(font) => {
+                let mut driver = ConsoleDisplayDriver::new();
+                driver.init(font);
+                Some(Box::new(driver))
+            },
+            #[cfg(feature = "gui")]
+            DisplayMode::LogViewer(size, font) => {
+                let mut driver = LogViewerDisplayDriver::new();
+                driver.init(&mut TextDisplayDriverArgs::new(
+                    Arc::new(RwLock::new(size)),
+                    Arc::new(RwLock::new(font))
+                ));
+                Some(Box::new(driver))
+            },
+            #[cfg(feature = "gui")]
+            DisplayMode::Settings(size, font) => {
+                let mut driver = SettingsDisplayDriver::new();
+                driver.init(&mut TextDisplayDriverArgs::new(
+                    Arc::new(RwLock::new(size)),
+                    Arc::new(RwLock::new(font))
+                ));
+                Some(Box::new(driver))
+            },
+            #[cfg(not(feature = "gui"))]
+            DisplayMode::LogViewer(..) | DisplayMode::Settings(..) => None
         }
     }
 }
@@ -36,49 +101,176 @@ pub enum DisplayType {
     Simple,
     Buffered
 } impl DisplayType {
-    pub fn new(&self) -> Arc<Mutex<dyn DisplayApi + Send>> {
-        match self {
-            DisplayType::Unknown => panic!("Unknown display type!"),
+    /// Creates a display of this type for the screen of the output.
+    pub fn new(&self, output: usize) -> Result<Arc<Mutex<dyn DisplayApi + Send>>, KernelError> {
+        Ok(match self {
+            DisplayType::Unknown => return Err(KernelError::InvalidConfiguration("Unknown display type")),
             DisplayType::Simple => Arc::new(Mutex::new(
-                SimpleDisplay::new()
+                SimpleDisplay::new(output)?
             )), DisplayType::Buffered => Arc::new(Mutex::new(
-                BufferedDisplay::new()
+                BufferedDisplay::new(output)?
             ))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FrameStats {
+    /// The frames drawn during the last full second.
+    pub fps: u32,
+    /// Amount of frames drawn.
+    pub frames: u64,
+    /// Amount of frames that were due but skipped, because nothing changed.
+    pub skipped: u64
+}
+
+/// Limits drawing to a frame rate, independent of how often the timer ticks.
+struct FrameScheduler {
+    fps: u32,
+    /// Ticks between two frames.
+    interval: u64,
+    last_frame: Option<u64>,
+    window_start: u64,
+    window_frames: u32,
+    stats: FrameStats
+} impl FrameScheduler {
+    fn new(fps: u32) -> Self { Self {
+        fps,
+        interval: Self::interval(fps),
+        last_frame: None,
+        window_start: 0,
+        window_frames: 0,
+        stats: FrameStats::default()
+    } }
+
+    fn interval(fps: u32) -> u64 {
+        (crate::drivers::timer::tick_hz() / fps.max(1) as u64).max(1)
+    }
+
+    /// Returns in how many ticks the next frame is due, zero if it is due now.
+    fn due_in(&self, tick: u64) -> u64 {
+        self.last_frame.map_or(0, |last_frame| (last_frame + self.interval).saturating_sub(tick))
+    }
+
+    fn record(&mut self, tick: u64, drawn: bool) {
+        self.last_frame = Some(tick);
+        if drawn {
+            self.stats.frames += 1;
+            self.window_frames += 1;
+        } else { self.stats.skipped += 1; }
+
+        if tick - self.window_start >= crate::drivers::timer::tick_hz() {
+            self.stats.fps = self.window_frames;
+            self.window_frames = 0;
+            self.window_start = tick;
         }
     }
 }
 
-pub struct DisplayManager {
+/// A screen, with the display drawing to it and the driver showing a mode on it.
+struct Output {
+    /// The frame buffer output the screen is.
+    index: usize,
     display: Arc<Mutex<dyn DisplayApi + Send>>,
+    driver_manager: DisplayDriverManager,
+    mode: DisplayMode
+}
+
+pub struct DisplayManager {
+    /// The screens, the primary one (output 0) first.
+    outputs: Vec<Output>,
     display_type: DisplayType,
-    driver_manager: DisplayDriverManager
+    frame_scheduler: FrameScheduler
 } #[allow(dead_code)] impl DisplayManager {
-    /// Creates a new display manager. Be careful as multiple display managers will overwrite each other.
-    pub fn new(display_type: DisplayType) -> Self {
-        let display = display_type.new();
-        let driver_manager = DisplayDriverManager::new();
+    /// Creates a new display manager with a display for every screen. Be careful as multiple display
+    /// managers will overwrite each other. Fails if there is no primary frame buffer to display
+    /// anything on, other screens that can't be used are skipped.
+    pub fn new(display_type: DisplayType) -> Result<Self, KernelError> {
+        let mut outputs = Vec::new();
+        for index in crate::internal::framebuffer::outputs() {
+            let display = match display_type.new(index) {
+                Ok(display) => display,
+                Err(err) if index == 0 => return Err(err),
+                Err(err) => {
+                    log::warn!("Skipping display output {}: {}", index, err);
+                    continue;
+                }
+            };
+            // Blank until a mode is assigned to the screen
+            let mut driver_manager = DisplayDriverManager::new();
+            driver_manager.set_driver(DisplayMode::Dummy.get_driver(), display.clone());
+            outputs.push(Output { index, display, driver_manager, mode: DisplayMode::Dummy });
+        }
+        if outputs.first().map_or(true, |output| output.index != 0) {
+            return Err(KernelError::NoFrameBuffer);
+        }
 
-        Self { display, display_type, driver_manager }
+        let frame_scheduler = FrameScheduler::new(DEFAULT_FPS);
+
+        Ok(Self { outputs, display_type, frame_scheduler })
     }
 
-    /// Sets the display mode. This will in turn also set the driver for the display.
-    pub fn set_mode(&mut self, mode: DisplayMode) {
-        let driver = mode.get_driver();
+    /// Sets the display mode of the primary screen. This will in turn also set the driver for the display.
+    pub fn set_mode(&mut self, mode: DisplayMode) -> Result<(), KernelError> {
+        self.set_output_mode(0, mode)
+    }
 
-        match driver {
-            DisplayDriverType::Text(..) => {
-                if self.display_type != DisplayType::Buffered {
-                    panic!("Text mode can only be used with a buffered display!");
-                }
-            }, _ => {}
+    /// Sets the display mode of the screen, each screen has a driver of its own.
+    pub fn set_output_mode(&mut self, index: usize, mode: DisplayMode) -> Result<(), KernelError> {
+        if !cfg!(feature = "gui") && matches!(mode, DisplayMode::LogViewer(..) | DisplayMode::Settings(..)) {
+            return Err(KernelError::InvalidConfiguration("Display mode needs the gui feature"));
+        }
+        if let DisplayMode::Text(..) | DisplayMode::LogViewer(..) | DisplayMode::Settings(..) = mode {
+            if self.display_type != DisplayType::Buffered {
+                return Err(KernelError::InvalidConfiguration("Text modes can only be used with a buffered display"));
+            }
         }
 
-        self.driver_manager.set_driver(driver, self.display.clone());
+        let output = self.outputs.iter_mut().find(|output| output.index == index)
+            .ok_or(KernelError::InvalidConfiguration("No such display output"))?;
+        output.driver_manager.set_driver(mode.get_driver(), output.display.clone());
+        output.mode = mode;
+        Ok(())
+    }
+
+    /// Returns every screen with its resolution and display mode.
+    pub fn outputs(&self) -> impl Iterator<Item = (usize, Size, DisplayMode)> + '_ {
+        self.outputs.iter().map(|output| {
+            let size = output.display.lock().get_info()
+                .map_or(Size::new(0, 0), |info| Size::new(info.width, info.height));
+            (output.index, size, output.mode)
+        })
     }
 
-    /// Returns the current driver type, which can be used to get the actual driver.
-    pub fn get_driver(&mut self) -> &mut DisplayDriverType {
-        &mut self.driver_manager.current_driver
+    /// Switches the screen to the resolution and bit depth through the Bochs VBE display interface
+    /// and recreates the display for it. The current driver still draws to the old display, so the
+    /// display mode has to be set again afterwards.
+    pub fn set_resolution(&mut self, mode: VideoMode) -> Result<(), KernelError> {
+        crate::drivers::vbe::set_mode(mode)?;
+        self.outputs[0].display = self.display_type.new(0)?;
+        Ok(())
+    }
+
+    /// Sets the display mode like `set_mode`, but falls back to the console mode if the display
+    /// can't be used in the requested mode. Returns the mode that was actually set.
+    pub fn set_mode_or_fallback(&mut self, mode: DisplayMode) -> DisplayMode {
+        match self.set_mode(mode) {
+            Ok(()) => mode,
+            Err(err) => {
+                let fallback = DisplayMode::Console(Fonts::default());
+                log::warn!("Failed to set display mode {:?}, falling back to {:?}: {}", mode, fallback, err);
+                // The console works with every display type, so this can't fail
+                let primary = &mut self.outputs[0];
+                primary.driver_manager.set_driver(fallback.get_driver(), primary.display.clone());
+                primary.mode = fallback;
+                fallback
+            }
+        }
+    }
+
+    /// Returns the driver of the primary screen if it is of the given type, to use its driver-specific API.
+    pub fn get_driver<T: DisplayDriverExt>(&mut self) -> Option<&mut T> {
+        self.outputs[0].driver_manager.get_driver::<T>()
     }
 
     /// Returns the current display type.
@@ -86,13 +278,64 @@ pub struct DisplayManager {
         self.display_type
     }
 
-    /// Clears the screen.
-    pub fn clear_screen(&mut self) {
-        self.driver_manager.clear(Colors::Black.into());
+    /// Clears every screen.
+    pub fn clear_screen(&mut self) -> Result<(), KernelError> {
+        for output in self.outputs.iter_mut() {
+            output.driver_manager.clear(Colors::Black.into())?;
+        }
+        Ok(())
+    }
+
+    /// Sets how many frames per second `draw_frame` draws at most.
+    pub fn set_frame_rate(&mut self, fps: u32) {
+        self.frame_scheduler.fps = fps;
+        self.frame_scheduler.interval = FrameScheduler::interval(fps);
+    }
+
+    /// Keeps the frame rate when the length of a tick changed.
+    pub fn on_tick_rate_changed(&mut self) {
+        self.frame_scheduler.interval = FrameScheduler::interval(self.frame_scheduler.fps);
+    }
+
+    /// Returns whether a frame is due at the tick, so callers only prepare content that gets drawn.
+    pub fn frame_due(&self, tick: u64) -> bool {
+        self.frame_scheduler.due_in(tick) == 0
+    }
+
+    /// Returns in how many ticks the next frame is due.
+    pub fn next_frame_in(&self, tick: u64) -> u64 {
+        self.frame_scheduler.due_in(tick)
+    }
+
+    /// Draws the changes if a frame is due at the tick and something changed since the last one.
+    /// Returns whether a frame was drawn.
+    pub fn draw_frame(&mut self, tick: u64) -> Result<bool, KernelError> {
+        if !self.frame_due(tick) {
+            return Ok(false);
+        }
+        if !self.outputs.iter().any(|output| output.driver_manager.is_dirty()) {
+            self.frame_scheduler.record(tick, false);
+            return Ok(false);
+        }
+
+        self.draw_all()?;
+        self.frame_scheduler.record(tick, true);
+        Ok(true)
+    }
+
+    /// Returns the frame rate statistics of `draw_frame`.
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_scheduler.stats
     }
 
-    /// Draws all the changes to the screen using the current driver.
-    pub fn draw_all(&mut self) {
-        self.driver_manager.draw_all();
+    /// Draws all the changes to every screen using their drivers, returning the first error.
+    /// If a display is busy its changes stay pending and get drawn by the next call.
+    pub fn draw_all(&mut self) -> Result<(), KernelError> {
+        crate::trace!(TraceCategory::DrawBegin);
+        let result = self.outputs.iter_mut()
+            .map(|output| output.driver_manager.draw_all())
+            .fold(Ok(()), Result::and);
+        crate::trace!(TraceCategory::DrawEnd);
+        Ok(result?)
     }
 }
\ No newline at end of file
@@ -0,0 +1,79 @@
+use alloc::vec::Vec;
+use crate::api::error::KernelError;
+use crate::api::time::{DateTime, Month};
+use crate::internal::efi::{FirmwareInfo, Guid};
+
+/// The owner of the variables the kernel keeps its own settings in.
+static SETTINGS_VENDOR: Guid = Guid {
+    data1: 0x416B6A6F, data2: 0x4F53, data3: 0x4B52,
+    data4: [0x8E, 0x4C, 0x1F, 0x62, 0x0B, 0x93, 0x5D, 0x27]
+};
+
+/// Reads and writes EFI variables and the firmware's clock through the UEFI runtime services.
+/// Everything fails with `KernelError::HardwareMissing` when the kernel wasn't booted through UEFI.
+pub struct FirmwareManager {
+    info: Option<&'static FirmwareInfo>
+} #[allow(dead_code)] impl FirmwareManager {
+    /// Takes what the runtime services were set up with, None if they are not available.
+    pub fn new(info: Option<&'static FirmwareInfo>) -> Self { Self { info } }
+
+    /// Returns the vendor and version of the firmware, None without the runtime services.
+    pub fn info(&self) -> Option<&'static FirmwareInfo> {
+        self.info
+    }
+
+    /// Reads the date and time of the firmware's clock, in whatever time zone it is kept in.
+    pub fn time(&self) -> Result<DateTime, KernelError> {
+        let time = crate::internal::efi::time()?;
+        let month = Month::from_u8(time.month)
+            .ok_or(KernelError::InvalidConfiguration("Firmware clock reported an invalid month"))?;
+        Ok(DateTime::new(
+            time.nanosecond, time.second, time.minute, time.hour,
+            time.day, month, time.year as i32
+        ))
+    }
+
+    /// Reads a variable, without its attributes.
+    pub fn variable(&self, name: &str, vendor: &Guid) -> Result<Vec<u8>, KernelError> {
+        crate::internal::efi::variable(name, vendor).map(|(data, _)| data)
+    }
+
+    /// Writes a variable that survives reboots and stays accessible after boot, an empty one gets
+    /// deleted.
+    pub fn set_variable(&self, name: &str, vendor: &Guid, data: &[u8]) -> Result<(), KernelError> {
+        let attributes = crate::internal::efi::VARIABLE_NON_VOLATILE
+            | crate::internal::efi::VARIABLE_BOOTSERVICE_ACCESS
+            | crate::internal::efi::VARIABLE_RUNTIME_ACCESS;
+        crate::internal::efi::set_variable(name, vendor, attributes, data)
+    }
+
+    /// Returns the numbers of the `BootXXXX` entries in the order the firmware tries them.
+    pub fn boot_order(&self) -> Result<Vec<u16>, KernelError> {
+        let data = self.variable("BootOrder", &crate::internal::efi::GLOBAL_VARIABLE)?;
+        Ok(data.chunks_exact(2).map(|entry| u16::from_le_bytes([entry[0], entry[1]])).collect())
+    }
+
+    pub fn set_boot_order(&self, order: &[u16]) -> Result<(), KernelError> {
+        let data = order.iter().flat_map(|entry| entry.to_le_bytes()).collect::<Vec<_>>();
+        self.set_variable("BootOrder", &crate::internal::efi::GLOBAL_VARIABLE, &data)
+    }
+
+    /// Returns the number of the `BootXXXX` entry the system was booted from.
+    pub fn boot_current(&self) -> Result<u16, KernelError> {
+        let data = self.variable("BootCurrent", &crate::internal::efi::GLOBAL_VARIABLE)?;
+        match data.as_slice() {
+            [low, high, ..] => Ok(u16::from_le_bytes([*low, *high])),
+            _ => Err(KernelError::InvalidConfiguration("BootCurrent variable is too short"))
+        }
+    }
+
+    /// Reads a setting the kernel stored in an earlier boot.
+    pub fn setting(&self, name: &str) -> Result<Vec<u8>, KernelError> {
+        self.variable(name, &SETTINGS_VENDOR)
+    }
+
+    /// Stores a setting of the kernel in the firmware, so it survives reboots.
+    pub fn set_setting(&self, name: &str, value: &[u8]) -> Result<(), KernelError> {
+        self.set_variable(name, &SETTINGS_VENDOR, value)
+    }
+}
\ No newline at end of file
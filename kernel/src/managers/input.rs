@@ -0,0 +1,38 @@
+use alloc::sync::Arc;
+use spin::Mutex;
+use crate::api::input::{FocusId, InputConsumer};
+use crate::systems::input::InputRouter;
+
+pub struct InputManager {
+    router: Arc<Mutex<InputRouter>>
+} #[allow(dead_code)] impl InputManager {
+    pub fn new() -> Self {
+        let router = Arc::new(Mutex::new(InputRouter::new()));
+        crate::api::event::EventDispatcher::global().register(router.clone());
+        Self { router }
+    }
+
+    /// Gives the focus to the consumer, until it is unfocused again.
+    pub fn focus(&self, consumer: Arc<Mutex<dyn InputConsumer + Send>>) -> FocusId {
+        crate::internal::idt::without_interrupts(|| self.router.lock().focus(consumer))
+    }
+
+    /// Takes the focus away from the consumer, it returns to the consumer focused before.
+    pub fn unfocus(&self, id: FocusId) -> bool {
+        crate::internal::idt::without_interrupts(|| self.router.lock().unfocus(id))
+    }
+
+    /// Returns the consumer that currently has the focus.
+    pub fn focused(&self) -> Option<FocusId> {
+        self.router.try_lock().and_then(|router| router.focused())
+    }
+
+    /// Returns how many inputs no consumer took.
+    pub fn unhandled(&self) -> Option<u64> {
+        self.router.try_lock().map(|router| router.unhandled())
+    }
+} impl Default for InputManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,70 @@
+use alloc::sync::Arc;
+use spin::Mutex;
+use crate::api::keyboard::{Modifiers, TypematicDelay};
+use crate::internal::platform::PlatformDeviceKind;
+use crate::systems::keyboard::Ps2Keyboard;
+
+/// Typematic settings applied at startup, slightly snappier than the keyboard's power-on default
+/// of 500 ms and 10.9 characters per second.
+static DEFAULT_TYPEMATIC_DELAY: TypematicDelay = TypematicDelay::Ms250;
+static DEFAULT_TYPEMATIC_RATE: u32 = 20;
+
+pub struct KeyboardManager {
+    keyboard: Arc<Mutex<Ps2Keyboard>>,
+    typematic: (TypematicDelay, u32)
+} #[allow(dead_code)] impl KeyboardManager {
+    pub fn new() -> Self {
+        let keyboard = Arc::new(Mutex::new(Ps2Keyboard::new()));
+        crate::api::event::EventDispatcher::global().register(keyboard.clone());
+
+        let mut manager = Self { keyboard, typematic: (DEFAULT_TYPEMATIC_DELAY, DEFAULT_TYPEMATIC_RATE) };
+        // Commands to a controller that isn't there would only time out
+        if !crate::internal::platform::is_present(PlatformDeviceKind::Ps2Keyboard) {
+            log::info!("Firmware describes no PS/2 keyboard, leaving the controller alone.");
+            return manager;
+        }
+        if manager.set_typematic(DEFAULT_TYPEMATIC_DELAY, DEFAULT_TYPEMATIC_RATE) {
+            log::info!(
+                "Keyboard typematic set to {:?} delay and {} characters per second.",
+                DEFAULT_TYPEMATIC_DELAY, DEFAULT_TYPEMATIC_RATE
+            );
+        } else { log::warn!("Keyboard did not acknowledge the typematic settings."); }
+        if !manager.set_locks(false, false, false) {
+            log::warn!("Keyboard did not acknowledge the LED update.");
+        }
+        manager
+    }
+
+    /// Returns the current state of the modifier and lock keys.
+    pub fn modifiers(&self) -> Option<Modifiers> {
+        self.keyboard.try_lock().map(|keyboard| keyboard.modifiers())
+    }
+
+    /// Sets the lock keys, the LEDs follow them. Returns whether the keyboard acknowledged it.
+    pub fn set_locks(&mut self, caps_lock: bool, num_lock: bool, scroll_lock: bool) -> bool {
+        self.keyboard.try_lock()
+            .map(|mut keyboard| keyboard.set_locks(caps_lock, num_lock, scroll_lock))
+            .unwrap_or(false)
+    }
+
+    /// Returns the typematic delay and rate (in characters per second) last set.
+    pub fn typematic(&self) -> (TypematicDelay, u32) {
+        self.typematic
+    }
+
+    /// Sets the typematic delay and rate (in characters per second, 2 to 30).
+    /// Returns whether the keyboard acknowledged it.
+    pub fn set_typematic(&mut self, delay: TypematicDelay, rate: u32) -> bool {
+        let acknowledged = crate::internal::idt::without_interrupts(|| {
+            crate::internal::keyboard::set_typematic(delay, rate)
+        });
+        if acknowledged {
+            self.typematic = (delay, rate.clamp(2, 30));
+        }
+        acknowledged
+    }
+} impl Default for KeyboardManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
\ No newline at end of file
@@ -1,2 +1,9 @@
 pub mod time;
-pub mod display;
\ No newline at end of file
+pub mod display;
+pub mod keyboard;
+pub mod input;
+pub mod module;
+pub mod shutdown;
+pub mod thermal;
+pub mod power;
+pub mod firmware;
\ No newline at end of file
@@ -0,0 +1,58 @@
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use crate::api::module::{ModuleError, ModuleInfo};
+use crate::internal::protocol::BootModule;
+use crate::systems::module::LoadedModule;
+
+/// Loads kernel modules on demand from the initial ramdisk, a `newc` cpio archive passed by the
+/// bootloader. A module is found by its file name without directories and extension.
+pub struct ModuleManager {
+    initrd: Option<&'static [u8]>,
+    modules: Vec<LoadedModule>
+} #[allow(dead_code)] impl ModuleManager {
+    /// Uses the first boot module that is a cpio archive as the initial ramdisk.
+    pub fn new(boot_modules: &[BootModule]) -> Self { Self {
+        initrd: boot_modules.iter()
+            .map(|module| module.data())
+            .find(|data| crate::internal::initrd::is_archive(data)),
+        modules: Vec::new()
+    } }
+
+    pub fn has_initrd(&self) -> bool {
+        self.initrd.is_some()
+    }
+
+    /// Returns the names of the modules in the initial ramdisk.
+    pub fn available(&self) -> Vec<&'static str> {
+        self.initrd.map(|initrd| crate::internal::initrd::files(initrd)
+            .map(|(path, ..)| module_name(path))
+            .collect()
+        ).unwrap_or_default()
+    }
+
+    pub fn loaded(&self) -> Vec<ModuleInfo> {
+        self.modules.iter().map(|module| module.info()).collect()
+    }
+
+    /// Loads the module with the name from the initial ramdisk and runs its `module_init`.
+    pub fn load(&mut self, name: &str) -> Result<ModuleInfo, ModuleError> {
+        if self.modules.iter().any(|module| module.name() == name) {
+            return Err(ModuleError::AlreadyLoaded(name.to_string()));
+        }
+        let initrd = self.initrd.ok_or(ModuleError::NoInitrd)?;
+        let (.., object) = crate::internal::initrd::files(initrd)
+            .find(|(path, ..)| module_name(path) == name)
+            .ok_or_else(|| ModuleError::NotFound(name.to_string()))?;
+
+        let module = LoadedModule::load(name, object)?;
+        let info = module.info();
+        log::info!("Loaded module {} at {:#X} with {} bytes.", info.name, info.base, info.size);
+        self.modules.push(module);
+        Ok(info)
+    }
+}
+
+fn module_name(path: &str) -> &str {
+    let file = path.rsplit('/').next().unwrap_or(path);
+    file.split('.').next().unwrap_or(file)
+}
\ No newline at end of file
@@ -0,0 +1,159 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use aml::{AmlContext, AmlValue, LevelType};
+use crate::internal::acpi::AcpiNamespace;
+
+static BATTERY_ID: &str = "PNP0C0A";
+static AC_ADAPTER_ID: &str = "ACPI0003";
+/// Capacities and rates the firmware doesn't know are reported as this.
+static UNKNOWN_VALUE: u64 = 0xFFFF_FFFF;
+static STATE_DISCHARGING: u64 = 1 << 0;
+static STATE_CHARGING: u64 = 1 << 1;
+static STATE_CRITICAL: u64 = 1 << 2;
+/// The `_STA` bit telling whether a battery is inserted.
+static STATUS_BATTERY_PRESENT: u64 = 1 << 4;
+static BATTERY_OBJECTS: [&str; 3] = ["_STA", "_BIF", "_BST"];
+static AC_ADAPTER_OBJECTS: [&str; 1] = ["_PSR"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BatteryStatus {
+    pub present: bool,
+    /// The remaining charge relative to the last full charge, None if the firmware doesn't know.
+    pub charge_percent: Option<u8>,
+    pub charging: bool,
+    pub discharging: bool,
+    /// The battery is about to run out.
+    pub critical: bool
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PowerStatus {
+    /// Whether an AC adapter is plugged in, None if there is no AC adapter device.
+    pub ac_online: Option<bool>,
+    pub batteries: Vec<BatteryStatus>
+}
+
+/// Reads the state of the ACPI batteries (`_BIF`/`_BST`) and AC adapters (`_PSR`). The state is
+/// cached and only read again by `refresh`, as evaluating the methods can be slow.
+pub struct PowerManager {
+    aml: Option<AcpiNamespace>,
+    batteries: Vec<String>,
+    adapters: Vec<String>,
+    status: PowerStatus
+} #[allow(dead_code)] impl PowerManager {
+    /// Finds the batteries and AC adapters in the namespace, there are none without one.
+    pub fn new(aml: Option<AcpiNamespace>) -> Self {
+        let mut manager = Self { aml, batteries: Vec::new(), adapters: Vec::new(), status: PowerStatus::default() };
+        if let Some(aml) = manager.aml.clone() {
+            let mut aml = aml.lock();
+            for path in crate::internal::acpi::find_levels(&mut aml, LevelType::Device) {
+                let id = crate::internal::acpi::evaluate(&mut aml, &path, "_HID").ok()
+                    .and_then(|id| crate::internal::acpi::hardware_id(&id));
+                let (devices, objects) = match id.as_deref() {
+                    Some(id) if id == BATTERY_ID => (&mut manager.batteries, BATTERY_OBJECTS.as_slice()),
+                    Some(id) if id == AC_ADAPTER_ID => (&mut manager.adapters, AC_ADAPTER_OBJECTS.as_slice()),
+                    _ => continue
+                };
+                // Evaluating those would panic on every refresh
+                if objects.iter().all(|object| crate::internal::acpi::is_serviceable(&aml, &path, object)) {
+                    devices.push(path);
+                } else {
+                    log::warn!("Not polling {}, it accesses operation regions the kernel can't service.", path);
+                }
+            }
+        }
+        manager.refresh();
+        manager
+    }
+
+    pub fn battery_count(&self) -> usize {
+        self.batteries.len()
+    }
+
+    pub fn adapter_count(&self) -> usize {
+        self.adapters.len()
+    }
+
+    /// Returns the state as of the last refresh.
+    pub fn status(&self) -> &PowerStatus {
+        &self.status
+    }
+
+    /// Reads the state of the batteries and AC adapters again.
+    pub fn refresh(&mut self) {
+        let Some(aml) = self.aml.clone() else { return };
+        let mut aml = aml.lock();
+
+        self.status.ac_online = self.adapters.iter()
+            .filter_map(|path| Self::integer(&mut aml, path, "_PSR"))
+            .map(|online| online != 0)
+            .reduce(|any, online| any || online);
+        self.status.batteries = self.batteries.iter()
+            .map(|path| Self::battery_status(&mut aml, path))
+            .collect();
+    }
+
+    fn battery_status(aml: &mut AmlContext, path: &str) -> BatteryStatus {
+        // Batteries without `_STA` are always present
+        let present = Self::integer(aml, path, "_STA").map_or(true, |status| status & STATUS_BATTERY_PRESENT != 0);
+        if !present {
+            return BatteryStatus::default();
+        }
+
+        let last_full_capacity = Self::package_field(aml, path, "_BIF", 2);
+        let Some(state) = Self::package(aml, path, "_BST") else {
+            return BatteryStatus { present, ..BatteryStatus::default() };
+        };
+        let field = |index: usize| match state.get(index) {
+            Some(AmlValue::Integer(value)) => Some(*value),
+            _ => None
+        };
+
+        let flags = field(0).unwrap_or(0);
+        let charge_percent = match (field(2), last_full_capacity) {
+            (Some(remaining), Some(full)) if remaining != UNKNOWN_VALUE && full != UNKNOWN_VALUE && full > 0 => {
+                Some((remaining * 100 / full).min(100) as u8)
+            }, _ => None
+        };
+        BatteryStatus {
+            present,
+            charge_percent,
+            charging: flags & STATE_CHARGING != 0,
+            discharging: flags & STATE_DISCHARGING != 0,
+            critical: flags & STATE_CRITICAL != 0
+        }
+    }
+
+    fn integer(aml: &mut AmlContext, path: &str, object: &str) -> Option<u64> {
+        let value = crate::internal::acpi::evaluate(aml, path, object).ok()?;
+        value.as_integer(aml).ok()
+    }
+
+    fn package(aml: &mut AmlContext, path: &str, object: &str) -> Option<Vec<AmlValue>> {
+        match crate::internal::acpi::evaluate(aml, path, object).ok()? {
+            AmlValue::Package(elements) => Some(elements),
+            _ => None
+        }
+    }
+
+    fn package_field(aml: &mut AmlContext, path: &str, object: &str, index: usize) -> Option<u64> {
+        match Self::package(aml, path, object)?.get(index)? {
+            AmlValue::Integer(value) => Some(*value),
+            _ => None
+        }
+    }
+
+    /// Describes the power state in a few words for the status bar, like `87% charging`. None if
+    /// there is no battery.
+    pub fn summary(&self) -> Option<String> {
+        let battery = self.status.batteries.iter().find(|battery| battery.present)?;
+        let charge = battery.charge_percent.map_or("?".into(), |percent| format!("{}%", percent));
+        let state = if battery.charging {
+            " charging"
+        } else if self.status.ac_online == Some(true) {
+            " on AC"
+        } else { "" };
+        Some(format!("{}{}", charge, state))
+    }
+}
\ No newline at end of file
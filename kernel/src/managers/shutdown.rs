@@ -0,0 +1,40 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use crate::api::error::KernelError;
+
+type ShutdownHook = Box<dyn FnOnce() -> Result<(), KernelError> + Send>;
+
+/// Collects the cleanup work of the subsystems, so shutting down quiesces them in the reverse order
+/// they were initialized in: what was set up last (and might depend on the rest) is torn down first.
+pub struct ShutdownManager {
+    hooks: Vec<(&'static str, ShutdownHook)>
+} #[allow(dead_code)] impl ShutdownManager {
+    pub fn new() -> Self { Self {
+        hooks: Vec::new()
+    } }
+
+    /// Registers the cleanup of a subsystem, which should happen right after its initialization.
+    pub fn register<F>(&mut self, name: &'static str, hook: F)
+        where F: FnOnce() -> Result<(), KernelError> + Send + 'static {
+        self.hooks.push((name, Box::new(hook)));
+    }
+
+    /// Runs all hooks, last registered first. A failing hook is logged and doesn't stop the others,
+    /// the system shuts down regardless. Returns how many hooks failed.
+    pub fn run(&mut self) -> usize {
+        let mut failed = 0;
+        while let Some((name, hook)) = self.hooks.pop() {
+            let start = crate::internal::tsc::read();
+            match hook() {
+                Ok(()) => log::info!(
+                    "Shutdown of '{}' finished in {}.",
+                    name, crate::boot::Duration(crate::internal::tsc::read().saturating_sub(start))
+                ), Err(err) => {
+                    log::warn!("Shutdown of '{}' failed: {}", name, err);
+                    failed += 1;
+                }
+            }
+        }
+        failed
+    }
+}
\ No newline at end of file
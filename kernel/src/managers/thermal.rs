@@ -0,0 +1,85 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use aml::LevelType;
+use crate::api::error::KernelError;
+use crate::internal::acpi::AcpiNamespace;
+use crate::api::event::Event;
+use crate::api::thermal::{Temperature, ThermalTrip};
+
+/// An ACPI thermal zone, like the CPU package or the chassis.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThermalZone {
+    /// The path of the zone in the ACPI namespace.
+    pub path: String,
+    /// The temperature the system has to shut down at, if the zone has one.
+    pub critical: Option<Temperature>,
+    /// Whether the zone was at its critical temperature the last time it was polled.
+    tripped: bool
+}
+
+/// Reads the temperatures of the thermal zones in the ACPI namespace, and reports zones that
+/// reached their critical temperature with `Event::ThermalTrip`.
+pub struct ThermalManager {
+    aml: Option<AcpiNamespace>,
+    zones: Vec<ThermalZone>
+} #[allow(dead_code)] impl ThermalManager {
+    /// Finds the thermal zones in the namespace, there are none without one.
+    pub fn new(aml: Option<AcpiNamespace>) -> Self {
+        let mut manager = Self { aml, zones: Vec::new() };
+        manager.zones = manager.find_zones();
+        manager
+    }
+
+    fn find_zones(&self) -> Vec<ThermalZone> {
+        let Some(aml) = self.aml.as_ref() else { return Vec::new() };
+        let mut paths = crate::internal::acpi::find_levels(&mut aml.lock(), LevelType::ThermalZone);
+        // Evaluating those would panic on every poll
+        paths.retain(|path| {
+            let serviceable = ["_TMP", "_CRT"].iter()
+                .all(|object| crate::internal::acpi::is_serviceable(&aml.lock(), path, object));
+            if !serviceable {
+                log::warn!("Not polling {}, it accesses operation regions the kernel can't service.", path);
+            }
+            serviceable
+        });
+        paths.into_iter().map(|path| ThermalZone {
+            critical: self.evaluate(&path, "_CRT").ok().map(Temperature),
+            path,
+            tripped: false
+        }).collect()
+    }
+
+    fn evaluate(&self, path: &str, object: &str) -> Result<u64, KernelError> {
+        let aml = self.aml.as_ref().ok_or(KernelError::HardwareMissing("ACPI namespace"))?;
+        let mut aml = aml.lock();
+        let value = crate::internal::acpi::evaluate(&mut aml, path, object)?;
+        value.as_integer(&aml).map_err(|_| KernelError::InvalidConfiguration("Thermal zone object is not an integer"))
+    }
+
+    pub fn zones(&self) -> &[ThermalZone] {
+        &self.zones
+    }
+
+    /// Reads the current temperature of the zone by evaluating its `_TMP`.
+    pub fn temperature(&self, zone: &ThermalZone) -> Result<Temperature, KernelError> {
+        self.evaluate(&zone.path, "_TMP").map(Temperature)
+    }
+
+    /// Reads the temperatures of the zones with a critical temperature and pushes a thermal trip
+    /// event for each one that reached it since the last poll.
+    pub fn poll(&mut self) {
+        for index in 0..self.zones.len() {
+            let Some(critical) = self.zones[index].critical else { continue };
+            let Ok(temperature) = self.temperature(&self.zones[index]) else { continue };
+
+            let zone = &mut self.zones[index];
+            let tripped = temperature >= critical;
+            if tripped && !zone.tripped {
+                crate::api::event::EventDispatcher::global().push(Event::ThermalTrip(ThermalTrip {
+                    zone: zone.path.clone(), temperature, critical
+                }));
+            }
+            zone.tripped = tripped;
+        }
+    }
+}
\ No newline at end of file
@@ -1,22 +1,87 @@
 use alloc::sync::Arc;
 use spin::Mutex;
-use crate::api::time::TimeApi;
-use crate::systems::time::SimpleClock;
+use crate::api::error::KernelError;
+use crate::api::time::{DateTime, Duration, Instant, TimeApi};
+use crate::internal::cmos::RtcRate;
+use crate::systems::time::{SimpleClock, TickAccounting, TickRateCheck};
+
+/// The clock interpolates with timer ticks and only needs the real-time clock to find out where a
+/// second starts, so 64 Hz keeps that within ~16 ms without waking the CPU a thousand times a second.
+pub static CLOCK_RTC_RATE: RtcRate = RtcRate::Hz64;
 
 pub struct TimeManager {
-    clock: Arc<Mutex<dyn TimeApi + Send>>
+    clock: Arc<Mutex<dyn TimeApi + Send>>,
+    accounting: Arc<Mutex<TickAccounting>>,
+    tick_rate_check: Arc<Mutex<TickRateCheck>>
 } #[allow(dead_code)] impl TimeManager {
     pub fn new() -> Self {
-        let clock = Arc::new(Mutex::new(SimpleClock::new()));
+        let clock = Arc::new(Mutex::new(SimpleClock::new(crate::internal::cmos::clock_drift_ppm() as i64)));
         crate::api::event::EventDispatcher::global().register(clock.clone());
-        Self { clock }
+        let accounting = Arc::new(Mutex::new(TickAccounting::new()));
+        crate::api::event::EventDispatcher::global().register(accounting.clone());
+        let tick_rate_check = Arc::new(Mutex::new(TickRateCheck::new()));
+        crate::api::event::EventDispatcher::global().register(tick_rate_check.clone());
+        Self { clock, accounting, tick_rate_check }
+    }
+
+    /// Returns the time since the boot started, measured with the time stamp counter. Before it
+    /// is calibrated this is zero.
+    pub fn uptime(&self) -> Duration {
+        Instant::now().since_boot()
+    }
+
+    /// Returns how far the timer tick rate deviated from the real-time clock, in parts per
+    /// million, or None if not enough time passed to tell yet.
+    pub fn tick_drift_ppm(&self) -> Result<Option<i64>, KernelError> {
+        let check = self.tick_rate_check.try_lock().ok_or(KernelError::Busy("Tick rate check"))?;
+        Ok(check.drift_ppm())
+    }
+
+    /// Sets the rate of the real-time clock interrupts the clock synchronizes with.
+    /// Without a real-time clock the clock only advances with the timer ticks.
+    pub fn set_rtc_rate(&self, rate: RtcRate) -> Result<(), KernelError> {
+        let cmos = crate::internal::cmos::Cmos::global()
+            .ok_or(KernelError::HardwareMissing("Real-time clock"))?;
+        cmos.lock().set_rate(rate);
+        Ok(())
     }
 
-    pub fn with_clock<F, T>(&self, func: F) -> Option<T>
+    /// Sets the date and time of the real-time clock. The clock picks the new time up with the
+    /// next real-time clock interrupt.
+    pub fn set_time(&self, date_time: DateTime) -> Result<(), KernelError> {
+        let cmos = crate::internal::cmos::Cmos::global()
+            .ok_or(KernelError::HardwareMissing("Real-time clock"))?;
+        // The real-time clock interrupt takes the lock too
+        crate::internal::idt::without_interrupts(|| cmos.lock().set_rtc(date_time))?;
+        self.tick_rate_check.lock().restart();
+        self.clock.lock().reset_corrections();
+        log::info!("Real-time clock set to {}.", date_time);
+        Ok(())
+    }
+
+    /// Corrects the clock by the given nanoseconds without making it jump, see `ClockDiscipline`.
+    /// The drift estimate the correction leads to is kept for the next boot.
+    pub fn adjust_time(&self, correction_nanos: i64) -> Result<(), KernelError> {
+        let mut clock = self.clock.try_lock().ok_or(KernelError::Busy("Clock"))?;
+        if let Some(drift_ppm) = clock.adjust(correction_nanos) {
+            log::info!("Real-time clock drift estimated at {} ppm.", drift_ppm);
+            crate::internal::cmos::set_clock_drift_ppm(drift_ppm as i16);
+        }
+        Ok(())
+    }
+
+    pub fn with_clock<F, T>(&self, func: F) -> Result<T, KernelError>
         where F: FnOnce(&mut dyn TimeApi) -> T
     {
-        if let Some(mut clock) = self.clock.try_lock() {
-            Some(func(&mut *clock))
-        } else { None }
+        let mut clock = self.clock.try_lock().ok_or(KernelError::Busy("Clock"))?;
+        Ok(func(&mut *clock))
+    }
+
+    /// Gives access to the busy/idle tick accounting.
+    pub fn with_accounting<F, T>(&self, func: F) -> Result<T, KernelError>
+        where F: FnOnce(&TickAccounting) -> T
+    {
+        let accounting = self.accounting.try_lock().ok_or(KernelError::Busy("Tick accounting"))?;
+        Ok(func(&accounting))
     }
 }
\ No newline at end of file
@@ -0,0 +1,107 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use spin::Mutex;
+use crate::api::event::{Event, EventDispatcher, EventHandler};
+use crate::drivers::display::text::TextDisplayDriver;
+use crate::managers::display::DisplayManager;
+
+static ALLOC_ITERATIONS: u64 = 10000;
+static REDRAW_ITERATIONS: u64 = 100;
+static DISPATCH_ITERATIONS: u64 = 10000;
+/// Events get dispatched in batches, as the queue drops events beyond its capacity.
+static DISPATCH_BATCH: u64 = 100;
+
+/// The result of a benchmark, displayed as a single `BENCH key=value ...` line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchResult {
+    pub name: &'static str,
+    pub iterations: u64,
+    pub cycles: u64
+} impl Display for BenchResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let per_iteration = self.cycles / self.iterations.max(1);
+        // Nanoseconds are only known once the time stamp counter is calibrated
+        let nanos = match crate::internal::tsc::khz() {
+            0 => 0,
+            khz => self.cycles * 1_000_000 / khz / self.iterations.max(1)
+        };
+        write!(
+            f, "BENCH name={} iterations={} cycles={} cycles_per_iteration={} ns_per_iteration={}",
+            self.name, self.iterations, self.cycles, per_iteration, nanos
+        )
+    }
+}
+
+/// Runs all benchmarks and passes each result to the function. The redraw benchmark is skipped
+/// if the display isn't in text mode.
+pub fn run(display_manager: Option<&mut DisplayManager>, output: &mut dyn FnMut(BenchResult)) {
+    output(alloc_churn());
+    if let Some(result) = display_manager.and_then(text_redraw) {
+        output(result);
+    }
+    output(event_dispatch());
+}
+
+fn measure(name: &'static str, iterations: u64, mut func: impl FnMut(u64)) -> BenchResult {
+    let start = crate::internal::tsc::read();
+    for iteration in 0..iterations {
+        func(iteration);
+    }
+    BenchResult { name, iterations, cycles: crate::internal::tsc::read().saturating_sub(start) }
+}
+
+/// Allocates and frees blocks of varying sizes, keeping some alive so the heap fragments.
+fn alloc_churn() -> BenchResult {
+    let mut live: Vec<Box<[u8]>> = Vec::with_capacity(64);
+    measure("alloc_churn", ALLOC_ITERATIONS, |iteration| {
+        let size = 16 << (iteration % 8);
+        let block = alloc::vec![iteration as u8; size as usize].into_boxed_slice();
+        if live.len() < 64 {
+            live.push(block);
+        } else {
+            live[(iteration * 7 % 64) as usize] = block;
+        }
+    })
+}
+
+/// Fills the whole text buffer with a different character every time and draws it.
+fn text_redraw(display_manager: &mut DisplayManager) -> Option<BenchResult> {
+    display_manager.get_driver::<TextDisplayDriver>()?;
+    let result = measure("text_redraw", REDRAW_ITERATIONS, |iteration| {
+        if let Some(driver) = display_manager.get_driver::<TextDisplayDriver>() {
+            driver.fill((b'A' + (iteration % 26) as u8) as char);
+        }
+        if let Err(err) = display_manager.draw_all() {
+            log::debug!("Failed to draw during benchmark: {}", err);
+        }
+    });
+    if let Some(driver) = display_manager.get_driver::<TextDisplayDriver>() {
+        driver.clear_buffer();
+    }
+    Some(result)
+}
+
+struct CountingHandler(u64);
+
+impl EventHandler for CountingHandler {
+    fn handle(&mut self, _event: Event) {
+        self.0 += 1;
+    }
+}
+
+/// Pushes and dispatches events through a dispatcher of its own, so no other handler sees them.
+fn event_dispatch() -> BenchResult {
+    let dispatcher = EventDispatcher::new();
+    let handler = Arc::new(Mutex::new(CountingHandler(0)));
+    dispatcher.register(handler.clone());
+    let result = measure("event_dispatch", DISPATCH_ITERATIONS / DISPATCH_BATCH, |_| {
+        for _ in 0..DISPATCH_BATCH {
+            dispatcher.push(Event::Scancode(0));
+        }
+        dispatcher.dispatch();
+    });
+    let dispatched = handler.lock().0;
+    BenchResult { iterations: dispatched, ..result }
+}
\ No newline at end of file
@@ -0,0 +1,34 @@
+use alloc::string::String;
+use spin::Mutex;
+
+/// The most bytes the clipboard holds, longer text is cut off.
+static MAX_LENGTH: usize = 64 * 1024;
+
+static CLIPBOARD: Mutex<String> = Mutex::new(String::new());
+
+/// Replaces the contents of the clipboard. Text beyond `MAX_LENGTH` bytes is cut off at the
+/// character boundary before it.
+pub fn set(text: &str) {
+    let mut end = text.len().min(MAX_LENGTH);
+    while !text.is_char_boundary(end) { end -= 1; }
+
+    let mut clipboard = CLIPBOARD.lock();
+    clipboard.clear();
+    clipboard.push_str(&text[..end]);
+}
+
+/// Returns a copy of the contents of the clipboard.
+#[allow(dead_code)]
+pub fn get() -> String {
+    CLIPBOARD.lock().clone()
+}
+
+#[allow(dead_code)]
+pub fn clear() {
+    CLIPBOARD.lock().clear();
+}
+
+#[allow(dead_code)]
+pub fn is_empty() -> bool {
+    CLIPBOARD.lock().is_empty()
+}
\ No newline at end of file
@@ -0,0 +1,75 @@
+use alloc::{format, vec};
+use alloc::string::String;
+use crate::api::control::ControlCommand;
+use crate::api::event::{Event, EventHandler};
+use crate::internal::framebuffer::PixelFormat;
+
+static MAX_LINE_LENGTH: usize = 128;
+
+/// Collects the bytes received on the control serial port into lines and turns them into commands.
+pub struct ControlChannel {
+    line: String
+} impl ControlChannel {
+    pub fn new() -> Self { Self {
+        line: String::new()
+    } }
+
+    fn receive(&mut self, byte: u8) {
+        match byte {
+            b'\r' | b'\n' => {
+                if self.line.trim().is_empty() { return; }
+
+                match ControlCommand::parse(self.line.trim()) {
+                    Ok(command) => {
+                        log::info!("Received control command: {:?}", command);
+                        crate::api::event::EventDispatcher::global().push(Event::Control(command));
+                    }, Err(message) => {
+                        crate::internal::serial::write_control(format_args!("ERR {}\n", message));
+                    }
+                }
+                self.line.clear();
+            }, byte if byte.is_ascii() && !byte.is_ascii_control() => {
+                if self.line.len() < MAX_LINE_LENGTH {
+                    self.line.push(byte as char);
+                } else {
+                    crate::internal::serial::write_control(format_args!("ERR Line too long\n"));
+                    self.line.clear();
+                }
+            }, _ => {}
+        }
+    }
+} impl EventHandler for ControlChannel {
+    fn handle(&mut self, event: Event) {
+        if let Event::ControlInput(byte) = event {
+            self.receive(byte);
+        }
+    }
+}
+
+/// Sends the current frame buffer contents over the control port as a binary PPM image.
+/// The image is preceded by a `SCREENSHOT <length>` line so the host side knows how much to read.
+pub fn send_screenshot() -> bool {
+    crate::internal::framebuffer::with_framebuffer(|fb, info| {
+        let header = format!("P6\n{} {}\n255\n", info.width, info.height);
+        let length = header.len() + info.width * info.height * 3;
+        crate::internal::serial::write_control(format_args!("SCREENSHOT {}\n", length));
+        crate::internal::serial::write_control_raw(header.as_bytes());
+
+        let mut row = vec![0u8; info.width * 3];
+        for y in 0..info.height {
+            for x in 0..info.width {
+                let offset = (y * info.stride + x) * info.bytes_per_pixel;
+                let pixel = &fb[offset..offset + info.bytes_per_pixel];
+                let (red, green, blue) = match info.pixel_format {
+                    PixelFormat::Rgb => (pixel[0], pixel[1], pixel[2]),
+                    PixelFormat::Bgr => (pixel[2], pixel[1], pixel[0]),
+                    _ => (pixel[0], pixel[0], pixel[0])
+                };
+                row[x * 3] = red;
+                row[x * 3 + 1] = green;
+                row[x * 3 + 2] = blue;
+            }
+            crate::internal::serial::write_control_raw(&row);
+        }
+    }).is_ok()
+}
\ No newline at end of file
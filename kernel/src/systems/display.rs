@@ -1,7 +1,9 @@
 use alloc::string::ToString;
 use alloc::vec;
 use alloc::vec::Vec;
-use bootloader_api::info::{FrameBufferInfo, PixelFormat};
+use core::fmt::{Display, Formatter};
+use spin::Once;
+use crate::internal::framebuffer::FrameBufferInfo;
 use embedded_graphics::geometry::{Dimensions, Point};
 use embedded_graphics::mono_font::{MonoFont, MonoTextStyle};
 use embedded_graphics::{Drawable, Pixel};
@@ -10,41 +12,96 @@ use embedded_graphics::prelude::DrawTarget;
 use embedded_graphics::primitives::Rectangle;
 use embedded_graphics::text::{DecorationColor, Text, TextStyle};
 use embedded_graphics::text::renderer::CharacterStyle;
-use crate::api::display::{Color, DisplayApi, Position, TextAlignment, TextBaseline, TextLineHeight};
+use crate::api::display::{Color, DisplayApi, DisplayError, Position, Region, Size, TextAlignment, TextBaseline, TextLineHeight};
+use crate::api::error::KernelError;
+
+trait DisplayContext: Sized + DrawTarget<Color = Rgb888, Error = DisplayError> {
+    /// Creates the context for the screen of the output.
+    fn new(output: usize) -> Result<Self, DisplayError>;
+    fn set_pixel(&mut self, position: Position, color: Color) -> Result<(), DisplayError>;
+    /// Mixes the color into the pixel according to the color's alpha.
+    fn blend_pixel(&mut self, position: Position, color: Color) -> Result<(), DisplayError>;
+    fn swap(&mut self) -> Result<(), DisplayError>;
+    fn info(&self) -> Result<FrameBufferInfo, DisplayError>;
+    fn clip(&self) -> Option<Region>;
+    /// The alpha pixels drawn through `DrawTarget` get, as embedded-graphics colors have none.
+    fn alpha(&self) -> u8;
+    fn set_alpha(&mut self, alpha: u8);
+
+    /// Returns the part of the screen drawing is allowed to, None if nothing can be drawn.
+    fn visible_region(&self) -> Result<Option<Region>, DisplayError> {
+        let info = self.info()?;
+        let screen = Region::new(Position::new(0, 0), Size::new(info.width, info.height));
+        Ok(match self.clip() {
+            Some(clip) => screen.intersection(clip),
+            None => Some(screen)
+        })
+    }
 
-trait DisplayContext {
-    fn new() -> Self;
-    fn set_pixel(&mut self, position: Position, color: Color);
-    fn swap(&mut self);
-}
+    /// Draws the pixel, blending it if it is not opaque.
+    fn draw_pixel(&mut self, position: Position, color: Color) -> Result<(), DisplayError> {
+        let Some(region) = self.visible_region()? else { return Ok(()); };
+        if !region.contains(position) { return Ok(()); }
 
-pub struct SimpleDisplay {
-    context: SimpleDisplayContext
-} impl SimpleDisplay {
-    pub fn new() -> Self {
-        Self { context: SimpleDisplayContext::new() }
+        if color.is_opaque() {
+            self.set_pixel(position, color)
+        } else {
+            self.blend_pixel(position, color)
+        }
     }
-} impl DisplayApi for SimpleDisplay {
-    fn draw(&mut self, buffer: &[u8]) {
-        crate::internal::framebuffer::with_framebuffer(|fb, _| {
-            if buffer.len() != fb.len() {
-                panic!("Frame buffer data does not match the expected size!");
+
+    /// Fills the region with the color, blending it if it is not opaque.
+    fn fill_rect(&mut self, region: Region, color: Color) -> Result<(), DisplayError> {
+        let Some(visible) = self.visible_region()? else { return Ok(()); };
+        let Some(region) = region.intersection(visible) else { return Ok(()); };
+
+        for y in region.position.y..region.position.y + region.size.height {
+            for x in region.position.x..region.position.x + region.size.width {
+                if color.is_opaque() {
+                    self.set_pixel(Position::new(x, y), color)?;
+                } else {
+                    self.blend_pixel(Position::new(x, y), color)?;
+                }
             }
+        }
+        Ok(())
+    }
 
-            for (i, byte) in buffer.iter().enumerate() {
-                fb[i] = *byte;
+    /// Draws pixels coming from embedded-graphics, skipping the ones outside of the visible region.
+    fn draw_pixels<I>(&mut self, pixels: I) -> Result<(), DisplayError>
+        where I: IntoIterator<Item = Pixel<Rgb888>> {
+
+        let Some(region) = self.visible_region()? else { return Ok(()); };
+        let alpha = self.alpha();
+        for pixel in pixels.into_iter() {
+            let Pixel(point, color) = pixel;
+            if point.x < 0 || point.y < 0 { continue; }
+            let position = Position::new(point.x as usize, point.y as usize);
+            if !region.contains(position) { continue; }
+
+            let color = Color::with_alpha(color.r(), color.g(), color.b(), alpha);
+            if color.is_opaque() {
+                self.set_pixel(position, color)?;
+            } else {
+                self.blend_pixel(position, color)?;
             }
-        }).unwrap_or_else(|| panic!("No framebuffer available when drawing to display!"));
+        }
+
+        Ok(())
     }
 
-    fn draw_char(
-        &mut self, character: char, position: Position,
+    /// Draws the text, the alpha of the text and background colors is honored.
+    fn draw_text(
+        &mut self, text: &str, position: Position,
         text_color: Color, background_color: Option<Color>,
         font: MonoFont, underline: bool, strikethrough: bool,
         baseline: TextBaseline, alignment: TextAlignment, line_height: TextLineHeight
-    ) {
+    ) -> Result<(), DisplayError> {
         let mut font_style = MonoTextStyle::new(&font, text_color.into());
-        font_style.background_color = background_color.map(|color| color.into());
+        // A translucent background is blended separately, embedded-graphics only draws opaque ones
+        font_style.background_color = background_color
+            .filter(|color| color.is_opaque())
+            .map(|color| color.into());
 
         if underline { font_style.set_underline_color(DecorationColor::TextColor); }
         if strikethrough { font_style.set_strikethrough_color(DecorationColor::TextColor); }
@@ -54,75 +111,228 @@ pub struct SimpleDisplay {
         text_style.alignment = alignment.into();
         text_style.line_height = line_height.into();
 
-        let binding = character.to_string();
         let text = Text::with_text_style(
-            &*binding, Point::new(position.x as i32, position.y as i32),
+            text, Point::new(position.x as i32, position.y as i32),
             font_style, text_style
         );
 
-        if let Err(_) = text.draw(&mut self.context) {
-            panic!("Failed to draw character!")
+        if let Some(background_color) = background_color.filter(|color| !color.is_opaque()) {
+            if let Some(region) = rectangle_to_region(text.bounding_box()) {
+                self.fill_rect(region, background_color)?;
+            }
         }
+
+        self.set_alpha(text_color.alpha);
+        let result = text.draw(self).map(|_| ());
+        self.set_alpha(u8::MAX);
+        result
     }
+}
 
-    fn draw_text(
-        &mut self, text: &str, position: Position,
-        text_color: Color, background_color: Option<Color>,
-        font: MonoFont, underline: bool, strikethrough: bool,
-        baseline: TextBaseline, alignment: TextAlignment, line_height: TextLineHeight
-    ) {
-        let mut font_style = MonoTextStyle::new(&font, text_color.into());
-        font_style.background_color = background_color.map(|color| color.into());
+static TRANSFORM: Once<DisplayTransform> = Once::new();
 
-        if underline { font_style.set_underline_color(DecorationColor::TextColor); }
-        if strikethrough { font_style.set_strikethrough_color(DecorationColor::TextColor); }
+/// How far the picture is turned clockwise on the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Upright,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270
+}
 
-        let mut text_style = TextStyle::default();
-        text_style.baseline = baseline.into();
-        text_style.alignment = alignment.into();
-        text_style.line_height = line_height.into();
+/// Rotation and integer scaling applied when a buffered display is swapped to the screen, for
+/// rotated panels and screens where the fonts are too small. Drawing happens on a back buffer with
+/// the rotated size divided by the scale; what doesn't divide evenly stays black at the edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayTransform {
+    pub rotation: Rotation,
+    /// Every drawn pixel becomes a square of this many screen pixels, 1 to 3.
+    pub scale: usize
+} impl DisplayTransform {
+    pub const fn identity() -> Self { Self {
+        rotation: Rotation::Upright,
+        scale: 1
+    } }
+
+    /// Parses the rotation in degrees (0, 90, 180 or 270) and the scale (1 to 3), both optional.
+    pub fn parse(rotation: Option<&str>, scale: Option<&str>) -> Result<Self, &'static str> {
+        let rotation = match rotation {
+            None | Some("0") => Rotation::Upright,
+            Some("90") => Rotation::Clockwise90,
+            Some("180") => Rotation::Clockwise180,
+            Some("270") => Rotation::Clockwise270,
+            Some(_) => return Err("Rotation must be 0, 90, 180 or 270")
+        };
+        let scale = match scale {
+            None => 1,
+            Some(scale) => scale.parse().ok().filter(|scale| (1..=3).contains(scale))
+                .ok_or("Scale must be 1, 2 or 3")?
+        };
+        Ok(Self { rotation, scale })
+    }
 
-        let text = Text::with_text_style(
-            text, Point::new(position.x as i32, position.y as i32),
-            font_style, text_style
-        );
+    pub fn is_identity(&self) -> bool {
+        *self == Self::identity()
+    }
 
-        if let Err(_) = text.draw(&mut self.context) {
-            panic!("Failed to draw text!")
+    /// Returns the layout drawing happens in on a screen with the layout of `info`.
+    fn logical_info(&self, info: FrameBufferInfo) -> FrameBufferInfo {
+        if self.is_identity() {
+            return info;
         }
+        let (width, height) = match self.rotation {
+            Rotation::Upright | Rotation::Clockwise180 => (info.width, info.height),
+            Rotation::Clockwise90 | Rotation::Clockwise270 => (info.height, info.width)
+        };
+        let (width, height) = (width / self.scale, height / self.scale);
+        FrameBufferInfo { byte_len: width * height * info.bytes_per_pixel, width, height, stride: width, ..info }
     }
 
-    fn clear(&mut self, color: Color) {
-        crate::internal::framebuffer::with_framebuffer(|fb, info| {
-            for byte_offset in (0..fb.len()).step_by(info.bytes_per_pixel) {
-                set_pixel_in_at(fb, info, byte_offset, color);
+    /// Copies the logical buffer to the screen, turning and enlarging every pixel.
+    fn apply(&self, target: &mut [u8], target_info: FrameBufferInfo, source: &[u8], source_info: FrameBufferInfo) {
+        let (width, height, scale) = (source_info.width, source_info.height, self.scale);
+        let bytes_per_pixel = target_info.bytes_per_pixel;
+        let target_row = target_info.stride * bytes_per_pixel;
+        for y in 0..height {
+            for x in 0..width {
+                let (column, row) = match self.rotation {
+                    Rotation::Upright => (x, y),
+                    Rotation::Clockwise90 => (height - 1 - y, x),
+                    Rotation::Clockwise180 => (width - 1 - x, height - 1 - y),
+                    Rotation::Clockwise270 => (y, width - 1 - x)
+                };
+                let start = (y * width + x) * bytes_per_pixel;
+                let pixel = &source[start..start + bytes_per_pixel];
+                let first = (row * scale * target_info.stride + column * scale) * bytes_per_pixel;
+                for line in 0..scale {
+                    let start = first + line * target_row;
+                    crate::internal::blit::fill(&mut target[start..start + scale * bytes_per_pixel], pixel);
+                }
             }
-        }).unwrap_or_else(|| panic!("No framebuffer available when clearing display!"));
+        }
+    }
+} impl Display for DisplayTransform {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let degrees = match self.rotation {
+            Rotation::Upright => 0,
+            Rotation::Clockwise90 => 90,
+            Rotation::Clockwise180 => 180,
+            Rotation::Clockwise270 => 270
+        };
+        write!(f, "rotated by {} degrees, scaled {}x", degrees, self.scale)
     }
+}
+
+/// Sets the transform buffered displays created from now on use. Can only be set once, at boot.
+pub fn set_transform(transform: DisplayTransform) {
+    TRANSFORM.call_once(|| transform);
+}
 
-    fn swap(&mut self) { self.context.swap(); }
+/// Returns the transform of buffered displays.
+pub fn transform() -> DisplayTransform {
+    TRANSFORM.get().copied().unwrap_or(DisplayTransform::identity())
+}
 
-    fn get_info(&self) -> FrameBufferInfo {
-        crate::internal::framebuffer::with_framebuffer(|_, info| info)
-            .unwrap_or_else(|| panic!("No framebuffer available when getting info!"))
+pub struct SimpleDisplay {
+    context: SimpleDisplayContext
+} impl SimpleDisplay {
+    /// Creates a display drawing straight to the screen of the output.
+    pub fn new(output: usize) -> Result<Self, DisplayError> {
+        Ok(Self { context: SimpleDisplayContext::new(output)? })
     }
+
+    /// Creates a simple display that writes to the primary frame buffer without taking any locks.
+    /// Only meant for fatal paths where the interrupted code might still hold the frame buffer lock.
+    pub unsafe fn emergency() -> Self {
+        Self { context: SimpleDisplayContext { output: 0, unlocked: true, clip: None, alpha: u8::MAX } }
+    }
+} impl DisplayApi for SimpleDisplay {
+    fn draw(&mut self, buffer: &[u8]) -> Result<(), DisplayError> {
+        let Some(region) = self.context.visible_region()? else { return Ok(()); };
+        self.context.with_framebuffer(|fb, info| {
+            copy_region(fb, buffer, info, region)
+        }).map_err(|_| DisplayError::NoFrameBuffer)?
+    }
+
+    fn draw_pixel(&mut self, position: Position, color: Color) -> Result<(), DisplayError> {
+        self.context.draw_pixel(position, color)
+    }
+
+    fn fill_rect(&mut self, region: Region, color: Color) -> Result<(), DisplayError> {
+        self.context.fill_rect(region, color)
+    }
+
+    fn draw_char(
+        &mut self, character: char, position: Position,
+        text_color: Color, background_color: Option<Color>,
+        font: MonoFont, underline: bool, strikethrough: bool,
+        baseline: TextBaseline, alignment: TextAlignment, line_height: TextLineHeight
+    ) -> Result<(), DisplayError> {
+        self.context.draw_text(
+            &character.to_string(), position,
+            text_color, background_color,
+            font, underline, strikethrough,
+            baseline, alignment, line_height
+        )
+    }
+
+    fn draw_text(
+        &mut self, text: &str, position: Position,
+        text_color: Color, background_color: Option<Color>,
+        font: MonoFont, underline: bool, strikethrough: bool,
+        baseline: TextBaseline, alignment: TextAlignment, line_height: TextLineHeight
+    ) -> Result<(), DisplayError> {
+        self.context.draw_text(
+            text, position,
+            text_color, background_color,
+            font, underline, strikethrough,
+            baseline, alignment, line_height
+        )
+    }
+
+    fn blit(&mut self, pixels: &[u8], info: FrameBufferInfo, position: Position) -> Result<(), DisplayError> {
+        let Some(region) = self.context.visible_region()? else { return Ok(()); };
+        self.context.with_framebuffer(|fb, fb_info| {
+            blit_buffer(fb, fb_info, region, pixels, info, position)
+        }).map_err(|_| DisplayError::NoFrameBuffer)?
+    }
+
+    fn clear(&mut self, color: Color) -> Result<(), DisplayError> {
+        let Some(region) = self.context.visible_region()? else { return Ok(()); };
+        self.context.with_framebuffer(|fb, info| {
+            fill_region(fb, info, region, color)
+        }).map_err(|_| DisplayError::NoFrameBuffer)?
+    }
+
+    fn swap(&mut self) -> Result<(), DisplayError> { self.context.swap() }
+
+    fn get_info(&self) -> Result<FrameBufferInfo, DisplayError> { self.context.info() }
+
+    fn set_clip(&mut self, clip: Option<Region>) { self.context.clip = clip; }
+
+    fn get_clip(&self) -> Option<Region> { self.context.clip }
 }
 
 pub struct BufferedDisplay {
     context: BufferedDisplayContext
 } impl BufferedDisplay {
-    pub fn new() -> Self {
-        Self { context: BufferedDisplayContext::new() }
+    /// Creates a display drawing to a back buffer that is swapped to the screen of the output.
+    pub fn new(output: usize) -> Result<Self, DisplayError> {
+        Ok(Self { context: BufferedDisplayContext::new(output)? })
     }
 } impl DisplayApi for BufferedDisplay {
-    fn draw(&mut self, buffer: &[u8]) {
-        if buffer.len() != self.context.back_buffer.len() {
-            panic!("Buffer data does not match the expected size!");
-        }
+    fn draw(&mut self, buffer: &[u8]) -> Result<(), DisplayError> {
+        let Some(region) = self.context.visible_region()? else { return Ok(()); };
+        let info = self.context.info()?;
+        copy_region(&mut self.context.back_buffer, buffer, info, region)
+    }
 
-        for (i, byte) in buffer.iter().enumerate() {
-            self.context.back_buffer[i] = *byte;
-        }
+    fn draw_pixel(&mut self, position: Position, color: Color) -> Result<(), DisplayError> {
+        self.context.draw_pixel(position, color)
+    }
+
+    fn fill_rect(&mut self, region: Region, color: Color) -> Result<(), DisplayError> {
+        self.context.fill_rect(region, color)
     }
 
     fn draw_char(
@@ -130,27 +340,94 @@ pub struct BufferedDisplay {
         text_color: Color, background_color: Option<Color>,
         font: MonoFont, underline: bool, strikethrough: bool,
         baseline: TextBaseline, alignment: TextAlignment, line_height: TextLineHeight
-    ) {
-        let mut font_style = MonoTextStyle::new(&font, text_color.into());
-        font_style.background_color = background_color.map(|color| color.into());
+    ) -> Result<(), DisplayError> {
+        self.context.draw_text(
+            &character.to_string(), position,
+            text_color, background_color,
+            font, underline, strikethrough,
+            baseline, alignment, line_height
+        )
+    }
 
-        if underline { font_style.set_underline_color(DecorationColor::TextColor); }
-        if strikethrough { font_style.set_strikethrough_color(DecorationColor::TextColor); }
+    fn draw_text(
+        &mut self, text: &str, position: Position,
+        text_color: Color, background_color: Option<Color>,
+        font: MonoFont, underline: bool, strikethrough: bool,
+        baseline: TextBaseline, alignment: TextAlignment, line_height: TextLineHeight
+    ) -> Result<(), DisplayError> {
+        self.context.draw_text(
+            text, position,
+            text_color, background_color,
+            font, underline, strikethrough,
+            baseline, alignment, line_height
+        )
+    }
 
-        let mut text_style = TextStyle::default();
-        text_style.baseline = baseline.into();
-        text_style.alignment = alignment.into();
-        text_style.line_height = line_height.into();
+    fn blit(&mut self, pixels: &[u8], info: FrameBufferInfo, position: Position) -> Result<(), DisplayError> {
+        let Some(region) = self.context.visible_region()? else { return Ok(()); };
+        let back_buffer_info = self.context.info()?;
+        blit_buffer(&mut self.context.back_buffer, back_buffer_info, region, pixels, info, position)
+    }
 
-        let binding = character.to_string();
-        let text = Text::with_text_style(
-            &*binding, Point::new(position.x as i32, position.y as i32),
-            font_style, text_style
-        );
+    fn clear(&mut self, color: Color) -> Result<(), DisplayError> {
+        let Some(region) = self.context.visible_region()? else { return Ok(()); };
+        let info = self.context.info()?;
+        fill_region(&mut self.context.back_buffer, info, region, color)
+    }
 
-        if let Err(_) = text.draw(&mut self.context) {
-            panic!("Failed to draw character!")
-        }
+    fn swap(&mut self) -> Result<(), DisplayError> { self.context.swap() }
+
+    fn get_info(&self) -> Result<FrameBufferInfo, DisplayError> { self.context.info() }
+
+    fn set_clip(&mut self, clip: Option<Region>) { self.context.clip = clip; }
+
+    fn get_clip(&self) -> Option<Region> { self.context.clip }
+}
+
+/// An owned pixel buffer in the pixel format of the frame buffer. Content that rarely changes can
+/// be drawn into it once and then blitted to a display every frame, instead of being re-rendered.
+pub struct Surface {
+    context: SurfaceContext
+} #[allow(dead_code)] impl Surface {
+    pub fn new(size: Size) -> Result<Self, DisplayError> {
+        Ok(Self { context: SurfaceContext::with_size(size)? })
+    }
+
+    pub fn get_size(&self) -> Size {
+        Size::new(self.context.info.width, self.context.info.height)
+    }
+
+    /// Copies the whole surface to the position on the display, honoring the display's clip.
+    pub fn blit_to(&self, display: &mut dyn DisplayApi, position: Position) -> Result<(), DisplayError> {
+        display.blit(&self.context.buffer, self.context.info, position)
+    }
+} impl DisplayApi for Surface {
+    fn draw(&mut self, buffer: &[u8]) -> Result<(), DisplayError> {
+        let Some(region) = self.context.visible_region()? else { return Ok(()); };
+        let info = self.context.info;
+        copy_region(&mut self.context.buffer, buffer, info, region)
+    }
+
+    fn draw_pixel(&mut self, position: Position, color: Color) -> Result<(), DisplayError> {
+        self.context.draw_pixel(position, color)
+    }
+
+    fn fill_rect(&mut self, region: Region, color: Color) -> Result<(), DisplayError> {
+        self.context.fill_rect(region, color)
+    }
+
+    fn draw_char(
+        &mut self, character: char, position: Position,
+        text_color: Color, background_color: Option<Color>,
+        font: MonoFont, underline: bool, strikethrough: bool,
+        baseline: TextBaseline, alignment: TextAlignment, line_height: TextLineHeight
+    ) -> Result<(), DisplayError> {
+        self.context.draw_text(
+            &character.to_string(), position,
+            text_color, background_color,
+            font, underline, strikethrough,
+            baseline, alignment, line_height
+        )
     }
 
     fn draw_text(
@@ -158,151 +435,232 @@ pub struct BufferedDisplay {
         text_color: Color, background_color: Option<Color>,
         font: MonoFont, underline: bool, strikethrough: bool,
         baseline: TextBaseline, alignment: TextAlignment, line_height: TextLineHeight
-    ) {
-        let mut font_style = MonoTextStyle::new(&font, text_color.into());
-        font_style.background_color = background_color.map(|color| color.into());
+    ) -> Result<(), DisplayError> {
+        self.context.draw_text(
+            text, position,
+            text_color, background_color,
+            font, underline, strikethrough,
+            baseline, alignment, line_height
+        )
+    }
 
-        if underline { font_style.set_underline_color(DecorationColor::TextColor); }
-        if strikethrough { font_style.set_strikethrough_color(DecorationColor::TextColor); }
+    fn blit(&mut self, pixels: &[u8], info: FrameBufferInfo, position: Position) -> Result<(), DisplayError> {
+        let Some(region) = self.context.visible_region()? else { return Ok(()); };
+        let surface_info = self.context.info;
+        blit_buffer(&mut self.context.buffer, surface_info, region, pixels, info, position)
+    }
 
-        let mut text_style = TextStyle::default();
-        text_style.baseline = baseline.into();
-        text_style.alignment = alignment.into();
-        text_style.line_height = line_height.into();
+    fn clear(&mut self, color: Color) -> Result<(), DisplayError> {
+        let Some(region) = self.context.visible_region()? else { return Ok(()); };
+        let info = self.context.info;
+        fill_region(&mut self.context.buffer, info, region, color)
+    }
 
-        let text = Text::with_text_style(
-            text, Point::new(position.x as i32, position.y as i32),
-            font_style, text_style
-        );
+    fn swap(&mut self) -> Result<(), DisplayError> { self.context.swap() }
+
+    fn get_info(&self) -> Result<FrameBufferInfo, DisplayError> { self.context.info() }
+
+    fn set_clip(&mut self, clip: Option<Region>) { self.context.clip = clip; }
+
+    fn get_clip(&self) -> Option<Region> { self.context.clip }
+}
 
-        if let Err(_) = text.draw(&mut self.context) {
-            panic!("Failed to draw text!")
+struct SimpleDisplayContext {
+    output: usize,
+    unlocked: bool,
+    clip: Option<Region>,
+    alpha: u8
+} impl SimpleDisplayContext {
+    fn with_framebuffer<F, R>(&self, func: F) -> Result<R, KernelError>
+        where F: FnOnce(&mut [u8], FrameBufferInfo) -> R {
+
+        if self.unlocked {
+            unsafe { crate::internal::framebuffer::with_framebuffer_unlocked(func) }
+        } else {
+            crate::internal::framebuffer::with_output(self.output, func)
         }
     }
+} impl DisplayContext for SimpleDisplayContext {
+    fn new(output: usize) -> Result<Self, DisplayError> { Ok(Self {
+        output,
+        unlocked: false,
+        clip: None,
+        alpha: u8::MAX
+    }) }
+
+    fn set_pixel(&mut self, position: Position, color: Color) -> Result<(), DisplayError> {
+        self.with_framebuffer(|fb, info| {
+            set_pixel_in_at(fb, info, pixel_offset(info, position)?, color)
+        }).map_err(|_| DisplayError::NoFrameBuffer)?
+    }
 
-    fn clear(&mut self, color: Color) {
-        crate::internal::framebuffer::with_framebuffer(|_, info| {
-            for byte_offset in (0..self.context.back_buffer.len()).step_by(info.bytes_per_pixel) {
-                set_pixel_in_at(&mut self.context.back_buffer, info, byte_offset, color);
-            }
-        }).unwrap_or_else(|| panic!("No framebuffer available when clearing display!"));
+    fn blend_pixel(&mut self, position: Position, color: Color) -> Result<(), DisplayError> {
+        self.with_framebuffer(|fb, info| {
+            let offset = pixel_offset(info, position)?;
+            let background = get_pixel_in_at(fb, info, offset)?;
+            set_pixel_in_at(fb, info, offset, color.blend_over(background))
+        }).map_err(|_| DisplayError::NoFrameBuffer)?
     }
 
-    fn swap(&mut self) { self.context.swap(); }
+    fn swap(&mut self) -> Result<(), DisplayError> { Ok(()) }
 
-    fn get_info(&self) -> FrameBufferInfo {
-        crate::internal::framebuffer::with_framebuffer(|_, info| info)
-            .unwrap_or_else(|| panic!("No framebuffer available when getting info!"))
+    fn info(&self) -> Result<FrameBufferInfo, DisplayError> {
+        self.with_framebuffer(|_, info| info).map_err(|_| DisplayError::NoFrameBuffer)
     }
-}
 
-struct SimpleDisplayContext;
-impl DisplayContext for SimpleDisplayContext {
-    fn new() -> Self { Self {} }
+    fn clip(&self) -> Option<Region> { self.clip }
 
-    fn set_pixel(&mut self, position: Position, color: Color) {
-        crate::internal::framebuffer::with_framebuffer(|fb, info| {
-            let byte_offset = {
-                let line_offset = position.y * info.stride;
-                let pixel_offset = line_offset + position.x;
-                pixel_offset * info.bytes_per_pixel
-            };
+    fn alpha(&self) -> u8 { self.alpha }
 
-            set_pixel_in_at(fb, info, byte_offset, color);
-        }).unwrap_or_else(|| panic!("No framebuffer available when setting pixel!"));
-    }
-
-    fn swap(&mut self) {}
+    fn set_alpha(&mut self, alpha: u8) { self.alpha = alpha; }
 } impl DrawTarget for SimpleDisplayContext {
     type Color = Rgb888;
-    type Error = core::convert::Infallible;
+    type Error = DisplayError;
 
     fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
         where I: IntoIterator<Item = Pixel<Self::Color>> {
-
-        for pixel in pixels.into_iter() {
-            let Pixel(point, color) = pixel;
-            self.set_pixel(Position::new(
-                point.x as usize,
-                point.y as usize
-            ), Color::new(
-                color.r(),
-                color.g(),
-                color.b()
-            ));
-        }
-
-        Ok(())
+        self.draw_pixels(pixels)
     }
 } impl Dimensions for SimpleDisplayContext {
     fn bounding_box(&self) -> Rectangle {
-        crate::internal::framebuffer::with_framebuffer(|_, info| {
-            get_bounds(info)
-        }).unwrap_or_else(|| panic!("No framebuffer available when getting bounds!"))
+        // Without a frame buffer there is nothing to draw to, setting pixels reports the error
+        self.with_framebuffer(|_, info| get_bounds(info))
+            .unwrap_or(Rectangle::zero())
     }
 }
 
 struct BufferedDisplayContext {
+    output: usize,
     back_buffer: Vec<u8>,
+    transform: DisplayTransform,
+    clip: Option<Region>,
+    alpha: u8
 } impl DisplayContext for BufferedDisplayContext {
-    fn new() -> Self {
-        let fb_len = crate::internal::framebuffer::with_framebuffer(|fb, _| {
-            fb.len()
-        }).unwrap_or_else(|| panic!("No framebuffer available when creating buffered display context!"));
-
-        Self { back_buffer: vec![0; fb_len] }
+    fn new(output: usize) -> Result<Self, DisplayError> {
+        let transform = transform();
+        let length = crate::internal::framebuffer::with_output(output, |fb, info| {
+            if transform.is_identity() { return fb.len(); }
+            // The edges the logical buffer doesn't reach are never drawn again
+            fb.fill(0);
+            transform.logical_info(info).byte_len
+        }).map_err(|_| DisplayError::NoFrameBuffer)?;
+
+        Ok(Self { output, back_buffer: vec![0; length], transform, clip: None, alpha: u8::MAX })
     }
 
-    fn set_pixel(&mut self, position: Position, color: Color) {
-        crate::internal::framebuffer::with_framebuffer(|_, info| {
-            let byte_offset = {
-                let line_offset = position.y * info.stride;
-                let pixel_offset = line_offset + position.x;
-                pixel_offset * info.bytes_per_pixel
-            };
+    fn set_pixel(&mut self, position: Position, color: Color) -> Result<(), DisplayError> {
+        let info = self.info()?;
+        set_pixel_in_at(&mut self.back_buffer, info, pixel_offset(info, position)?, color)
+    }
 
-            set_pixel_in_at(&mut self.back_buffer, info, byte_offset, color);
-        }).unwrap_or_else(|| panic!("No framebuffer available when setting pixel!"));
+    fn blend_pixel(&mut self, position: Position, color: Color) -> Result<(), DisplayError> {
+        let info = self.info()?;
+        let offset = pixel_offset(info, position)?;
+        let background = get_pixel_in_at(&self.back_buffer, info, offset)?;
+        set_pixel_in_at(&mut self.back_buffer, info, offset, color.blend_over(background))
     }
 
-    fn swap(&mut self) {
-        crate::internal::framebuffer::with_framebuffer(|fb, _| {
-            let frame_buffer_len = fb.len();
-            let back_buffer_len = self.back_buffer.len();
+    fn swap(&mut self) -> Result<(), DisplayError> {
+        crate::internal::framebuffer::with_output(self.output, |fb, info| {
+            let logical_info = self.transform.logical_info(info);
+            let length = if self.transform.is_identity() { fb.len() } else { logical_info.byte_len };
+            if length != self.back_buffer.len() {
+                return Err(DisplayError::SizeMismatch);
+            }
 
-            if frame_buffer_len != back_buffer_len {
-                panic!("Frame buffer and back buffer lengths do not match!");
+            if self.transform.is_identity() {
+                crate::internal::blit::copy(fb, &self.back_buffer);
+            } else {
+                self.transform.apply(fb, info, &self.back_buffer, logical_info);
             }
+            Ok(())
+        }).map_err(|_| DisplayError::NoFrameBuffer)?
+    }
 
-            fb.copy_from_slice(&self.back_buffer);
-        }).unwrap_or_else(|| panic!("No framebuffer available when swapping display!"));
+    fn info(&self) -> Result<FrameBufferInfo, DisplayError> {
+        crate::internal::framebuffer::with_output(self.output, |_, info| self.transform.logical_info(info))
+            .map_err(|_| DisplayError::NoFrameBuffer)
     }
+
+    fn clip(&self) -> Option<Region> { self.clip }
+
+    fn alpha(&self) -> u8 { self.alpha }
+
+    fn set_alpha(&mut self, alpha: u8) { self.alpha = alpha; }
 } impl DrawTarget for BufferedDisplayContext {
     type Color = Rgb888;
-    type Error = core::convert::Infallible;
+    type Error = DisplayError;
 
     fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
         where I: IntoIterator<Item = Pixel<Self::Color>> {
+        self.draw_pixels(pixels)
+    }
+} impl Dimensions for BufferedDisplayContext {
+    fn bounding_box(&self) -> Rectangle {
+        // Without a frame buffer there is nothing to draw to, setting pixels reports the error
+        self.info().map(get_bounds).unwrap_or(Rectangle::zero())
+    }
+}
 
-        for pixel in pixels.into_iter() {
-            let Pixel(point, color) = pixel;
-            self.set_pixel(Position::new(
-                point.x as usize,
-                point.y as usize
-            ), Color::new(
-                color.r(),
-                color.g(),
-                color.b()
-            ));
-        }
+struct SurfaceContext {
+    buffer: Vec<u8>,
+    info: FrameBufferInfo,
+    clip: Option<Region>,
+    alpha: u8
+} impl SurfaceContext {
+    /// Creates a surface of the given size with the pixel format of the frame buffer.
+    fn with_size(size: Size) -> Result<Self, DisplayError> {
+        let fb_info = crate::internal::framebuffer::with_framebuffer(|_, info| info)
+            .map_err(|_| DisplayError::NoFrameBuffer)?;
+        let byte_len = size.width * size.height * fb_info.bytes_per_pixel;
+        let info = FrameBufferInfo {
+            byte_len,
+            width: size.width,
+            height: size.height,
+            pixel_format: fb_info.pixel_format,
+            bytes_per_pixel: fb_info.bytes_per_pixel,
+            stride: size.width
+        };
+        Ok(Self { buffer: vec![0; byte_len], info, clip: None, alpha: u8::MAX })
+    }
+} impl DisplayContext for SurfaceContext {
+    /// Creates a surface the size of the screen of the output.
+    fn new(output: usize) -> Result<Self, DisplayError> {
+        let info = crate::internal::framebuffer::with_output(output, |_, info| info)
+            .map_err(|_| DisplayError::NoFrameBuffer)?;
+        Self::with_size(Size::new(info.width, info.height))
+    }
 
-        Ok(())
+    fn set_pixel(&mut self, position: Position, color: Color) -> Result<(), DisplayError> {
+        set_pixel_in_at(&mut self.buffer, self.info, pixel_offset(self.info, position)?, color)
     }
-} impl Dimensions for BufferedDisplayContext {
+
+    fn blend_pixel(&mut self, position: Position, color: Color) -> Result<(), DisplayError> {
+        let offset = pixel_offset(self.info, position)?;
+        let background = get_pixel_in_at(&self.buffer, self.info, offset)?;
+        set_pixel_in_at(&mut self.buffer, self.info, offset, color.blend_over(background))
+    }
+
+    fn swap(&mut self) -> Result<(), DisplayError> { Ok(()) }
+
+    fn info(&self) -> Result<FrameBufferInfo, DisplayError> { Ok(self.info) }
+
+    fn clip(&self) -> Option<Region> { self.clip }
+
+    fn alpha(&self) -> u8 { self.alpha }
+
+    fn set_alpha(&mut self, alpha: u8) { self.alpha = alpha; }
+} impl DrawTarget for SurfaceContext {
+    type Color = Rgb888;
+    type Error = DisplayError;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where I: IntoIterator<Item = Pixel<Self::Color>> {
+        self.draw_pixels(pixels)
+    }
+} impl Dimensions for SurfaceContext {
     fn bounding_box(&self) -> Rectangle {
-        crate::internal::framebuffer::with_framebuffer(|_, info| {
-            get_bounds(info)
-        }).unwrap_or_else(|| panic!("No framebuffer available when getting bounds!"))
+        get_bounds(self.info)
     }
 }
 
@@ -316,24 +674,139 @@ fn get_bounds(info: FrameBufferInfo) -> Rectangle {
     )
 }
 
-fn set_pixel_in_at(frame_buffer: &mut [u8], frame_buffer_info: FrameBufferInfo, index: usize, color: Color) {
-    let pixel_buffer = &mut frame_buffer[index..index + frame_buffer_info.bytes_per_pixel];
-
-    match frame_buffer_info.pixel_format {
-        PixelFormat::Rgb => {
-            pixel_buffer[0] = color.red;
-            pixel_buffer[1] = color.green;
-            pixel_buffer[2] = color.blue;
-        },
-        PixelFormat::Bgr => {
-            pixel_buffer[0] = color.blue;
-            pixel_buffer[1] = color.green;
-            pixel_buffer[2] = color.red;
-        },
-        PixelFormat::U8 => {
-            let gray = color.red / 3 + color.green / 3 + color.blue / 3;
-            pixel_buffer[0] = gray;
-        },
-        other => panic!("Unsupported pixel format: {:?}", other)
+/// Converts the rectangle into a region, cutting off the parts left of or above the screen.
+fn rectangle_to_region(rectangle: Rectangle) -> Option<Region> {
+    let right = rectangle.top_left.x + rectangle.size.width as i32;
+    let bottom = rectangle.top_left.y + rectangle.size.height as i32;
+    let (left, top) = (rectangle.top_left.x.max(0), rectangle.top_left.y.max(0));
+    (left < right && top < bottom).then(|| Region::new(
+        Position::new(left as usize, top as usize),
+        Size::new((right - left) as usize, (bottom - top) as usize)
+    ))
+}
+
+/// Returns the byte offset of the pixel, or an error if it lies outside of the visible screen.
+fn pixel_offset(info: FrameBufferInfo, position: Position) -> Result<usize, DisplayError> {
+    if position.x >= info.width || position.y >= info.height {
+        return Err(DisplayError::OutOfBounds);
+    }
+    Ok((position.y * info.stride + position.x) * info.bytes_per_pixel)
+}
+
+/// Copies the region from a buffer with the layout of the frame buffer into the target.
+fn copy_region(target: &mut [u8], source: &[u8], info: FrameBufferInfo, region: Region) -> Result<(), DisplayError> {
+    if source.len() != target.len() {
+        return Err(DisplayError::SizeMismatch);
+    }
+    if region.size.width == 0 || region.size.height == 0 {
+        return Ok(());
+    }
+
+    // Full width rows are contiguous, so they are copied in one go
+    if region.position.x == 0 && region.size.width == info.stride {
+        let start = pixel_offset(info, region.position)?;
+        let rows = start..start + region.size.height * info.stride * info.bytes_per_pixel;
+        crate::internal::blit::copy(
+            target.get_mut(rows.clone()).ok_or(DisplayError::OutOfBounds)?,
+            source.get(rows).ok_or(DisplayError::OutOfBounds)?
+        );
+        return Ok(());
     }
+
+    let row_length = region.size.width * info.bytes_per_pixel;
+    for y in region.position.y..region.position.y + region.size.height {
+        let start = pixel_offset(info, Position::new(region.position.x, y))?;
+        let row = start..start + row_length;
+        crate::internal::blit::copy(
+            target.get_mut(row.clone()).ok_or(DisplayError::OutOfBounds)?,
+            source.get(row).ok_or(DisplayError::OutOfBounds)?
+        );
+    }
+    Ok(())
+}
+
+/// Sets every pixel of the region to the color. The color is encoded once and the first row is
+/// filled with it, the other rows are copies of that one.
+fn fill_region(buffer: &mut [u8], info: FrameBufferInfo, region: Region, color: Color) -> Result<(), DisplayError> {
+    if region.size.width == 0 || region.size.height == 0 {
+        return Ok(());
+    }
+
+    let value = info.pixel_format.encode(color.red, color.green, color.blue)
+        .ok_or(DisplayError::UnsupportedPixelFormat)?;
+    let mut pixel = [0; 8];
+    let bytes_per_pixel = info.bytes_per_pixel.min(pixel.len());
+    pixel[..4].copy_from_slice(&value.to_le_bytes());
+
+    let row_length = region.size.width * info.bytes_per_pixel;
+    let first = pixel_offset(info, region.position)?;
+    let last = pixel_offset(info, Position::new(
+        region.position.x, region.position.y + region.size.height - 1
+    ))?;
+    let area = buffer.get_mut(first..last + row_length).ok_or(DisplayError::OutOfBounds)?;
+
+    let (first_row, rest) = area.split_at_mut(row_length);
+    crate::internal::blit::fill(first_row, &pixel[..bytes_per_pixel]);
+    let row_stride = info.stride * info.bytes_per_pixel;
+    for row in 1..region.size.height {
+        let start = row * row_stride - row_length;
+        crate::internal::blit::copy(&mut rest[start..start + row_length], first_row);
+    }
+    Ok(())
+}
+
+/// Copies the pixels of a buffer with the source layout to the position in the target, cutting
+/// off what lies outside of the visible region.
+fn blit_buffer(
+    target: &mut [u8], target_info: FrameBufferInfo, visible: Region,
+    source: &[u8], source_info: FrameBufferInfo, position: Position
+) -> Result<(), DisplayError> {
+    if source_info.pixel_format != target_info.pixel_format
+        || source_info.bytes_per_pixel != target_info.bytes_per_pixel {
+        return Err(DisplayError::UnsupportedPixelFormat);
+    }
+    let destination = Region::new(position, Size::new(source_info.width, source_info.height));
+    let Some(destination) = destination.intersection(visible) else { return Ok(()); };
+
+    let row_length = destination.size.width * target_info.bytes_per_pixel;
+    for row in 0..destination.size.height {
+        let y = destination.position.y + row;
+        let target_start = pixel_offset(target_info, Position::new(destination.position.x, y))?;
+        let source_start = pixel_offset(source_info, Position::new(
+            destination.position.x - position.x, y - position.y
+        ))?;
+        crate::internal::blit::copy(
+            target.get_mut(target_start..target_start + row_length).ok_or(DisplayError::OutOfBounds)?,
+            source.get(source_start..source_start + row_length).ok_or(DisplayError::OutOfBounds)?
+        );
+    }
+    Ok(())
+}
+
+fn set_pixel_in_at(
+    frame_buffer: &mut [u8], frame_buffer_info: FrameBufferInfo, index: usize, color: Color
+) -> Result<(), DisplayError> {
+    let pixel_buffer = frame_buffer.get_mut(index..index + frame_buffer_info.bytes_per_pixel)
+        .ok_or(DisplayError::OutOfBounds)?;
+
+    let value = frame_buffer_info.pixel_format.encode(color.red, color.green, color.blue)
+        .ok_or(DisplayError::UnsupportedPixelFormat)?;
+    let length = pixel_buffer.len().min(4);
+    pixel_buffer[..length].copy_from_slice(&value.to_le_bytes()[..length]);
+    Ok(())
+}
+
+/// Reads the pixel back as an opaque color, the inverse of `set_pixel_in_at`.
+fn get_pixel_in_at(
+    frame_buffer: &[u8], frame_buffer_info: FrameBufferInfo, index: usize
+) -> Result<Color, DisplayError> {
+    let pixel_buffer = frame_buffer.get(index..index + frame_buffer_info.bytes_per_pixel)
+        .ok_or(DisplayError::OutOfBounds)?;
+
+    let mut bytes = [0; 4];
+    let length = pixel_buffer.len().min(4);
+    bytes[..length].copy_from_slice(&pixel_buffer[..length]);
+    let (red, green, blue) = frame_buffer_info.pixel_format.decode(u32::from_le_bytes(bytes))
+        .ok_or(DisplayError::UnsupportedPixelFormat)?;
+    Ok(Color::new(red, green, blue))
 }
\ No newline at end of file
@@ -0,0 +1,60 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::api::event::{Event, EventHandler};
+use crate::api::input::{FocusId, Input, InputConsumer};
+
+/// Routes input to the consumers on the focus stack. The consumer focused last gets the input
+/// first and passes it on to the one below if it doesn't consume it.
+#[derive(Default)]
+pub struct InputRouter {
+    focus_stack: Vec<(FocusId, Arc<Mutex<dyn InputConsumer + Send>>)>,
+    next_id: u64,
+    unhandled: u64
+} #[allow(dead_code)] impl InputRouter {
+    pub fn new() -> Self { Self {
+        focus_stack: Vec::new(),
+        next_id: 0,
+        unhandled: 0
+    } }
+
+    /// Puts the consumer on top of the focus stack.
+    pub fn focus(&mut self, consumer: Arc<Mutex<dyn InputConsumer + Send>>) -> FocusId {
+        let id = FocusId(self.next_id);
+        self.next_id += 1;
+        self.focus_stack.push((id, consumer));
+        id
+    }
+
+    /// Removes the consumer from the focus stack, the focus returns to the one below it.
+    pub fn unfocus(&mut self, id: FocusId) -> bool {
+        let length = self.focus_stack.len();
+        self.focus_stack.retain(|(focus_id, ..)| *focus_id != id);
+        self.focus_stack.len() != length
+    }
+
+    /// Returns the consumer that currently has the focus.
+    pub fn focused(&self) -> Option<FocusId> {
+        self.focus_stack.last().map(|(id, ..)| *id)
+    }
+
+    /// Returns how many inputs no consumer took.
+    pub fn unhandled(&self) -> u64 {
+        self.unhandled
+    }
+
+    fn route(&mut self, input: Input) {
+        for (.., consumer) in self.focus_stack.iter().rev() {
+            if let Some(mut consumer) = consumer.try_lock() {
+                if consumer.on_input(&input) { return; }
+            } else { log::warn!("Input consumer is locked, passing input on."); }
+        }
+        self.unhandled += 1;
+    }
+} impl EventHandler for InputRouter {
+    fn handle(&mut self, event: Event) {
+        if let Event::Input(input) = event {
+            self.route(input);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,144 @@
+use crate::api::event::{Event, EventHandler};
+use crate::api::input::{Input, InputEvent, InputSource};
+use crate::api::keyboard::{KeyCode, Modifiers};
+use crate::internal::keyboard::{CAPS_LOCK_LED, NUM_LOCK_LED, SCROLL_LOCK_LED};
+
+static EXTENDED_PREFIX: u8 = 0xE0;
+static RELEASE_BIT: u8 = 0x80;
+
+/// Decodes scancode set 1 (which the PS/2 controller translates to by default) into keys, keeps
+/// track of the modifiers and mirrors the lock keys on the keyboard LEDs.
+#[derive(Default)]
+pub struct Ps2Keyboard {
+    extended: bool,
+    modifiers: Modifiers
+} #[allow(dead_code)] impl Ps2Keyboard {
+    pub fn new() -> Self { Self {
+        extended: false,
+        modifiers: Modifiers::default()
+    } }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    /// Sets the lock keys and updates the LEDs to match.
+    pub fn set_locks(&mut self, caps_lock: bool, num_lock: bool, scroll_lock: bool) -> bool {
+        self.modifiers.caps_lock = caps_lock;
+        self.modifiers.num_lock = num_lock;
+        self.modifiers.scroll_lock = scroll_lock;
+        self.update_leds()
+    }
+
+    fn update_leds(&self) -> bool {
+        let mut leds = 0;
+        if self.modifiers.scroll_lock { leds |= SCROLL_LOCK_LED; }
+        if self.modifiers.num_lock { leds |= NUM_LOCK_LED; }
+        if self.modifiers.caps_lock { leds |= CAPS_LOCK_LED; }
+        crate::internal::idt::without_interrupts(|| crate::internal::keyboard::set_leds(leds))
+    }
+
+    fn on_scancode(&mut self, scancode: u8) {
+        if scancode == EXTENDED_PREFIX {
+            self.extended = true;
+            return;
+        }
+
+        let pressed = scancode & RELEASE_BIT == 0;
+        let key = Self::decode(scancode & !RELEASE_BIT, self.extended);
+        self.extended = false;
+
+        match key {
+            KeyCode::LeftShift => self.modifiers.left_shift = pressed,
+            KeyCode::RightShift => self.modifiers.right_shift = pressed,
+            KeyCode::LeftControl | KeyCode::RightControl => self.modifiers.control = pressed,
+            KeyCode::LeftAlt | KeyCode::RightAlt => self.modifiers.alt = pressed,
+            KeyCode::CapsLock | KeyCode::NumLock | KeyCode::ScrollLock if pressed => {
+                match key {
+                    KeyCode::CapsLock => self.modifiers.caps_lock = !self.modifiers.caps_lock,
+                    KeyCode::NumLock => self.modifiers.num_lock = !self.modifiers.num_lock,
+                    _ => self.modifiers.scroll_lock = !self.modifiers.scroll_lock
+                }
+                if !self.update_leds() {
+                    log::warn!("Keyboard did not acknowledge the LED update.");
+                }
+            }, _ => {}
+        }
+
+        let dispatcher = crate::api::event::EventDispatcher::global();
+        dispatcher.push(Event::Input(Input::new(InputSource::Keyboard, InputEvent::Key {
+            code: key, pressed, modifiers: self.modifiers
+        })));
+        if pressed && !self.modifiers.control && !self.modifiers.alt {
+            if let Some(character) = key.to_char(self.modifiers) {
+                dispatcher.push(Event::Input(Input::new(InputSource::Keyboard, InputEvent::Text(character))));
+            }
+        }
+    }
+
+    /// Decodes the make code of a key, `extended` if it came after the 0xE0 prefix.
+    pub fn decode(code: u8, extended: bool) -> KeyCode {
+        if extended {
+            return match code {
+                0x1C => KeyCode::KeypadEnter,
+                0x1D => KeyCode::RightControl,
+                0x35 => KeyCode::KeypadSlash,
+                0x38 => KeyCode::RightAlt,
+                0x47 => KeyCode::Home,
+                0x48 => KeyCode::ArrowUp,
+                0x49 => KeyCode::PageUp,
+                0x4B => KeyCode::ArrowLeft,
+                0x4D => KeyCode::ArrowRight,
+                0x4F => KeyCode::End,
+                0x50 => KeyCode::ArrowDown,
+                0x51 => KeyCode::PageDown,
+                0x52 => KeyCode::Insert,
+                0x53 => KeyCode::Delete,
+                code => KeyCode::Unknown(code)
+            };
+        }
+
+        match code {
+            0x01 => KeyCode::Escape,
+            0x02 => KeyCode::Key1, 0x03 => KeyCode::Key2, 0x04 => KeyCode::Key3,
+            0x05 => KeyCode::Key4, 0x06 => KeyCode::Key5, 0x07 => KeyCode::Key6,
+            0x08 => KeyCode::Key7, 0x09 => KeyCode::Key8, 0x0A => KeyCode::Key9,
+            0x0B => KeyCode::Key0,
+            0x0C => KeyCode::Minus, 0x0D => KeyCode::Equals,
+            0x0E => KeyCode::Backspace, 0x0F => KeyCode::Tab,
+            0x10 => KeyCode::Q, 0x11 => KeyCode::W, 0x12 => KeyCode::E, 0x13 => KeyCode::R,
+            0x14 => KeyCode::T, 0x15 => KeyCode::Y, 0x16 => KeyCode::U, 0x17 => KeyCode::I,
+            0x18 => KeyCode::O, 0x19 => KeyCode::P,
+            0x1A => KeyCode::LeftBracket, 0x1B => KeyCode::RightBracket,
+            0x1C => KeyCode::Enter, 0x1D => KeyCode::LeftControl,
+            0x1E => KeyCode::A, 0x1F => KeyCode::S, 0x20 => KeyCode::D, 0x21 => KeyCode::F,
+            0x22 => KeyCode::G, 0x23 => KeyCode::H, 0x24 => KeyCode::J, 0x25 => KeyCode::K,
+            0x26 => KeyCode::L,
+            0x27 => KeyCode::Semicolon, 0x28 => KeyCode::Quote, 0x29 => KeyCode::Backtick,
+            0x2A => KeyCode::LeftShift, 0x2B => KeyCode::Backslash,
+            0x2C => KeyCode::Z, 0x2D => KeyCode::X, 0x2E => KeyCode::C, 0x2F => KeyCode::V,
+            0x30 => KeyCode::B, 0x31 => KeyCode::N, 0x32 => KeyCode::M,
+            0x33 => KeyCode::Comma, 0x34 => KeyCode::Period, 0x35 => KeyCode::Slash,
+            0x36 => KeyCode::RightShift, 0x37 => KeyCode::KeypadMultiply,
+            0x38 => KeyCode::LeftAlt, 0x39 => KeyCode::Space, 0x3A => KeyCode::CapsLock,
+            0x3B => KeyCode::F1, 0x3C => KeyCode::F2, 0x3D => KeyCode::F3, 0x3E => KeyCode::F4,
+            0x3F => KeyCode::F5, 0x40 => KeyCode::F6, 0x41 => KeyCode::F7, 0x42 => KeyCode::F8,
+            0x43 => KeyCode::F9, 0x44 => KeyCode::F10,
+            0x45 => KeyCode::NumLock, 0x46 => KeyCode::ScrollLock,
+            0x47 => KeyCode::Keypad7, 0x48 => KeyCode::Keypad8, 0x49 => KeyCode::Keypad9,
+            0x4A => KeyCode::KeypadMinus,
+            0x4B => KeyCode::Keypad4, 0x4C => KeyCode::Keypad5, 0x4D => KeyCode::Keypad6,
+            0x4E => KeyCode::KeypadPlus,
+            0x4F => KeyCode::Keypad1, 0x50 => KeyCode::Keypad2, 0x51 => KeyCode::Keypad3,
+            0x52 => KeyCode::Keypad0, 0x53 => KeyCode::KeypadPeriod,
+            0x57 => KeyCode::F11, 0x58 => KeyCode::F12,
+            code => KeyCode::Unknown(code)
+        }
+    }
+} impl EventHandler for Ps2Keyboard {
+    fn handle(&mut self, event: Event) {
+        if let Event::Scancode(scancode) = event {
+            self.on_scancode(scancode);
+        }
+    }
+}
\ No newline at end of file
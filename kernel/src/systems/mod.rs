@@ -1,2 +1,10 @@
 pub mod time;
-pub mod display;
\ No newline at end of file
+pub mod display;
+pub mod control;
+pub mod keyboard;
+pub mod input;
+pub mod module;
+#[cfg(feature = "tests")] pub mod bench;
+pub mod worker;
+pub mod settings;
+pub mod clipboard;
\ No newline at end of file
@@ -0,0 +1,377 @@
+use alloc::alloc::{alloc_zeroed, dealloc};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use x86_64::instructions::port::Port;
+use crate::api::module::{KernelSymbol, ModuleError, ModuleInfo};
+
+static ELF_MAGIC: &[u8] = b"\x7fELF";
+static ELF_CLASS_64: u8 = 2;
+static ELF_DATA_LITTLE_ENDIAN: u8 = 1;
+static ELF_TYPE_RELOCATABLE: u16 = 1;
+static ELF_MACHINE_X86_64: u16 = 62;
+
+static SECTION_HEADER_SIZE: usize = 64;
+static SECTION_PROGBITS: u32 = 1;
+static SECTION_SYMTAB: u32 = 2;
+static SECTION_RELA: u32 = 4;
+static SECTION_NOBITS: u32 = 8;
+static SECTION_FLAG_ALLOC: u64 = 0x2;
+
+static SYMBOL_SIZE: usize = 24;
+static SYMBOL_UNDEFINED: u16 = 0;
+static SYMBOL_ABSOLUTE: u16 = 0xfff1;
+static SYMBOL_COMMON: u16 = 0xfff2;
+
+static RELOCATION_SIZE: usize = 24;
+// Constants, as they are matched on
+const R_X86_64_64: u32 = 1;
+const R_X86_64_PC32: u32 = 2;
+const R_X86_64_PLT32: u32 = 4;
+const R_X86_64_GOTPCREL: u32 = 9;
+const R_X86_64_32: u32 = 10;
+const R_X86_64_32S: u32 = 11;
+const R_X86_64_GOTPCRELX: u32 = 41;
+const R_X86_64_REX_GOTPCRELX: u32 = 42;
+
+/// Every symbol gets a GOT entry and a stub jumping to it, as the kernel's functions are usually
+/// too far away from the heap for the 32-bit relative calls modules are compiled with.
+static SLOT_SIZE: usize = 24;
+static ENTRY_POINT: &str = "module_init";
+
+/// The functions the kernel exports to modules, everything else can't be linked against.
+static KERNEL_SYMBOLS: &[KernelSymbol] = &[
+    KernelSymbol { name: "kernel_log", address: kernel_log as *const () },
+    KernelSymbol { name: "kernel_alloc", address: kernel_alloc as *const () },
+    KernelSymbol { name: "kernel_free", address: kernel_free as *const () },
+    KernelSymbol { name: "kernel_inb", address: kernel_inb as *const () },
+    KernelSymbol { name: "kernel_outb", address: kernel_outb as *const () }
+];
+
+/// Logs the UTF-8 message with the level (1 = error up to 5 = trace), prefixed with "module".
+extern "C" fn kernel_log(level: u32, message: *const u8, length: usize) {
+    let message = unsafe { core::slice::from_raw_parts(message, length) };
+    let message = core::str::from_utf8(message).unwrap_or("<invalid UTF-8>");
+    match level {
+        1 => log::error!("[module] {}", message),
+        2 => log::warn!("[module] {}", message),
+        3 => log::info!("[module] {}", message),
+        4 => log::debug!("[module] {}", message),
+        _ => log::trace!("[module] {}", message)
+    }
+}
+
+extern "C" fn kernel_alloc(size: usize, align: usize) -> *mut u8 {
+    match Layout::from_size_align(size, align) {
+        Ok(layout) if size > 0 => unsafe { alloc_zeroed(layout) },
+        _ => core::ptr::null_mut()
+    }
+}
+
+extern "C" fn kernel_free(pointer: *mut u8, size: usize, align: usize) {
+    if let (false, Ok(layout)) = (pointer.is_null(), Layout::from_size_align(size, align)) {
+        unsafe { dealloc(pointer, layout) }
+    }
+}
+
+extern "C" fn kernel_inb(port: u16) -> u8 {
+    unsafe { Port::<u8>::new(port).read() }
+}
+
+extern "C" fn kernel_outb(port: u16, value: u8) {
+    unsafe { Port::<u8>::new(port).write(value) }
+}
+
+/// A relocatable object loaded into kernel memory. Only allocated `PROGBITS` and `NOBITS` sections
+/// are supported and undefined symbols are resolved against the functions the kernel exports.
+pub struct LoadedModule {
+    name: String,
+    memory: *mut u8,
+    layout: Layout
+} #[allow(dead_code)] impl LoadedModule {
+    /// Loads the object, links it and runs its `module_init` function.
+    pub fn load(name: &str, object: &[u8]) -> Result<Self, ModuleError> {
+        let elf = Elf::parse(object)?;
+
+        // Lay out the allocated sections, followed by the symbol slots
+        let mut section_offsets: Vec<Option<usize>> = Vec::with_capacity(elf.section_count);
+        let mut size = 0;
+        let mut align = 16;
+        for index in 0..elf.section_count {
+            let section = elf.section(index)?;
+            if section.flags & SECTION_FLAG_ALLOC == 0 {
+                section_offsets.push(None);
+                continue;
+            }
+            if section.kind != SECTION_PROGBITS && section.kind != SECTION_NOBITS {
+                return Err(ModuleError::UnsupportedSection(elf.section_name(&section).to_string()));
+            }
+            let section_align = (section.align as usize).max(1);
+            if !section_align.is_power_of_two() {
+                return Err(ModuleError::InvalidObject("Section alignment is not a power of two"));
+            }
+            size = align_up(size, section_align)?;
+            section_offsets.push(Some(size));
+            size = size.checked_add(section.size as usize).ok_or(ModuleError::InvalidObject("Module is too large"))?;
+            align = align.max(section_align);
+        }
+        let symbols = elf.symbol_table()?;
+        let symbol_count = symbols.size as usize / SYMBOL_SIZE;
+        let slots_offset = align_up(size, 16)?;
+        size = symbol_count.checked_mul(SLOT_SIZE).and_then(|slots| slots.checked_add(slots_offset))
+            .ok_or(ModuleError::InvalidObject("Module is too large"))?;
+
+        let layout = Layout::from_size_align(size, align)
+            .map_err(|_| ModuleError::InvalidObject("Module is too large"))?;
+        let memory = unsafe { alloc_zeroed(layout) };
+        if memory.is_null() {
+            return Err(ModuleError::OutOfMemory);
+        }
+        // From here on the memory is freed by the module's drop if anything fails
+        let module = Self { name: name.to_string(), memory, layout };
+        let base = memory as usize;
+
+        for (index, offset) in section_offsets.iter().enumerate() {
+            let (Some(offset), Ok(section)) = (offset, elf.section(index)) else { continue; };
+            if section.kind == SECTION_PROGBITS {
+                let contents = elf.bytes(section.offset as usize, section.size as usize)?;
+                unsafe { core::ptr::copy_nonoverlapping(contents.as_ptr(), memory.add(*offset), contents.len()) };
+            }
+        }
+
+        // Resolve every symbol and fill its GOT entry and stub
+        let mut values = Vec::with_capacity(symbol_count);
+        let mut entry = None;
+        for index in 0..symbol_count {
+            let symbol = elf.symbol(&symbols, index)?;
+            let value = if index == 0 {
+                0
+            } else if symbol.section == SYMBOL_UNDEFINED {
+                let name = elf.symbol_name(&symbols, &symbol)?;
+                KERNEL_SYMBOLS.iter().find(|exported| exported.name == name)
+                    .ok_or_else(|| ModuleError::UnresolvedSymbol(name.to_string()))?
+                    .address as u64
+            } else if symbol.section == SYMBOL_ABSOLUTE {
+                symbol.value
+            } else if symbol.section == SYMBOL_COMMON {
+                return Err(ModuleError::UnsupportedSection("COMMON".to_string()));
+            } else {
+                match section_offsets.get(symbol.section as usize) {
+                    Some(Some(offset)) => ((base + offset) as u64).checked_add(symbol.value)
+                        .ok_or(ModuleError::InvalidObject("Symbol value out of range"))?,
+                    _ => 0
+                }
+            };
+
+            let slot = memory.wrapping_add(slots_offset + index * SLOT_SIZE);
+            unsafe {
+                core::ptr::write_unaligned(slot as *mut u64, value);
+                // movabs rax, value; jmp rax
+                let stub = slot.add(8);
+                core::ptr::write_unaligned(stub as *mut [u8; 2], [0x48, 0xb8]);
+                core::ptr::write_unaligned(stub.add(2) as *mut u64, value);
+                core::ptr::write_unaligned(stub.add(10) as *mut [u8; 2], [0xff, 0xe0]);
+            }
+            if index != 0 && symbol.section != SYMBOL_UNDEFINED
+                && elf.symbol_name(&symbols, &symbol)? == ENTRY_POINT {
+                entry = Some(value);
+            }
+            values.push(value);
+        }
+
+        // Apply the relocations of the allocated sections
+        for index in 0..elf.section_count {
+            let section = elf.section(index)?;
+            if section.kind != SECTION_RELA { continue; }
+            let Some(Some(target_offset)) = section_offsets.get(section.info as usize) else { continue; };
+            let target_size = elf.section(section.info as usize)?.size as usize;
+
+            for relocation in 0..section.size as usize / RELOCATION_SIZE {
+                let relocation = (section.offset as usize).checked_add(relocation * RELOCATION_SIZE)
+                    .ok_or(ModuleError::InvalidObject("Relocation out of range"))?;
+                let offset = elf.read_u64(relocation)? as usize;
+                let info = elf.read_u64(relocation + 8)?;
+                let addend = elf.read_u64(relocation + 16)? as i64;
+                let (symbol, kind) = ((info >> 32) as usize, info as u32);
+
+                let value = *values.get(symbol).ok_or(ModuleError::InvalidObject("Relocation symbol out of range"))?;
+                let slot = (base + slots_offset + symbol * SLOT_SIZE) as u64;
+                let width = if kind == R_X86_64_64 { 8 } else { 4 };
+                // The place has to be inside the section the relocations are for
+                if offset.checked_add(width).map_or(true, |end| end > target_size) {
+                    return Err(ModuleError::InvalidObject("Relocation out of range"));
+                }
+                let place = (base + target_offset + offset) as u64;
+
+                let undefined = elf.symbol(&symbols, symbol)?.section == SYMBOL_UNDEFINED && symbol != 0;
+                let result = match kind {
+                    R_X86_64_64 => {
+                        unsafe { core::ptr::write_unaligned(place as *mut u64, value.wrapping_add(addend as u64)) };
+                        continue;
+                    },
+                    R_X86_64_PC32 | R_X86_64_PLT32 => {
+                        let target = if undefined { slot + 8 } else { value };
+                        target.wrapping_add(addend as u64).wrapping_sub(place) as i64
+                    },
+                    R_X86_64_GOTPCREL | R_X86_64_GOTPCRELX | R_X86_64_REX_GOTPCRELX => {
+                        slot.wrapping_add(addend as u64).wrapping_sub(place) as i64
+                    },
+                    R_X86_64_32 => {
+                        let result = value.wrapping_add(addend as u64);
+                        if result > u32::MAX as u64 { return Err(ModuleError::RelocationOverflow(kind)); }
+                        unsafe { core::ptr::write_unaligned(place as *mut u32, result as u32) };
+                        continue;
+                    },
+                    R_X86_64_32S => value.wrapping_add(addend as u64) as i64,
+                    _ => return Err(ModuleError::UnsupportedRelocation(kind))
+                };
+                let result = i32::try_from(result).map_err(|_| ModuleError::RelocationOverflow(kind))?;
+                unsafe { core::ptr::write_unaligned(place as *mut i32, result) };
+            }
+        }
+
+        let entry = entry.ok_or(ModuleError::MissingInit)?;
+        let init: extern "C" fn() -> i32 = unsafe { core::mem::transmute(entry as usize) };
+        match init() {
+            0 => Ok(module),
+            code => Err(ModuleError::InitFailed(code))
+        }
+    }
+
+    pub fn info(&self) -> ModuleInfo {
+        ModuleInfo { name: self.name.clone(), base: self.memory as usize, size: self.layout.size() }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+} impl Drop for LoadedModule {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.memory, self.layout) }
+    }
+} unsafe impl Send for LoadedModule {}
+
+/// Rounds the offset up to the alignment, which is a power of two.
+fn align_up(offset: usize, align: usize) -> Result<usize, ModuleError> {
+    offset.checked_add(align - 1).map(|offset| offset & !(align - 1))
+        .ok_or(ModuleError::InvalidObject("Module is too large"))
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64
+}
+
+struct Symbol {
+    name: u32,
+    section: u16,
+    value: u64
+}
+
+/// A bounds-checked view of a relocatable ELF object.
+struct Elf<'a> {
+    data: &'a [u8],
+    section_offset: usize,
+    section_count: usize,
+    string_section: usize
+} impl<'a> Elf<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, ModuleError> {
+        if data.len() < 64 || !data.starts_with(ELF_MAGIC) {
+            return Err(ModuleError::InvalidObject("Not an ELF file"));
+        }
+        if data[4] != ELF_CLASS_64 || data[5] != ELF_DATA_LITTLE_ENDIAN {
+            return Err(ModuleError::InvalidObject("Not a 64-bit little endian ELF file"));
+        }
+        let elf = Self { data, section_offset: 0, section_count: 0, string_section: 0 };
+        if elf.read_u16(16)? != ELF_TYPE_RELOCATABLE || elf.read_u16(18)? != ELF_MACHINE_X86_64 {
+            return Err(ModuleError::InvalidObject("Not a relocatable x86_64 object"));
+        }
+        if elf.read_u16(0x3a)? as usize != SECTION_HEADER_SIZE {
+            return Err(ModuleError::InvalidObject("Unexpected section header size"));
+        }
+        Ok(Self {
+            section_offset: elf.read_u64(0x28)? as usize,
+            section_count: elf.read_u16(0x3c)? as usize,
+            string_section: elf.read_u16(0x3e)? as usize,
+            ..elf
+        })
+    }
+
+    fn bytes(&self, offset: usize, length: usize) -> Result<&'a [u8], ModuleError> {
+        offset.checked_add(length).and_then(|end| self.data.get(offset..end))
+            .ok_or(ModuleError::InvalidObject("Object is cut off"))
+    }
+
+    fn read_u16(&self, offset: usize) -> Result<u16, ModuleError> {
+        Ok(u16::from_le_bytes(self.bytes(offset, 2)?.try_into().unwrap_or_default()))
+    }
+
+    fn read_u32(&self, offset: usize) -> Result<u32, ModuleError> {
+        Ok(u32::from_le_bytes(self.bytes(offset, 4)?.try_into().unwrap_or_default()))
+    }
+
+    fn read_u64(&self, offset: usize) -> Result<u64, ModuleError> {
+        Ok(u64::from_le_bytes(self.bytes(offset, 8)?.try_into().unwrap_or_default()))
+    }
+
+    fn section(&self, index: usize) -> Result<SectionHeader, ModuleError> {
+        let header = index.checked_mul(SECTION_HEADER_SIZE).and_then(|offset| offset.checked_add(self.section_offset))
+            .ok_or(ModuleError::InvalidObject("Section out of range"))?;
+        Ok(SectionHeader {
+            name: self.read_u32(header)?,
+            kind: self.read_u32(header + 4)?,
+            flags: self.read_u64(header + 8)?,
+            offset: self.read_u64(header + 24)?,
+            size: self.read_u64(header + 32)?,
+            link: self.read_u32(header + 40)?,
+            info: self.read_u32(header + 44)?,
+            align: self.read_u64(header + 48)?
+        })
+    }
+
+    fn section_name(&self, section: &SectionHeader) -> &'a str {
+        self.section(self.string_section)
+            .and_then(|strings| self.string(&strings, section.name))
+            .unwrap_or("<unnamed>")
+    }
+
+    fn string(&self, strings: &SectionHeader, offset: u32) -> Result<&'a str, ModuleError> {
+        let table = self.bytes(strings.offset as usize, strings.size as usize)?;
+        let string = table.get(offset as usize..).ok_or(ModuleError::InvalidObject("String out of range"))?;
+        let length = string.iter().position(|byte| *byte == 0).unwrap_or(string.len());
+        core::str::from_utf8(&string[..length]).map_err(|_| ModuleError::InvalidObject("String is not UTF-8"))
+    }
+
+    fn symbol_table(&self) -> Result<SectionHeader, ModuleError> {
+        for index in 0..self.section_count {
+            let section = self.section(index)?;
+            if section.kind == SECTION_SYMTAB {
+                return Ok(section);
+            }
+        }
+        Err(ModuleError::InvalidObject("No symbol table"))
+    }
+
+    fn symbol(&self, symbols: &SectionHeader, index: usize) -> Result<Symbol, ModuleError> {
+        if index >= symbols.size as usize / SYMBOL_SIZE {
+            return Err(ModuleError::InvalidObject("Symbol out of range"));
+        }
+        let symbol = (symbols.offset as usize).checked_add(index * SYMBOL_SIZE)
+            .ok_or(ModuleError::InvalidObject("Symbol out of range"))?;
+        Ok(Symbol {
+            name: self.read_u32(symbol)?,
+            section: self.read_u16(symbol + 6)?,
+            value: self.read_u64(symbol + 8)?
+        })
+    }
+
+    fn symbol_name(&self, symbols: &SectionHeader, symbol: &Symbol) -> Result<&'a str, ModuleError> {
+        self.string(&self.section(symbols.link as usize)?, symbol.name)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,113 @@
+use log::LevelFilter;
+use crate::api::display::{Fonts, Size};
+use crate::api::time::TimeOffset;
+use crate::managers::display::DisplayMode;
+
+/// All time zones in the order of their ids.
+pub const TIME_OFFSET_COUNT: usize = 38;
+/// All log levels in the order of their ids.
+pub static LOG_LEVELS: [LevelFilter; 6] = [
+    LevelFilter::Off, LevelFilter::Error, LevelFilter::Warn,
+    LevelFilter::Info, LevelFilter::Debug, LevelFilter::Trace
+];
+
+/// How the kernel draws to the display once booted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum DisplaySetting {
+    /// A text buffer of 80x25 characters.
+    #[default]
+    Text = 0,
+    /// The minimal console, for displays the text buffer doesn't work on.
+    Console = 1
+} impl DisplaySetting {
+    pub const ALL: [DisplaySetting; 2] = [DisplaySetting::Text, DisplaySetting::Console];
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Self::ALL.get(value as usize).copied()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DisplaySetting::Text => "Text 80x25",
+            DisplaySetting::Console => "Console"
+        }
+    }
+
+    pub fn mode(&self) -> DisplayMode {
+        match self {
+            DisplaySetting::Text => DisplayMode::Text(Size::new(80, 25), Fonts::default()),
+            DisplaySetting::Console => DisplayMode::Console(Fonts::default())
+        }
+    }
+}
+
+/// What the kernel starts once booted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum BootTarget {
+    /// The status screen in the chosen display mode.
+    #[default]
+    Status = 0,
+    /// The log viewer, with the input focus.
+    LogViewer = 1,
+    /// Runs the benchmarks, then shows the status screen.
+    Benchmark = 2
+} impl BootTarget {
+    pub const ALL: [BootTarget; 3] = [BootTarget::Status, BootTarget::LogViewer, BootTarget::Benchmark];
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Self::ALL.get(value as usize).copied()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BootTarget::Status => "Status screen",
+            BootTarget::LogViewer => "Log viewer",
+            BootTarget::Benchmark => "Benchmark"
+        }
+    }
+}
+
+/// The settings chosen on the settings screen, persisted in the CMOS and applied on every boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootSettings {
+    pub display: DisplaySetting,
+    /// The time zone the clock is shown in.
+    pub time_offset: TimeOffset,
+    pub log_level: LevelFilter,
+    pub target: BootTarget
+} impl BootSettings {
+    /// Reads the persisted settings. Settings that were never set or can't be read take their
+    /// default.
+    pub fn load() -> Self {
+        let defaults = Self::default();
+        let Some(settings) = crate::internal::cmos::settings() else { return defaults; };
+        Self {
+            display: DisplaySetting::from_u8(settings.display_mode).unwrap_or(defaults.display),
+            time_offset: settings.time_offset.checked_sub(1).and_then(TimeOffset::from_u8)
+                .unwrap_or(defaults.time_offset),
+            log_level: (settings.log_level as usize).checked_sub(1).and_then(|id| LOG_LEVELS.get(id)).copied()
+                .unwrap_or(defaults.log_level),
+            target: BootTarget::from_u8(settings.boot_target).unwrap_or(defaults.target)
+        }
+    }
+
+    /// Persists the settings for the next boots.
+    pub fn save(&self) {
+        let log_level = LOG_LEVELS.iter().position(|level| *level == self.log_level).unwrap_or(0);
+        crate::internal::cmos::update_settings(|settings| {
+            settings.display_mode = self.display as u8;
+            settings.time_offset = self.time_offset.as_u8() + 1;
+            settings.log_level = log_level as u8 + 1;
+            settings.boot_target = self.target as u8;
+        });
+    }
+} impl Default for BootSettings {
+    fn default() -> Self { Self {
+        display: DisplaySetting::default(),
+        time_offset: TimeOffset::A,
+        log_level: LevelFilter::Trace,
+        target: BootTarget::default()
+    } }
+}
\ No newline at end of file
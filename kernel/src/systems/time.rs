@@ -1,25 +1,288 @@
-use crate::api::time::{DateTime, Month, TimeApi, TimeOffset};
+use crate::api::time::{ClockStatus, Date, DateTime, Duration, Instant, LeapSecond, Month, TimeApi, TimeOffset};
 use crate::api::event::{Event, EventHandler};
+use crate::internal::cmos::Rtc;
+use crate::drivers::timer::TimerTick;
 
+/// Corrections are slewed in by running the clock at most this much faster or slower, like adjtime.
+static MAX_SLEW_PPM: i64 = 500;
+/// A larger drift estimate means the corrections were wrong, not the clock.
+static MAX_DRIFT_PPM_ESTIMATE: i64 = 500;
+/// Corrections need to be at least this far apart for the drift to be estimated from them, as
+/// the error of a correction is spread over less time otherwise.
+static MIN_ESTIMATE_SECONDS: u64 = 900;
+static NANOS_PER_SECOND: i64 = 1_000_000_000;
+
+/// Clock that follows the real-time clock and interpolates between its one-second steps using
+/// the timer ticks. Corrections are applied on top of it by the clock discipline.
 pub struct SimpleClock {
-    current_time: DateTime
+    current_time: DateTime,
+    last_rtc: Option<Rtc>,
+    discipline: ClockDiscipline,
+    tick_hz: u64
 } impl SimpleClock {
-    pub fn new() -> Self { Self {
-        current_time: DateTime::new(0, 0, 0, 0, 1, Month::January, 1970)
+    /// Creates the clock, correcting for the drift of the real-time clock estimated earlier.
+    pub fn new(drift_ppm: i64) -> Self { Self {
+        current_time: DateTime::new(0, 0, 0, 0, 1, Month::January, 1970),
+        last_rtc: None,
+        discipline: ClockDiscipline::new(drift_ppm),
+        tick_hz: crate::drivers::timer::tick_hz()
     } }
+
+    fn on_rtc(&mut self, rtc: Rtc) {
+        // The real-time clock only has second resolution, so only a change of its reading tells
+        // us where a second starts.
+        if self.last_rtc.as_ref() != Some(&rtc) {
+            self.current_time = DateTime::from_rtc(rtc.clone());
+            self.last_rtc = Some(rtc);
+        }
+    }
+
+    fn on_timer(&mut self, tick: TimerTick) {
+        // Never run past the end of the current second, the real-time clock decides when the next
+        // one starts. This keeps the clock from jumping backwards when it resynchronizes.
+        let nanos = tick.ticks * 1_000_000_000 / self.tick_hz;
+        let remaining = 999_999_999 - self.current_time.nano() as u64;
+        self.current_time = self.current_time.add(Duration::from_nanos(nanos.min(remaining)));
+        self.discipline.advance(nanos, self.current_time);
+    }
 } impl TimeApi for SimpleClock {
     fn now(&self) -> DateTime {
-        self.current_time.clone()
+        self.discipline.apply(self.current_time)
     }
 
     fn with_offset(&self, offset: TimeOffset) -> DateTime {
-        self.current_time.with_offset(offset).clone()
+        self.now().with_offset(offset)
+    }
+
+    fn adjust(&mut self, correction_nanos: i64) -> Option<i64> {
+        self.discipline.adjust(correction_nanos)
+    }
+
+    fn reset_corrections(&mut self) {
+        self.discipline.reset();
+    }
+
+    fn schedule_leap_second(&mut self, date: Date, leap_second: LeapSecond) {
+        self.discipline.schedule_leap_second(date, leap_second);
+    }
+
+    fn status(&self) -> ClockStatus {
+        self.discipline.status()
     }
 } impl EventHandler for SimpleClock {
     fn handle(&mut self, event: Event) {
         match event {
-            Event::Rtc(date_time) => self.current_time = DateTime::from_rtc(date_time),
+            Event::Rtc(rtc) => self.on_rtc(rtc),
+            Event::Timer(tick) => self.on_timer(tick),
+            Event::TickRateChanged(hz) => self.tick_hz = hz,
             _ => {}
         }
     }
+}
+
+/// Corrects a clock without ever making it jump: corrections are slewed in by running the clock
+/// slightly faster or slower until they are used up, and the drift of the underlying clock that
+/// the corrections reveal is corrected for continuously. Leap seconds are slewed in the same way
+/// after the day they end, instead of showing 23:59:60.
+pub struct ClockDiscipline {
+    /// What gets added to the time of the underlying clock, in nanoseconds.
+    offset: i64,
+    /// The part of the corrections that still has to be slewed in.
+    pending: i64,
+    drift_ppm: i64,
+    /// The part of the drift correction below a nanosecond, in millionths of a nanosecond.
+    drift_remainder: i64,
+    /// When the last correction arrived, to estimate the drift from the next one.
+    last_correction: Option<Instant>,
+    leap_second: Option<(Date, LeapSecond)>
+} impl ClockDiscipline {
+    pub fn new(drift_ppm: i64) -> Self { Self {
+        offset: 0,
+        pending: 0,
+        drift_ppm: drift_ppm.clamp(-MAX_DRIFT_PPM_ESTIMATE, MAX_DRIFT_PPM_ESTIMATE),
+        drift_remainder: 0,
+        last_correction: None,
+        leap_second: None
+    } }
+
+    pub fn status(&self) -> ClockStatus {
+        ClockStatus { offset_nanos: self.offset, pending_nanos: self.pending, drift_ppm: self.drift_ppm }
+    }
+
+    /// Drops the corrections made so far, keeping the drift estimate.
+    pub fn reset(&mut self) {
+        self.offset = 0;
+        self.pending = 0;
+    }
+
+    pub fn schedule_leap_second(&mut self, date: Date, leap_second: LeapSecond) {
+        self.leap_second = Some((date, leap_second));
+    }
+
+    /// Returns the time of the underlying clock with the corrections applied.
+    pub fn apply(&self, time: DateTime) -> DateTime {
+        if self.offset == 0 { return time; }
+        DateTime::from_unix_nanos(time.unix_nanos() + self.offset)
+    }
+
+    /// Queues the correction to be slewed in. Corrections far enough apart tell how far the
+    /// underlying clock drifted in between, which updates the drift estimate; it is returned if
+    /// it changed, so it can be persisted.
+    pub fn adjust(&mut self, correction: i64) -> Option<i64> {
+        self.pending += correction;
+        let now = Instant::now();
+        let last_correction = self.last_correction.replace(now)?;
+
+        let interval = now.duration_since(last_correction);
+        if interval.seconds() < MIN_ESTIMATE_SECONDS { return None; }
+        let interval_nanos = interval.seconds() as i128 * NANOS_PER_SECOND as i128 + interval.nanos() as i128;
+        // The drift correction was already running, so the correction is what it missed. Only
+        // half of that is taken, so a single bad correction can't throw the estimate off.
+        let missed_ppm = (correction as i128 * 1_000_000 / interval_nanos) as i64;
+        let drift_ppm = (self.drift_ppm + missed_ppm / 2).clamp(-MAX_DRIFT_PPM_ESTIMATE, MAX_DRIFT_PPM_ESTIMATE);
+        if drift_ppm == self.drift_ppm { return None; }
+        self.drift_ppm = drift_ppm;
+        Some(drift_ppm)
+    }
+
+    /// Moves the corrections on by the nanoseconds the clock advanced, which is now at the given time.
+    fn advance(&mut self, nanos: u64, now: DateTime) {
+        let nanos = nanos as i64;
+
+        self.drift_remainder += nanos * self.drift_ppm;
+        self.offset += self.drift_remainder / 1_000_000;
+        self.drift_remainder %= 1_000_000;
+
+        let slew = (nanos * MAX_SLEW_PPM / 1_000_000).min(self.pending.abs());
+        let slew = if self.pending < 0 { -slew } else { slew };
+        self.offset += slew;
+        self.pending -= slew;
+
+        if let Some((date, leap_second)) = self.leap_second {
+            if self.apply(now).date().as_ordinal_date() > date.as_ordinal_date() {
+                self.pending += match leap_second {
+                    LeapSecond::Insert => -NANOS_PER_SECOND,
+                    LeapSecond::Delete => NANOS_PER_SECOND
+                };
+                self.leap_second = None;
+                log::info!("Slewing in the leap second at the end of {}.", date);
+            }
+        }
+    }
+}
+
+/// Keeps track of how many timer ticks the CPU spent working versus halted.
+#[derive(Default)]
+pub struct TickAccounting {
+    busy_ticks: u64,
+    idle_ticks: u64,
+    interrupts: u64
+} #[allow(dead_code)] impl TickAccounting {
+    pub fn new() -> Self { Self::default() }
+
+    /// Returns the amount of ticks the CPU was busy.
+    pub fn busy_ticks(&self) -> u64 { self.busy_ticks }
+
+    /// Returns the amount of ticks the CPU was halted.
+    pub fn idle_ticks(&self) -> u64 { self.idle_ticks }
+
+    /// Returns the amount of timer events that were needed to count all ticks.
+    pub fn interrupts(&self) -> u64 { self.interrupts }
+
+    /// Returns the percentage of ticks the CPU was halted.
+    pub fn idle_percent(&self) -> u64 {
+        let total = self.busy_ticks + self.idle_ticks;
+        if total == 0 { 0 } else { self.idle_ticks * 100 / total }
+    }
+
+    fn account(&mut self, tick: TimerTick) {
+        if tick.idle {
+            self.idle_ticks += tick.ticks;
+        } else {
+            self.busy_ticks += tick.ticks;
+        }
+        self.interrupts += 1;
+    }
+} impl EventHandler for TickAccounting {
+    fn handle(&mut self, event: Event) {
+        if let Event::Timer(tick) = event {
+            self.account(tick);
+        }
+    }
+}
+
+/// Seconds of real-time clock time over which the timer ticks are compared against it.
+static CHECK_SECONDS: u64 = 10;
+/// Drift beyond this is reported as lost (or extra) timer interrupts.
+static MAX_DRIFT_PPM: i64 = 2000;
+
+/// Checks the timer against the real-time clock: over whole seconds of real-time clock time, as
+/// many timer ticks have to arrive as the timer frequency says. Fewer ticks mean timer interrupts
+/// got lost, e.g. because interrupts were disabled for too long.
+#[derive(Default)]
+pub struct TickRateCheck {
+    ticks: u64,
+    last_rtc: Option<Rtc>,
+    /// Ticks and real-time clock seconds since the start of the current window, which starts at
+    /// the first change of the real-time clock reading.
+    window: Option<(u64, u64)>,
+    drift_ppm: Option<i64>,
+    tick_hz: u64
+} #[allow(dead_code)] impl TickRateCheck {
+    pub fn new() -> Self { Self { tick_hz: crate::drivers::timer::tick_hz(), ..Self::default() } }
+
+    /// Returns the deviation of the tick rate from the real-time clock measured over the last
+    /// completed window, in parts per million. Negative when ticks were lost.
+    pub fn drift_ppm(&self) -> Option<i64> { self.drift_ppm }
+
+    /// Starts over with a new window, for when the real-time clock was set and its reading jumps.
+    pub fn restart(&mut self) {
+        self.last_rtc = None;
+        self.window = None;
+    }
+
+    fn on_rtc(&mut self, rtc: Rtc) {
+        let Some(last_rtc) = self.last_rtc.replace(rtc.clone()) else { return; };
+        if last_rtc == rtc { return; }
+
+        let Some((start_ticks, seconds)) = self.window.as_mut() else {
+            self.window = Some((self.ticks, 0));
+            return;
+        };
+        // Coalesced real-time clock events may have skipped seconds, so take the difference
+        let elapsed = (seconds_of_day(&rtc) + 86400 - seconds_of_day(&last_rtc)) % 86400;
+        *seconds += elapsed;
+        if *seconds < CHECK_SECONDS { return; }
+
+        let expected = (*seconds * self.tick_hz) as i64;
+        let counted = (self.ticks - *start_ticks) as i64;
+        let drift_ppm = (counted - expected) * 1_000_000 / expected;
+        if drift_ppm.abs() > MAX_DRIFT_PPM {
+            log::warn!(
+                "Timer counted {} ticks in {} seconds instead of {} ({} ppm), timer interrupts are being lost.",
+                counted, seconds, expected, drift_ppm
+            );
+        } else {
+            log::debug!("Timer drift against the real-time clock is {} ppm.", drift_ppm);
+        }
+        self.drift_ppm = Some(drift_ppm);
+        self.window = Some((self.ticks, 0));
+    }
+} impl EventHandler for TickRateCheck {
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Rtc(rtc) => self.on_rtc(rtc),
+            Event::Timer(tick) => self.ticks += tick.ticks,
+            Event::TickRateChanged(hz) => {
+                // Ticks of different lengths can't be compared in one window
+                self.tick_hz = hz;
+                self.restart();
+            },
+            _ => {}
+        }
+    }
+}
+
+fn seconds_of_day(rtc: &Rtc) -> u64 {
+    rtc.hours as u64 * 3600 + rtc.minutes as u64 * 60 + rtc.seconds as u64
 }
\ No newline at end of file
@@ -0,0 +1,71 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+static QUEUE: Mutex<JobQueue> = Mutex::new(JobQueue::new());
+static COMPLETED: AtomicU64 = AtomicU64::new(0);
+/// Jobs run per call of `run_pending`, so a long queue doesn't delay events.
+static JOBS_PER_RUN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WorkerStats {
+    /// Jobs waiting to be run.
+    pub queued: usize,
+    /// The most jobs that were waiting at once.
+    pub high_water_mark: usize,
+    pub completed: u64
+}
+
+struct JobQueue {
+    jobs: VecDeque<Job>,
+    high_water_mark: usize
+} impl JobQueue {
+    const fn new() -> Self { Self {
+        jobs: VecDeque::new(),
+        high_water_mark: 0
+    } }
+}
+
+/// Queues a job to run in the background, like writing back caches or flushing logs. There are
+/// no kernel threads yet, so jobs run one after another on the main loop once it has dispatched
+/// all events, before the CPU goes to sleep. Must not be called from interrupt handlers, as it
+/// allocates.
+pub fn spawn_blocking(job: impl FnOnce() + Send + 'static) {
+    let job: Job = Box::new(job);
+    crate::internal::idt::without_interrupts(|| {
+        let mut queue = QUEUE.lock();
+        queue.jobs.push_back(job);
+        queue.high_water_mark = queue.high_water_mark.max(queue.jobs.len());
+    });
+}
+
+/// Runs a few queued jobs, returns how many ran. The queue isn't locked while a job
+/// runs, so jobs can queue further jobs.
+pub fn run_pending() -> usize {
+    let mut ran = 0;
+    while ran < JOBS_PER_RUN {
+        let Some(job) = crate::internal::idt::without_interrupts(|| QUEUE.lock().jobs.pop_front()) else { break; };
+        job();
+        COMPLETED.fetch_add(1, Ordering::SeqCst);
+        ran += 1;
+    }
+    ran
+}
+
+pub fn stats() -> WorkerStats {
+    let (queued, high_water_mark) = crate::internal::idt::without_interrupts(|| {
+        let queue = QUEUE.lock();
+        (queue.jobs.len(), queue.high_water_mark)
+    });
+    WorkerStats { queued, high_water_mark, completed: COMPLETED.load(Ordering::SeqCst) }
+}
+
+/// Returns the statistics like `stats`, or None if the queue is locked. For interrupt handlers,
+/// which may have interrupted code queueing a job.
+pub fn try_stats() -> Option<WorkerStats> {
+    let queue = QUEUE.try_lock()?;
+    Some(WorkerStats { queued: queue.jobs.len(), high_water_mark: queue.high_water_mark, completed: COMPLETED.load(Ordering::SeqCst) })
+}
\ No newline at end of file
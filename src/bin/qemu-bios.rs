@@ -3,7 +3,58 @@ use std::{
     process::{self, Command},
 };
 
+/// Options given on the command line, defaulting to the `[package.metadata.os]` values.
+struct Options {
+    disk: Option<String>,
+    disk_bus: String,
+    snapshot: bool,
+    nic: Option<String>
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        disk: Some(env!("DISK_IMAGE").to_string()).filter(|disk| !disk.is_empty()),
+        disk_bus: env!("DISK_BUS").to_string(),
+        snapshot: env!("SNAPSHOT").parse::<bool>().unwrap(),
+        nic: Some(env!("NIC").to_string()).filter(|nic| !nic.is_empty())
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--disk" => options.disk = Some(args.next().unwrap_or_else(|| usage("--disk needs an image"))),
+            "--disk-bus" => options.disk_bus = args.next().unwrap_or_else(|| usage("--disk-bus needs a bus")),
+            "--snapshot" => options.snapshot = true,
+            "--nic" => options.nic = Some(args.next().unwrap_or_else(|| usage("--nic needs a specification"))),
+            _ => usage(&format!("Unknown argument '{}'", arg))
+        }
+    }
+    options
+}
+
+fn usage(error: &str) -> ! {
+    eprintln!("{}", error);
+    eprintln!("Usage: [--disk <image>] [--disk-bus <ahci|virtio|ide>] [--snapshot] [--nic <spec, e.g. user,model=virtio>]");
+    process::exit(2);
+}
+
+/// Attaches the image as a second disk on the bus, the boot disk stays on the first AHCI port.
+fn attach_disk(qemu: &mut Command, image: &str, bus: &str) {
+    let format = if image.ends_with(".qcow2") { "qcow2" } else { "raw" };
+    qemu.arg("-drive").arg(format!("if=none,id=disk0,format={},file={}", format, image));
+    match bus {
+        "ahci" => { qemu.arg("-device").arg("ide-hd,drive=disk0,bus=ide.1"); },
+        "virtio" => { qemu.arg("-device").arg("virtio-blk-pci,drive=disk0"); },
+        "ide" => {
+            qemu.arg("-device").arg("piix3-ide,id=legacy-ide");
+            qemu.arg("-device").arg("ide-hd,drive=disk0,bus=legacy-ide.0");
+        }, _ => usage(&format!("Unknown disk bus '{}'", bus))
+    }
+    println!("Disk {} attached via {}", image, bus);
+}
+
 fn main() {
+    let options = parse_options();
     println!("BIOS disk image at {}", env!("BIOS_IMAGE"));
 
     let mut qemu = Command::new(
@@ -17,6 +68,8 @@ fn main() {
     qemu.arg(format!("format=raw,file={}", env!("BIOS_IMAGE")));
 
     qemu.arg("-serial").arg("stdio");
+    qemu.arg("-serial").arg(format!("tcp:127.0.0.1:{},server,nowait", env!("CONTROL_PORT")));
+    println!("Control channel on tcp://127.0.0.1:{}", env!("CONTROL_PORT"));
 
     let accel_enabled = env!("ACCEL_ENABLED").to_string()
         .parse::<bool>().unwrap();
@@ -41,6 +94,18 @@ fn main() {
     qemu.arg("-smp").arg(env!("CPU_COUNT"));
     println!("Available CPUs: {}", env!("CPU_COUNT"));
 
+    if let Some(disk) = options.disk.as_deref() {
+        attach_disk(&mut qemu, disk, &options.disk_bus);
+    }
+    if options.snapshot {
+        qemu.arg("-snapshot");
+        println!("Snapshot mode, disk writes are discarded on exit.");
+    }
+    if let Some(nic) = options.nic.as_deref() {
+        qemu.arg("-nic").arg(nic);
+        println!("Network: {}", nic);
+    }
+
     qemu.arg("-S");
 
     let exit_status = qemu.status().unwrap();
@@ -0,0 +1,83 @@
+use std::{
+    env, fs,
+    io::{self, Read},
+    process,
+};
+
+const RECORD_SIZE: usize = 18;
+
+fn category(value: u8) -> (&'static str, &'static str) {
+    match value {
+        0 => ("Interrupt", "i"),
+        1 => ("Dispatch", "B"),
+        2 => ("Dispatch", "E"),
+        3 => ("Draw", "B"),
+        4 => ("Draw", "E"),
+        5 => ("Custom", "i"),
+        _ => ("Unknown", "i")
+    }
+}
+
+fn main() {
+    let mut dump = Vec::new();
+    match env::args().nth(1) {
+        Some(path) => dump = fs::read(&path).unwrap_or_else(|err| {
+            eprintln!("Failed to read trace dump {}: {}", path, err);
+            process::exit(1);
+        }),
+        None => if let Err(err) = io::stdin().read_to_end(&mut dump) {
+            eprintln!("Failed to read trace dump from stdin: {}", err);
+            process::exit(1);
+        }
+    }
+
+    let start = dump.windows(6).position(|window| window == b"TRACE ").unwrap_or_else(|| {
+        eprintln!("No trace dump header found!");
+        process::exit(1);
+    });
+    let header_end = start + dump[start..].iter().position(|byte| *byte == b'\n').unwrap_or_else(|| {
+        eprintln!("Trace dump header is incomplete!");
+        process::exit(1);
+    });
+
+    let header = String::from_utf8_lossy(&dump[start..header_end]).to_string();
+    let fields: Vec<u64> = header.split_whitespace().skip(1)
+        .map(|field| field.parse())
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|err| {
+            eprintln!("Trace dump header \"{}\" is malformed: {}", header, err);
+            process::exit(1);
+        });
+    let [tsc_khz, count, dropped] = fields[..] else {
+        eprintln!("Trace dump header \"{}\" needs the tsc kHz, the record count and the dropped count!", header);
+        process::exit(1);
+    };
+    let (tsc_khz, count) = (tsc_khz.max(1), count as usize);
+    eprintln!("Converting {} records ({} dropped) at {} kHz.", count, dropped, tsc_khz);
+
+    let records = &dump[header_end + 1..];
+    if count.checked_mul(RECORD_SIZE).map_or(true, |size| records.len() < size) {
+        eprintln!("Trace dump is truncated!");
+        process::exit(1);
+    }
+
+    let mut events = Vec::with_capacity(count);
+    let mut first_tsc = u64::MAX;
+    for record in records.chunks_exact(RECORD_SIZE).take(count) {
+        let tsc = u64::from_le_bytes(record[2..10].try_into().unwrap());
+        first_tsc = first_tsc.min(tsc);
+        events.push((record[0], record[1], tsc, u64::from_le_bytes(record[10..18].try_into().unwrap())));
+    }
+    events.sort_by_key(|event| event.2);
+
+    let events: Vec<String> = events.iter().map(|(cpu, value, tsc, payload)| {
+        let (name, phase) = category(*value);
+        let micros = (tsc - first_tsc) as f64 * 1000.0 / tsc_khz as f64;
+        format!(
+            "{{\"name\":\"{}\",\"ph\":\"{}\",\"ts\":{:.3},\"pid\":0,\"tid\":{},\"s\":\"t\",\"args\":{{\"payload\":{}}}}}",
+            name, phase, micros, cpu, payload
+        )
+    }).collect();
+
+    println!("{{\"traceEvents\":[{}]}}", events.join(","));
+}
\ No newline at end of file
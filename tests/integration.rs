@@ -0,0 +1,159 @@
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{self, Child, Command, Stdio},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const CONNECT_ATTEMPTS: u32 = 50;
+
+/// A booted kernel, with the lines of its serial log and of its control channel.
+struct Machine {
+    qemu: Child,
+    log: Receiver<String>,
+    control: Option<(TcpStream, Receiver<String>)>,
+    control_port: u16
+}
+
+impl Machine {
+    /// Boots the BIOS image headless, with the control channel on a free port.
+    fn boot() -> Result<Self, String> {
+        let control_port = TcpListener::bind("127.0.0.1:0").and_then(|listener| listener.local_addr())
+            .map_err(|err| format!("No free port for the control channel: {}", err))?.port();
+
+        let mut qemu = Command::new(format!("{}/tools/qemu/qemu-system-x86_64", env!("CARGO_MANIFEST_DIR")))
+            .arg("-machine").arg("q35")
+            .arg("-drive").arg(format!("format=raw,file={}", env!("BIOS_IMAGE")))
+            .arg("-display").arg("none")
+            .arg("-serial").arg("stdio")
+            .arg("-serial").arg(format!("tcp:127.0.0.1:{},server,nowait", control_port))
+            .arg("-m").arg(env!("AVAILABLE_MEMORY"))
+            .arg("-smp").arg(env!("CPU_COUNT"))
+            .stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::inherit())
+            .spawn().map_err(|err| format!("Failed to start QEMU: {}", err))?;
+
+        let log = read_lines(qemu.stdout.take().unwrap());
+        Ok(Self { qemu, log, control: None, control_port })
+    }
+
+    /// Connects to the control channel on first use, QEMU only listens once it is up.
+    fn control(&mut self) -> Result<&mut (TcpStream, Receiver<String>), String> {
+        if self.control.is_none() {
+            let mut attempts = 0;
+            let stream = loop {
+                match TcpStream::connect(("127.0.0.1", self.control_port)) {
+                    Ok(stream) => break stream,
+                    Err(err) if attempts >= CONNECT_ATTEMPTS => return Err(format!("Failed to connect to the control channel: {}", err)),
+                    Err(..) => { attempts += 1; thread::sleep(Duration::from_millis(100)); }
+                }
+            };
+            let lines = read_lines(stream.try_clone().map_err(|err| err.to_string())?);
+            self.control = Some((stream, lines));
+        }
+        Ok(self.control.as_mut().unwrap())
+    }
+}
+
+impl Drop for Machine {
+    fn drop(&mut self) {
+        let _ = self.qemu.kill();
+        let _ = self.qemu.wait();
+    }
+}
+
+fn read_lines(reader: impl std::io::Read + Send + 'static) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else { break; };
+            if sender.send(line).is_err() { break; }
+        }
+    });
+    receiver
+}
+
+/// Waits for a line containing the text, failing once the timeout passed.
+fn expect(lines: &Receiver<String>, text: &str, timeout: Duration, verbose: bool) -> Result<(), String> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match lines.recv_timeout(left) {
+            Ok(line) => {
+                if verbose { println!("    | {}", line); }
+                if line.contains(text) { return Ok(()); }
+            },
+            Err(mpsc::RecvTimeoutError::Timeout) => return Err(format!("Timed out after {:?} waiting for '{}'", timeout, text)),
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Err(format!("Output ended while waiting for '{}'", text))
+        }
+    }
+}
+
+/// Runs a script, one command per line:
+///
+/// - `timeout <seconds>` - sets how long the following expectations wait.
+/// - `expect <text>` - waits for a serial log line containing the text.
+/// - `send <command>` - sends a command over the control channel.
+/// - `expect-control <text>` - waits for a control channel line containing the text.
+///
+/// Empty lines and lines starting with `#` are skipped.
+fn run_script(path: &Path, verbose: bool) -> Result<(), String> {
+    let script = fs::read_to_string(path).map_err(|err| format!("Failed to read script: {}", err))?;
+    let mut machine = Machine::boot()?;
+    let mut timeout = DEFAULT_TIMEOUT;
+
+    for (number, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue; }
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let at_line = |err: String| format!("Line {}: {}", number + 1, err);
+
+        match command {
+            "timeout" => timeout = Duration::from_secs(
+                argument.parse().map_err(|_| at_line(format!("Invalid timeout '{}'", argument)))?
+            ),
+            "expect" => expect(&machine.log, argument, timeout, verbose).map_err(at_line)?,
+            "send" => {
+                let (stream, _) = machine.control().map_err(at_line)?;
+                writeln!(stream, "{}", argument).map_err(|err| at_line(err.to_string()))?;
+            },
+            "expect-control" => {
+                let (_, lines) = machine.control().map_err(at_line)?;
+                expect(lines, argument, timeout, verbose).map_err(at_line)?;
+            },
+            _ => return Err(at_line(format!("Unknown command '{}'", command)))
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let verbose = env::var("TEST_VERBOSE").is_ok();
+    let filter = env::args().skip(1).find(|arg| !arg.starts_with('-'));
+
+    let mut scripts: Vec<PathBuf> = fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts"))
+        .unwrap().map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "expect"))
+        .filter(|path| filter.as_ref().map_or(true, |filter| path.to_string_lossy().contains(filter.as_str())))
+        .collect();
+    scripts.sort();
+
+    let mut failed = 0;
+    for script in scripts.iter() {
+        let name = script.file_stem().unwrap().to_string_lossy();
+        match run_script(script, verbose) {
+            Ok(()) => println!("test {} ... ok", name),
+            Err(err) => {
+                println!("test {} ... FAILED\n    {}", name, err);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("\n{} passed, {} failed", scripts.len() - failed, failed);
+    process::exit(if failed > 0 { 1 } else { 0 });
+}
\ No newline at end of file
@@ -0,0 +1,128 @@
+//! Runs the calendar arithmetic of the kernel's `api/time.rs` on the host. The module only needs a
+//! few kernel items, which are stubbed below so it builds without the rest of the kernel.
+
+#[allow(dead_code)]
+#[path = "../kernel/src/api/time.rs"]
+mod time;
+
+mod internal {
+    pub mod cmos {
+        pub struct Rtc {
+            pub seconds: u8,
+            pub minutes: u8,
+            pub hours: u8,
+            pub day: u8,
+            pub month: u8,
+            pub year: u16
+        }
+    }
+
+    pub mod tsc {
+        pub fn read() -> u64 { 0 }
+
+        pub fn khz() -> u64 { 0 }
+    }
+}
+
+mod boot {
+    pub fn start() -> u64 { 0 }
+}
+
+use time::{Date, Duration, Month, Weekday};
+
+/// Dates with their days since 1970-01-01 and weekday, taken from the proleptic Gregorian calendar.
+const KNOWN_DATES: &[((u8, u8, i32), i64, Weekday)] = &[
+    ((1, 1, 1970), 0, Weekday::Thursday),
+    ((31, 12, 1969), -1, Weekday::Wednesday),
+    ((29, 2, 2000), 11_016, Weekday::Tuesday),
+    ((1, 1, 2000), 10_957, Weekday::Saturday),
+    ((1, 3, 1900), -25_508, Weekday::Thursday),
+    ((28, 2, 1900), -25_509, Weekday::Wednesday),
+    ((19, 1, 2038), 24_855, Weekday::Tuesday),
+    ((15, 10, 1582), -141_427, Weekday::Friday),
+    ((1, 1, 1), -719_162, Weekday::Monday),
+    ((1, 1, 0), -719_528, Weekday::Saturday),
+    ((31, 12, 9999), 2_932_896, Weekday::Friday)
+];
+
+/// Dates with their ISO 8601 week date, most of them in a week that spans the new year.
+const KNOWN_WEEK_DATES: &[((u8, u8, i32), (i32, u8, Weekday))] = &[
+    ((31, 12, 2020), (2020, 53, Weekday::Thursday)),
+    ((3, 1, 2021), (2020, 53, Weekday::Sunday)),
+    ((4, 1, 2021), (2021, 1, Weekday::Monday)),
+    ((1, 1, 2005), (2004, 53, Weekday::Saturday)),
+    ((2, 1, 2005), (2004, 53, Weekday::Sunday)),
+    ((31, 12, 2007), (2008, 1, Weekday::Monday)),
+    ((29, 12, 2008), (2009, 1, Weekday::Monday)),
+    ((3, 1, 2010), (2009, 53, Weekday::Sunday)),
+    ((1, 1, 2007), (2007, 1, Weekday::Monday)),
+    ((28, 12, 2015), (2015, 53, Weekday::Monday)),
+    ((17, 6, 2024), (2024, 25, Weekday::Monday))
+];
+
+fn date((day, month, year): (u8, u8, i32)) -> Date {
+    Date::new(day, Month::from_u8(month).unwrap(), year)
+}
+
+#[test]
+fn days_since_epoch_matches_known_dates() {
+    for &(calendar, days, weekday) in KNOWN_DATES {
+        let date = date(calendar);
+        assert_eq!(date.days_since_epoch(), days, "{}", date);
+        assert_eq!(Date::from_days_since_epoch(days), date, "{}", days);
+        assert_eq!(date.weekday() as u8, weekday as u8, "{}", date);
+    }
+}
+
+#[test]
+fn days_since_epoch_round_trips() {
+    // Two full eras around the epoch, both directions of the division have to agree
+    let mut previous = Date::from_days_since_epoch(-146_097 * 2 - 1);
+    for days in -146_097 * 2..=146_097 * 2 {
+        let date = Date::from_days_since_epoch(days);
+        assert_eq!(date.days_since_epoch(), days, "{}", date);
+        assert!(date.day() >= 1 && date.day() <= date.days_in_month(), "{}", date);
+        assert_eq!((date.weekday() as i64 - previous.weekday() as i64).rem_euclid(7), 1, "{}", date);
+        previous = date;
+    }
+}
+
+#[test]
+fn week_dates_match_known_dates() {
+    for &(calendar, (year, week, weekday)) in KNOWN_WEEK_DATES {
+        let date = date(calendar);
+        let week_date = date.as_week_date();
+        assert_eq!((week_date.0, week_date.1, week_date.2 as u8), (year, week, weekday as u8), "{}", date);
+        assert_eq!(date.week(), week, "{}", date);
+    }
+}
+
+#[test]
+fn weeks_are_continuous_across_years() {
+    // Every Monday starts the next week, which only goes back to 1 at the start of an ISO year
+    let mut previous = date((1, 1, 1999)).as_week_date();
+    for days in date((2, 1, 1999)).days_since_epoch()..date((31, 12, 2031)).days_since_epoch() {
+        let week_date = Date::from_days_since_epoch(days).as_week_date();
+        if let Weekday::Monday = week_date.2 {
+            if week_date.1 == 1 {
+                assert!(previous.1 == 52 || previous.1 == 53);
+                assert_eq!(week_date.0, previous.0 + 1);
+            } else {
+                assert_eq!((week_date.0, week_date.1), (previous.0, previous.1 + 1));
+            }
+        } else {
+            assert_eq!((week_date.0, week_date.1), (previous.0, previous.1));
+        }
+        previous = week_date;
+    }
+}
+
+#[test]
+fn adding_days_crosses_year_boundaries() {
+    assert_eq!(date((31, 12, 2020)).add(Duration::from_days(1)), date((1, 1, 2021)));
+    assert_eq!(date((1, 1, 2021)).sub(Duration::from_days(1)), date((31, 12, 2020)));
+    assert_eq!(date((1, 3, 2000)).sub(Duration::from_days(1)), date((29, 2, 2000)));
+    assert_eq!(date((1, 3, 1900)).sub(Duration::from_days(1)), date((28, 2, 1900)));
+    assert_eq!(date((1, 1, 1970)).sub(Duration::from_days(366)), date((31, 12, 1968)));
+    assert_eq!(date((15, 6, 2024)).add(Duration::from_days(146_097)), date((15, 6, 2424)));
+}
\ No newline at end of file